@@ -1,19 +1,34 @@
-use crate::cache::CacheExt;
+use crate::cache::ContractSource;
+use crate::cache::{network_id, CacheExt};
+use crate::gql_result::GQLBlockInterface;
+use crate::gql_result::GQLKeyEdgeInterface;
 use crate::gql_result::GQLNodeParent;
 use crate::gql_result::GQLResultInterface;
 use crate::gql_result::GQLTransactionsResultInterface;
 use crate::gql_result::{GQLBundled, GQLEdgeInterface};
+use crate::gql_stream::PageParser;
+use crate::miscellaneous::get_sort_key;
 use crate::miscellaneous::ContractType;
 use crate::miscellaneous::{get_contract_type, get_contract_type_raw};
 use crate::utils::{decode_base_64, get_tags};
 use deno_core::error::AnyError;
+use deno_core::futures::future::try_join_all;
 use deno_core::futures::stream;
+use deno_core::futures::try_join;
 use deno_core::futures::StreamExt;
-use once_cell::sync::OnceCell;
+use deno_core::serde_json::{self, Value};
+use once_cell::sync::{Lazy, OnceCell};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
 use reqwest::Client;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -50,6 +65,32 @@ pub struct Tag {
   pub value: String,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+  /// The gateway doesn't know a transaction the contract is made of: the
+  /// contract itself, its source or its init state. Unlike a network error,
+  /// retrying won't help; the id is most likely wrong.
+  ContractNotFound { id: String },
+  /// The evaluation downloaded more than its budget from the gateway, see
+  /// [`Arweave::with_download_budget`].
+  DownloadBudgetExceeded { limit: u64 },
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::ContractNotFound { id } => {
+        write!(f, "Transaction {} not found on the gateway", id)
+      }
+      Error::DownloadBudgetExceeded { limit } => {
+        write!(f, "Downloaded more than the budget of {} bytes", limit)
+      }
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Deserialize, Serialize, Default, Clone)]
 pub struct TransactionData {
   pub format: usize,
@@ -90,6 +131,18 @@ impl TransactionData {
       .map(|t| Ok(String::from_utf8(base64::decode(&t.value)?)?))
       .ok_or_else(|| AnyError::msg(format!("{} tag not found", tag)))?
   }
+
+  /// Values of every `tag` tag, in the order they appear in the
+  /// transaction.
+  pub fn get_tag_values(&self, tag: &str) -> Result<Vec<String>, AnyError> {
+    let encoded_tag = base64::encode_config(tag, base64::URL_SAFE_NO_PAD);
+    self
+      .tags
+      .iter()
+      .filter(|t| t.name == encoded_tag)
+      .map(|t| Ok(String::from_utf8(base64::decode(&t.value)?)?))
+      .collect()
+  }
 }
 
 #[derive(Clone)]
@@ -103,9 +156,63 @@ pub struct Arweave {
   pub host: String,
   pub port: i32,
   pub protocol: ArweaveProtocol,
+  /// Path under which the gateway is mounted, e.g. `/arweave` for a
+  /// reverse-proxied gateway. Empty for gateways served at the root.
+  pub path_prefix: String,
+  /// Base URL of a separate gateway used for GraphQL queries. When unset,
+  /// GraphQL goes to the same gateway as data requests.
+  pub gql_host: Option<String>,
+  /// Whether the gateway is arlocal, see [`Arweave::with_arlocal`].
+  pub arlocal: bool,
+  /// Bytes downloaded from the gateway, shared by the clones of this
+  /// client.
+  pub downloads: Downloads,
+  /// Bytes of the data body of an interaction read as its input, see
+  /// [`Arweave::with_max_body_input`].
+  pub max_body_input: usize,
+  /// Network the cache entries of this client belong to, see
+  /// [`Arweave::with_cache_network`]. `None` derives it from the gateway.
+  pub cache_network: Option<String>,
   client: Client,
 }
 
+/// Running total of the bytes response bodies took, and the budget they
+/// must stay within.
+#[derive(Clone, Debug, Default)]
+pub struct Downloads {
+  total: Arc<AtomicU64>,
+  budget: Option<u64>,
+}
+
+impl Downloads {
+  pub fn new(budget: Option<u64>) -> Downloads {
+    Downloads {
+      total: Arc::new(AtomicU64::new(0)),
+      budget,
+    }
+  }
+
+  /// Bytes downloaded so far, including the part of a response that went
+  /// over the budget, if any.
+  pub fn total(&self) -> u64 {
+    self.total.load(Ordering::SeqCst)
+  }
+
+  pub fn budget(&self) -> Option<u64> {
+    self.budget
+  }
+
+  fn add(&self, bytes: u64) -> Result<(), AnyError> {
+    let total = self.total.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    match self.budget {
+      Some(limit) if total > limit => {
+        Err(AnyError::new(Error::DownloadBudgetExceeded { limit }))
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct TagFilter {
   name: String,
@@ -128,10 +235,16 @@ pub struct InteractionVariables {
   after: Option<String>,
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct IdsVariables {
+  ids: Vec<String>,
+  first: usize,
+}
+
 #[derive(Deserialize, Serialize)]
-pub struct GraphqlQuery {
+pub struct GraphqlQuery<V = InteractionVariables> {
   query: String,
-  variables: InteractionVariables,
+  variables: V,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -143,38 +256,477 @@ pub struct LoadedContract {
   pub init_state: String,
   pub min_fee: Option<String>,
   pub contract_transaction: TransactionData,
+  /// The block the contract was deployed in. `None` for bundled contracts
+  /// and while the contract isn't mined.
+  #[serde(default)]
+  pub block: Option<GQLBlockInterface>,
+}
+
+#[derive(Deserialize)]
+struct GQLBlockNode {
+  block: Option<GQLBlockInterface>,
+}
+
+#[derive(Deserialize)]
+struct GQLBlockEdge {
+  node: GQLBlockNode,
+}
+
+impl LoadedContract {
+  /// Number of interactions the contract accepts, from the
+  /// `Max-Interactions` tag of its creation transaction. Contracts with
+  /// built-in spam protection use it.
+  pub fn max_interactions(&self) -> Option<usize> {
+    let max_interactions =
+      self.contract_transaction.get_tag("Max-Interactions").ok()?;
+    max_interactions.trim().parse().ok()
+  }
+
+  /// Gas each interaction of a WASM contract may consume, from the
+  /// `Max-Gas` tag of its creation transaction. Lets a contract declare a
+  /// tighter bound than the evaluator's, see
+  /// `three_em_executor::executor::EvaluateOptions::max_gas`.
+  pub fn max_gas(&self) -> Option<usize> {
+    let max_gas = self.contract_transaction.get_tag("Max-Gas").ok()?;
+    max_gas.trim().parse().ok()
+  }
 }
 
 enum State {
   Next(Option<String>, InteractionVariables),
-  #[allow(dead_code)]
   End,
 }
 
 pub static MAX_REQUEST: usize = 100;
 
+/// Default [`Arweave::max_body_input`], the executor's default limit on the
+/// length of an input.
+pub const DEFAULT_MAX_BODY_INPUT: usize = 1024 * 1024;
+
+/// Data bodies [`Arweave::fetch_body_inputs`] downloads at once.
+const MAX_BODY_FETCHES: usize = 16;
+
+/// Times the data body of an interaction is requested before its fetch
+/// fails, when the gateway can't be reached or answers with an error.
+const BODY_FETCH_ATTEMPTS: usize = 3;
+
 static ARWEAVE_CACHE: OnceCell<Arc<Mutex<dyn CacheExt + Send + Sync>>> =
   OnceCell::new();
 
+/// Block timestamps by block URL, see [`Arweave::get_block_timestamp`].
+static BLOCK_TIMESTAMPS: Lazy<Mutex<HashMap<String, u64>>> =
+  Lazy::new(Default::default);
+
 pub fn get_cache() -> &'static Arc<Mutex<dyn CacheExt + Send + Sync>> {
   ARWEAVE_CACHE.get().expect("cache is not initialized")
 }
 
+/// Runs `operation` against the configured cache, if any. Operations on
+/// the entries of a network go through [`Arweave::try_cache`] instead.
+///
+/// A cache backend that fails must not fail the evaluation using it, so
+/// errors are logged and come back as `None`, the same as when no cache is
+/// configured. Callers then carry on uncached.
+pub fn try_cache<T>(
+  operation: impl FnOnce(&mut (dyn CacheExt + Send + Sync)) -> Result<T, AnyError>,
+) -> Option<T> {
+  let cache = ARWEAVE_CACHE.get()?;
+  let result = match cache.lock() {
+    Ok(mut cache) => operation(&mut *cache),
+    Err(_) => Err(AnyError::msg("a cache operation panicked earlier")),
+  };
+
+  match result {
+    Ok(value) => Some(value),
+    Err(err) => {
+      tracing::warn!("Cache unavailable, continuing without it: {}", err);
+      None
+    }
+  }
+}
+
+/// Tag of a contract source transaction naming one part of a source split
+/// across several transactions, for contracts too large for one.
+///
+/// A source transaction with such tags holds no code itself. Its source is
+/// the data of the transactions they name, concatenated in the order the
+/// tags appear; tags are part of the signed transaction, so the order can't
+/// change. Its `Content-Type` applies to the whole source.
+pub const SOURCE_PART_TAG: &str = "Contract-Src-Part";
+
+/// Tag of a contract transaction carrying its whole source as a data URI,
+/// `data:<content type>[;base64],<source>`, for contracts small enough to
+/// fit in a tag.
+///
+/// The source is decoded from the tag rather than fetched, and the contract
+/// is its own source transaction. The tag takes precedence over
+/// `Contract-Src`, except when a source transaction is given explicitly, like
+/// after an evolve.
+pub const INLINE_SOURCE_TAG: &str = "Contract-Src-Inline";
+
+/// Decodes a `data:` URI into a source. Data without `;base64` is
+/// percent-decoded. A missing media type leaves the content type to the
+/// contract transaction.
+pub fn parse_data_uri(uri: &str) -> Result<ContractSource, AnyError> {
+  let (header, data) = uri
+    .strip_prefix("data:")
+    .and_then(|uri| uri.split_once(','))
+    .ok_or_else(|| AnyError::msg("Inline source is not a data URI"))?;
+  let (media_type, is_base64) = match header.strip_suffix(";base64") {
+    Some(media_type) => (media_type, true),
+    None => (header, false),
+  };
+  let data = if is_base64 {
+    base64::decode(data).map_err(|err| {
+      AnyError::msg(format!("Inline source is not valid base64: {}", err))
+    })?
+  } else {
+    percent_decode(data)?
+  };
+  // Parameters like `;charset=utf-8` don't change the contract type.
+  let content_type = media_type.split(';').next().unwrap_or_default().trim();
+
+  Ok(ContractSource {
+    content_type: if content_type.is_empty() {
+      None
+    } else {
+      Some(content_type.to_owned())
+    },
+    data,
+  })
+}
+
+fn percent_decode(data: &str) -> Result<Vec<u8>, AnyError> {
+  let mut bytes = data.bytes();
+  let mut decoded = Vec::with_capacity(data.len());
+  while let Some(byte) = bytes.next() {
+    if byte != b'%' {
+      decoded.push(byte);
+      continue;
+    }
+    let hex = [bytes.next(), bytes.next()];
+    let escape = match hex {
+      [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+      _ => None,
+    };
+    decoded.push(escape.ok_or_else(|| {
+      AnyError::msg("Inline source has an invalid percent escape")
+    })?);
+  }
+  Ok(decoded)
+}
+
+/// Where arlocal serves its gateway by default.
+pub const ARLOCAL_URL: &str = "http://localhost:1984";
+
+/// The arlocal release [`Arweave::with_arlocal`] is written against.
+pub const ARLOCAL_VERSION: &str = "1.1.30";
+
+/// Rewrites an arlocal GraphQL response into what arweave.net would return.
+///
+/// arlocal lists transactions posted since the last `/mine` with a `null`
+/// block, where gateways leave pending transactions out, and can leave the
+/// timestamp of a block `null`. Pending transactions are dropped and missing
+/// timestamps become `0`, so evaluations stay reproducible.
+fn normalize_arlocal_response(response: &mut Value) {
+  let edges = match response
+    .pointer_mut("/data/transactions/edges")
+    .and_then(Value::as_array_mut)
+  {
+    Some(edges) => edges,
+    None => return,
+  };
+
+  edges.retain(|edge| !edge["node"]["block"].is_null());
+  for edge in edges {
+    if let Some(block) = edge["node"]["block"].as_object_mut() {
+      let timestamp = block.entry("timestamp").or_insert(Value::Null);
+      if timestamp.is_null() {
+        *timestamp = Value::from(0);
+      }
+    }
+  }
+}
+
+/// Environment variable holding headers sent with every gateway request.
+/// Secrets belong here rather than in flags, which end up in shell history.
+pub const GATEWAY_HEADERS_ENV: &str = "THREE_EM_GATEWAY_HEADERS";
+
+/// Parses one `Name: value` header per line, as in `curl -H`. Blank lines
+/// are skipped. Errors name the offending line but never echo a value.
+pub fn parse_headers(spec: &str) -> Result<Vec<(String, String)>, AnyError> {
+  spec
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| !line.trim().is_empty())
+    .map(|(index, line)| match line.split_once(':') {
+      Some((name, value)) if !name.trim().is_empty() => {
+        Ok((name.trim().to_string(), value.trim().to_string()))
+      }
+      _ => Err(AnyError::msg(format!(
+        "Invalid gateway header on line {}, expected 'Name: value'",
+        index + 1
+      ))),
+    })
+    .collect()
+}
+
+/// The filter fetched interactions go through before they are returned.
+fn keep_interaction(p: &GQLEdgeInterface) -> bool {
+  (p.node.parent.is_none())
+    || p
+      .node
+      .parent
+      .as_ref()
+      .unwrap_or(&GQLNodeParent { id: None })
+      .id
+      .is_none()
+    || (p.node.bundledIn.is_none())
+    || p
+      .node
+      .bundledIn
+      .as_ref()
+      .unwrap_or(&GQLBundled { id: None })
+      .id
+      .is_none()
+}
+
+/// Whether a request that failed with `err` may succeed if sent again: the
+/// gateway couldn't be reached, took too long or failed itself.
+fn is_transient(err: &AnyError) -> bool {
+  match err.downcast_ref::<reqwest::Error>() {
+    Some(err) => {
+      err.is_connect()
+        || err.is_timeout()
+        || err.is_body()
+        || err
+          .status()
+          .map_or(false, |status| status.is_server_error())
+    }
+    None => false,
+  }
+}
+
 impl Arweave {
   pub fn new<T>(port: i32, host: String, protocol: String, cache: T) -> Arweave
   where
     T: CacheExt + Send + Sync + Debug + 'static,
   {
-    ARWEAVE_CACHE.set(Arc::new(Mutex::new(cache)));
-
-    Arweave {
+    let arweave = Arweave {
       port,
       host,
       protocol: match &protocol[..] {
         "http" => ArweaveProtocol::HTTP,
         "https" | _ => ArweaveProtocol::HTTPS,
       },
+      path_prefix: String::new(),
+      gql_host: None,
+      arlocal: false,
+      downloads: Downloads::default(),
+      max_body_input: DEFAULT_MAX_BODY_INPUT,
+      cache_network: None,
       client: Client::new(),
+    };
+    ARWEAVE_CACHE.set(Arc::new(Mutex::new(cache)));
+
+    arweave
+  }
+
+  /// Creates a client from a full gateway base URL such as
+  /// `https://gateway.example.com:8443/arweave`.
+  pub fn new_from_url<T>(base_url: &str, cache: T) -> Result<Arweave, AnyError>
+  where
+    T: CacheExt + Send + Sync + Debug + 'static,
+  {
+    let arweave = Arweave::new_from_url_no_cache(base_url)?;
+    ARWEAVE_CACHE.set(Arc::new(Mutex::new(cache)));
+    Ok(arweave)
+  }
+
+  pub fn new_from_url_no_cache(base_url: &str) -> Result<Arweave, AnyError> {
+    let url = reqwest::Url::parse(base_url)?;
+    let protocol = match url.scheme() {
+      "http" => ArweaveProtocol::HTTP,
+      "https" => ArweaveProtocol::HTTPS,
+      scheme => {
+        return Err(AnyError::msg(format!(
+          "Unsupported gateway protocol '{}'",
+          scheme
+        )))
+      }
+    };
+    let host = url
+      .host_str()
+      .ok_or_else(|| AnyError::msg("Gateway URL must contain a host"))?
+      .to_string();
+    let port = url.port_or_known_default().unwrap_or(443) as i32;
+    let path_prefix = url.path().trim_end_matches('/').to_string();
+
+    Ok(Arweave {
+      port,
+      host,
+      protocol,
+      path_prefix,
+      gql_host: None,
+      arlocal: false,
+      downloads: Downloads::default(),
+      max_body_input: DEFAULT_MAX_BODY_INPUT,
+      cache_network: None,
+      client: Client::new(),
+    })
+  }
+
+  /// Sends GraphQL queries (interaction fetches) to the gateway at
+  /// `base_url` while transaction and data requests keep using this
+  /// client's host.
+  pub fn with_gql_url(mut self, base_url: &str) -> Result<Arweave, AnyError> {
+    let url = reqwest::Url::parse(base_url)?;
+    match url.scheme() {
+      "http" | "https" => {}
+      scheme => {
+        return Err(AnyError::msg(format!(
+          "Unsupported GraphQL gateway protocol '{}'",
+          scheme
+        )))
+      }
+    }
+    if url.host_str().is_none() {
+      return Err(AnyError::msg("GraphQL gateway URL must contain a host"));
+    }
+
+    self.gql_host = Some(url.as_str().trim_end_matches('/').to_string());
+    Ok(self)
+  }
+
+  /// Tolerates the ways arlocal, the local test network, answers GraphQL
+  /// queries differently from arweave.net. See
+  /// [`normalize_arlocal_response`] and [`ARLOCAL_VERSION`].
+  pub fn with_arlocal(mut self) -> Arweave {
+    self.arlocal = true;
+    self
+  }
+
+  /// Fails gateway requests with [`Error::DownloadBudgetExceeded`] once
+  /// their bodies add up to more than `bytes`. The total starts over.
+  pub fn with_download_budget(mut self, bytes: u64) -> Arweave {
+    self.downloads = Downloads::new(Some(bytes));
+    self
+  }
+
+  /// Counts downloads against `downloads`, so that clients built for
+  /// foreign reads share the budget of the evaluation reading them.
+  pub fn with_downloads(mut self, downloads: Downloads) -> Arweave {
+    self.downloads = downloads;
+    self
+  }
+
+  /// Stops reading the data body of an interaction as its input after
+  /// `bytes` and one more, enough for the executor to reject it as too
+  /// long, if its limit is the same. See [`Arweave::fetch_body_inputs`].
+  pub fn with_max_body_input(mut self, bytes: usize) -> Arweave {
+    self.max_body_input = bytes;
+    self
+  }
+
+  /// Keeps the cache entries of this client under `network` instead of the
+  /// one its gateway serves, e.g. to share them between gateways serving
+  /// the same network. See [`Arweave::network`].
+  pub fn with_cache_network(mut self, network: &str) -> Arweave {
+    self.cache_network = Some(network.to_owned());
+    self
+  }
+
+  /// Bytes downloaded from the gateway so far, see [`Downloads::total`].
+  pub fn downloaded_bytes(&self) -> u64 {
+    self.downloads.total()
+  }
+
+  /// Reads the body of `response` a chunk at a time, so that a response
+  /// over the budget is cut short instead of read in full.
+  async fn read_body(
+    &self,
+    mut response: reqwest::Response,
+  ) -> Result<Vec<u8>, AnyError> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+      self.downloads.add(chunk.len() as u64)?;
+      body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+  }
+
+  async fn read_json<T: DeserializeOwned>(
+    &self,
+    response: reqwest::Response,
+  ) -> Result<T, AnyError> {
+    Ok(serde_json::from_slice(&self.read_body(response).await?)?)
+  }
+
+  /// Parses a GraphQL page while it downloads, see [`PageParser`],
+  /// decompressing it first if the gateway sent it zstd compressed.
+  async fn read_page<E: DeserializeOwned + Send>(
+    &self,
+    mut response: reqwest::Response,
+  ) -> Result<GQLResultInterface<E>, AnyError> {
+    let compressed = response
+      .headers()
+      .get(CONTENT_ENCODING)
+      .map_or(false, |encoding| encoding == "zstd");
+    let mut parser = PageParser::new();
+    {
+      let mut sink: Box<dyn Write + Send + '_> = if compressed {
+        Box::new(zstd::stream::write::Decoder::new(&mut parser)?)
+      } else {
+        Box::new(&mut parser)
+      };
+      while let Some(chunk) = response.chunk().await? {
+        self.downloads.add(chunk.len() as u64)?;
+        sink.write_all(&chunk)?;
+      }
+      sink.flush()?;
+    }
+    parser.finish()
+  }
+
+  async fn read_text(
+    &self,
+    response: reqwest::Response,
+  ) -> Result<String, AnyError> {
+    Ok(String::from_utf8(self.read_body(response).await?)?)
+  }
+
+  /// Attaches `headers` to every request, e.g. an `Authorization` header
+  /// for a private gateway. Values are marked sensitive and never end up
+  /// in errors or debug output.
+  pub fn with_headers(
+    mut self,
+    headers: &[(String, String)],
+  ) -> Result<Arweave, AnyError> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+      let header_name =
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+          AnyError::msg(format!("Invalid gateway header name '{}'", name))
+        })?;
+      let mut header_value = HeaderValue::from_str(value).map_err(|_| {
+        AnyError::msg(format!("Invalid value for gateway header '{}'", name))
+      })?;
+      header_value.set_sensitive(true);
+      header_map.insert(header_name, header_value);
+    }
+
+    self.client = Client::builder().default_headers(header_map).build()?;
+    Ok(self)
+  }
+
+  /// Attaches the headers in the [`GATEWAY_HEADERS_ENV`] environment
+  /// variable, if it is set. See [`parse_headers`] for its format.
+  pub fn with_env_headers(self) -> Result<Arweave, AnyError> {
+    match std::env::var(GATEWAY_HEADERS_ENV) {
+      Ok(spec) => self.with_headers(&parse_headers(&spec)?),
+      Err(_) => Ok(self),
     }
   }
 
@@ -186,81 +738,222 @@ impl Arweave {
         "http" => ArweaveProtocol::HTTP,
         "https" | _ => ArweaveProtocol::HTTPS,
       },
+      path_prefix: String::new(),
+      gql_host: None,
+      arlocal: false,
+      downloads: Downloads::default(),
+      max_body_input: DEFAULT_MAX_BODY_INPUT,
+      cache_network: None,
       client: Client::new(),
     }
   }
 
+  /// Fails with [`Error::ContractNotFound`] if the gateway doesn't know
+  /// `transaction_id`.
   pub async fn get_transaction(
     &self,
     transaction_id: &str,
-  ) -> reqwest::Result<TransactionData> {
-    let request = self
+  ) -> Result<TransactionData, AnyError> {
+    let response = self.get_transaction_response(transaction_id).await?;
+    let transaction = self.read_json(response).await?;
+    Ok(transaction)
+  }
+
+  /// The transaction as the gateway sends it, every field included. Fails
+  /// with [`Error::ContractNotFound`] if the gateway doesn't know
+  /// `transaction_id`.
+  pub async fn get_transaction_json(
+    &self,
+    transaction_id: &str,
+  ) -> Result<String, AnyError> {
+    let response = self.get_transaction_response(transaction_id).await?;
+    self.read_text(response).await
+  }
+
+  async fn get_transaction_response(
+    &self,
+    transaction_id: &str,
+  ) -> Result<reqwest::Response, AnyError> {
+    let response = self
       .client
-      .get(format!("{}/tx/{}", self.get_host(), transaction_id))
+      .get(self.get_url(&format!("tx/{}", transaction_id)))
       .send()
-      .await
-      .unwrap();
-    let transaction = request.json::<TransactionData>().await;
-    transaction
+      .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+      return Err(AnyError::new(Error::ContractNotFound {
+        id: transaction_id.to_owned(),
+      }));
+    }
+
+    Ok(response.error_for_status()?)
   }
 
+  /// Fails with [`Error::ContractNotFound`] if the gateway doesn't know
+  /// `transaction_id`.
   pub async fn get_bundled_contract(
     &self,
     transaction_id: &str,
-  ) -> reqwest::Result<BundledContract> {
-    let request = self
-      .client
-      .get(format!("{}/{}", self.get_host(), transaction_id))
-      .send()
-      .await
-      .unwrap();
-    let transaction = request.json::<BundledContract>().await;
-    transaction
+  ) -> Result<BundledContract, AnyError> {
+    let response = self.get_data_response(transaction_id).await?;
+    self.read_json::<BundledContract>(response).await
   }
 
-  pub async fn get_transaction_data(&self, transaction_id: &str) -> Vec<u8> {
-    let request = self
-      .client
-      .get(format!("{}/{}", self.get_host(), transaction_id))
-      .send()
-      .await
-      .unwrap();
-    request.bytes().await.unwrap().to_vec()
+  /// Fails with [`Error::ContractNotFound`] if the gateway doesn't know
+  /// `transaction_id`.
+  pub async fn get_transaction_data(
+    &self,
+    transaction_id: &str,
+  ) -> Result<Vec<u8>, AnyError> {
+    let response = self.get_data_response(transaction_id).await?;
+    self.read_body(response).await
+  }
+
+  async fn get_data_response(
+    &self,
+    transaction_id: &str,
+  ) -> Result<reqwest::Response, AnyError> {
+    let response = self.client.get(self.get_url(transaction_id)).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+      return Err(AnyError::new(Error::ContractNotFound {
+        id: transaction_id.to_owned(),
+      }));
+    }
+
+    Ok(response.error_for_status()?)
   }
 
   pub async fn get_transaction_block(
     &self,
     transaction_id: &str,
-  ) -> reqwest::Result<BlockInfo> {
+  ) -> Result<BlockInfo, AnyError> {
     let request = self
       .client
-      .get(format!("{}/tx/{}/status", self.get_host(), transaction_id))
+      .get(self.get_url(&format!("tx/{}/status", transaction_id)))
       .send()
       .await?;
 
-    let status = request.json::<TransactionStatus>().await?;
+    let status = self.read_json::<TransactionStatus>(request).await?;
     let block_hash = status.block_indep_hash;
 
     let request = self
       .client
-      .get(format!("{}/block/hash/{}", self.get_host(), block_hash))
+      .get(self.get_url(&format!("block/hash/{}", block_hash)))
       .send()
       .await?;
 
-    request.json::<BlockInfo>().await
+    self.read_json::<BlockInfo>(request).await
   }
 
-  pub async fn get_network_info(&self) -> NetworkInfo {
-    let info = self
+  /// Returns the current balance of `address` in winston.
+  pub async fn get_wallet_balance(
+    &self,
+    address: &str,
+  ) -> Result<String, AnyError> {
+    let response = self
       .client
-      .get(format!("{}/info", self.get_host()))
+      .get(self.get_url(&format!("wallet/{}/balance", address)))
       .send()
-      .await
+      .await?;
+    self.read_text(response).await
+  }
+
+  /// Anchor a new transaction references, instead of the last transaction
+  /// of its owner.
+  pub async fn get_tx_anchor(&self) -> Result<String, AnyError> {
+    let response = self
+      .client
+      .get(self.get_url("tx_anchor"))
+      .send()
+      .await?
+      .error_for_status()?;
+    self.read_text(response).await
+  }
+
+  /// Reward in winston the gateway asks for a transaction with `data_size`
+  /// bytes of data.
+  pub async fn get_price(&self, data_size: usize) -> Result<String, AnyError> {
+    let response = self
+      .client
+      .get(self.get_url(&format!("price/{}", data_size)))
+      .send()
+      .await?
+      .error_for_status()?;
+    self.read_text(response).await
+  }
+
+  /// Posts a signed transaction, as the JSON `/tx` takes.
+  pub async fn post_transaction(
+    &self,
+    transaction: &Value,
+  ) -> Result<(), AnyError> {
+    let response = self
+      .client
+      .post(self.get_url("tx"))
+      .json(transaction)
+      .send()
+      .await?;
+    let status = response.status();
+    if !status.is_success() {
+      let reason = response.text().await.unwrap_or_default();
+      return Err(AnyError::msg(format!(
+        "The gateway rejected the transaction ({}): {}",
+        status, reason
+      )));
+    }
+    Ok(())
+  }
+
+  pub async fn get_network_info(&self) -> Result<NetworkInfo, AnyError> {
+    let response = self.client.get(self.get_url("info")).send().await?;
+    self.read_json::<NetworkInfo>(response).await
+  }
+
+  /// When the block at `height` was mined, in seconds since the epoch.
+  /// Blocks don't change once mined, so each is only asked for once per
+  /// process.
+  pub async fn get_block_timestamp(
+    &self,
+    height: usize,
+  ) -> Result<u64, AnyError> {
+    #[derive(Deserialize)]
+    struct Block {
+      timestamp: u64,
+    }
+
+    let url = self.get_url(&format!("block/height/{}", height));
+    if let Some(timestamp) = BLOCK_TIMESTAMPS.lock().unwrap().get(&url) {
+      return Ok(*timestamp);
+    }
+    let response = self.client.get(&url).send().await?;
+    let block = self.read_json::<Block>(response).await?;
+    BLOCK_TIMESTAMPS
+      .lock()
       .unwrap()
-      .json::<NetworkInfo>()
-      .await
-      .unwrap();
-    info
+      .insert(url, block.timestamp);
+    Ok(block.timestamp)
+  }
+
+  /// Height of the last block mined at or before `timestamp`, in seconds
+  /// since the epoch, found by a binary search over block timestamps. 0 if
+  /// `timestamp` is before the first block.
+  pub async fn get_height_at(&self, timestamp: u64) -> Result<usize, AnyError> {
+    let tip = self.get_network_info().await?.height;
+    if self.get_block_timestamp(tip).await? <= timestamp {
+      return Ok(tip);
+    }
+
+    // Block `low` is at or before `timestamp`, unless it is the first
+    // block, and block `high` is after it.
+    let (mut low, mut high) = (0, tip);
+    while high - low > 1 {
+      let middle = low + (high - low) / 2;
+      if self.get_block_timestamp(middle).await? <= timestamp {
+        low = middle;
+      } else {
+        high = middle;
+      }
+    }
+    Ok(low)
   }
 
   pub async fn get_interactions(
@@ -268,19 +961,45 @@ impl Arweave {
     contract_id: String,
     height: Option<usize>,
     cache: bool,
+  ) -> Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> {
+    self
+      .fetch_interactions(contract_id, height, cache, None)
+      .await
+  }
+
+  /// Same as [`Arweave::get_interactions`] up to the tip, but only caches
+  /// the interactions mined at or below `confirmed_height`, which a reorg
+  /// can no longer drop. Those above it are fetched again on every call.
+  pub async fn get_confirmed_interactions(
+    &self,
+    contract_id: String,
+    confirmed_height: usize,
+    cache: bool,
+  ) -> Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> {
+    self
+      .fetch_interactions(contract_id, None, cache, Some(confirmed_height))
+      .await
+  }
+
+  /// Caches the interactions up to `max_cached_height`, or all of them.
+  async fn fetch_interactions(
+    &self,
+    contract_id: String,
+    height: Option<usize>,
+    cache: bool,
+    max_cached_height: Option<usize>,
   ) -> Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> {
     let mut interactions: Option<Vec<GQLEdgeInterface>> = None;
 
     let height_result = match height {
       Some(size) => size,
-      None => self.get_network_info().await.height,
+      None => self.get_network_info().await?.height,
     };
 
     if cache {
-      if let Some(cache_interactions) = get_cache()
-        .lock()
-        .unwrap()
-        .find_interactions(contract_id.to_owned())
+      if let Some(cache_interactions) = self
+        .try_cache(|cache| cache.find_interactions(contract_id.to_owned()))
+        .flatten()
       {
         if !cache_interactions.is_empty() {
           if height.is_some() {
@@ -292,7 +1011,7 @@ impl Arweave {
     }
 
     let variables = self
-      .get_default_gql_variables(contract_id.to_owned(), height_result)
+      .get_default_gql_variables(vec![contract_id.to_owned()], height_result)
       .await;
 
     let mut final_result: Vec<GQLEdgeInterface> = Vec::new();
@@ -300,110 +1019,259 @@ impl Arweave {
     let mut new_interactions_index: usize = 0;
 
     if let Some(mut cache_interactions) = interactions {
-      let last_transaction_edge = cache_interactions.last().unwrap();
-      let has_more_from_last_interaction = self
-        .has_more(&variables, last_transaction_edge.cursor.to_owned())
+      let last_cursor = cache_interactions.last().unwrap().cursor.to_owned();
+      let mut new_interactions = self
+        .get_interactions_after(
+          contract_id.to_owned(),
+          height_result,
+          last_cursor,
+        )
         .await?;
 
-      if has_more_from_last_interaction {
+      if !new_interactions.is_empty() {
         // Start from what's going to be the next interaction. if doing len - 1, that would mean we will also include the last interaction cached: not ideal.
         new_interactions_index = cache_interactions.len();
-        let fetch_more_interactions = self
-          .stream_interactions(
-            Some(last_transaction_edge.cursor.to_owned()),
-            variables.to_owned(),
-          )
-          .await;
-
-        for result in fetch_more_interactions {
-          let mut new_tx_infos = result.edges.clone();
-          cache_interactions.append(&mut new_tx_infos);
-        }
+        cache_interactions.append(&mut new_interactions);
         new_transactions = true;
       }
 
       final_result.append(&mut cache_interactions);
     } else {
-      let transactions = self
-        .get_next_interaction_page(variables.clone(), false, None)
-        .await?;
+      let mut tx_infos = self.fetch_all_interactions(variables).await?;
+      final_result.append(&mut tx_infos);
+      new_transactions = true;
+    }
+
+    let to_return: Vec<GQLEdgeInterface>;
+
+    if new_transactions {
+      let filtered: Vec<GQLEdgeInterface> =
+        final_result.into_iter().filter(keep_interaction).collect();
 
-      let mut tx_infos = transactions.edges.clone();
+      if cache {
+        // Interactions come in height order.
+        let cached = match max_cached_height {
+          Some(max_height) => filtered
+            .iter()
+            .take_while(|interaction| {
+              interaction.node.block.height <= max_height
+            })
+            .count(),
+          None => filtered.len(),
+        };
+        self.try_cache(|cache| {
+          cache.cache_interactions(contract_id, &filtered[..cached])
+        });
+      }
 
-      let mut cursor: Option<String> = None;
-      let max_edge = self.get_max_edges(&transactions.edges);
-      let maybe_edge = transactions.edges.get(max_edge);
+      to_return = filtered;
+    } else {
+      to_return = final_result;
+    }
 
-      if let Some(data) = maybe_edge {
-        let owned = data;
-        cursor = Some(owned.cursor.to_owned());
+    let are_there_new_interactions = cache && new_transactions;
+    Ok((
+      to_return,
+      new_interactions_index,
+      are_there_new_interactions,
+    ))
+  }
+
+  /// Fetches the interactions with a contract known by several ids, its
+  /// lineage, as [`Arweave::get_interactions`] does for each of them, and
+  /// merges them into one history sorted by [`get_sort_key`]. An
+  /// interaction naming more than one of the ids is kept once.
+  ///
+  /// With a single id, this is [`Arweave::get_interactions`]. Otherwise
+  /// the interactions new to the cache are spread through the history, so
+  /// they all come back as new, from index 0.
+  pub async fn get_lineage_interactions(
+    &self,
+    contract_ids: &[String],
+    height: Option<usize>,
+    cache: bool,
+  ) -> Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> {
+    if let [contract_id] = contract_ids {
+      return self
+        .get_interactions(contract_id.to_owned(), height, cache)
+        .await;
+    }
+
+    let fetches = contract_ids.iter().map(|contract_id| {
+      self.get_interactions(contract_id.to_owned(), height, cache)
+    });
+    let mut seen = HashSet::new();
+    let mut interactions = Vec::new();
+    let mut are_there_new_interactions = false;
+    for (fetched, _, new) in try_join_all(fetches).await? {
+      are_there_new_interactions |= new;
+      interactions.extend(
+        fetched
+          .into_iter()
+          .filter(|interaction| seen.insert(interaction.node.id.to_owned())),
+      );
+    }
+    interactions.sort_by_cached_key(|interaction| {
+      let node = &interaction.node;
+      get_sort_key(&node.block.height, &node.block.id, &node.id)
+    });
+
+    Ok((interactions, 0, are_there_new_interactions))
+  }
+
+  /// Fetches the interactions with a contract up to `height` that come
+  /// after the one at `cursor`, in gateway order.
+  ///
+  /// This is how cached interactions are brought up to date: only the
+  /// interactions the cache doesn't hold yet are transferred, however long
+  /// the contract's history is.
+  pub async fn get_interactions_after(
+    &self,
+    contract_id: String,
+    height: usize,
+    cursor: String,
+  ) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+    let mut variables = self
+      .get_default_gql_variables(vec![contract_id], height)
+      .await;
+    variables.after = Some(cursor);
+
+    let interactions = self.fetch_all_interactions(variables).await?;
+    Ok(interactions.into_iter().filter(keep_interaction).collect())
+  }
+
+  /// Fetches the interactions of all `contract_ids` with one paginated
+  /// query instead of one per contract, bypassing the interaction cache.
+  ///
+  /// The result is the union of what [`Arweave::get_interactions`] returns
+  /// for each contract, in gateway order and with every interaction once,
+  /// even when it names several of the contracts. Pick a contract's own
+  /// interactions out of it by their `Contract` tags.
+  pub async fn get_shared_interactions(
+    &self,
+    contract_ids: &[String],
+    height: Option<usize>,
+  ) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+    let height = match height {
+      Some(height) => height,
+      None => self.get_network_info().await?.height,
+    };
+    let variables = self
+      .get_default_gql_variables(contract_ids.to_vec(), height)
+      .await;
+
+    let interactions = self.fetch_all_interactions(variables).await?;
+    Ok(interactions.into_iter().filter(keep_interaction).collect())
+  }
+
+  async fn get_next_interaction_page(
+    &self,
+    mut variables: InteractionVariables,
+    from_last_page: bool,
+    max_results: Option<usize>,
+  ) -> Result<GQLTransactionsResultInterface, AnyError> {
+    let mut query = String::from(
+      r#"query Transactions($tags: [TagFilter!]!, $blockFilter: BlockFilter!, $first: Int!, $after: String) {
+    transactions(tags: $tags, block: $blockFilter, first: $first, sort: HEIGHT_ASC, after: $after) {
+      pageInfo {
+        hasNextPage
+      }
+      edges {
+        node {
+          id
+          owner { address }
+          recipient
+          tags {
+            name
+            value
+          }
+          block {
+            height
+            id
+            timestamp
+          }
+          fee { winston }
+          quantity { winston }
+          parent { id }
+        }
+        cursor
+      }
+    }
+  }"#,
+    );
+
+    if from_last_page {
+      query = query.replace("HEIGHT_ASC", "HEIGHT_DESC");
+      variables.first = max_results.unwrap_or(100);
+    }
+
+    self.post_graphql(query, variables).await
+  }
+
+  /// Fetches the sort-relevant fields of every interaction with a contract
+  /// up to `height`, without their tags.
+  ///
+  /// This is the first phase of a bounded-memory fetch: the keys are small
+  /// enough to hold all at once for sorting, and the full interactions are
+  /// then loaded a page at a time with [`Arweave::get_interactions_by_id`].
+  pub async fn get_interaction_keys(
+    &self,
+    contract_id: String,
+    height: Option<usize>,
+  ) -> Result<Vec<GQLKeyEdgeInterface>, AnyError> {
+    let height = match height {
+      Some(height) => height,
+      None => self.get_network_info().await?.height,
+    };
+    let mut variables = self
+      .get_default_gql_variables(vec![contract_id], height)
+      .await;
+    let query = String::from(
+      r#"query Transactions($tags: [TagFilter!]!, $blockFilter: BlockFilter!, $first: Int!, $after: String) {
+    transactions(tags: $tags, block: $blockFilter, first: $first, sort: HEIGHT_ASC, after: $after) {
+      pageInfo {
+        hasNextPage
       }
-
-      let results = self.stream_interactions(cursor, variables).await;
-
-      for result in results {
-        let mut new_tx_infos = result.edges.clone();
-        tx_infos.append(&mut new_tx_infos);
+      edges {
+        node {
+          id
+          owner { address }
+          block {
+            height
+            id
+          }
+        }
+        cursor
       }
-
-      final_result.append(&mut tx_infos);
-      new_transactions = true;
     }
+  }"#,
+    );
 
-    let to_return: Vec<GQLEdgeInterface>;
-
-    if new_transactions {
-      let filtered: Vec<GQLEdgeInterface> = final_result
-        .into_iter()
-        .filter(|p| {
-          (p.node.parent.is_none())
-            || p
-              .node
-              .parent
-              .as_ref()
-              .unwrap_or(&GQLNodeParent { id: None })
-              .id
-              .is_none()
-            || (p.node.bundledIn.is_none())
-            || p
-              .node
-              .bundledIn
-              .as_ref()
-              .unwrap_or(&GQLBundled { id: None })
-              .id
-              .is_none()
-        })
-        .collect();
+    let mut keys: Vec<GQLKeyEdgeInterface> = Vec::new();
+    loop {
+      let page = self
+        .post_graphql::<_, GQLKeyEdgeInterface>(query.clone(), &variables)
+        .await?;
+      let has_next_page = page.page_info.has_next_page;
+      variables.after = page.edges.last().map(|edge| edge.cursor.to_owned());
+      keys.extend(page.edges);
 
-      if cache {
-        get_cache()
-          .lock()
-          .unwrap()
-          .cache_interactions(contract_id, &filtered);
+      if !has_next_page || variables.after.is_none() {
+        break;
       }
-
-      to_return = filtered;
-    } else {
-      to_return = final_result;
     }
 
-    let are_there_new_interactions = cache && new_transactions;
-    Ok((
-      to_return,
-      new_interactions_index,
-      are_there_new_interactions,
-    ))
+    Ok(keys)
   }
 
-  async fn get_next_interaction_page(
+  /// Fetches full interactions by id, in the order of `ids`.
+  pub async fn get_interactions_by_id(
     &self,
-    mut variables: InteractionVariables,
-    from_last_page: bool,
-    max_results: Option<usize>,
-  ) -> Result<GQLTransactionsResultInterface, AnyError> {
-    let mut query = String::from(
-      r#"query Transactions($tags: [TagFilter!]!, $blockFilter: BlockFilter!, $first: Int!, $after: String) {
-    transactions(tags: $tags, block: $blockFilter, first: $first, sort: HEIGHT_ASC, after: $after) {
+    ids: &[String],
+  ) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+    let query = String::from(
+      r#"query Transactions($ids: [ID!], $first: Int!) {
+    transactions(ids: $ids, first: $first) {
       pageInfo {
         hasNextPage
       }
@@ -431,23 +1299,88 @@ impl Arweave {
   }"#,
     );
 
-    if from_last_page {
-      query = query.replace("HEIGHT_ASC", "HEIGHT_DESC");
-      variables.first = max_results.unwrap_or(100);
+    let mut found: HashMap<String, GQLEdgeInterface> = HashMap::new();
+    for chunk in ids.chunks(MAX_REQUEST) {
+      let variables = IdsVariables {
+        ids: chunk.to_vec(),
+        first: chunk.len(),
+      };
+      let mut page = self
+        .post_graphql::<_, GQLEdgeInterface>(query.clone(), &variables)
+        .await?;
+      self.fetch_body_inputs(&mut page.edges).await?;
+      for edge in page.edges {
+        found.insert(edge.node.id.to_owned(), edge);
+      }
     }
 
-    let graphql_query = GraphqlQuery { query, variables };
+    ids
+      .iter()
+      .map(|id| {
+        found.remove(id).ok_or_else(|| {
+          AnyError::msg(format!("Interaction {} not found on gateway", id))
+        })
+      })
+      .collect()
+  }
 
-    let req_url = format!("{}/graphql", self.get_host());
-    let result = self
-      .client
-      .post(req_url)
-      .json(&graphql_query)
-      .send()
-      .await
-      .unwrap();
+  /// Fetches the block a transaction was mined in, `None` while it is
+  /// pending.
+  pub async fn get_transaction_gql_block(
+    &self,
+    transaction_id: &str,
+  ) -> Result<Option<GQLBlockInterface>, AnyError> {
+    let query = String::from(
+      r#"query Transactions($ids: [ID!], $first: Int!) {
+    transactions(ids: $ids, first: $first) {
+      pageInfo {
+        hasNextPage
+      }
+      edges {
+        node {
+          block {
+            height
+            id
+            timestamp
+          }
+        }
+      }
+    }
+  }"#,
+    );
+
+    let variables = IdsVariables {
+      ids: vec![transaction_id.to_owned()],
+      first: 1,
+    };
+    let mut page = self
+      .post_graphql::<_, GQLBlockEdge>(query, &variables)
+      .await?;
+    Ok(page.edges.pop().and_then(|edge| edge.node.block))
+  }
+
+  async fn post_graphql<V: Serialize, E: DeserializeOwned + Send>(
+    &self,
+    query: String,
+    variables: V,
+  ) -> Result<GQLTransactionsResultInterface<E>, AnyError> {
+    let graphql_query = GraphqlQuery { query, variables };
+    let request = self.client.post(self.get_gql_url()).json(&graphql_query);
+    // arlocal responses are read whole, so they are asked for uncompressed.
+    let request = if self.arlocal {
+      request
+    } else {
+      request.header(ACCEPT_ENCODING, "zstd")
+    };
+    let response = request.send().await?;
 
-    let data = result.json::<GQLResultInterface>().await?;
+    let data = if self.arlocal {
+      let mut response = self.read_json::<Value>(response).await?;
+      normalize_arlocal_response(&mut response);
+      serde_json::from_value::<GQLResultInterface<E>>(response)?
+    } else {
+      self.read_page::<E>(response).await?
+    };
 
     Ok(data.data.transactions)
   }
@@ -465,55 +1398,61 @@ impl Arweave {
     let mut result: Option<LoadedContract> = None;
 
     if is_contract_in_bundled {
-      if let Ok(bundle_tx_search) =
-        self.get_bundled_contract(&contract_id.clone()).await
-      {
-        let owner = bundle_tx_search
-          .contractOwner
-          .unwrap_or_else(|| String::new());
-        let content_type = bundle_tx_search
-          .contentType
-          .unwrap_or_else(|| String::new());
-        let init_state =
-          bundle_tx_search.initState.unwrap_or_else(|| String::new());
-        let contract_data = bundle_tx_search.contractSrc;
-        return Ok(LoadedContract {
-          id: contract_id.clone(),
-          contract_src_tx_id: contract_id.clone(),
-          contract_src: contract_data,
-          contract_type: get_contract_type_raw(content_type),
-          init_state,
-          min_fee: None,
-          contract_transaction: TransactionData {
-            format: 2,
-            id: contract_id,
-            last_tx: String::new(),
-            owner,
-            tags: vec![],
-            target: String::new(),
-            quantity: String::new(),
-            data: String::new(),
-            reward: String::new(),
-            signature: String::new(),
-            data_size: String::new(),
-            data_root: String::new(),
-          },
-        });
-      } else {
-        panic!("Bundled contract was not found during query")
-      }
+      let bundle_tx_search = self.get_bundled_contract(&contract_id).await?;
+      let owner = bundle_tx_search
+        .contractOwner
+        .unwrap_or_else(|| String::new());
+      // Bundled contracts without a content type have always been run
+      // as JavaScript.
+      let content_type = bundle_tx_search
+        .contentType
+        .unwrap_or_else(|| String::from("application/javascript"));
+      let init_state =
+        bundle_tx_search.initState.unwrap_or_else(|| String::new());
+      let contract_data = bundle_tx_search.contractSrc;
+      return Ok(LoadedContract {
+        id: contract_id.clone(),
+        contract_src_tx_id: contract_id.clone(),
+        contract_src: contract_data,
+        contract_type: get_contract_type_raw(content_type),
+        init_state,
+        min_fee: None,
+        block: None,
+        contract_transaction: TransactionData {
+          format: 2,
+          id: contract_id,
+          last_tx: String::new(),
+          owner,
+          tags: vec![],
+          target: String::new(),
+          quantity: String::new(),
+          data: String::new(),
+          reward: String::new(),
+          signature: String::new(),
+          data_size: String::new(),
+          data_root: String::new(),
+        },
+      });
     }
 
     if cache {
-      result = get_cache()
-        .lock()
-        .unwrap()
-        .find_contract(contract_id.to_owned());
+      result = self
+        .try_cache(|cache| cache.find_contract(contract_id.to_owned()))
+        .flatten();
     }
 
     if result.is_some() {
       let mut cached_result = result.unwrap();
 
+      // Cached while pending, or before deployment blocks were recorded.
+      if cached_result.block.is_none() {
+        cached_result.block =
+          self.get_transaction_gql_block(&contract_id).await?;
+        if cached_result.block.is_some() {
+          self.try_cache(|cache| cache.cache_contract(&cached_result));
+        }
+      }
+
       if simulated {
         if let Some(init_state) = contract_init_state {
           cached_result.init_state = init_state;
@@ -524,18 +1463,32 @@ impl Arweave {
     } else {
       let contract_transaction = self.get_transaction(&contract_id).await?;
 
-      let contract_src = contract_src_tx_id
-        .or_else(|| contract_transaction.get_tag("Contract-Src").ok())
-        .ok_or_else(|| {
-          AnyError::msg("Contract-Src tag not found in transaction")
-        })?;
+      let inline_source = match contract_src_tx_id {
+        Some(_) => None,
+        None => contract_transaction.get_tag(INLINE_SOURCE_TAG).ok(),
+      };
 
       let min_fee = contract_transaction.get_tag("Min-Fee").ok();
 
-      let contract_src_tx = self.get_transaction(&contract_src).await?;
-
-      let contract_src_data =
-        self.get_transaction_data(&contract_src_tx.id).await;
+      let (contract_src, source, block) = match inline_source {
+        Some(uri) => (
+          contract_id.to_owned(),
+          parse_data_uri(&uri)?,
+          self.get_transaction_gql_block(&contract_id).await?,
+        ),
+        None => {
+          let contract_src = contract_src_tx_id
+            .or_else(|| contract_transaction.get_tag("Contract-Src").ok())
+            .ok_or_else(|| {
+              AnyError::msg("Contract-Src tag not found in transaction")
+            })?;
+          let (source, block) = try_join!(
+            self.load_contract_source(&contract_src),
+            self.get_transaction_gql_block(&contract_id)
+          )?;
+          (contract_src, source, block)
+        }
+      };
 
       let mut state: String;
 
@@ -555,7 +1508,7 @@ impl Arweave {
 
           if state.is_empty() {
             state = String::from_utf8(
-              self.get_transaction_data(&contract_transaction.id).await,
+              self.get_transaction_data(&contract_transaction.id).await?,
             )
             .unwrap();
           }
@@ -565,43 +1518,117 @@ impl Arweave {
       let contract_type = get_contract_type(
         contract_type,
         &contract_transaction,
-        &contract_src_tx,
+        source.content_type,
       )?;
 
       let final_result = LoadedContract {
         id: contract_id,
         contract_src_tx_id: contract_src,
-        contract_src: contract_src_data,
+        contract_src: source.data,
         contract_type,
         init_state: state,
         min_fee,
         contract_transaction,
+        block,
       };
 
       if cache {
-        get_cache().lock().unwrap().cache_contract(&final_result);
+        self.try_cache(|cache| cache.cache_contract(&final_result));
       }
 
       Ok(final_result)
     }
   }
 
-  fn get_host(&self) -> String {
+  /// Fetches a contract source, going through the source cache whenever a
+  /// cache is configured. Sources are immutable, so this is independent of
+  /// whether contract or state caching is enabled. A source split with
+  /// [`SOURCE_PART_TAG`] is cached once assembled.
+  async fn load_contract_source(
+    &self,
+    source_tx_id: &str,
+  ) -> Result<ContractSource, AnyError> {
+    if let Some(source) = self
+      .try_cache(|cache| cache.find_source(source_tx_id.to_owned()))
+      .flatten()
+    {
+      return Ok(source);
+    }
+
+    let source_tx = self.get_transaction(source_tx_id).await?;
+    let parts = source_tx.get_tag_values(SOURCE_PART_TAG)?;
+    let data = if parts.is_empty() {
+      self.get_transaction_data(&source_tx.id).await?
+    } else {
+      try_join_all(parts.iter().map(|part| self.get_transaction_data(part)))
+        .await?
+        .concat()
+    };
+    let source = ContractSource {
+      content_type: source_tx.get_tag("Content-Type").ok(),
+      data,
+    };
+
+    self
+      .try_cache(|cache| cache.cache_source(source_tx_id.to_owned(), &source));
+
+    Ok(source)
+  }
+
+  /// The network this client talks to, see [`network_id`], unless another
+  /// one was set with [`Arweave::with_cache_network`].
+  pub fn network(&self) -> String {
+    match &self.cache_network {
+      Some(network) => network.to_owned(),
+      None => network_id(&self.get_host()),
+    }
+  }
+
+  /// Runs `operation` against the configured cache, on the entries of the
+  /// network this client talks to. See [`try_cache`].
+  pub fn try_cache<T>(
+    &self,
+    operation: impl FnOnce(&mut (dyn CacheExt + Send + Sync)) -> Result<T, AnyError>,
+  ) -> Option<T> {
+    let network = self.network();
+    try_cache(|cache| {
+      cache.set_network(&network);
+      operation(cache)
+    })
+  }
+
+  pub fn get_host(&self) -> String {
     let protocol = match self.protocol {
       ArweaveProtocol::HTTP => "http",
       ArweaveProtocol::HTTPS => "https",
     };
 
     if self.port == 80 {
-      format!("{}://{}", protocol, self.host)
+      format!("{}://{}{}", protocol, self.host, self.path_prefix)
     } else {
-      format!("{}://{}:{}", protocol, self.host, self.port)
+      format!(
+        "{}://{}:{}{}",
+        protocol, self.host, self.port, self.path_prefix
+      )
+    }
+  }
+
+  fn get_url(&self, path: &str) -> String {
+    format!("{}/{}", self.get_host(), path)
+  }
+
+  fn get_gql_url(&self) -> String {
+    match &self.gql_host {
+      Some(gql_host) => format!("{}/graphql", gql_host),
+      None => self.get_url("graphql"),
     }
   }
 
+  /// Variables selecting the interactions of any of `contract_ids`: a
+  /// tag filter with several values matches any one of them.
   async fn get_default_gql_variables(
     &self,
-    contract_id: String,
+    contract_ids: Vec<String>,
     height: usize,
   ) -> InteractionVariables {
     let app_name_tag: TagFilter = TagFilter {
@@ -611,7 +1638,7 @@ impl Arweave {
 
     let contract_tag: TagFilter = TagFilter {
       name: "Contract".to_owned(),
-      values: vec![contract_id],
+      values: contract_ids,
     };
 
     let variables: InteractionVariables = InteractionVariables {
@@ -624,6 +1651,120 @@ impl Arweave {
     variables
   }
 
+  /// Fetches every page of interactions matching `variables`, starting
+  /// after `variables.after` if set, along with the inputs carried in their
+  /// data. See [`Arweave::fetch_body_inputs`].
+  async fn fetch_all_interactions(
+    &self,
+    variables: InteractionVariables,
+  ) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+    let mut interactions = self.fetch_interaction_pages(variables).await?;
+    self.fetch_body_inputs(&mut interactions).await?;
+    Ok(interactions)
+  }
+
+  async fn fetch_interaction_pages(
+    &self,
+    variables: InteractionVariables,
+  ) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+    let transactions = self
+      .get_next_interaction_page(variables.clone(), false, None)
+      .await?;
+
+    let mut tx_infos = transactions.edges.clone();
+    // Streaming without a cursor would start over from the first page.
+    if tx_infos.is_empty() || !transactions.page_info.has_next_page {
+      return Ok(tx_infos);
+    }
+
+    let mut cursor: Option<String> = None;
+    let max_edge = self.get_max_edges(&transactions.edges);
+    let maybe_edge = transactions.edges.get(max_edge);
+
+    if let Some(data) = maybe_edge {
+      let owned = data;
+      cursor = Some(owned.cursor.to_owned());
+    }
+
+    let results = self.stream_interactions(cursor, variables).await;
+
+    for result in results {
+      let mut new_tx_infos = result.edges.clone();
+      tx_infos.append(&mut new_tx_infos);
+    }
+
+    Ok(tx_infos)
+  }
+
+  /// Fills in [`crate::gql_result::GQLNodeInterface::body_input`] for the
+  /// bundled interactions without an `Input` tag, from the data of their
+  /// data item. Interactions whose data is missing, empty or not UTF-8 are
+  /// left without input.
+  async fn fetch_body_inputs(
+    &self,
+    interactions: &mut [GQLEdgeInterface],
+  ) -> Result<(), AnyError> {
+    let untagged = interactions.iter_mut().filter(|interaction| {
+      let bundled = interaction
+        .node
+        .bundledIn
+        .as_ref()
+        .map_or(false, |bundle| bundle.id.is_some());
+      bundled && !interaction.node.tags.iter().any(|tag| tag.name == "Input")
+    });
+    let mut fetches = stream::iter(untagged.map(|interaction| async move {
+      interaction.node.body_input =
+        self.fetch_body_input(&interaction.node.id).await?;
+      Ok::<(), AnyError>(())
+    }))
+    .buffer_unordered(MAX_BODY_FETCHES);
+    while let Some(fetch) = fetches.next().await {
+      fetch?;
+    }
+
+    Ok(())
+  }
+
+  /// The input in the data body of `id`, see [`Arweave::fetch_body_inputs`].
+  /// Fetches failing on the way to the gateway, or because of it, are
+  /// retried.
+  async fn fetch_body_input(
+    &self,
+    id: &str,
+  ) -> Result<Option<String>, AnyError> {
+    let mut attempts = 1;
+    loop {
+      match self.try_fetch_body_input(id).await {
+        Err(err) if attempts < BODY_FETCH_ATTEMPTS && is_transient(&err) => {
+          attempts += 1;
+        }
+        result => return result,
+      }
+    }
+  }
+
+  async fn try_fetch_body_input(
+    &self,
+    id: &str,
+  ) -> Result<Option<String>, AnyError> {
+    let response = self.client.get(self.get_url(id)).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+      return Ok(None);
+    }
+    let mut response = response.error_for_status()?;
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+      self.downloads.add(chunk.len() as u64)?;
+      body.extend_from_slice(&chunk);
+      // Too long an input is invalid, whatever the rest of it is.
+      if body.len() > self.max_body_input {
+        body.truncate(self.max_body_input + 1);
+        return Ok(Some(String::from_utf8_lossy(&body).into_owned()));
+      }
+    }
+    Ok(String::from_utf8(body).ok().filter(|body| !body.is_empty()))
+  }
+
   async fn stream_interactions(
     &self,
     cursor: Option<String>,
@@ -651,7 +1792,12 @@ impl Arweave {
 
             if let Some(result_edge) = edge {
               let cursor = result_edge.cursor.to_owned();
-              Some((tx, State::Next(Some(cursor), variables)))
+              let next = if tx.page_info.has_next_page {
+                State::Next(Some(cursor), variables)
+              } else {
+                State::End
+              };
+              Some((tx, next))
             } else {
               None
             }
@@ -673,29 +1819,20 @@ impl Arweave {
       len - 1
     }
   }
-
-  async fn has_more(
-    &self,
-    variables: &InteractionVariables,
-    cursor: String,
-  ) -> Result<bool, AnyError> {
-    let mut variables = variables.to_owned();
-    variables.after = Some(cursor);
-    variables.first = 1;
-
-    let load_transactions = self
-      .get_next_interaction_page(variables, false, None)
-      .await?;
-
-    Ok(!load_transactions.edges.is_empty())
-  }
 }
 
 #[cfg(test)]
 mod tests {
+  use crate::arweave::normalize_arlocal_response;
+  use crate::arweave::parse_data_uri;
+  use crate::arweave::parse_headers;
   use crate::arweave::Arweave;
   use crate::cache::ArweaveCache;
   use crate::cache::CacheExt;
+  use crate::gql_result::GQLResultInterface;
+  use deno_core::serde_json;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
 
   #[tokio::test]
   pub async fn test_build_host() {
@@ -721,4 +1858,422 @@ mod tests {
     );
     assert_eq!(arweave.get_host(), "https://arweave.net:500");
   }
+
+  #[tokio::test]
+  pub async fn test_build_host_from_url() {
+    let arweave = Arweave::new_from_url_no_cache(
+      "https://gateway.example.com:8443/arweave/",
+    )
+    .unwrap();
+    assert_eq!(arweave.host, "gateway.example.com");
+    assert_eq!(arweave.port, 8443);
+    assert_eq!(arweave.path_prefix, "/arweave");
+    assert_eq!(
+      arweave.get_url("graphql"),
+      "https://gateway.example.com:8443/arweave/graphql"
+    );
+    assert_eq!(
+      arweave.get_url("tx/abc/status"),
+      "https://gateway.example.com:8443/arweave/tx/abc/status"
+    );
+
+    let arweave =
+      Arweave::new_from_url_no_cache("http://localhost:1984").unwrap();
+    assert_eq!(arweave.path_prefix, "");
+    assert_eq!(arweave.get_url("info"), "http://localhost:1984/info");
+
+    let arweave = Arweave::new_from_url_no_cache("http://arweave.net").unwrap();
+    assert_eq!(arweave.get_url("info"), "http://arweave.net/info");
+
+    assert!(Arweave::new_from_url_no_cache("ftp://arweave.net").is_err());
+  }
+
+  #[tokio::test]
+  pub async fn test_separate_gql_endpoint() {
+    let arweave =
+      Arweave::new_from_url_no_cache("https://data.example.com/arweave")
+        .unwrap()
+        .with_gql_url("https://gql.example.com:8443/")
+        .unwrap();
+    assert_eq!(
+      arweave.get_gql_url(),
+      "https://gql.example.com:8443/graphql"
+    );
+    assert_eq!(
+      arweave.get_url("tx/abc"),
+      "https://data.example.com:443/arweave/tx/abc"
+    );
+    assert_eq!(
+      arweave.get_url("info"),
+      "https://data.example.com:443/arweave/info"
+    );
+
+    // A single endpoint serves both.
+    let arweave =
+      Arweave::new_from_url_no_cache("http://localhost:1984").unwrap();
+    assert_eq!(arweave.get_gql_url(), "http://localhost:1984/graphql");
+    assert_eq!(arweave.get_url("tx/abc"), "http://localhost:1984/tx/abc");
+
+    assert!(Arweave::new_from_url_no_cache("http://localhost:1984")
+      .unwrap()
+      .with_gql_url("ftp://gql.example.com")
+      .is_err());
+  }
+
+  #[tokio::test]
+  pub async fn test_contract_source_cache() {
+    let arweave = Arweave::new(
+      443,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    // Sources are cached even when contract caching is off.
+    let contract = arweave
+      .load_contract(
+        String::from("t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE"),
+        None,
+        None,
+        None,
+        false,
+        false,
+        false,
+      )
+      .await
+      .unwrap();
+
+    let source = arweave
+      .try_cache(|cache| {
+        cache.find_source(contract.contract_src_tx_id.to_owned())
+      })
+      .flatten()
+      .unwrap();
+    assert_eq!(source.data, contract.contract_src);
+  }
+
+  #[tokio::test]
+  pub async fn test_gateway_headers() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let gateway = tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut request = vec![0; 4096];
+      let len = socket.read(&mut request).await.unwrap();
+      socket
+        .write_all(
+          b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\n1000",
+        )
+        .await
+        .unwrap();
+      String::from_utf8_lossy(&request[..len]).to_lowercase()
+    });
+
+    let headers =
+      parse_headers("Authorization: Bearer secret\n\nX-Api-Key: k").unwrap();
+    let arweave =
+      Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+        .unwrap()
+        .with_headers(&headers)
+        .unwrap();
+
+    let balance = arweave.get_wallet_balance("wallet").await.unwrap();
+    assert_eq!(balance, "1000");
+    let request = gateway.await.unwrap();
+    assert!(request.contains("authorization: bearer secret\r\n"));
+    assert!(request.contains("x-api-key: k\r\n"));
+  }
+
+  #[tokio::test]
+  pub async fn test_bundled_contract_not_found() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut request = vec![0; 4096];
+      socket.read(&mut request).await.unwrap();
+      socket
+        .write_all(
+          b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    });
+
+    let arweave =
+      Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+        .unwrap();
+    let err = arweave.get_bundled_contract("missing").await.unwrap_err();
+    match err.downcast_ref::<Error>() {
+      Some(Error::ContractNotFound { id }) => assert_eq!(id, "missing"),
+      _ => panic!("unexpected error: {}", err),
+    }
+  }
+
+  #[tokio::test]
+  pub async fn test_get_interactions_after() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // Two pages after `cursor-2`, the last cached interaction.
+    let gateway = tokio::spawn(async move {
+      let mut afters = vec![];
+      for (ids, has_next_page) in
+        [(&["tx3", "tx4"][..], true), (&["tx5"], false)]
+      {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![];
+        let mut buf = vec![0; 4096];
+        let body = loop {
+          let len = socket.read(&mut buf).await.unwrap();
+          request.extend_from_slice(&buf[..len]);
+          let request = String::from_utf8_lossy(&request);
+          if let Some((head, body)) = request.split_once("\r\n\r\n") {
+            let content_length = head
+              .lines()
+              .find_map(|line| {
+                line
+                  .to_lowercase()
+                  .strip_prefix("content-length:")
+                  .map(|value| value.trim().parse::<usize>().unwrap())
+              })
+              .unwrap();
+            if body.len() >= content_length {
+              break body.to_owned();
+            }
+          }
+        };
+        let query: serde_json::Value = serde_json::from_str(&body).unwrap();
+        afters.push(query["variables"]["after"].clone());
+
+        let edges: Vec<_> = ids
+          .iter()
+          .map(|id| {
+            serde_json::json!({
+              "cursor": format!("cursor-{}", &id[2..]),
+              "node": {
+                "id": id,
+                "owner": { "address": "owner" },
+                "tags": [],
+                "block": { "id": "block", "timestamp": 1, "height": 1 },
+              },
+            })
+          })
+          .collect();
+        let body = serde_json::json!({
+          "data": {
+            "transactions": {
+              "pageInfo": { "hasNextPage": has_next_page },
+              "edges": edges,
+            }
+          }
+        })
+        .to_string();
+        let head = format!(
+          "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+          body.len()
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(body.as_bytes()).await.unwrap();
+      }
+      afters
+    });
+
+    let arweave =
+      Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+        .unwrap();
+    let interactions = arweave
+      .get_interactions_after(
+        String::from("contract"),
+        100,
+        String::from("cursor-2"),
+      )
+      .await
+      .unwrap();
+
+    let ids: Vec<&str> = interactions
+      .iter()
+      .map(|interaction| interaction.node.id.as_str())
+      .collect();
+    assert_eq!(ids, ["tx3", "tx4", "tx5"]);
+    // Nothing before the cached cursor is asked for again.
+    assert_eq!(gateway.await.unwrap(), ["cursor-2", "cursor-4"]);
+  }
+
+  #[tokio::test]
+  pub async fn test_get_lineage_interactions() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // `txB` names both ids of the contract.
+    let history = |contract_id: &str| match contract_id {
+      "old-id" => vec![("tx1", 1), ("txB", 2), ("tx5", 5)],
+      _ => vec![("txB", 2), ("tx3", 3), ("tx4", 4)],
+    };
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![];
+        let mut buf = vec![0; 4096];
+        let body = loop {
+          let len = socket.read(&mut buf).await.unwrap();
+          request.extend_from_slice(&buf[..len]);
+          let request = String::from_utf8_lossy(&request);
+          if let Some((head, body)) = request.split_once("\r\n\r\n") {
+            let content_length = head
+              .lines()
+              .find_map(|line| {
+                line
+                  .to_lowercase()
+                  .strip_prefix("content-length:")
+                  .map(|value| value.trim().parse::<usize>().unwrap())
+              })
+              .unwrap();
+            if body.len() >= content_length {
+              break body.to_owned();
+            }
+          }
+        };
+        let query: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let contract_id = query["variables"]["tags"][1]["values"][0]
+          .as_str()
+          .unwrap()
+          .to_owned();
+
+        let edges: Vec<_> = history(&contract_id)
+          .into_iter()
+          .map(|(id, height)| {
+            serde_json::json!({
+              "cursor": id,
+              "node": {
+                "id": id,
+                "owner": { "address": "owner" },
+                "tags": [{ "name": "Input", "value": "{}" }],
+                "block": {
+                  "id": format!("blk{}", height),
+                  "timestamp": height,
+                  "height": height,
+                },
+              },
+            })
+          })
+          .collect();
+        let body = serde_json::json!({
+          "data": {
+            "transactions": {
+              "pageInfo": { "hasNextPage": false },
+              "edges": edges,
+            }
+          }
+        })
+        .to_string();
+        let head = format!(
+          "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+          body.len()
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(body.as_bytes()).await.unwrap();
+      }
+    });
+
+    let arweave =
+      Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+        .unwrap();
+    let lineage = [String::from("old-id"), String::from("new-id")];
+    let (interactions, new_interaction_index, _) = arweave
+      .get_lineage_interactions(&lineage, Some(100), false)
+      .await
+      .unwrap();
+
+    let ids: Vec<&str> = interactions
+      .iter()
+      .map(|interaction| interaction.node.id.as_str())
+      .collect();
+    assert_eq!(ids, ["tx1", "txB", "tx3", "tx4", "tx5"]);
+    assert_eq!(new_interaction_index, 0);
+
+    // A single id is a plain fetch.
+    let (interactions, _, _) = arweave
+      .get_lineage_interactions(&lineage[1..], Some(100), false)
+      .await
+      .unwrap();
+    assert_eq!(interactions.len(), 3);
+  }
+
+  #[tokio::test]
+  pub async fn test_normalize_arlocal_response() {
+    let edge = |id: &str, block: serde_json::Value| {
+      serde_json::json!({
+        "cursor": id,
+        "node": {
+          "id": id,
+          "owner": { "address": "owner" },
+          "tags": [],
+          "block": block,
+        },
+      })
+    };
+    let block = |timestamp: Option<usize>| match timestamp {
+      Some(timestamp) => {
+        serde_json::json!({ "id": "b", "height": 1, "timestamp": timestamp })
+      }
+      None => serde_json::json!({ "id": "b", "height": 1, "timestamp": null }),
+    };
+    let mut response = serde_json::json!({
+      "data": {
+        "transactions": {
+          "pageInfo": { "hasNextPage": false },
+          "edges": [
+            edge("mined", block(Some(5))),
+            edge("pending", serde_json::Value::Null),
+            edge("untimed", block(None)),
+            edge("no-timestamp", serde_json::json!({ "id": "b", "height": 1 })),
+          ],
+        }
+      }
+    });
+
+    normalize_arlocal_response(&mut response);
+    let data: GQLResultInterface = serde_json::from_value(response).unwrap();
+    let blocks: Vec<(&str, usize)> = data
+      .data
+      .transactions
+      .edges
+      .iter()
+      .map(|edge| (edge.node.id.as_str(), edge.node.block.timestamp))
+      .collect();
+    assert_eq!(blocks, [("mined", 5), ("untimed", 0), ("no-timestamp", 0)]);
+  }
+
+  #[tokio::test]
+  pub async fn test_parse_headers() {
+    assert_eq!(
+      parse_headers(" Authorization : Bearer a:b ").unwrap(),
+      vec![(String::from("Authorization"), String::from("Bearer a:b"))]
+    );
+    let err = parse_headers("X-Api-Key: k\nsecret-token").unwrap_err();
+    assert!(err.to_string().contains("line 2"));
+    assert!(!err.to_string().contains("secret-token"));
+  }
+
+  #[tokio::test]
+  pub async fn test_parse_data_uri() {
+    let source =
+      parse_data_uri("data:application/javascript;base64,ZXhwb3J0IHt9")
+        .unwrap();
+    assert_eq!(source.content_type.unwrap(), "application/javascript");
+    assert_eq!(source.data, b"export {}");
+
+    let source = parse_data_uri(
+      "data:application/javascript;charset=utf-8,export%20%7B%7D",
+    )
+    .unwrap();
+    assert_eq!(source.content_type.unwrap(), "application/javascript");
+    assert_eq!(source.data, b"export {}");
+
+    let source = parse_data_uri("data:,x").unwrap();
+    assert!(source.content_type.is_none());
+    assert_eq!(source.data, b"x");
+
+    assert!(parse_data_uri("Contract-Src").is_err());
+    assert!(parse_data_uri("data:;base64,!").is_err());
+    assert!(parse_data_uri("data:,%zz").is_err());
+  }
 }
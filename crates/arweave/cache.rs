@@ -1,30 +1,289 @@
 use crate::arweave::LoadedContract;
 use crate::gql_result::GQLEdgeInterface;
+use deno_core::error::AnyError;
 use deno_core::serde_json::Value;
 use indexmap::map::IndexMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::fs::{create_dir_all, remove_file, File};
-use std::io::BufReader;
-use std::path::PathBuf;
+use std::fs::{self, create_dir_all, remove_file, File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// Storage for contracts, interactions and evaluated states.
+///
+/// Every operation can fail, e.g. when the backend is a separate service
+/// that is down. Callers go through [`crate::arweave::try_cache`], which
+/// treats failures as cache misses so evaluation carries on uncached.
 pub trait CacheExt: Debug {
   fn new() -> Self
   where
     Self: Sized;
-  fn find_contract(&mut self, contract_id: String) -> Option<LoadedContract>;
+  fn find_contract(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<LoadedContract>, AnyError>;
   fn find_interactions(
     &mut self,
     contract_id: String,
-  ) -> Option<Vec<GQLEdgeInterface>>;
-  fn find_state(&mut self, contract_id: String) -> Option<StateResult>;
-  fn cache_contract(&mut self, loaded_contract: &LoadedContract);
+  ) -> Result<Option<Vec<GQLEdgeInterface>>, AnyError>;
+  fn find_state(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<StateResult>, AnyError>;
+  fn find_source(
+    &mut self,
+    source_tx_id: String,
+  ) -> Result<Option<ContractSource>, AnyError>;
+  /// Latest state snapshot of `contract_id` taken at or before block
+  /// `timestamp`, along with the timestamp it was taken at.
+  fn find_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+  ) -> Result<Option<(usize, StateResult)>, AnyError>;
+  fn cache_contract(
+    &mut self,
+    loaded_contract: &LoadedContract,
+  ) -> Result<(), AnyError>;
   fn cache_interactions(
     &mut self,
     contract_id: String,
     interactions: &[GQLEdgeInterface],
-  );
-  fn cache_states(&mut self, contract_id: String, state: StateResult);
+  ) -> Result<(), AnyError>;
+  fn cache_states(
+    &mut self,
+    contract_id: String,
+    state: StateResult,
+  ) -> Result<(), AnyError>;
+  fn cache_source(
+    &mut self,
+    source_tx_id: String,
+    source: &ContractSource,
+  ) -> Result<(), AnyError>;
+  /// Keeps `state` as a snapshot of `contract_id` taken at block
+  /// `timestamp`, next to earlier snapshots. Only the latest
+  /// [`MAX_SNAPSHOTS`] of each contract are kept.
+  fn cache_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+    state: StateResult,
+  ) -> Result<(), AnyError>;
+  /// File that processes sharing this cache lock while they evaluate
+  /// `contract_id`, see [`CacheLock`]. `None` if the cache doesn't
+  /// coordinate processes.
+  fn evaluation_lock_file(&self, _contract_id: String) -> Option<PathBuf> {
+    None
+  }
+  /// Largest state, in bytes of JSON, that evaluations cache. Bigger ones
+  /// are evaluated but never given to [`CacheExt::cache_states`] or
+  /// [`CacheExt::cache_state_at`]. `None` caches states of any size.
+  fn max_state_size(&self) -> Option<usize> {
+    None
+  }
+  /// Hits and misses of [`CacheExt::find_state`] since the cache was
+  /// created or its stats were last reset. Caches that don't count them
+  /// report none.
+  fn stats(&self) -> CacheStats {
+    CacheStats::default()
+  }
+  /// Starts counting [`CacheExt::stats`] over from zero.
+  fn reset_stats(&self) {}
+  /// Tells the cache which network, see [`network_id`], the operations that
+  /// follow are for: the one of the client running them, see
+  /// [`crate::arweave::Arweave::try_cache`]. The same contract id can exist
+  /// on several networks, e.g. mainnet and arlocal, so caches keyed on
+  /// contract ids must keep their entries apart.
+  fn set_network(&mut self, _network: &str) {}
+}
+
+/// How often state lookups found a state, see [`CacheExt::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+  pub hits: u64,
+  /// Lookups that found no state, including ones that failed.
+  pub misses: u64,
+}
+
+impl CacheStats {
+  /// Share of lookups that were hits, `None` before the first lookup.
+  pub fn hit_ratio(&self) -> Option<f64> {
+    let lookups = self.hits + self.misses;
+    if lookups == 0 {
+      return None;
+    }
+    Some(self.hits as f64 / lookups as f64)
+  }
+}
+
+/// Counters behind [`CacheStats`]. Atomic, so counting a lookup costs next
+/// to nothing and needs no lock.
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl CacheCounters {
+  /// Counts the outcome of a lookup.
+  pub fn record<T>(&self, found: &Result<Option<T>, AnyError>) {
+    let counter = match found {
+      Ok(Some(_)) => &self.hits,
+      _ => &self.misses,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+
+  pub fn reset(&self) {
+    self.hits.store(0, Ordering::Relaxed);
+    self.misses.store(0, Ordering::Relaxed);
+  }
+}
+
+/// zstd level used by [`ArweaveCache::with_compression`] when none is given.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Every zstd frame starts with these bytes, while JSON entries start with
+/// `{` or `[`. Entries are told apart by them, so uncompressed entries stay
+/// readable once compression is turned on and the other way around.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// CBOR entries start with the self-described CBOR tag, which JSON can't
+/// start with either. Like with compression, entries in both formats stay
+/// readable whichever format new ones are written in.
+const CBOR_MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+/// How new cache entries are serialized, see [`ArweaveCache::with_format`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheFormat {
+  Json,
+  /// Smaller than JSON and faster to parse, which adds up for big states.
+  Cbor,
+}
+
+impl Default for CacheFormat {
+  fn default() -> Self {
+    CacheFormat::Json
+  }
+}
+
+/// Format state entries are written in. Bump it whenever the way states
+/// are evaluated or stored changes, and register a [`StateMigration`] from
+/// the previous format if old entries can be carried over.
+///
+/// Entries written before formats were tagged are format 1.
+pub const STATE_FORMAT: u32 = 2;
+
+/// Snapshots kept per contract by [`CacheExt::cache_state_at`]. Older ones
+/// are dropped, so `--since` falls back to a full evaluation for times
+/// before the oldest snapshot left.
+pub const MAX_SNAPSHOTS: usize = 32;
+
+/// Network id of the gateways serving mainnet, see [`network_id`].
+pub const MAINNET: &str = "mainnet";
+
+/// The host of the default gateway. Other gateways serve mainnet too, but
+/// can't be told apart from a private network by their URL.
+const MAINNET_HOST: &str = "arweave.net";
+
+/// A stable id for the network the gateway at `base_url` serves:
+/// [`MAINNET`] for arweave.net, otherwise its host, port and path, e.g.
+/// `localhost_1984` for arlocal.
+pub fn network_id(base_url: &str) -> String {
+  let url = match reqwest::Url::parse(base_url) {
+    Ok(url) => url,
+    Err(_) => return sanitize_network(base_url),
+  };
+  let host = url.host_str().unwrap_or_default();
+  if host == MAINNET_HOST {
+    return String::from(MAINNET);
+  }
+
+  let mut id = host.to_owned();
+  if let Some(port) = url.port_or_known_default() {
+    id.push_str(&format!("_{}", port));
+  }
+  id.push_str(url.path().trim_end_matches('/'));
+  sanitize_network(&id)
+}
+
+/// Keeps network ids usable as a folder name.
+fn sanitize_network(network: &str) -> String {
+  network
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect()
+}
+
+/// Upgrades a raw state entry from the format it is registered for to the
+/// next one.
+pub type StateMigration = fn(Value) -> Result<Value, AnyError>;
+
+/// Migrations applied to state entries of an older [`STATE_FORMAT`], keyed
+/// by the format they upgrade from.
+#[derive(Debug, Clone)]
+pub struct StateMigrations(BTreeMap<u32, StateMigration>);
+
+impl Default for StateMigrations {
+  fn default() -> Self {
+    let mut migrations = StateMigrations(BTreeMap::new());
+    // Format 2 only added the tag.
+    migrations.register(1, Ok);
+    migrations
+  }
+}
+
+impl StateMigrations {
+  /// Registers the migration from format `from` to `from + 1`, replacing
+  /// any earlier one.
+  pub fn register(&mut self, from: u32, migration: StateMigration) {
+    self.0.insert(from, migration);
+  }
+
+  /// Brings `entry` up to [`STATE_FORMAT`]. Entries without a migration
+  /// path, including ones written by a newer 3em, come back as `None` so
+  /// their state is evaluated again.
+  fn migrate(&self, mut entry: Value) -> Result<Option<Value>, AnyError> {
+    let format = match entry.get("format") {
+      None => Some(1),
+      Some(format) => format.as_u64().and_then(|f| u32::try_from(f).ok()),
+    };
+    let mut format = match format {
+      Some(format) => format,
+      None => return Ok(None),
+    };
+
+    while format < STATE_FORMAT {
+      match self.0.get(&format) {
+        Some(migration) => entry = migration(entry)?,
+        None => return Ok(None),
+      }
+      format += 1;
+    }
+
+    if format == STATE_FORMAT {
+      Ok(Some(entry))
+    } else {
+      Ok(None)
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -32,12 +291,125 @@ pub struct ArweaveCache {
   pub contracts_cache_folder: PathBuf,
   pub interactions_cache_folder: PathBuf,
   pub states_cache_folder: PathBuf,
+  pub sources_cache_folder: PathBuf,
+  /// zstd level new entries are compressed with. `None` writes them
+  /// uncompressed.
+  pub compression_level: Option<i32>,
+  /// Serialization of new entries, compressed or not.
+  pub format: CacheFormat,
+  /// Applied to state entries written in an older format.
+  pub migrations: StateMigrations,
+  /// Where lock files are kept, `None` unless
+  /// [`ArweaveCache::with_locking`] was called.
+  pub locks_folder: Option<PathBuf>,
+  /// See [`ArweaveCache::with_max_state_size`].
+  pub max_state_size: Option<usize>,
+  /// Hits and misses of [`CacheExt::find_state`].
+  pub counters: CacheCounters,
+  /// The network entries belong to, see [`ArweaveCache::with_network`].
+  /// `None` to keep those of each network apart.
+  pub network: Option<String>,
+  /// The network of the client the current operation is for, see
+  /// [`CacheExt::set_network`].
+  pub client_network: String,
+}
+
+/// An advisory lock on a file, held until dropped.
+///
+/// Locks only coordinate the processes that take them: readers of the
+/// cache never do. They are no-ops on platforms other than Unix.
+#[derive(Debug)]
+pub struct CacheLock(File);
+
+impl CacheLock {
+  /// Waits until the lock is free and takes it.
+  pub fn acquire(path: &Path) -> Result<CacheLock, AnyError> {
+    let file = open_lock_file(path)?;
+    lock_file(&file, true)?;
+    Ok(CacheLock(file))
+  }
+
+  /// Takes the lock, or returns `None` if it is held.
+  pub fn try_acquire(path: &Path) -> Result<Option<CacheLock>, AnyError> {
+    let file = open_lock_file(path)?;
+    match lock_file(&file, false) {
+      Ok(()) => Ok(Some(CacheLock(file))),
+      Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
+      Err(err) => Err(err.into()),
+    }
+  }
+}
+
+fn open_lock_file(path: &Path) -> std::io::Result<File> {
+  if let Some(folder) = path.parent() {
+    create_dir_all(folder)?;
+  }
+  OpenOptions::new().create(true).write(true).open(path)
+}
+
+#[cfg(unix)]
+fn lock_file(file: &File, wait: bool) -> std::io::Result<()> {
+  use std::os::unix::io::AsRawFd;
+
+  let operation = if wait {
+    libc::LOCK_EX
+  } else {
+    libc::LOCK_EX | libc::LOCK_NB
+  };
+  // Released when the file is closed.
+  if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+    Ok(())
+  } else {
+    Err(std::io::Error::last_os_error())
+  }
+}
+
+#[cfg(not(unix))]
+fn lock_file(_file: &File, _wait: bool) -> std::io::Result<()> {
+  Ok(())
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct StateResult {
   pub state: Value,
   pub validity: IndexMap<String, Value>,
+  /// Whether `state` was rewritten before it was cached, by the
+  /// `cache_transform` of the evaluation. It isn't the state the contract
+  /// evaluates to then: evaluations never continue from it, and it must
+  /// not be served as the state of the contract.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub transformed: bool,
+}
+
+/// A [`StateResult`] as stored, tagged with its [`STATE_FORMAT`].
+#[derive(Serialize)]
+struct StateEntry<'a> {
+  format: u32,
+  #[serde(flatten)]
+  state: &'a StateResult,
+}
+
+/// Contract source as stored on Arweave.
+///
+/// Sources are immutable, so they are cached by their transaction id
+/// independently of contract and state caching.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ContractSource {
+  /// `Content-Type` tag of the source transaction, if any.
+  pub content_type: Option<String>,
+  pub data: Vec<u8>,
+}
+
+/// A contract or source entry as stored: the source bytes are left out and
+/// kept once per content in a blob named by their hash, see
+/// [`ArweaveCache::write_blob`]. Entries written before sources were
+/// deduplicated have no hash and hold the bytes themselves.
+#[derive(Serialize, Deserialize)]
+struct DedupEntry<T> {
+  #[serde(default)]
+  source_hash: Option<String>,
+  #[serde(flatten)]
+  entry: T,
 }
 
 impl Default for ArweaveCache {
@@ -52,112 +424,486 @@ impl CacheExt for ArweaveCache {
       let root_cache_dir = cache_dir.join("3em").join("contracts");
       let interactions_cache_dir = cache_dir.join("3em").join("interactions");
       let states_cache_dir = cache_dir.join("3em").join("states");
+      let sources_cache_dir = cache_dir.join("3em").join("sources");
 
       create_dir_all(&root_cache_dir).unwrap();
       create_dir_all(&interactions_cache_dir).unwrap();
       create_dir_all(&states_cache_dir).unwrap();
+      create_dir_all(&sources_cache_dir).unwrap();
 
       ArweaveCache {
         contracts_cache_folder: root_cache_dir,
         interactions_cache_folder: interactions_cache_dir,
         states_cache_folder: states_cache_dir,
+        sources_cache_folder: sources_cache_dir,
+        compression_level: None,
+        format: CacheFormat::default(),
+        migrations: StateMigrations::default(),
+        locks_folder: None,
+        max_state_size: None,
+        counters: CacheCounters::default(),
+        network: None,
+        client_network: String::from(MAINNET),
       }
     } else {
       panic!("Cache folder could not be set");
     }
   }
 
-  fn find_contract(&mut self, contract_id: String) -> Option<LoadedContract> {
+  fn find_contract(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<LoadedContract>, AnyError> {
     let cache_file = self.get_cache_file(contract_id);
+    let stored: DedupEntry<LoadedContract> = match read_entry(&cache_file)? {
+      Some(stored) => stored,
+      None => return Ok(None),
+    };
 
-    let file = File::open(cache_file);
-
-    match file {
-      Ok(data) => {
-        let reader = BufReader::new(data);
-        let loaded_contract: LoadedContract =
-          deno_core::serde_json::from_reader(reader).unwrap();
-        Some(loaded_contract)
+    let mut contract = stored.entry;
+    if let Some(hash) = stored.source_hash {
+      match self.read_blob(&hash)? {
+        Some(source) => contract.contract_src = source,
+        None => return Ok(None),
       }
-      Err(_) => None,
     }
+    Ok(Some(contract))
   }
 
   fn find_interactions(
     &mut self,
     contract_id: String,
-  ) -> Option<Vec<GQLEdgeInterface>> {
+  ) -> Result<Option<Vec<GQLEdgeInterface>>, AnyError> {
     let cache_file = self.get_cache_interaction_file(contract_id);
+    read_entry(&cache_file)
+  }
+
+  fn find_state(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<StateResult>, AnyError> {
+    let cache_file = self.get_cache_state_file(contract_id);
+    let found = self.read_state(&cache_file);
+    self.counters.record(&found);
+    found
+  }
 
-    let file = File::open(cache_file);
+  fn find_source(
+    &mut self,
+    source_tx_id: String,
+  ) -> Result<Option<ContractSource>, AnyError> {
+    let cache_file = self.get_cache_source_file(source_tx_id);
+    let stored: DedupEntry<ContractSource> = match read_entry(&cache_file)? {
+      Some(stored) => stored,
+      None => return Ok(None),
+    };
 
-    match file {
-      Ok(data) => {
-        let reader = BufReader::new(data);
-        let interactions: Vec<GQLEdgeInterface> =
-          deno_core::serde_json::from_reader(reader).unwrap();
-        Some(interactions)
+    let mut source = stored.entry;
+    if let Some(hash) = stored.source_hash {
+      match self.read_blob(&hash)? {
+        Some(data) => source.data = data,
+        None => return Ok(None),
       }
-      Err(_) => None,
     }
+    Ok(Some(source))
   }
 
-  fn find_state(&mut self, contract_id: String) -> Option<StateResult> {
-    let cache_file = self.get_cache_state_file(contract_id);
+  fn find_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+  ) -> Result<Option<(usize, StateResult)>, AnyError> {
+    let folder = self.get_cache_snapshot_folder(contract_id);
+    let entries = match fs::read_dir(folder) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(err.into()),
+    };
 
-    let file = File::open(cache_file);
+    let mut latest: Option<(usize, PathBuf)> = None;
+    for entry in entries {
+      let path = entry?.path();
+      let taken_at = match path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.parse::<usize>().ok())
+      {
+        Some(taken_at) if taken_at <= timestamp => taken_at,
+        _ => continue,
+      };
+      if latest
+        .as_ref()
+        .map_or(true, |(latest_at, _)| taken_at > *latest_at)
+      {
+        latest = Some((taken_at, path));
+      }
+    }
 
-    match file {
-      Ok(data) => {
-        let reader = BufReader::new(data);
-        let state: StateResult =
-          deno_core::serde_json::from_reader(reader).unwrap();
-        Some(state)
+    match latest {
+      Some((taken_at, cache_file)) => {
+        Ok(self.read_state(&cache_file)?.map(|state| (taken_at, state)))
       }
-      Err(_) => None,
+      None => Ok(None),
     }
   }
 
-  fn cache_contract(&mut self, loaded_contract: &LoadedContract) {
+  fn cache_contract(
+    &mut self,
+    loaded_contract: &LoadedContract,
+  ) -> Result<(), AnyError> {
+    let _lock = self.lock_writes(&loaded_contract.id)?;
+    let source_hash = self.write_blob(&loaded_contract.contract_src)?;
+    let entry = DedupEntry {
+      source_hash: Some(source_hash),
+      entry: LoadedContract {
+        contract_src: vec![],
+        ..loaded_contract.clone()
+      },
+    };
     let cache_file = self.get_cache_file(loaded_contract.id.to_owned());
-    deno_core::serde_json::to_writer(
-      &File::create(cache_file).unwrap(),
-      loaded_contract,
-    )
-    .unwrap();
+    self.write_file(&cache_file, self.encode_entry(&entry)?, true)
   }
 
   fn cache_interactions(
     &mut self,
     contract_id: String,
     interactions: &[GQLEdgeInterface],
-  ) {
+  ) -> Result<(), AnyError> {
+    let _lock = self.lock_writes(&contract_id)?;
     let cache_file = self.get_cache_interaction_file(contract_id);
-    deno_core::serde_json::to_writer(
-      &File::create(cache_file).unwrap(),
-      interactions,
-    )
-    .unwrap();
+    self.write_entry(&cache_file, interactions)
   }
 
-  fn cache_states(&mut self, contract_id: String, state: StateResult) {
+  fn cache_states(
+    &mut self,
+    contract_id: String,
+    state: StateResult,
+  ) -> Result<(), AnyError> {
+    let _lock = self.lock_writes(&contract_id)?;
     let cache_file = self.get_cache_state_file(contract_id);
-    deno_core::serde_json::to_writer(
-      &File::create(cache_file).unwrap(),
-      &state,
-    )
-    .unwrap();
+    self.write_state(&cache_file, &state)
+  }
+
+  fn cache_source(
+    &mut self,
+    source_tx_id: String,
+    source: &ContractSource,
+  ) -> Result<(), AnyError> {
+    let source_hash = self.write_blob(&source.data)?;
+    let entry = DedupEntry {
+      source_hash: Some(source_hash),
+      entry: ContractSource {
+        content_type: source.content_type.clone(),
+        data: vec![],
+      },
+    };
+    let cache_file = self.get_cache_source_file(source_tx_id);
+    self.write_file(&cache_file, self.encode_entry(&entry)?, true)
+  }
+
+  fn cache_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+    state: StateResult,
+  ) -> Result<(), AnyError> {
+    let _lock = self.lock_writes(&contract_id)?;
+    let folder = self.get_cache_snapshot_folder(contract_id);
+    create_dir_all(&folder)?;
+    self.write_state(&folder.join(format!("{}.json", timestamp)), &state)?;
+
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&folder)? {
+      let path = entry?.path();
+      if let Some(taken_at) = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.parse::<usize>().ok())
+      {
+        snapshots.push((taken_at, path));
+      }
+    }
+    if snapshots.len() > MAX_SNAPSHOTS {
+      snapshots.sort_unstable_by_key(|(taken_at, _)| *taken_at);
+      for (_, path) in &snapshots[..snapshots.len() - MAX_SNAPSHOTS] {
+        match remove_file(path) {
+          Err(err) if err.kind() != ErrorKind::NotFound => {
+            return Err(err.into())
+          }
+          _ => {}
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn evaluation_lock_file(&self, contract_id: String) -> Option<PathBuf> {
+    self.locks_folder.as_ref().map(|folder| {
+      self
+        .in_network(folder)
+        .join(format!("{}.evaluation.lock", contract_id))
+    })
+  }
+
+  fn max_state_size(&self) -> Option<usize> {
+    self.max_state_size
+  }
+
+  fn stats(&self) -> CacheStats {
+    self.counters.stats()
+  }
+
+  fn set_network(&mut self, network: &str) {
+    self.client_network = network.to_owned();
+  }
+
+  fn reset_stats(&self) {
+    self.counters.reset()
   }
 }
 
+/// Reads a cache entry. A missing entry is a miss, anything else that goes
+/// wrong is an error.
+fn read_entry<T: DeserializeOwned>(
+  cache_file: &Path,
+) -> Result<Option<T>, AnyError> {
+  let data = match fs::read(cache_file) {
+    Ok(data) => data,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  let data = if data.starts_with(&ZSTD_MAGIC) {
+    zstd::decode_all(data.as_slice())?
+  } else {
+    data
+  };
+
+  let entry = match data.strip_prefix(&CBOR_MAGIC) {
+    Some(cbor) => ciborium::de::from_reader(cbor)?,
+    None => deno_core::serde_json::from_slice(&data)?,
+  };
+  Ok(Some(entry))
+}
+
 impl ArweaveCache {
+  /// Compresses new entries with zstd at `level`, or
+  /// [`DEFAULT_COMPRESSION_LEVEL`] if `None`. State-heavy contracts take a
+  /// fraction of the disk space for a little CPU on every write and read.
+  pub fn with_compression(mut self, level: Option<i32>) -> ArweaveCache {
+    self.compression_level = Some(level.unwrap_or(DEFAULT_COMPRESSION_LEVEL));
+    self
+  }
+
+  /// Writes new entries in `format`. Existing entries stay readable, so
+  /// this can be changed freely.
+  pub fn with_format(mut self, format: CacheFormat) -> ArweaveCache {
+    self.format = format;
+    self
+  }
+
+  /// Registers `migration` from state format `from`, see
+  /// [`StateMigrations::register`].
+  pub fn with_migration(
+    mut self,
+    from: u32,
+    migration: StateMigration,
+  ) -> ArweaveCache {
+    self.migrations.register(from, migration);
+    self
+  }
+
+  /// Makes the cache safe to share between processes, e.g. on a volume
+  /// mounted by several nodes. Writes of a contract's entries take a lock
+  /// on it and replace entries atomically, so readers never see a partial
+  /// one, and [`CacheExt::evaluation_lock_file`] lets a process wait for
+  /// another one evaluating the same contract instead of duplicating its
+  /// work.
+  pub fn with_locking(mut self) -> ArweaveCache {
+    let locks_folder = self.states_cache_folder.with_file_name("locks");
+    create_dir_all(&locks_folder).unwrap();
+    self.locks_folder = Some(locks_folder);
+    self
+  }
+
+  /// Leaves states bigger than `bytes`, serialized as JSON, out of the
+  /// cache, so that one contract can't fill the disk. They are still
+  /// evaluated, just again on every run.
+  pub fn with_max_state_size(mut self, bytes: usize) -> ArweaveCache {
+    self.max_state_size = Some(bytes);
+    self
+  }
+
+  /// Keeps entries under `network` whatever the client, e.g. to share them
+  /// between gateways serving the same network.
+  pub fn with_network(mut self, network: &str) -> ArweaveCache {
+    self.network = Some(sanitize_network(network));
+    self
+  }
+
+  /// `folder` for the network of the current operation. Mainnet entries
+  /// stay where they always were, every other network gets folders of its
+  /// own.
+  fn in_network(&self, folder: &Path) -> PathBuf {
+    let network = self.network.as_deref().unwrap_or(&self.client_network);
+    if network == MAINNET {
+      return folder.to_owned();
+    }
+
+    match (folder.parent(), folder.file_name()) {
+      (Some(parent), Some(kind)) => {
+        parent.join("networks").join(network).join(kind)
+      }
+      _ => folder.join("networks").join(network),
+    }
+  }
+
+  /// Serializes writes of `contract_id`'s entries between processes, when
+  /// locking is on.
+  fn lock_writes(
+    &self,
+    contract_id: &str,
+  ) -> Result<Option<CacheLock>, AnyError> {
+    match &self.locks_folder {
+      Some(folder) => {
+        let path = self
+          .in_network(folder)
+          .join(format!("{}.lock", contract_id));
+        Ok(Some(CacheLock::acquire(&path)?))
+      }
+      None => Ok(None),
+    }
+  }
+
+  /// Reads a state entry, migrated to [`STATE_FORMAT`].
+  fn read_state(
+    &self,
+    cache_file: &Path,
+  ) -> Result<Option<StateResult>, AnyError> {
+    let entry = match read_entry(cache_file)? {
+      Some(entry) => entry,
+      None => return Ok(None),
+    };
+
+    match self.migrations.migrate(entry)? {
+      Some(entry) => Ok(Some(deno_core::serde_json::from_value(entry)?)),
+      None => Ok(None),
+    }
+  }
+
+  fn write_state(
+    &self,
+    cache_file: &Path,
+    state: &StateResult,
+  ) -> Result<(), AnyError> {
+    let entry = StateEntry {
+      format: STATE_FORMAT,
+      state,
+    };
+    self.write_entry(cache_file, &entry)
+  }
+
+  fn write_entry<T: Serialize + ?Sized>(
+    &self,
+    cache_file: &Path,
+    value: &T,
+  ) -> Result<(), AnyError> {
+    // Readers don't take locks, so they must never see a partial entry.
+    let atomic = self.locks_folder.is_some();
+    self.write_file(cache_file, self.encode_entry(value)?, atomic)
+  }
+
+  fn encode_entry<T: Serialize + ?Sized>(
+    &self,
+    value: &T,
+  ) -> Result<Vec<u8>, AnyError> {
+    let data = match self.format {
+      CacheFormat::Json => deno_core::serde_json::to_vec(value)?,
+      CacheFormat::Cbor => {
+        let mut data = CBOR_MAGIC.to_vec();
+        ciborium::ser::into_writer(value, &mut data)?;
+        data
+      }
+    };
+    self.compress(data)
+  }
+
+  fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, AnyError> {
+    match self.compression_level {
+      Some(level) => Ok(zstd::encode_all(data.as_slice(), level)?),
+      None => Ok(data),
+    }
+  }
+
+  /// Writes `data` to `cache_file`. An `atomic` write replaces the file
+  /// whole, even if the process dies halfway through.
+  fn write_file(
+    &self,
+    cache_file: &Path,
+    data: Vec<u8>,
+    atomic: bool,
+  ) -> Result<(), AnyError> {
+    if let Some(folder) = cache_file.parent() {
+      create_dir_all(folder)?;
+    }
+    if atomic {
+      let partial =
+        cache_file.with_extension(format!("{}.partial", std::process::id()));
+      fs::write(&partial, data)?;
+      fs::rename(&partial, cache_file)?;
+    } else {
+      fs::write(cache_file, data)?;
+    }
+    Ok(())
+  }
+
+  /// Stores `source` once, however many contracts and source transactions
+  /// share it, and returns the hash that [`ArweaveCache::read_blob`] reads
+  /// it back with.
+  ///
+  /// Blobs are written whole before the entries pointing to them, so a
+  /// crash leaves at most a blob nothing points to, never an entry
+  /// pointing to a missing or partial blob.
+  fn write_blob(&self, source: &[u8]) -> Result<String, AnyError> {
+    let hash = hex::encode(Sha256::digest(source));
+    // A damaged blob is replaced.
+    if self.read_blob(&hash)?.is_none() {
+      let blob_file = self.get_cache_blob_file(&hash);
+      create_dir_all(blob_file.parent().unwrap())?;
+      self.write_file(&blob_file, self.compress(source.to_vec())?, true)?;
+    }
+    Ok(hash)
+  }
+
+  /// The source stored under `hash`. `None` if it is missing, or doesn't
+  /// hash to `hash` any more, so that it is fetched and stored again.
+  fn read_blob(&self, hash: &str) -> Result<Option<Vec<u8>>, AnyError> {
+    let data = match fs::read(self.get_cache_blob_file(hash)) {
+      Ok(data) => data,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(err.into()),
+    };
+    let matches = |data: &[u8]| hex::encode(Sha256::digest(data)) == hash;
+    // A source can start like a zstd frame without being one.
+    if matches(&data) {
+      return Ok(Some(data));
+    }
+    if data.starts_with(&ZSTD_MAGIC) {
+      if let Ok(data) = zstd::decode_all(data.as_slice()) {
+        if matches(&data) {
+          return Ok(Some(data));
+        }
+      }
+    }
+    Ok(None)
+  }
+
   pub async fn delete_cache_interactions(&self, contract_id: String) {
     let cache_file = self.get_cache_interaction_file(contract_id);
     remove_file(cache_file).unwrap();
   }
 
   fn get_cache_file(&self, contract_id: String) -> PathBuf {
-    let mut cache_file = self.contracts_cache_folder.to_owned();
+    let mut cache_file = self.in_network(&self.contracts_cache_folder);
 
     cache_file.push(format!("{}.json", contract_id));
 
@@ -165,7 +911,7 @@ impl ArweaveCache {
   }
 
   fn get_cache_interaction_file(&self, contract_id: String) -> PathBuf {
-    let mut cache_file = self.interactions_cache_folder.to_owned();
+    let mut cache_file = self.in_network(&self.interactions_cache_folder);
 
     cache_file.push(format!("{}.json", contract_id));
 
@@ -173,10 +919,482 @@ impl ArweaveCache {
   }
 
   fn get_cache_state_file(&self, contract_id: String) -> PathBuf {
-    let mut cache_file = self.states_cache_folder.to_owned();
+    let mut cache_file = self.in_network(&self.states_cache_folder);
 
     cache_file.push(format!("{}_result.json", contract_id));
 
     cache_file
   }
+
+  fn get_cache_snapshot_folder(&self, contract_id: String) -> PathBuf {
+    let mut cache_folder = self.in_network(&self.states_cache_folder);
+
+    cache_folder.push(format!("{}_snapshots", contract_id));
+
+    cache_folder
+  }
+
+  fn get_cache_source_file(&self, source_tx_id: String) -> PathBuf {
+    let mut cache_file = self.in_network(&self.sources_cache_folder);
+
+    cache_file.push(format!("{}.json", source_tx_id));
+
+    cache_file
+  }
+
+  fn get_cache_blob_file(&self, hash: &str) -> PathBuf {
+    let mut cache_file = self.in_network(&self.sources_cache_folder);
+
+    cache_file.push("blobs");
+    cache_file.push(hash);
+
+    cache_file
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::arweave::{LoadedContract, TransactionData, ARLOCAL_URL};
+  use crate::cache::{
+    network_id, ArweaveCache, CacheCounters, CacheExt, CacheFormat, CacheLock,
+    CacheStats, ContractSource, StateMigrations, StateResult, CBOR_MAGIC,
+    MAINNET, STATE_FORMAT, ZSTD_MAGIC,
+  };
+  use crate::miscellaneous::ContractType;
+  use deno_core::serde_json::json;
+  use deno_core::serde_json::Value;
+  use indexmap::map::IndexMap;
+  use std::fs;
+
+  fn cache_in(name: &str) -> ArweaveCache {
+    let root = std::env::temp_dir().join(name);
+    let folder = |kind: &str| {
+      let folder = root.join(kind);
+      fs::create_dir_all(&folder).unwrap();
+      folder
+    };
+
+    ArweaveCache {
+      contracts_cache_folder: folder("contracts"),
+      interactions_cache_folder: folder("interactions"),
+      states_cache_folder: folder("states"),
+      sources_cache_folder: folder("sources"),
+      compression_level: None,
+      format: CacheFormat::default(),
+      migrations: StateMigrations::default(),
+      locks_folder: None,
+      max_state_size: None,
+      counters: CacheCounters::default(),
+      network: None,
+      client_network: String::from(MAINNET),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_compression_round_trip() {
+    let mut validity = IndexMap::new();
+    validity.insert(String::from("tx1"), json!(true));
+    let state = StateResult {
+      state: json!({ "balances": { "alice": 100, "bob": 5 } }),
+      validity,
+      transformed: false,
+    };
+
+    let mut plain = cache_in("3em_test_cache_compression");
+    plain
+      .cache_states(String::from("PLAIN"), state.clone())
+      .unwrap();
+    let plain_file = plain.get_cache_state_file(String::from("PLAIN"));
+    assert!(!fs::read(&plain_file).unwrap().starts_with(&ZSTD_MAGIC));
+
+    let mut compressed =
+      cache_in("3em_test_cache_compression").with_compression(None);
+    compressed
+      .cache_states(String::from("ZSTD"), state.clone())
+      .unwrap();
+    let compressed_file = compressed.get_cache_state_file(String::from("ZSTD"));
+    assert!(fs::read(&compressed_file).unwrap().starts_with(&ZSTD_MAGIC));
+
+    // Either cache reads both kinds of entries.
+    for cache in [&mut plain, &mut compressed] {
+      for id in ["PLAIN", "ZSTD"] {
+        let found = cache.find_state(String::from(id)).unwrap().unwrap();
+        assert_eq!(found.state, state.state);
+        assert_eq!(found.validity, state.validity);
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_format_round_trip() {
+    let mut validity = IndexMap::new();
+    validity.insert(String::from("tx1"), json!(true));
+    validity.insert(String::from("tx2"), json!("Error: Invalid operation"));
+    let state = StateResult {
+      state: json!({
+        "balances": { "alice": 100, "bob": -5 },
+        "supply": u64::MAX,
+        "ratio": 0.1,
+        "whole": 2.0,
+        "name": "Ünïcödé",
+        "log": [null, true, [], {}],
+      }),
+      validity,
+      transformed: false,
+    };
+
+    let ids = ["JSON", "CBOR", "CBOR_ZSTD"];
+    let mut caches = [
+      cache_in("3em_test_cache_format"),
+      cache_in("3em_test_cache_format").with_format(CacheFormat::Cbor),
+      cache_in("3em_test_cache_format")
+        .with_format(CacheFormat::Cbor)
+        .with_compression(None),
+    ];
+    for (id, cache) in ids.iter().zip(caches.iter_mut()) {
+      cache.cache_states(id.to_string(), state.clone()).unwrap();
+    }
+    let cbor_file = caches[1].get_cache_state_file(String::from("CBOR"));
+    assert!(fs::read(&cbor_file).unwrap().starts_with(&CBOR_MAGIC));
+
+    // Any cache reads entries in any format back to the same value.
+    for cache in caches.iter_mut() {
+      for id in ids {
+        let found = cache.find_state(id.to_string()).unwrap().unwrap();
+        assert_eq!(found.state, state.state);
+        assert_eq!(found.validity, state.validity);
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_corrupt_entry_is_an_error() {
+    let mut cache = cache_in("3em_test_cache_corrupt");
+    fs::write(cache.get_cache_state_file(String::from("CORRUPT")), "{")
+      .unwrap();
+
+    assert!(cache.find_state(String::from("CORRUPT")).is_err());
+    assert!(cache.find_state(String::from("MISSING")).unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_stats() {
+    let mut cache = cache_in("3em_test_cache_stats");
+    let id = String::from("STATS");
+    let _ = fs::remove_file(cache.get_cache_state_file(id.to_owned()));
+    assert_eq!(cache.stats().hit_ratio(), None);
+
+    assert!(cache.find_state(id.to_owned()).unwrap().is_none());
+    let state = StateResult {
+      state: json!({}),
+      validity: IndexMap::new(),
+      transformed: false,
+    };
+    cache.cache_states(id.to_owned(), state).unwrap();
+    assert!(cache.find_state(id.to_owned()).unwrap().is_some());
+    let stats = cache.stats();
+    assert_eq!(stats, CacheStats { hits: 1, misses: 1 });
+    assert_eq!(stats.hit_ratio(), Some(0.5));
+
+    cache.reset_stats();
+    assert_eq!(cache.stats(), CacheStats::default());
+  }
+
+  #[tokio::test]
+  async fn test_state_migrations() {
+    let mut cache = cache_in("3em_test_cache_migrations");
+    let write = |cache: &ArweaveCache, id: &str, entry: Value| {
+      fs::write(
+        cache.get_cache_state_file(String::from(id)),
+        entry.to_string(),
+      )
+      .unwrap();
+    };
+
+    // Written before formats were tagged.
+    write(&cache, "V1", json!({ "state": { "v": 1 }, "validity": {} }));
+    let found = cache.find_state(String::from("V1")).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "v": 1 }));
+
+    // New entries are tagged and read back as is.
+    cache.cache_states(String::from("V1"), found).unwrap();
+    let stored: Value = deno_core::serde_json::from_slice(
+      &fs::read(cache.get_cache_state_file(String::from("V1"))).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(stored["format"], json!(STATE_FORMAT));
+    assert!(cache.find_state(String::from("V1")).unwrap().is_some());
+
+    // No migration path, or written by a newer version: evaluated again.
+    write(
+      &cache,
+      "V0",
+      json!({ "format": 0, "state": {}, "validity": {} }),
+    );
+    assert!(cache.find_state(String::from("V0")).unwrap().is_none());
+    let newer =
+      json!({ "format": STATE_FORMAT + 1, "state": {}, "validity": {} });
+    write(&cache, "NEWER", newer);
+    assert!(cache.find_state(String::from("NEWER")).unwrap().is_none());
+
+    // Registered migrations run on older entries.
+    let mut cache = cache.with_migration(1, |mut entry| {
+      entry["state"]["migrated"] = json!(true);
+      Ok(entry)
+    });
+    write(&cache, "V1_LEGACY", json!({ "state": {}, "validity": {} }));
+    let found = cache
+      .find_state(String::from("V1_LEGACY"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(found.state, json!({ "migrated": true }));
+
+    cache.migrations = StateMigrations::default();
+    let found = cache
+      .find_state(String::from("V1_LEGACY"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(found.state, json!({}));
+  }
+
+  #[tokio::test]
+  async fn test_find_state_at() {
+    let snapshot = |balance: usize| StateResult {
+      state: json!({ "balance": balance }),
+      validity: IndexMap::new(),
+      transformed: false,
+    };
+
+    let mut cache = cache_in("3em_test_cache_snapshots");
+    let id = String::from("SNAPSHOTS");
+    for (taken_at, balance) in [(1000, 1), (3000, 3), (2000, 2)] {
+      cache
+        .cache_state_at(id.to_owned(), taken_at, snapshot(balance))
+        .unwrap();
+    }
+
+    assert!(cache.find_state_at(id.to_owned(), 999).unwrap().is_none());
+    let (taken_at, found) =
+      cache.find_state_at(id.to_owned(), 2999).unwrap().unwrap();
+    assert_eq!(taken_at, 2000);
+    assert_eq!(found.state, json!({ "balance": 2 }));
+    let (taken_at, _) =
+      cache.find_state_at(id.to_owned(), 3000).unwrap().unwrap();
+    assert_eq!(taken_at, 3000);
+    assert!(cache
+      .find_state_at(String::from("NONE"), 3000)
+      .unwrap()
+      .is_none());
+  }
+
+  #[tokio::test]
+  async fn test_snapshot_retention() {
+    let mut cache = cache_in("3em_test_cache_snapshot_retention");
+    let id = String::from("RETENTION");
+    for taken_at in 1..=MAX_SNAPSHOTS + 8 {
+      let state = StateResult {
+        state: json!({ "taken_at": taken_at }),
+        validity: IndexMap::new(),
+        transformed: false,
+      };
+      cache
+        .cache_state_at(id.to_owned(), taken_at, state)
+        .unwrap();
+    }
+
+    let folder = cache.get_cache_snapshot_folder(id.to_owned());
+    assert_eq!(fs::read_dir(folder).unwrap().count(), MAX_SNAPSHOTS);
+    assert!(cache.find_state_at(id.to_owned(), 8).unwrap().is_none());
+    let (taken_at, _) = cache.find_state_at(id.to_owned(), 9).unwrap().unwrap();
+    assert_eq!(taken_at, 9);
+  }
+
+  #[tokio::test]
+  async fn test_concurrent_writers() {
+    let name = "3em_test_concurrent_writers";
+    let writers: Vec<_> = (0..2)
+      .map(|writer| {
+        std::thread::spawn(move || {
+          let mut cache = cache_in(name).with_locking();
+          for round in 0..50 {
+            let state = StateResult {
+              state: json!({
+                "writer": writer,
+                "round": round,
+                "data": "x".repeat(256 * 1024),
+              }),
+              validity: IndexMap::new(),
+              transformed: false,
+            };
+            cache.cache_states(String::from("contract"), state).unwrap();
+          }
+        })
+      })
+      .collect();
+
+    // Readers never see a partial entry.
+    let mut reader = cache_in(name).with_locking();
+    for _ in 0..200 {
+      if let Some(entry) = reader.find_state(String::from("contract")).unwrap()
+      {
+        assert_eq!(entry.state["data"].as_str().unwrap().len(), 256 * 1024);
+      }
+    }
+    for writer in writers {
+      writer.join().unwrap();
+    }
+
+    let entry = reader
+      .find_state(String::from("contract"))
+      .unwrap()
+      .unwrap();
+    assert_eq!(entry.state["round"], 49);
+    let leftovers = fs::read_dir(&reader.states_cache_folder)
+      .unwrap()
+      .filter(|entry| {
+        let path = entry.as_ref().unwrap().path();
+        path.extension().map_or(false, |ext| ext == "partial")
+      })
+      .count();
+    assert_eq!(leftovers, 0);
+  }
+
+  #[tokio::test]
+  async fn test_network_namespaces() {
+    assert_eq!(network_id("https://arweave.net"), MAINNET);
+    assert_eq!(network_id("http://arweave.net:1984/"), MAINNET);
+    assert_eq!(network_id(ARLOCAL_URL), "localhost_1984");
+    assert_eq!(
+      network_id("https://gateway.example.com/arweave"),
+      "gateway_example_com_443_arweave"
+    );
+
+    let name = "3em_test_network_namespaces";
+    let _ = fs::remove_dir_all(std::env::temp_dir().join(name));
+    let state = |network: &str| StateResult {
+      state: json!({ "network": network }),
+      validity: IndexMap::new(),
+      transformed: false,
+    };
+    let id = String::from("contract");
+
+    let mut mainnet = cache_in(name);
+    mainnet.set_network(&network_id("https://arweave.net"));
+    let mut arlocal = cache_in(name).with_locking();
+    arlocal.set_network(&network_id(ARLOCAL_URL));
+    assert_ne!(
+      arlocal.evaluation_lock_file(id.to_owned()),
+      cache_in(name)
+        .with_locking()
+        .evaluation_lock_file(id.to_owned())
+    );
+
+    assert!(arlocal.find_state(id.to_owned()).unwrap().is_none());
+    mainnet.cache_states(id.to_owned(), state(MAINNET)).unwrap();
+    assert!(arlocal.find_state(id.to_owned()).unwrap().is_none());
+    let arlocal_state = state("arlocal");
+    arlocal.cache_states(id.to_owned(), arlocal_state).unwrap();
+    let found = mainnet.find_state(id.to_owned()).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "network": MAINNET }));
+    let found = arlocal.find_state(id.to_owned()).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "network": "arlocal" }));
+
+    // The same cache follows the network of each operation.
+    let mut default = cache_in(name);
+    let found = default.find_state(id.to_owned()).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "network": MAINNET }));
+    default.set_network(&network_id(ARLOCAL_URL));
+    let found = default.find_state(id.to_owned()).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "network": "arlocal" }));
+
+    // A network set explicitly wins over the gateway's.
+    let mut shared = cache_in(name).with_network("mainnet");
+    shared.set_network(&network_id(ARLOCAL_URL));
+    let found = shared.find_state(id.to_owned()).unwrap().unwrap();
+    assert_eq!(found.state, json!({ "network": MAINNET }));
+  }
+
+  #[tokio::test]
+  async fn test_source_dedup() {
+    let name = "3em_test_source_dedup";
+    let _ = fs::remove_dir_all(std::env::temp_dir().join(name));
+    let source = b"export function handle(state) { return { state }; }";
+    let contract = |id: &str| LoadedContract {
+      id: id.to_owned(),
+      contract_src_tx_id: format!("{}_SRC", id),
+      contract_src: source.to_vec(),
+      contract_type: ContractType::JAVASCRIPT,
+      init_state: String::from("{}"),
+      min_fee: None,
+      block: None,
+      contract_transaction: TransactionData::default(),
+    };
+    let blobs = |cache: &ArweaveCache| {
+      fs::read_dir(cache.sources_cache_folder.join("blobs"))
+        .unwrap()
+        .count()
+    };
+
+    // Two clones of a template, and their sources deployed separately.
+    let mut cache = cache_in(name).with_compression(None);
+    for id in ["PST1", "PST2"] {
+      cache.cache_contract(&contract(id)).unwrap();
+      let source = ContractSource {
+        content_type: Some(String::from("application/javascript")),
+        data: source.to_vec(),
+      };
+      cache.cache_source(format!("{}_SRC", id), &source).unwrap();
+    }
+    assert_eq!(blobs(&cache), 1);
+
+    let mut plain = cache_in(name);
+    for id in ["PST1", "PST2"] {
+      let found = plain.find_contract(id.to_owned()).unwrap().unwrap();
+      assert_eq!(found.id, id);
+      assert_eq!(found.contract_src, source);
+      let found = plain.find_source(format!("{}_SRC", id)).unwrap().unwrap();
+      assert_eq!(found.data, source);
+    }
+    let other = LoadedContract {
+      contract_src: b"export function handle() {}".to_vec(),
+      ..contract("OTHER")
+    };
+    plain.cache_contract(&other).unwrap();
+    assert_eq!(blobs(&plain), 2);
+
+    // Entries written before sources were deduplicated stay readable.
+    let legacy = deno_core::serde_json::to_vec(&contract("LEGACY")).unwrap();
+    fs::write(plain.get_cache_file(String::from("LEGACY")), legacy).unwrap();
+    let found = plain.find_contract(String::from("LEGACY")).unwrap();
+    assert_eq!(found.unwrap().contract_src, source);
+
+    // A lost or damaged blob is a miss, and is stored again.
+    for blob in fs::read_dir(plain.sources_cache_folder.join("blobs")).unwrap()
+    {
+      fs::write(blob.unwrap().path(), "damaged").unwrap();
+    }
+    assert!(plain.find_contract(String::from("PST1")).unwrap().is_none());
+    plain.cache_contract(&contract("PST1")).unwrap();
+    let found = plain.find_contract(String::from("PST1")).unwrap();
+    assert_eq!(found.unwrap().contract_src, source);
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn test_evaluation_lock() {
+    let name = "3em_test_evaluation_lock";
+    assert!(cache_in(name)
+      .evaluation_lock_file(String::from("contract"))
+      .is_none());
+
+    let cache = cache_in(name).with_locking();
+    let path = cache
+      .evaluation_lock_file(String::from("contract"))
+      .unwrap();
+    let lock = CacheLock::try_acquire(&path).unwrap();
+    assert!(lock.is_some());
+    assert!(CacheLock::try_acquire(&path).unwrap().is_none());
+    drop(lock);
+    assert!(CacheLock::try_acquire(&path).unwrap().is_some());
+  }
 }
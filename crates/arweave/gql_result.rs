@@ -92,6 +92,12 @@ pub struct GQLNodeInterface {
   #[serde(skip_serializing_if = "Option::is_none")]
   #[serde(default)]
   pub bundledIn: Option<GQLBundled>,
+  /// The input of an interaction that carries it in its data instead of an
+  /// `Input` tag, as some bundled interactions do. Not part of the GraphQL
+  /// response, the data is fetched separately.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[serde(default)]
+  pub body_input: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -100,19 +106,39 @@ pub struct GQLEdgeInterface {
   pub node: GQLNodeInterface,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GQLKeyBlockInterface {
+  pub id: String,
+  pub height: usize,
+}
+
+/// The fields of an interaction needed to sort and filter it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GQLKeyNodeInterface {
+  pub id: String,
+  pub owner: GQLOwnerInterface,
+  pub block: GQLKeyBlockInterface,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GQLKeyEdgeInterface {
+  pub cursor: String,
+  pub node: GQLKeyNodeInterface,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct GQLTransactionsResultInterface {
+pub struct GQLTransactionsResultInterface<E = GQLEdgeInterface> {
   pub page_info: GQLPageInfoInterface,
-  pub edges: Vec<GQLEdgeInterface>,
+  pub edges: Vec<E>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct GQLDataResultInterface {
-  pub transactions: GQLTransactionsResultInterface,
+pub struct GQLDataResultInterface<E = GQLEdgeInterface> {
+  pub transactions: GQLTransactionsResultInterface<E>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct GQLResultInterface {
-  pub data: GQLDataResultInterface,
+pub struct GQLResultInterface<E = GQLEdgeInterface> {
+  pub data: GQLDataResultInterface<E>,
 }
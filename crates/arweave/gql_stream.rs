@@ -0,0 +1,252 @@
+//! Parsing GraphQL pages of transactions as they download.
+//!
+//! A page of interactions runs to megabytes for busy contracts. Buffering
+//! the body and then parsing it holds both at once; [`PageParser`] instead
+//! parses every edge as soon as its bytes are in and drops them, so that a
+//! page costs little more than its parsed edges.
+
+use crate::gql_result::GQLResultInterface;
+use deno_core::error::AnyError;
+use deno_core::serde_json;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::io::Write;
+
+/// The path of the array holding the edges in a page.
+const EDGES_PATH: [&[u8]; 3] = [b"", b"data", b"transactions"];
+const EDGES_KEY: &[u8] = b"edges";
+
+/// Splits a page written to it into its edges, parsed one at a time, and
+/// the rest of the response, parsed by [`PageParser::finish`] with an empty
+/// edges array. The result is the same as parsing the whole body.
+///
+/// Only the structure of the JSON is tracked: the bytes JSON delimits
+/// tokens with are ASCII, so they can't be part of a multi-byte character
+/// and the body is scanned byte by byte wherever chunks split it.
+pub(crate) struct PageParser<E> {
+  /// The response without the elements of the edges array.
+  envelope: Vec<u8>,
+  /// The bytes of the edge being downloaded.
+  edge: Vec<u8>,
+  edges: Vec<E>,
+  /// The key every open object or array is the value of, empty for the
+  /// root and array elements.
+  path: Vec<Vec<u8>>,
+  /// The depth of the edges array once it is open.
+  edges_depth: Option<usize>,
+  in_string: bool,
+  escaped: bool,
+  /// The last string outside of the edges, and the key it became once
+  /// followed by a colon.
+  string: Vec<u8>,
+  key: Option<Vec<u8>>,
+}
+
+impl<E: DeserializeOwned> PageParser<E> {
+  pub fn new() -> PageParser<E> {
+    PageParser {
+      envelope: Vec::new(),
+      edge: Vec::new(),
+      edges: Vec::new(),
+      path: Vec::new(),
+      edges_depth: None,
+      in_string: false,
+      escaped: false,
+      string: Vec::new(),
+      key: None,
+    }
+  }
+
+  /// The page written so far, which must be all of it.
+  pub fn finish(self) -> Result<GQLResultInterface<E>, AnyError> {
+    let mut page: GQLResultInterface<E> =
+      serde_json::from_slice(&self.envelope)?;
+    page.data.transactions.edges = self.edges;
+    Ok(page)
+  }
+
+  fn feed(&mut self, data: &[u8]) -> Result<(), serde_json::Error> {
+    for &byte in data {
+      let in_edges = self.edges_depth == Some(self.path.len());
+      let in_edge = match self.edges_depth {
+        Some(depth) => self.path.len() > depth,
+        None => false,
+      };
+
+      if self.in_string {
+        if self.escaped {
+          self.escaped = false;
+        } else if byte == b'\\' {
+          self.escaped = true;
+        } else if byte == b'"' {
+          self.in_string = false;
+        }
+        if in_edge || in_edges {
+          self.edge.push(byte);
+        } else {
+          self.envelope.push(byte);
+          if self.in_string {
+            self.string.push(byte);
+          }
+        }
+        continue;
+      }
+
+      if in_edges {
+        match byte {
+          b',' | b']' => {
+            self.parse_edge()?;
+            if byte == b']' {
+              self.path.pop();
+              self.edges_depth = None;
+              self.envelope.push(byte);
+            }
+          }
+          b'{' | b'[' => {
+            self.path.push(Vec::new());
+            self.edge.push(byte);
+          }
+          b'"' => {
+            self.in_string = true;
+            self.edge.push(byte);
+          }
+          byte if byte.is_ascii_whitespace() => {}
+          byte => self.edge.push(byte),
+        }
+        continue;
+      }
+      if in_edge {
+        match byte {
+          b'{' | b'[' => self.path.push(Vec::new()),
+          b'}' | b']' => {
+            self.path.pop();
+          }
+          b'"' => self.in_string = true,
+          _ => {}
+        }
+        self.edge.push(byte);
+        continue;
+      }
+
+      match byte {
+        b'"' => {
+          self.in_string = true;
+          self.string.clear();
+        }
+        b':' => self.key = Some(std::mem::take(&mut self.string)),
+        b',' => self.key = None,
+        b'{' | b'[' => {
+          let key = self.key.take().unwrap_or_default();
+          if byte == b'[' && key == EDGES_KEY && self.path == EDGES_PATH {
+            self.edges_depth = Some(self.path.len() + 1);
+          }
+          self.path.push(key);
+        }
+        b'}' | b']' => {
+          self.path.pop();
+        }
+        _ => {}
+      }
+      self.envelope.push(byte);
+    }
+    Ok(())
+  }
+
+  fn parse_edge(&mut self) -> Result<(), serde_json::Error> {
+    if !self.edge.is_empty() {
+      self.edges.push(serde_json::from_slice(&self.edge)?);
+      self.edge.clear();
+    }
+    Ok(())
+  }
+}
+
+impl<E: DeserializeOwned> Write for PageParser<E> {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    self.feed(data)?;
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::gql_result::GQLEdgeInterface;
+  use crate::gql_result::GQLResultInterface;
+  use crate::gql_stream::PageParser;
+  use deno_core::serde_json;
+  use std::io::Write;
+
+  #[tokio::test]
+  async fn test_page_parser() {
+    let edge = |id: &str, input: &str| {
+      serde_json::json!({
+        "cursor": format!("cursor-{}", id),
+        "node": {
+          "id": id,
+          "owner": { "address": "owner" },
+          "tags": [{ "name": "Input", "value": input }],
+          "block": { "id": "block", "timestamp": 1, "height": 1 },
+        },
+      })
+    };
+    // Delimiters inside strings, and keys that aren't the edges.
+    let page = serde_json::json!({
+      "data": {
+        "transactions": {
+          "pageInfo": { "hasNextPage": true },
+          "edges": [
+            edge("tx1", r#"{"function":"transfer","edges":[1, 2]}"#),
+            edge("tx2", "\"}],\\"),
+            edge("tx3", "ünïcödé"),
+          ],
+        },
+      },
+      "edges": [],
+    });
+    let bodies = [
+      serde_json::to_vec(&page).unwrap(),
+      serde_json::to_vec_pretty(&page).unwrap(),
+    ];
+
+    for body in bodies.iter() {
+      let expected: GQLResultInterface = serde_json::from_slice(body).unwrap();
+      for chunk_size in [1, 3, 64, body.len()] {
+        let mut parser = PageParser::<GQLEdgeInterface>::new();
+        for chunk in body.chunks(chunk_size) {
+          parser.write_all(chunk).unwrap();
+        }
+        let parsed = parser.finish().unwrap();
+        assert_eq!(
+          serde_json::to_value(&parsed).unwrap(),
+          serde_json::to_value(&expected).unwrap()
+        );
+      }
+
+      // Compressed by the gateway.
+      let compressed = zstd::encode_all(body.as_slice(), 3).unwrap();
+      let mut parser = PageParser::<GQLEdgeInterface>::new();
+      let mut decoder = zstd::stream::write::Decoder::new(&mut parser).unwrap();
+      for chunk in compressed.chunks(5) {
+        decoder.write_all(chunk).unwrap();
+      }
+      decoder.flush().unwrap();
+      drop(decoder);
+      let parsed = parser.finish().unwrap();
+      assert_eq!(parsed.data.transactions.edges.len(), 3);
+      assert_eq!(
+        parsed.data.transactions.edges[1].node.tags[0].value,
+        "\"}],\\"
+      );
+    }
+
+    // A cut off page doesn't parse.
+    let body = &bodies[0];
+    let mut parser = PageParser::<GQLEdgeInterface>::new();
+    parser.write_all(&body[..body.len() / 2]).unwrap();
+    assert!(parser.finish().is_err());
+  }
+}
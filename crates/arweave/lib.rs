@@ -1,6 +1,7 @@
 pub mod arweave;
 pub mod cache;
 pub mod gql_result;
+mod gql_stream;
 pub mod lru_cache;
 pub mod miscellaneous;
 mod utils;
@@ -1,14 +1,26 @@
 use crate::arweave::LoadedContract;
+use crate::cache::CacheCounters;
 use crate::cache::CacheExt;
+use crate::cache::CacheStats;
+use crate::cache::ContractSource;
 use crate::cache::StateResult;
+use crate::cache::MAINNET;
+use crate::cache::MAX_SNAPSHOTS;
 use crate::gql_result::GQLEdgeInterface;
+use deno_core::error::AnyError;
 use lru::LruCache;
+use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub struct ArweaveLruCache {
   contracts: LruCache<String, LoadedContract>,
   interactions: LruCache<String, Vec<GQLEdgeInterface>>,
   states: LruCache<String, StateResult>,
+  sources: LruCache<String, ContractSource>,
+  snapshots: LruCache<String, BTreeMap<usize, StateResult>>,
+  counters: CacheCounters,
+  /// See [`CacheExt::set_network`].
+  network: String,
 }
 
 impl CacheExt for ArweaveLruCache {
@@ -17,39 +29,139 @@ impl CacheExt for ArweaveLruCache {
       contracts: LruCache::unbounded(),
       interactions: LruCache::unbounded(),
       states: LruCache::unbounded(),
+      sources: LruCache::unbounded(),
+      snapshots: LruCache::unbounded(),
+      counters: CacheCounters::default(),
+      network: String::from(MAINNET),
     }
   }
 
-  fn find_contract(&mut self, contract_id: String) -> Option<LoadedContract> {
-    self.contracts.get_mut(&contract_id).cloned()
+  fn find_contract(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<LoadedContract>, AnyError> {
+    let key = self.key(&contract_id);
+    Ok(self.contracts.get_mut(&key).cloned())
   }
 
   fn find_interactions(
     &mut self,
     contract_id: String,
-  ) -> Option<Vec<GQLEdgeInterface>> {
-    self.interactions.get_mut(&contract_id).cloned()
+  ) -> Result<Option<Vec<GQLEdgeInterface>>, AnyError> {
+    let key = self.key(&contract_id);
+    Ok(self.interactions.get_mut(&key).cloned())
+  }
+
+  fn find_state(
+    &mut self,
+    contract_id: String,
+  ) -> Result<Option<StateResult>, AnyError> {
+    let key = self.key(&contract_id);
+    let found = Ok(self.states.get_mut(&key).cloned());
+    self.counters.record(&found);
+    found
+  }
+
+  fn find_source(
+    &mut self,
+    source_tx_id: String,
+  ) -> Result<Option<ContractSource>, AnyError> {
+    let key = self.key(&source_tx_id);
+    Ok(self.sources.get_mut(&key).cloned())
   }
 
-  fn find_state(&mut self, contract_id: String) -> Option<StateResult> {
-    self.states.get_mut(&contract_id).cloned()
+  fn find_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+  ) -> Result<Option<(usize, StateResult)>, AnyError> {
+    let key = self.key(&contract_id);
+    let snapshot = self.snapshots.get_mut(&key).and_then(|snapshots| {
+      let (taken_at, state) = snapshots.range(..=timestamp).next_back()?;
+      Some((*taken_at, state.clone()))
+    });
+    Ok(snapshot)
   }
 
-  fn cache_contract(&mut self, loaded_contract: &LoadedContract) {
-    self
-      .contracts
-      .put(loaded_contract.id.to_owned(), loaded_contract.clone());
+  fn cache_contract(
+    &mut self,
+    loaded_contract: &LoadedContract,
+  ) -> Result<(), AnyError> {
+    let key = self.key(&loaded_contract.id);
+    self.contracts.put(key, loaded_contract.clone());
+    Ok(())
   }
 
   fn cache_interactions(
     &mut self,
     contract_id: String,
     interactions: &[GQLEdgeInterface],
-  ) {
-    self.interactions.put(contract_id, interactions.to_vec());
+  ) -> Result<(), AnyError> {
+    let key = self.key(&contract_id);
+    self.interactions.put(key, interactions.to_vec());
+    Ok(())
+  }
+
+  fn cache_states(
+    &mut self,
+    contract_id: String,
+    state: StateResult,
+  ) -> Result<(), AnyError> {
+    let key = self.key(&contract_id);
+    self.states.put(key, state);
+    Ok(())
   }
 
-  fn cache_states(&mut self, contract_id: String, state: StateResult) {
-    self.states.put(contract_id, state);
+  fn cache_source(
+    &mut self,
+    source_tx_id: String,
+    source: &ContractSource,
+  ) -> Result<(), AnyError> {
+    let key = self.key(&source_tx_id);
+    self.sources.put(key, source.clone());
+    Ok(())
+  }
+
+  fn cache_state_at(
+    &mut self,
+    contract_id: String,
+    timestamp: usize,
+    state: StateResult,
+  ) -> Result<(), AnyError> {
+    let key = self.key(&contract_id);
+    match self.snapshots.get_mut(&key) {
+      Some(snapshots) => {
+        snapshots.insert(timestamp, state);
+        while snapshots.len() > MAX_SNAPSHOTS {
+          let oldest = *snapshots.keys().next().unwrap();
+          snapshots.remove(&oldest);
+        }
+      }
+      None => {
+        self
+          .snapshots
+          .put(key, BTreeMap::from([(timestamp, state)]));
+      }
+    }
+    Ok(())
+  }
+
+  fn stats(&self) -> CacheStats {
+    self.counters.stats()
+  }
+
+  fn reset_stats(&self) {
+    self.counters.reset()
+  }
+
+  fn set_network(&mut self, network: &str) {
+    self.network = network.to_owned();
+  }
+}
+
+impl ArweaveLruCache {
+  /// Entries of different networks never share a key.
+  fn key(&self, id: &str) -> String {
+    format!("{}/{}", self.network, id)
   }
 }
@@ -11,24 +11,30 @@ pub enum ContractType {
   JAVASCRIPT,
   WASM,
   EVM,
+  /// A content type no engine can run, as found on the transaction.
+  UNSUPPORTED(String),
 }
 
 pub fn get_contract_type_raw(contract_type: String) -> ContractType {
-  match &(contract_type.to_lowercase())[..] {
-    "application/javascript" => ContractType::JAVASCRIPT,
+  // Parameters such as `charset` don't change the engine.
+  let essence = contract_type.split(';').next().unwrap_or("").trim();
+  match &(essence.to_lowercase())[..] {
+    "application/javascript"
+    | "application/x-javascript"
+    | "text/javascript" => ContractType::JAVASCRIPT,
     "application/wasm" => ContractType::WASM,
     "application/octet-stream" => ContractType::EVM,
-    _ => ContractType::JAVASCRIPT,
+    _ => ContractType::UNSUPPORTED(contract_type),
   }
 }
 
 pub fn get_contract_type(
   maybe_content_type: Option<String>,
   contract_transaction: &TransactionData,
-  source_transaction: &TransactionData,
+  source_content_type: Option<String>,
 ) -> Result<ContractType, AnyError> {
   let contract_type = maybe_content_type
-    .or_else(|| source_transaction.get_tag("Content-Type").ok())
+    .or(source_content_type)
     .or_else(|| contract_transaction.get_tag("Content-Type").ok())
     .ok_or_else(|| {
       AnyError::msg("Contract-Src tag not found in transaction")
@@ -39,6 +45,11 @@ pub fn get_contract_type(
   Ok(ty)
 }
 
+/// Identifies the ordering produced by [`get_sort_key`]. Bump it whenever the
+/// format changes so results computed with different orderings can be told
+/// apart.
+pub const SORT_KEY_ALGORITHM: &str = "smartweave-v1:height,sha256(block,tx)";
+
 pub fn get_sort_key(
   block_height: &usize,
   block_id: &str,
@@ -66,45 +77,58 @@ mod tests {
     let contract_type = get_contract_type(
       Some(String::from("invalid")),
       &get_fake_transaction("whatever"),
-      &get_fake_transaction("whatever"),
+      get_fake_content_type("whatever"),
     )
     .unwrap();
-    assert!(matches!(contract_type, ContractType::JAVASCRIPT));
+    assert!(
+      matches!(contract_type, ContractType::UNSUPPORTED(ty) if ty == "invalid")
+    );
     let contract_type = get_contract_type(
       None,
       &get_fake_transaction("whatever"),
-      &get_fake_transaction("whatever"),
+      get_fake_content_type("whatever"),
     )
     .unwrap();
-    assert!(matches!(contract_type, ContractType::JAVASCRIPT));
+    assert!(matches!(contract_type, ContractType::UNSUPPORTED(_)));
     let contract_type = get_contract_type(
       None,
       &get_fake_transaction(""),
-      &get_fake_transaction("whatever"),
+      get_fake_content_type("text/javascript"),
     )
     .unwrap();
     assert!(matches!(contract_type, ContractType::JAVASCRIPT));
     let contract_type = get_contract_type(
       None,
       &get_fake_transaction("whatever"),
-      &get_fake_transaction("application/wasm"),
+      get_fake_content_type("application/wasm"),
     )
     .unwrap();
     assert!(matches!(contract_type, ContractType::WASM));
     let contract_type = get_contract_type(
       None,
       &get_fake_transaction(""),
-      &get_fake_transaction("application/octet-stream"),
+      get_fake_content_type("application/javascript; charset=utf-8"),
     )
     .unwrap();
-    assert!(matches!(contract_type, ContractType::EVM));
+    assert!(matches!(contract_type, ContractType::JAVASCRIPT));
     let contract_type = get_contract_type(
       None,
       &get_fake_transaction(""),
+      get_fake_content_type("application/octet-stream"),
+    )
+    .unwrap();
+    assert!(matches!(contract_type, ContractType::EVM));
+    let contract_type = get_contract_type(
+      None,
       &get_fake_transaction(""),
+      get_fake_content_type(""),
     )
     .unwrap();
-    assert!(matches!(contract_type, ContractType::JAVASCRIPT));
+    assert!(matches!(contract_type, ContractType::UNSUPPORTED(_)));
+  }
+
+  fn get_fake_content_type(content_type: &str) -> Option<String> {
+    get_fake_transaction(content_type).get_tag("Content-Type").ok()
   }
 
   fn get_fake_transaction(content_type: &str) -> TransactionData {
@@ -0,0 +1,96 @@
+use deno_core::error::AnyError;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Contract ids a server is willing to evaluate.
+///
+/// The file holds one contract id per line. Blank lines and lines starting
+/// with `#` are ignored. The file is re-read whenever it changes on disk, so
+/// operators can edit it without restarting the server.
+pub struct Allowlist {
+  path: PathBuf,
+  ids: HashSet<String>,
+  /// Modification time and length of the file when it was last read.
+  version: Option<(SystemTime, u64)>,
+}
+
+impl Allowlist {
+  pub fn load(path: impl Into<PathBuf>) -> Result<Allowlist, AnyError> {
+    let mut allowlist = Allowlist {
+      path: path.into(),
+      ids: HashSet::new(),
+      version: None,
+    };
+    allowlist.reload()?;
+    Ok(allowlist)
+  }
+
+  pub fn is_allowed(&mut self, contract_id: &str) -> bool {
+    if self.file_version() != self.version {
+      // Keep serving the last good list if the file is mid-edit or gone.
+      if let Err(err) = self.reload() {
+        eprintln!(
+          "Failed to reload allowlist {}: {}",
+          self.path.display(),
+          err
+        );
+      }
+    }
+
+    self.ids.contains(contract_id)
+  }
+
+  fn reload(&mut self) -> Result<(), AnyError> {
+    let version = self.file_version();
+    let contents = fs::read_to_string(&self.path)?;
+
+    self.ids = parse_contract_ids(&contents).collect();
+    self.version = version;
+
+    Ok(())
+  }
+
+  fn file_version(&self) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(&self.path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+  }
+}
+
+/// The contract ids of a file in the format of an [`Allowlist`].
+pub fn parse_contract_ids(contents: &str) -> impl Iterator<Item = String> + '_ {
+  contents
+    .lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::allowlist::Allowlist;
+  use std::fs;
+
+  #[tokio::test]
+  async fn test_allowlist() {
+    let path = std::env::temp_dir().join("3em_test_allowlist.txt");
+    fs::write(&path, "# Curated contracts\nCONTRACT_A\n\n  CONTRACT_B  \n")
+      .unwrap();
+
+    let mut allowlist = Allowlist::load(&path).unwrap();
+    assert!(allowlist.is_allowed("CONTRACT_A"));
+    assert!(allowlist.is_allowed("CONTRACT_B"));
+    assert!(!allowlist.is_allowed("CONTRACT_C"));
+    assert!(!allowlist.is_allowed("# Curated contracts"));
+
+    // Picked up without reloading by hand.
+    fs::write(&path, "CONTRACT_C\n").unwrap();
+    assert!(!allowlist.is_allowed("CONTRACT_A"));
+    assert!(allowlist.is_allowed("CONTRACT_C"));
+
+    // The last good list is kept when the file goes away.
+    fs::remove_file(&path).unwrap();
+    assert!(allowlist.is_allowed("CONTRACT_C"));
+  }
+}
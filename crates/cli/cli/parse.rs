@@ -8,6 +8,9 @@ pub enum Flags {
     port: i32,
     host: String,
     node_capacity: i32,
+    config: Option<String>,
+    prewarm: Option<String>,
+    serve_during_prewarm: bool,
   },
   Run {
     host: String,
@@ -23,6 +26,51 @@ pub enum Flags {
     height: Option<usize>,
     no_cache: bool,
     show_errors: bool,
+    gateway_url: Option<String>,
+    gql_url: Option<String>,
+    allow_wallet_balance: bool,
+    allow_unsafe_client: bool,
+    manifest: bool,
+    only_owners: Vec<String>,
+    aliases: Vec<String>,
+    bounded_memory: bool,
+    extended_action: bool,
+    compress_cache: bool,
+    concurrency_safe: bool,
+    cache_format: String,
+    max_cached_state_size: Option<usize>,
+    cache_network: Option<String>,
+    max_download_bytes: Option<u64>,
+    since: Option<usize>,
+    init_state: Option<String>,
+    template: bool,
+    vars: Vec<String>,
+    genesis_interaction: bool,
+    run_init: bool,
+    wasi_sandbox: bool,
+    sequential_load: bool,
+    bytes_encoding: String,
+    max_input_bytes: usize,
+    max_read_depth: Option<usize>,
+    max_foreign_reads: Option<usize>,
+    max_gas: Option<usize>,
+    explain: bool,
+    explain_all: bool,
+    pst_summary: bool,
+    trace_hashes: bool,
+    dump_actions: Option<String>,
+    plan: bool,
+    json: bool,
+    max_display_depth: Option<usize>,
+    max_array_len: Option<usize>,
+    arlocal: bool,
+    confirmations: usize,
+    cache_confirmations: usize,
+    as_of: Option<String>,
+    fail_on_invalid: bool,
+    sample: Option<f64>,
+    sample_seed: Option<u64>,
+    schema: Option<String>,
   },
   DryRun {
     host: String,
@@ -31,17 +79,75 @@ pub enum Flags {
     pretty_print: bool,
     show_validity: bool,
     file: Option<String>,
+    init_state: Option<String>,
+    template: bool,
+    vars: Vec<String>,
+    mock_contracts: Vec<String>,
+    mock_only: bool,
+    max_display_depth: Option<usize>,
+    max_array_len: Option<usize>,
+    arlocal: bool,
+    submit: bool,
+    wallet: Option<String>,
+    contract_id: Option<String>,
+    fail_on_invalid: bool,
+    schema: Option<String>,
+  },
+  Validate {
+    host: String,
+    port: i32,
+    protocol: String,
+    contract_id: Option<String>,
+    source: Option<String>,
+    init_state: Option<String>,
+    contract_type: Option<String>,
+  },
+  Lint {
+    file: Option<String>,
+    json: bool,
+  },
+  Verify {
+    host: String,
+    port: i32,
+    protocol: String,
+    contract_id: Option<String>,
+    height: Option<usize>,
+    state_hash: Option<String>,
+    manifest: Option<String>,
+    state: Option<String>,
+  },
+  Diff {
+    host: String,
+    port: i32,
+    protocol: String,
+    contract_id: Option<String>,
+    height: Option<usize>,
+    other_contract_id: Option<String>,
+    other_height: Option<usize>,
+    json: bool,
+    fail_on_diff: bool,
   },
   Serve {
     server_host: String,
     server_port: u16,
+    allowlist: Option<String>,
+    json_rpc: bool,
+    metrics_top_contracts: Option<usize>,
+    prewarm: Option<String>,
+    serve_during_prewarm: bool,
   },
 }
 
 #[derive(Debug)]
 pub enum ParseResult {
-  Help { cmd: String },
-  Known { flag: Flags },
+  Help {
+    cmd: String,
+  },
+  Known {
+    flag: Flags,
+  },
+  /// No command, or one that doesn't exist.
+  Unknown,
 }
 
 fn parse_node_limit(
@@ -49,7 +155,9 @@ fn parse_node_limit(
 ) -> Result<i32, pico_args::Error> {
   let node_limit = arguments.opt_value_from_str("--node-limit")?.unwrap_or(8);
   if node_limit < 8 {
-    panic!("At least 8 nodes are needed.");
+    return Err(pico_args::Error::ArgumentParsingFailed {
+      cause: String::from("At least 8 nodes are needed."),
+    });
   }
   Ok(node_limit)
 }
@@ -77,7 +185,10 @@ pub fn parse() -> Result<ParseResult, pico_args::Error> {
           host: pargs
             .opt_value_from_str("--host")?
             .unwrap_or_else(|| String::from("127.0.0.1")),
-          node_capacity: parse_node_limit(&mut pargs).unwrap(),
+          node_capacity: parse_node_limit(&mut pargs)?,
+          config: pargs.opt_value_from_str("--config")?,
+          prewarm: pargs.opt_value_from_str("--prewarm")?,
+          serve_during_prewarm: pargs.contains("--serve-during-prewarm"),
         },
       },
       "dry-run" => ParseResult::Known {
@@ -91,7 +202,20 @@ pub fn parse() -> Result<ParseResult, pico_args::Error> {
             .unwrap_or_else(|| String::from("https")),
           pretty_print: pargs.contains("--pretty-print"),
           show_validity: pargs.contains("--show-validity"),
-          file: pargs.opt_value_from_str("--file").unwrap(),
+          file: pargs.opt_value_from_str("--file")?,
+          init_state: pargs.opt_value_from_str("--init-state")?,
+          template: pargs.contains("--template"),
+          vars: pargs.values_from_str("--var")?,
+          mock_contracts: pargs.values_from_str("--mock-contract")?,
+          mock_only: pargs.contains("--mock-only"),
+          max_display_depth: pargs.opt_value_from_str("--max-display-depth")?,
+          max_array_len: pargs.opt_value_from_str("--max-array-len")?,
+          arlocal: pargs.contains("--arlocal"),
+          submit: pargs.contains("--submit"),
+          wallet: pargs.opt_value_from_str("--wallet")?,
+          contract_id: pargs.opt_value_from_str("--contract-id")?,
+          fail_on_invalid: pargs.contains("--fail-on-invalid"),
+          schema: pargs.opt_value_from_str("--schema")?,
         },
       },
       "run" => ParseResult::Known {
@@ -103,7 +227,7 @@ pub fn parse() -> Result<ParseResult, pico_args::Error> {
           protocol: pargs
             .opt_value_from_str("--protocol")?
             .unwrap_or_else(|| String::from("https")),
-          tx: pargs.opt_value_from_str("--contract-id").unwrap(),
+          tx: pargs.opt_value_from_str("--contract-id")?,
           pretty_print: pargs.contains("--pretty-print"),
           no_print: pargs.contains("--no-print"),
           show_validity: pargs.contains("--show-validity"),
@@ -112,9 +236,120 @@ pub fn parse() -> Result<ParseResult, pico_args::Error> {
           save_path: pargs
             .opt_value_from_str("--save")?
             .unwrap_or_else(|| String::from("")),
-          height: { pargs.opt_value_from_str("--height").unwrap() },
+          height: pargs.opt_value_from_str("--height")?,
           no_cache: pargs.contains("--no-cache"),
           show_errors: pargs.contains("--show-errors"),
+          gateway_url: pargs.opt_value_from_str("--gateway-url")?,
+          gql_url: pargs.opt_value_from_str("--gql-url")?,
+          allow_wallet_balance: pargs.contains("--allow-wallet-balance"),
+          allow_unsafe_client: pargs.contains("--allow-unsafe-client"),
+          manifest: pargs.contains("--manifest"),
+          only_owners: pargs.values_from_str("--only-owner")?,
+          aliases: pargs.values_from_str("--alias")?,
+          bounded_memory: pargs.contains("--bounded-memory"),
+          extended_action: pargs.contains("--extended-action"),
+          compress_cache: pargs.contains("--compress-cache"),
+          concurrency_safe: pargs.contains("--concurrency-safe"),
+          cache_format: pargs
+            .opt_value_from_str("--cache-format")?
+            .unwrap_or_else(|| String::from("json")),
+          max_cached_state_size: pargs
+            .opt_value_from_str("--max-cached-state-size")?,
+          cache_network: pargs.opt_value_from_str("--cache-network")?,
+          max_download_bytes: pargs
+            .opt_value_from_str("--max-download-bytes")?,
+          since: pargs.opt_value_from_str("--since")?,
+          init_state: pargs.opt_value_from_str("--init-state")?,
+          template: pargs.contains("--template"),
+          vars: pargs.values_from_str("--var")?,
+          genesis_interaction: pargs.contains("--genesis-interaction"),
+          run_init: pargs.contains("--run-init"),
+          wasi_sandbox: pargs.contains("--wasi-sandbox"),
+          sequential_load: pargs.contains("--sequential-load"),
+          bytes_encoding: pargs
+            .opt_value_from_str("--bytes-encoding")?
+            .unwrap_or_else(|| String::from("base64")),
+          max_input_bytes: pargs
+            .opt_value_from_str("--max-input-bytes")?
+            .unwrap_or(1024 * 1024),
+          max_read_depth: pargs.opt_value_from_str("--max-read-depth")?,
+          max_foreign_reads: pargs.opt_value_from_str("--max-foreign-reads")?,
+          max_gas: pargs.opt_value_from_str("--max-gas")?,
+          explain: pargs.contains("--explain"),
+          explain_all: pargs.contains("--explain-all"),
+          pst_summary: pargs.contains("--pst-summary"),
+          trace_hashes: pargs.contains("--trace-hashes"),
+          dump_actions: pargs.opt_value_from_str("--dump-actions")?,
+          plan: pargs.contains("--plan"),
+          json: pargs.contains("--json"),
+          max_display_depth: pargs.opt_value_from_str("--max-display-depth")?,
+          max_array_len: pargs.opt_value_from_str("--max-array-len")?,
+          arlocal: pargs.contains("--arlocal"),
+          confirmations: pargs
+            .opt_value_from_str("--confirmations")?
+            .unwrap_or(0),
+          cache_confirmations: pargs
+            .opt_value_from_str("--cache-confirmations")?
+            .unwrap_or(0),
+          as_of: pargs.opt_value_from_str("--as-of")?,
+          fail_on_invalid: pargs.contains("--fail-on-invalid"),
+          sample: pargs.opt_value_from_str("--sample")?,
+          sample_seed: pargs.opt_value_from_str("--sample-seed")?,
+          schema: pargs.opt_value_from_str("--schema")?,
+        },
+      },
+      "validate" => ParseResult::Known {
+        flag: Flags::Validate {
+          host: pargs
+            .opt_value_from_str("--host")?
+            .unwrap_or_else(|| String::from("arweave.net")),
+          port: pargs.opt_value_from_str("--port")?.unwrap_or(443),
+          protocol: pargs
+            .opt_value_from_str("--protocol")?
+            .unwrap_or_else(|| String::from("https")),
+          contract_id: pargs.opt_value_from_str("--contract-id")?,
+          source: pargs.opt_value_from_str("--source")?,
+          init_state: pargs.opt_value_from_str("--init-state")?,
+          contract_type: pargs.opt_value_from_str("--contract-type")?,
+        },
+      },
+      "lint" => ParseResult::Known {
+        flag: Flags::Lint {
+          file: pargs.opt_value_from_str("--file")?,
+          json: pargs.contains("--json"),
+        },
+      },
+      "verify" => ParseResult::Known {
+        flag: Flags::Verify {
+          host: pargs
+            .opt_value_from_str("--host")?
+            .unwrap_or_else(|| String::from("arweave.net")),
+          port: pargs.opt_value_from_str("--port")?.unwrap_or(443),
+          protocol: pargs
+            .opt_value_from_str("--protocol")?
+            .unwrap_or_else(|| String::from("https")),
+          contract_id: pargs.opt_value_from_str("--contract-id")?,
+          height: pargs.opt_value_from_str("--height")?,
+          state_hash: pargs.opt_value_from_str("--state-hash")?,
+          manifest: pargs.opt_value_from_str("--manifest")?,
+          state: pargs.opt_value_from_str("--state")?,
+        },
+      },
+      "diff" => ParseResult::Known {
+        flag: Flags::Diff {
+          host: pargs
+            .opt_value_from_str("--host")?
+            .unwrap_or_else(|| String::from("arweave.net")),
+          port: pargs.opt_value_from_str("--port")?.unwrap_or(443),
+          protocol: pargs
+            .opt_value_from_str("--protocol")?
+            .unwrap_or_else(|| String::from("https")),
+          contract_id: pargs.opt_value_from_str("--contract-id")?,
+          height: pargs.opt_value_from_str("--height")?,
+          other_contract_id: pargs.opt_value_from_str("--other-contract-id")?,
+          other_height: pargs.opt_value_from_str("--other-height")?,
+          json: pargs.contains("--json"),
+          fail_on_diff: pargs.contains("--fail-on-diff"),
         },
       },
       "serve" => ParseResult::Known {
@@ -123,11 +358,15 @@ pub fn parse() -> Result<ParseResult, pico_args::Error> {
             .opt_value_from_str("--host")?
             .unwrap_or_else(|| String::from("127.0.0.1")),
           server_port: pargs.opt_value_from_str("--port")?.unwrap_or(5400),
+          allowlist: pargs.opt_value_from_str("--allowlist")?,
+          json_rpc: pargs.contains("--json-rpc"),
+          metrics_top_contracts: pargs
+            .opt_value_from_str("--metrics-top-contracts")?,
+          prewarm: pargs.opt_value_from_str("--prewarm")?,
+          serve_during_prewarm: pargs.contains("--serve-during-prewarm"),
         },
       },
-      "Unknown" | _ => ParseResult::Help {
-        cmd: String::from("none"),
-      },
+      "Unknown" | _ => ParseResult::Unknown,
     };
 
     Ok(flags)
@@ -0,0 +1,231 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the evaluation duration buckets, in seconds.
+const DURATION_BUCKETS: [f64; 8] = [0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0];
+
+/// Contracts tracked one by one, past which newly seen contracts go
+/// straight to `other`. Keeps memory bounded on a server evaluating
+/// whatever it is asked for, however many contracts only show up once.
+const MAX_TRACKED: usize = 10_000;
+
+/// The label of the contracts outside of the top ones.
+const OTHER: &str = "other";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ContractStats {
+  /// Evaluations that took at most the duration of the bucket at the same
+  /// index, and longer than the previous one.
+  buckets: [u64; DURATION_BUCKETS.len()],
+  evaluations: u64,
+  seconds: f64,
+  interactions: u64,
+}
+
+impl ContractStats {
+  fn observe(&mut self, duration: Duration, interactions: usize) {
+    let seconds = duration.as_secs_f64();
+    if let Some(bucket) =
+      DURATION_BUCKETS.iter().position(|bound| seconds <= *bound)
+    {
+      self.buckets[bucket] += 1;
+    }
+    self.evaluations += 1;
+    self.seconds += seconds;
+    self.interactions += interactions as u64;
+  }
+
+  fn add(&mut self, other: &ContractStats) {
+    for (bucket, count) in self.buckets.iter_mut().zip(other.buckets) {
+      *bucket += count;
+    }
+    self.evaluations += other.evaluations;
+    self.seconds += other.seconds;
+    self.interactions += other.interactions;
+  }
+}
+
+/// Evaluation durations and interaction counts of `serve`, labeled by
+/// contract, for `GET /metrics`.
+///
+/// Only the `top` contracts that took the longest to evaluate in total get
+/// series of their own; the others are summed up under the `other` label,
+/// so that the number of series stays bounded. A contract dropping out of
+/// the top moves its counts to `other`.
+pub struct ContractMetrics {
+  top: usize,
+  contracts: HashMap<String, ContractStats>,
+  /// Contracts seen after [`MAX_TRACKED`] were.
+  untracked: ContractStats,
+}
+
+impl ContractMetrics {
+  pub fn new(top: usize) -> ContractMetrics {
+    ContractMetrics {
+      top,
+      contracts: HashMap::new(),
+      untracked: ContractStats::default(),
+    }
+  }
+
+  /// Records an evaluation of `contract_id` that took `duration` and
+  /// evaluated `interactions` interactions.
+  pub fn observe(
+    &mut self,
+    contract_id: &str,
+    duration: Duration,
+    interactions: usize,
+  ) {
+    if !self.contracts.contains_key(contract_id)
+      && self.contracts.len() >= MAX_TRACKED
+    {
+      self.untracked.observe(duration, interactions);
+      return;
+    }
+    self
+      .contracts
+      .entry(contract_id.to_owned())
+      .or_default()
+      .observe(duration, interactions);
+  }
+
+  /// The series in the Prometheus text format.
+  pub fn render(&self) -> String {
+    let mut contracts: Vec<(&String, &ContractStats)> =
+      self.contracts.iter().collect();
+    // Costliest first, ties in a stable order.
+    contracts.sort_by(|(a_id, a), (b_id, b)| {
+      b.seconds
+        .partial_cmp(&a.seconds)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a_id.cmp(b_id))
+    });
+    let mut other = self.untracked.clone();
+    for (_, stats) in contracts.iter().skip(self.top) {
+      other.add(stats);
+    }
+    let mut series: Vec<(&str, &ContractStats)> = contracts
+      .into_iter()
+      .take(self.top)
+      .map(|(contract_id, stats)| (contract_id.as_str(), stats))
+      .collect();
+    if other.evaluations > 0 {
+      series.push((OTHER, &other));
+    }
+
+    let mut text = String::from(
+      "# HELP three_em_contract_evaluation_seconds Time spent evaluating each contract.\n\
+       # TYPE three_em_contract_evaluation_seconds histogram\n",
+    );
+    for (contract_id, stats) in &series {
+      let label = escape_label(contract_id);
+      let mut cumulative = 0;
+      for (bound, count) in DURATION_BUCKETS.iter().zip(stats.buckets) {
+        cumulative += count;
+        text.push_str(&format!(
+          "three_em_contract_evaluation_seconds_bucket{{contract=\"{}\",le=\"{}\"}} {}\n",
+          label, bound, cumulative
+        ));
+      }
+      text.push_str(&format!(
+        "three_em_contract_evaluation_seconds_bucket{{contract=\"{0}\",le=\"+Inf\"}} {1}\n\
+         three_em_contract_evaluation_seconds_sum{{contract=\"{0}\"}} {2}\n\
+         three_em_contract_evaluation_seconds_count{{contract=\"{0}\"}} {1}\n",
+        label, stats.evaluations, stats.seconds
+      ));
+    }
+
+    text.push_str(
+      "# HELP three_em_contract_interactions_total Interactions evaluated for each contract, those of cached states left out.\n\
+       # TYPE three_em_contract_interactions_total counter\n",
+    );
+    for (contract_id, stats) in &series {
+      text.push_str(&format!(
+        "three_em_contract_interactions_total{{contract=\"{}\"}} {}\n",
+        escape_label(contract_id),
+        stats.interactions
+      ));
+    }
+    text
+  }
+}
+
+/// Records an evaluation into `metrics`, if the server keeps any. See
+/// [`ContractMetrics::observe`].
+pub fn observe(
+  metrics: &Option<Arc<Mutex<ContractMetrics>>>,
+  contract_id: &str,
+  duration: Duration,
+  interactions: usize,
+) {
+  if let Some(metrics) = metrics {
+    metrics
+      .lock()
+      .unwrap()
+      .observe(contract_id, duration, interactions);
+  }
+}
+
+/// Contract ids come from requests, so they may hold anything.
+fn escape_label(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::contract_metrics::{escape_label, ContractMetrics};
+  use std::time::Duration;
+
+  #[tokio::test]
+  async fn test_contract_metrics() {
+    let mut metrics = ContractMetrics::new(2);
+    metrics.observe("cheap", Duration::from_millis(20), 1);
+    metrics.observe("heavy", Duration::from_secs(3), 1000);
+    metrics.observe("heavy", Duration::from_millis(200), 0);
+    let text = metrics.render();
+    assert!(text.contains(
+      "three_em_contract_evaluation_seconds_bucket{contract=\"heavy\",le=\"0.5\"} 1\n"
+    ));
+    assert!(text.contains(
+      "three_em_contract_evaluation_seconds_bucket{contract=\"heavy\",le=\"5\"} 2\n"
+    ));
+    assert!(text.contains(
+      "three_em_contract_evaluation_seconds_count{contract=\"heavy\"} 2\n"
+    ));
+    assert!(text.contains(
+      "three_em_contract_interactions_total{contract=\"heavy\"} 1000\n"
+    ));
+    assert!(text.contains(
+      "three_em_contract_interactions_total{contract=\"cheap\"} 1\n"
+    ));
+    assert!(!text.contains("\"other\""));
+
+    // However many contracts are evaluated, only the top two and `other`
+    // have series.
+    for i in 0..50 {
+      metrics.observe(&format!("contract-{}", i), Duration::from_millis(1), 1);
+    }
+    let text = metrics.render();
+    let series = text
+      .lines()
+      .filter(|line| line.starts_with("three_em_contract_interactions_total"))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      series,
+      [
+        "three_em_contract_interactions_total{contract=\"heavy\"} 1000",
+        "three_em_contract_interactions_total{contract=\"cheap\"} 1",
+        "three_em_contract_interactions_total{contract=\"other\"} 50",
+      ]
+    );
+    assert!(text.contains(
+      "three_em_contract_evaluation_seconds_count{contract=\"other\"} 50\n"
+    ));
+    assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+  }
+}
@@ -0,0 +1,119 @@
+use crate::exit_code;
+use crate::verify::{diff_values, Difference};
+use deno_core::error::AnyError;
+use serde_json::Value;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_executor::execute_contract;
+use three_em_executor::executor::{process_execution, EvaluateOptions};
+
+/// Evaluates `contract_id` at `height`, the latest height when it is
+/// missing, and returns its state like `run` prints it.
+async fn evaluate(
+  arweave: &Arweave,
+  contract_id: &str,
+  height: Option<usize>,
+) -> Result<Value, AnyError> {
+  let result = execute_contract(
+    contract_id.to_owned(),
+    height,
+    true,
+    false,
+    None,
+    None,
+    arweave,
+    &EvaluateOptions::default(),
+  )
+  .await?;
+  Ok(process_execution(result, false))
+}
+
+/// Evaluates both sides and lists where the state of the second one
+/// differs from the state of the first one.
+pub async fn compare(
+  arweave: &Arweave,
+  (contract_id, height): (&str, Option<usize>),
+  (other_contract_id, other_height): (&str, Option<usize>),
+) -> Result<Vec<Difference>, AnyError> {
+  // One after the other, V8 isolates can't interleave on a thread.
+  let before = evaluate(arweave, contract_id, height).await?;
+  let after = evaluate(arweave, other_contract_id, other_height).await?;
+  Ok(diff_values(&before, &after))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn diff(
+  port: i32,
+  host: String,
+  protocol: String,
+  contract_id: String,
+  height: Option<usize>,
+  other_contract_id: Option<String>,
+  other_height: Option<usize>,
+  json: bool,
+  fail_on_diff: bool,
+) -> Result<i32, AnyError> {
+  let arweave = Arweave::new(port, host, protocol, ArweaveCache::new())
+    .with_env_headers()?;
+  let other_contract_id =
+    other_contract_id.unwrap_or_else(|| contract_id.clone());
+  let diffs = compare(
+    &arweave,
+    (&contract_id, height),
+    (&other_contract_id, other_height),
+  )
+  .await?;
+
+  if json {
+    println!("{}", serde_json::to_string(&diffs)?);
+  } else if diffs.is_empty() {
+    println!("States are equal");
+  } else {
+    for diff in &diffs {
+      println!("{}", diff);
+    }
+  }
+
+  if fail_on_diff && !diffs.is_empty() {
+    return Ok(exit_code::FAILURE);
+  }
+  Ok(exit_code::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::diff::compare;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::cache::ArweaveCache;
+
+  #[tokio::test]
+  async fn test_compare() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id = "KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ";
+
+    let same = compare(
+      &arweave,
+      (contract_id, Some(822062)),
+      (contract_id, Some(822062)),
+    )
+    .await
+    .unwrap();
+    assert!(same.is_empty());
+
+    // No interaction is evaluated at height 0, leaving the init state.
+    let drifted = compare(
+      &arweave,
+      (contract_id, Some(822062)),
+      (contract_id, Some(0)),
+    )
+    .await
+    .unwrap();
+    assert!(!drifted.is_empty());
+    assert!(drifted.iter().all(|diff| diff.path.starts_with("state")));
+  }
+}
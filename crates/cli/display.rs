@@ -0,0 +1,137 @@
+use serde_json::{Map, Value};
+
+/// How much of a value `run` and `dry-run` print. Only the terminal output
+/// is truncated, saved files always hold the full value.
+#[derive(Clone, Copy, Default)]
+pub struct DisplayLimits {
+  /// Arrays and objects nested deeper than this are replaced by a summary.
+  pub max_depth: Option<usize>,
+  /// Arrays and objects with more entries than this only show the first
+  /// ones, followed by how many were left out.
+  pub max_array_len: Option<usize>,
+}
+
+impl DisplayLimits {
+  pub fn is_unlimited(&self) -> bool {
+    self.max_depth.is_none() && self.max_array_len.is_none()
+  }
+}
+
+/// Prints `value` as JSON, truncated by `limits`.
+pub fn print_value(value: &Value, pretty_print: bool, limits: &DisplayLimits) {
+  let truncated;
+  let value = if limits.is_unlimited() {
+    value
+  } else {
+    truncated = truncate(value, limits, 0);
+    &truncated
+  };
+
+  if pretty_print {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+  } else {
+    println!("{}", value);
+  }
+}
+
+/// A copy of `value` within `limits`. Left out entries are replaced by
+/// strings starting with `…`, so the result is still JSON.
+pub fn truncate(value: &Value, limits: &DisplayLimits, depth: usize) -> Value {
+  let collapsed = limits.max_depth.map_or(false, |max| depth >= max);
+  let max_len = limits.max_array_len.unwrap_or(usize::MAX);
+
+  match value {
+    Value::Array(items) if collapsed => {
+      Value::String(format!("… ({})", count(items.len(), "item")))
+    }
+    Value::Object(entries) if collapsed => {
+      Value::String(format!("… ({})", count(entries.len(), "key")))
+    }
+    Value::Array(items) => {
+      let mut shown: Vec<Value> = items
+        .iter()
+        .take(max_len)
+        .map(|item| truncate(item, limits, depth + 1))
+        .collect();
+      if items.len() > max_len {
+        let left_out = count(items.len() - max_len, "more item");
+        shown.push(Value::String(format!("… ({})", left_out)));
+      }
+      Value::Array(shown)
+    }
+    Value::Object(entries) => {
+      let mut shown: Map<String, Value> = entries
+        .iter()
+        .take(max_len)
+        .map(|(key, value)| {
+          (key.to_owned(), truncate(value, limits, depth + 1))
+        })
+        .collect();
+      if entries.len() > max_len {
+        let left_out = count(entries.len() - max_len, "more key");
+        shown.insert(String::from("…"), Value::String(left_out));
+      }
+      Value::Object(shown)
+    }
+    value => value.clone(),
+  }
+}
+
+fn count(n: usize, noun: &str) -> String {
+  if n == 1 {
+    format!("1 {}", noun)
+  } else {
+    format!("{} {}s", n, noun)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::display::{truncate, DisplayLimits};
+  use serde_json::json;
+
+  #[tokio::test]
+  async fn test_truncate() {
+    let state = json!({
+      "balances": { "a": 1, "b": 2, "c": 3 },
+      "log": [[1, 2], [3], [4], [5]],
+      "owner": "a",
+    });
+
+    let limits = DisplayLimits {
+      max_depth: Some(2),
+      max_array_len: Some(2),
+    };
+    assert_eq!(
+      truncate(&state, &limits, 0),
+      json!({
+        "balances": { "a": 1, "b": 2, "…": "1 more key" },
+        "log": ["… (2 items)", "… (1 item)", "… (2 more items)"],
+        "…": "1 more key",
+      })
+    );
+
+    let limits = DisplayLimits {
+      max_depth: Some(1),
+      ..Default::default()
+    };
+    assert_eq!(
+      truncate(&state, &limits, 0),
+      json!({
+        "balances": "… (3 keys)",
+        "log": "… (4 items)",
+        "owner": "a",
+      })
+    );
+
+    let limits = DisplayLimits {
+      max_array_len: Some(1),
+      ..Default::default()
+    };
+    assert_eq!(
+      truncate(&state["log"], &limits, 0),
+      json!([[1, "… (1 more item)"], "… (3 more items)"])
+    );
+    assert_eq!(truncate(&state, &DisplayLimits::default(), 0), state);
+  }
+}
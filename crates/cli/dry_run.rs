@@ -1,18 +1,28 @@
-use deno_core::error::AnyError;
+use crate::display::{print_value, DisplayLimits};
+use crate::exit_code;
+use crate::node_crypto::jwk_to_private_key;
+use crate::schema::{self, Schema};
+use crate::submit::submit_interaction;
+use crate::template::read_init_state;
+use deno_core::error::{generic_error, AnyError};
 use indexmap::map::IndexMap;
+use rsa::RsaPrivateKey;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::error::Error;
 use std::path::Path;
-use three_em_arweave::arweave::Arweave;
+use three_em_arweave::arweave::{Arweave, ARLOCAL_URL};
 use three_em_arweave::cache::ArweaveCache;
 use three_em_arweave::cache::CacheExt;
 use three_em_arweave::gql_result::{
   GQLAmountInterface, GQLEdgeInterface, GQLTagInterface,
 };
 use three_em_arweave::miscellaneous::ContractType;
-use three_em_executor::executor::{raw_execute_contract, ExecuteResult};
+use three_em_executor::executor::{
+  raw_execute_contract, EvaluateOptions, ExecuteResult, MockReads,
+};
 use three_em_executor::test_util::{
   generate_fake_interaction, generate_fake_loaded_contract_data,
 };
@@ -57,23 +67,45 @@ pub struct DryRunFile {
 
 #[allow(clippy::too_many_arguments)]
 pub async fn dry_run_result(
-  port: i32,
-  host: String,
-  protocol: String,
+  arweave: &Arweave,
   file: String,
-) -> ExecuteResult {
+  initial_state: Option<Value>,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
   let dry = read_dry_run_file(file);
 
   let file =
     std::fs::read(dry.contract_source).expect("File source does not exist");
-  let dry_contract = generate_fake_loaded_contract_data(
-    file.as_slice(),
+
+  dry_run_source(
+    arweave,
+    &file,
     dry.contract_type,
-    dry.initial_state.to_string(),
+    initial_state.unwrap_or(dry.initial_state),
+    &dry.interactions,
+    options,
+  )
+  .await
+}
+
+/// Evaluates `interactions` against a contract given by its source instead
+/// of a dry-run file. `arweave` is only used by contracts reading other
+/// contracts.
+pub async fn dry_run_source(
+  arweave: &Arweave,
+  contract_source: &[u8],
+  contract_type: ContractType,
+  initial_state: Value,
+  interactions: &[RawInteractions],
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
+  let dry_contract = generate_fake_loaded_contract_data(
+    contract_source,
+    contract_type,
+    initial_state.to_string(),
   );
 
-  let interactions = dry
-    .interactions
+  let interactions = interactions
     .iter()
     .map(|data| {
       generate_fake_interaction(
@@ -111,7 +143,7 @@ pub async fn dry_run_result(
     })
     .collect::<Vec<GQLEdgeInterface>>();
 
-  let execution = raw_execute_contract(
+  raw_execute_contract(
     String::from(""),
     dry_contract,
     interactions,
@@ -120,12 +152,14 @@ pub async fn dry_run_result(
     true,
     true,
     |_, _| panic!("Unimplemented"),
-    &Arweave::new(port, host, protocol, ArweaveCache::new()),
+    arweave,
+    options,
   )
-  .await;
-  execution
+  .await
 }
 
+/// Runs the interactions of `file`, returning the code to exit with, see
+/// [`exit_code`].
 #[allow(clippy::too_many_arguments)]
 pub async fn dry_run(
   port: i32,
@@ -134,10 +168,62 @@ pub async fn dry_run(
   pretty_print: bool,
   show_validity: bool,
   file: String,
-) -> Result<(), AnyError> {
-  let execution = dry_run_result(port, host, protocol, file).await;
+  init_state: Option<String>,
+  template: bool,
+  vars: Vec<String>,
+  mock_contracts: Vec<String>,
+  mock_only: bool,
+  max_display_depth: Option<usize>,
+  max_array_len: Option<usize>,
+  arlocal: bool,
+  submit: bool,
+  wallet: Option<String>,
+  contract_id: Option<String>,
+  fail_on_invalid: bool,
+  schema: Option<String>,
+) -> Result<i32, AnyError> {
+  let schema = schema.as_deref().map(Schema::read).transpose()?;
+  // Read first, so that a bad keyfile fails before the evaluation.
+  let submission = if submit {
+    let (wallet, contract_id) = wallet.zip(contract_id).ok_or_else(|| {
+      generic_error("Submitting requires a wallet and a contract id")
+    })?;
+    Some((read_wallet(&wallet)?, contract_id))
+  } else {
+    None
+  };
+  let initial_state = match init_state {
+    Some(path) => {
+      let init_state = read_init_state(&path, template, &vars)?;
+      Some(serde_json::from_str(&init_state).map_err(|err| {
+        generic_error(format!("Init state {} is not valid JSON: {}", path, err))
+      })?)
+    }
+    None => None,
+  };
+  let options = EvaluateOptions {
+    mock_reads: if mock_contracts.is_empty() && !mock_only {
+      None
+    } else {
+      Some(MockReads {
+        states: read_mock_contracts(&mock_contracts)?,
+        strict: mock_only,
+      })
+    },
+    ..Default::default()
+  };
+  let arweave = if arlocal {
+    Arweave::new_from_url(ARLOCAL_URL, ArweaveCache::new())?.with_arlocal()
+  } else {
+    Arweave::new(port, host, protocol, ArweaveCache::new())
+  };
+  let execution =
+    dry_run_result(&arweave, file.clone(), initial_state, &options).await?;
 
   if let ExecuteResult::V8(value, validity_table) = execution {
+    let conforms = schema
+      .as_ref()
+      .map_or(true, |schema| schema::report(schema, &value));
     let value = if show_validity {
       serde_json::json!({
           "state": value,
@@ -147,16 +233,83 @@ pub async fn dry_run(
       value
     };
 
-    if pretty_print {
-      println!("{}", serde_json::to_string_pretty(&value).unwrap());
-    } else {
-      println!("{}", value);
+    let display_limits = DisplayLimits {
+      max_depth: max_display_depth,
+      max_array_len,
+    };
+    print_value(&value, pretty_print, &display_limits);
+
+    // Before submitting, a state that drifted from its shape is a failure.
+    if !conforms {
+      return Ok(exit_code::FAILURE);
+    }
+
+    if let Some((wallet, contract_id)) = submission {
+      let interaction =
+        read_dry_run_file(file).interactions.pop().ok_or_else(|| {
+          generic_error("The dry run has no interaction to submit")
+        })?;
+      if validity_table.get(&interaction.id) != Some(&Value::Bool(true)) {
+        return Err(generic_error(format!(
+          "Interaction {} is invalid in the dry run, not submitting it",
+          interaction.id
+        )));
+      }
+      let id =
+        submit_interaction(&arweave, &wallet, &contract_id, &interaction.input)
+          .await?;
+      println!("Submitted interaction {}", id);
+    }
+
+    if fail_on_invalid
+      && validity_table
+        .values()
+        .any(|valid| valid != &Value::Bool(true))
+    {
+      return Ok(exit_code::INVALID_INTERACTIONS);
     }
   } else {
     panic!("Dry run is only implemented for WASM and JS contracts");
   }
 
-  Ok(())
+  Ok(exit_code::SUCCESS)
+}
+
+/// Reads the wallet keyfile `--submit` signs with.
+fn read_wallet(path: &str) -> Result<RsaPrivateKey, AnyError> {
+  let keyfile = std::fs::read_to_string(path).map_err(|err| {
+    generic_error(format!("Unable to read wallet {}: {}", path, err))
+  })?;
+  let jwk = serde_json::from_str(&keyfile).map_err(|err| {
+    generic_error(format!("Wallet {} is not valid JSON: {}", path, err))
+  })?;
+  jwk_to_private_key(&jwk).map_err(|err| {
+    generic_error(format!("Wallet {} is not a valid key: {}", path, err))
+  })
+}
+
+/// Reads the states of `--mock-contract ID=PATH` flags.
+fn read_mock_contracts(
+  mock_contracts: &[String],
+) -> Result<HashMap<String, Value>, AnyError> {
+  mock_contracts
+    .iter()
+    .map(|mock| {
+      let (id, path) = mock.split_once('=').ok_or_else(|| {
+        generic_error(format!(
+          "Invalid '--mock-contract {}', expected ID=PATH",
+          mock
+        ))
+      })?;
+      let state = std::fs::read_to_string(path).map_err(|err| {
+        generic_error(format!("Unable to read mock state {}: {}", path, err))
+      })?;
+      let state = serde_json::from_str(&state).map_err(|err| {
+        generic_error(format!("Mock state {} is not valid JSON: {}", path, err))
+      })?;
+      Ok((id.to_owned(), state))
+    })
+    .collect()
 }
 
 fn read_dry_run_file<P: AsRef<Path>>(path: P) -> DryRunFile {
@@ -169,18 +322,26 @@ fn read_dry_run_file<P: AsRef<Path>>(path: P) -> DryRunFile {
 #[cfg(test)]
 mod tests {
   use crate::dry_run::{dry_run, dry_run_result};
-  use three_em_executor::executor::ExecuteResult;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::cache::ArweaveCache;
+  use three_em_executor::executor::{EvaluateOptions, ExecuteResult};
 
   #[tokio::test]
   async fn test_dry_run() {
     let execution = dry_run_result(
-      443,
-      String::from("arweave.net"),
-      String::from("https"),
+      &Arweave::new(
+        443,
+        String::from("arweave.net"),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
       // Exit cargo directory
       String::from("../../testdata/contracts/dry_run_users_contract.json"),
+      None,
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await
+    .unwrap();
 
     if let ExecuteResult::V8(value, validity_table) = execution {
       assert_eq!(
@@ -0,0 +1,87 @@
+//! The codes `three_em` exits with, so that scripts can tell why a command
+//! failed without parsing its output. See the README for the full list.
+
+use deno_core::error::AnyError;
+use three_em_arweave::arweave::Error as ArweaveError;
+use three_em_executor::executor::Error as ExecutorError;
+
+pub const SUCCESS: i32 = 0;
+/// Any other error, or a check that failed: `verify` finding another hash,
+/// `diff --fail-on-diff` finding differences, `lint` finding APIs that
+/// diverge, a state not matching its `--schema`.
+pub const FAILURE: i32 = 1;
+/// An unknown command, a flag that doesn't parse, or missing or conflicting
+/// options.
+pub const USAGE: i32 = 2;
+/// The gateway couldn't be reached or answered with an error, or the
+/// evaluation downloaded more than `--max-download-bytes`.
+pub const NETWORK: i32 = 3;
+/// The gateway doesn't know the contract, its source or its init state.
+pub const CONTRACT_NOT_FOUND: i32 = 4;
+/// The contract can't be evaluated, like a source that doesn't compile or
+/// a content type no engine runs.
+pub const EVALUATION: i32 = 5;
+/// The evaluation succeeded, but some interactions were invalid, with
+/// `--fail-on-invalid`.
+pub const INVALID_INTERACTIONS: i32 = 6;
+
+/// The code a command failing with `err` exits with.
+pub fn of_error(err: &AnyError) -> i32 {
+  for cause in err.chain() {
+    if cause.is::<pico_args::Error>() {
+      return USAGE;
+    }
+    if cause.is::<reqwest::Error>() {
+      return NETWORK;
+    }
+    match cause.downcast_ref::<ArweaveError>() {
+      Some(ArweaveError::ContractNotFound { .. }) => return CONTRACT_NOT_FOUND,
+      Some(ArweaveError::DownloadBudgetExceeded { .. }) => return NETWORK,
+      None => {}
+    }
+    if cause.is::<ExecutorError>() {
+      return EVALUATION;
+    }
+  }
+  FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::exit_code;
+  use deno_core::error::{generic_error, AnyError};
+  use three_em_arweave::arweave::Error as ArweaveError;
+  use three_em_executor::executor::Error as ExecutorError;
+
+  #[tokio::test]
+  async fn test_of_error() {
+    let not_found = AnyError::new(ArweaveError::ContractNotFound {
+      id: String::from("tx"),
+    });
+    assert_eq!(
+      exit_code::of_error(&not_found),
+      exit_code::CONTRACT_NOT_FOUND
+    );
+    // Wrapped in context.
+    let not_found = not_found.context("Could not load the contract");
+    assert_eq!(
+      exit_code::of_error(&not_found),
+      exit_code::CONTRACT_NOT_FOUND
+    );
+
+    let budget =
+      AnyError::new(ArweaveError::DownloadBudgetExceeded { limit: 1 });
+    assert_eq!(exit_code::of_error(&budget), exit_code::NETWORK);
+    let unsupported = AnyError::new(ExecutorError::UnsupportedContractType {
+      contract_id: String::from("tx"),
+      content_type: String::from("text/plain"),
+    });
+    assert_eq!(exit_code::of_error(&unsupported), exit_code::EVALUATION);
+    let usage = AnyError::new(pico_args::Error::MissingArgument);
+    assert_eq!(exit_code::of_error(&usage), exit_code::USAGE);
+    assert_eq!(
+      exit_code::of_error(&generic_error("Something else")),
+      exit_code::FAILURE
+    );
+  }
+}
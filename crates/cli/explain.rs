@@ -0,0 +1,150 @@
+use serde_json::Value;
+use three_em_executor::{InteractionCounts, ValidityTable};
+
+/// What became of an interaction in a `run`, see [`outcomes`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+  /// Evaluated, and the contract accepted it.
+  Applied,
+  /// Evaluated or checked, and invalid. The reason is only known when it
+  /// was recorded in the validity table, e.g. with `--show-errors`.
+  Rejected(Option<String>),
+  /// Already reflected in the cached state, so not evaluated again.
+  Cached,
+  /// Left out by `--only-owner` or `--sample` before the evaluation.
+  Filtered,
+}
+
+impl Outcome {
+  fn describe(&self) -> String {
+    match self {
+      Outcome::Applied => String::from("applied successfully"),
+      Outcome::Rejected(Some(reason)) => format!("rejected ({})", reason),
+      Outcome::Rejected(None) => {
+        String::from("rejected (no reason recorded, see --show-errors)")
+      }
+      Outcome::Cached => String::from("skipped (cached)"),
+      Outcome::Filtered => {
+        String::from("filtered (by --only-owner or --sample)")
+      }
+    }
+  }
+}
+
+/// The outcome of every interaction of an evaluation: those in the
+/// validity table in evaluation order, then those `filtered` out of it.
+///
+/// The cached state covers a prefix of the validity table, the first
+/// `counts.skipped_from_cache` entries.
+pub fn outcomes(
+  validity: &ValidityTable,
+  counts: &InteractionCounts,
+  filtered: &[String],
+) -> Vec<(String, Outcome)> {
+  let evaluated = validity.iter().enumerate().map(|(index, (id, valid))| {
+    let outcome = if index < counts.skipped_from_cache {
+      Outcome::Cached
+    } else {
+      match valid {
+        Value::Bool(true) => Outcome::Applied,
+        Value::Bool(false) | Value::Null => Outcome::Rejected(None),
+        Value::String(reason) => Outcome::Rejected(Some(reason.to_owned())),
+        reason => Outcome::Rejected(Some(reason.to_string())),
+      }
+    };
+    (id.to_owned(), outcome)
+  });
+  let filtered = filtered.iter().map(|id| (id.to_owned(), Outcome::Filtered));
+
+  evaluated.chain(filtered).collect()
+}
+
+/// One line per outcome. Unless `all`, applied interactions are only
+/// counted, which keeps the output of large contracts to what needs a
+/// look.
+pub fn explain(outcomes: &[(String, Outcome)], all: bool) -> Vec<String> {
+  let mut lines: Vec<String> = outcomes
+    .iter()
+    .filter(|(_, outcome)| all || *outcome != Outcome::Applied)
+    .map(|(id, outcome)| format!("{}: {}", id, outcome.describe()))
+    .collect();
+
+  let applied = outcomes
+    .iter()
+    .filter(|(_, outcome)| *outcome == Outcome::Applied)
+    .count();
+  if !all && applied > 0 {
+    lines.push(format!(
+      "{} applied successfully, listed with --explain-all",
+      if applied == 1 {
+        String::from("1 interaction")
+      } else {
+        format!("{} interactions", applied)
+      }
+    ));
+  }
+
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::explain::{explain, outcomes, Outcome};
+  use serde_json::Value;
+  use three_em_executor::{InteractionCounts, ValidityTable};
+
+  #[tokio::test]
+  async fn test_explain() {
+    let mut validity = ValidityTable::new();
+    validity.insert(String::from("tx1"), Value::Bool(true));
+    validity.insert(String::from("tx2"), Value::Bool(true));
+    validity.insert(String::from("tx3"), Value::Bool(false));
+    validity.insert(String::from("tx4"), Value::Bool(true));
+    validity.insert(
+      String::from("tx5"),
+      Value::String(String::from("Error: Insufficient balance")),
+    );
+    // The cached state covers the first two.
+    let counts = InteractionCounts::new(3, 5);
+    let filtered = [String::from("tx6")];
+
+    let outcomes = outcomes(&validity, &counts, &filtered);
+    assert_eq!(
+      outcomes,
+      vec![
+        (String::from("tx1"), Outcome::Cached),
+        (String::from("tx2"), Outcome::Cached),
+        (String::from("tx3"), Outcome::Rejected(None)),
+        (String::from("tx4"), Outcome::Applied),
+        (
+          String::from("tx5"),
+          Outcome::Rejected(Some(String::from("Error: Insufficient balance")))
+        ),
+        (String::from("tx6"), Outcome::Filtered),
+      ]
+    );
+
+    assert_eq!(
+      explain(&outcomes, true),
+      vec![
+        "tx1: skipped (cached)",
+        "tx2: skipped (cached)",
+        "tx3: rejected (no reason recorded, see --show-errors)",
+        "tx4: applied successfully",
+        "tx5: rejected (Error: Insufficient balance)",
+        "tx6: filtered (by --only-owner or --sample)",
+      ]
+    );
+    assert_eq!(
+      explain(&outcomes, false),
+      vec![
+        "tx1: skipped (cached)",
+        "tx2: skipped (cached)",
+        "tx3: rejected (no reason recorded, see --show-errors)",
+        "tx5: rejected (Error: Insufficient balance)",
+        "tx6: filtered (by --only-owner or --sample)",
+        "1 interaction applied successfully, listed with --explain-all",
+      ]
+    );
+  }
+}
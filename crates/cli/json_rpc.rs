@@ -0,0 +1,576 @@
+use crate::allowlist::Allowlist;
+use crate::contract_metrics::{self, ContractMetrics};
+use crate::dry_run::{dry_run_source, RawInteractions};
+use deno_core::error::AnyError;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::arweave::Error as ArweaveError;
+use three_em_arweave::cache::{ArweaveCache, CacheExt};
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::cursor::Cursor;
+use three_em_executor::execute_contract_with_counts;
+use three_em_executor::executor::{process_execution, Error, EvaluateOptions};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// The contract could not be evaluated. `data.kind` tells why.
+const EVALUATION_ERROR: i64 = -32000;
+/// The contract isn't on the server's allowlist.
+const NOT_ALLOWED: i64 = -32001;
+
+struct RpcError {
+  code: i64,
+  message: String,
+  kind: Option<&'static str>,
+}
+
+impl RpcError {
+  fn new(code: i64, message: impl Into<String>) -> RpcError {
+    RpcError {
+      code,
+      message: message.into(),
+      kind: None,
+    }
+  }
+
+  fn evaluation(err: AnyError) -> RpcError {
+    RpcError {
+      code: EVALUATION_ERROR,
+      message: err.to_string(),
+      kind: Some(error_kind(&err)),
+    }
+  }
+
+  fn into_response(self, id: Value) -> Value {
+    let mut error = serde_json::json!({
+      "code": self.code,
+      "message": self.message,
+    });
+    if let Some(kind) = self.kind {
+      error["data"] = serde_json::json!({ "kind": kind });
+    }
+
+    serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": id })
+  }
+}
+
+/// Like [`crate::exit_code::of_error`], looks through the context errors
+/// are wrapped in.
+fn error_kind(err: &AnyError) -> &'static str {
+  for cause in err.chain() {
+    match cause.downcast_ref::<ArweaveError>() {
+      Some(ArweaveError::ContractNotFound { .. }) => return "ContractNotFound",
+      Some(ArweaveError::DownloadBudgetExceeded { .. }) => {
+        return "DownloadBudgetExceeded"
+      }
+      None => {}
+    }
+    if let Some(err) = cause.downcast_ref::<Error>() {
+      return match err {
+        Error::UnsupportedContractType { .. } => "UnsupportedContractType",
+        Error::InteractionCapReached { .. } => "InteractionCapReached",
+        Error::InteractionRejected { .. } => "InteractionRejected",
+        Error::MaxReadDepthExceeded { .. } => "MaxReadDepthExceeded",
+        Error::UnmockedRead { .. } => "UnmockedRead",
+        Error::EngineNotEnabled { .. } => "EngineNotEnabled",
+        Error::StateMutated => "StateMutated",
+        Error::TooManyForeignReads { .. } => "TooManyForeignReads",
+      };
+    }
+  }
+  "EvaluationFailed"
+}
+
+#[derive(Deserialize)]
+struct Request {
+  jsonrpc: String,
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+fn default_host() -> String {
+  String::from("arweave.net")
+}
+
+fn default_port() -> i32 {
+  443
+}
+
+fn default_protocol() -> String {
+  String::from("https")
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EvaluateContractParams {
+  contract_id: String,
+  height: Option<usize>,
+  #[serde(default = "default_host")]
+  gateway_host: String,
+  #[serde(default = "default_port")]
+  gateway_port: i32,
+  #[serde(default = "default_protocol")]
+  gateway_protocol: String,
+  #[serde(default)]
+  show_validity: bool,
+  #[serde(default)]
+  cache: bool,
+  #[serde(default)]
+  show_errors: bool,
+  /// Also return a cursor marking how far the state goes.
+  #[serde(default)]
+  with_cursor: bool,
+  /// A cursor from an earlier result. Only the interactions after it are
+  /// evaluated, on top of `state`, the state that came with it.
+  cursor: Option<String>,
+  state: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DryRunParams {
+  contract_type: ContractType,
+  /// The contract source itself, not a path.
+  contract_source: String,
+  initial_state: Value,
+  interactions: Vec<RawInteractions>,
+  #[serde(default = "default_host")]
+  gateway_host: String,
+  #[serde(default = "default_port")]
+  gateway_port: i32,
+  #[serde(default = "default_protocol")]
+  gateway_protocol: String,
+  #[serde(default)]
+  show_validity: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetCachedStateParams {
+  contract_id: String,
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+  serde_json::from_value(params).map_err(|err| {
+    RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", err))
+  })
+}
+
+fn check_allowed(
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_id: &str,
+) -> Result<(), RpcError> {
+  match allowlist {
+    Some(allowlist) if !allowlist.lock().unwrap().is_allowed(contract_id) => {
+      Err(RpcError::new(
+        NOT_ALLOWED,
+        "contractId is not on this server's allowlist.",
+      ))
+    }
+    _ => Ok(()),
+  }
+}
+
+async fn evaluate_contract(
+  params: EvaluateContractParams,
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_metrics: &Option<Arc<Mutex<ContractMetrics>>>,
+) -> Result<Value, RpcError> {
+  check_allowed(allowlist, &params.contract_id)?;
+
+  let resume = match (&params.cursor, params.state) {
+    (Some(cursor), Some(state)) => Some(
+      Cursor::decode(cursor)
+        .and_then(|cursor| {
+          cursor.resume(&params.contract_id, params.height, state)
+        })
+        .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))?,
+    ),
+    (None, None) => None,
+    _ => {
+      return Err(RpcError::new(
+        INVALID_PARAMS,
+        "cursor and state must be given together",
+      ))
+    }
+  };
+  let options = EvaluateOptions {
+    resume,
+    ..Default::default()
+  };
+
+  let arweave = Arweave::new(
+    params.gateway_port,
+    params.gateway_host,
+    params.gateway_protocol,
+    ArweaveCache::new(),
+  )
+  .with_env_headers()
+  .map_err(|err| RpcError::new(INTERNAL_ERROR, err.to_string()))?;
+  let start = Instant::now();
+  let (result, counts) = execute_contract_with_counts(
+    params.contract_id.to_owned(),
+    params.height,
+    params.cache,
+    params.show_errors,
+    None,
+    None,
+    &arweave,
+    &options,
+  )
+  .await
+  .map_err(RpcError::evaluation)?;
+  contract_metrics::observe(
+    contract_metrics,
+    &params.contract_id,
+    start.elapsed(),
+    counts.processed,
+  );
+
+  if !params.with_cursor {
+    return Ok(process_execution(result, params.show_validity));
+  }
+  let cursor =
+    Cursor::after(&params.contract_id, params.height, &result, &options);
+  // With the validity table, results are objects the cursor can go in.
+  let mut value = process_execution(result, true);
+  if let Some(object) = value.as_object_mut() {
+    if !params.show_validity {
+      object.remove("validity");
+    }
+    object.insert(String::from("cursor"), Value::String(cursor.encode()));
+  }
+  Ok(value)
+}
+
+async fn dry_run(params: DryRunParams) -> Result<Value, RpcError> {
+  let arweave = Arweave::new(
+    params.gateway_port,
+    params.gateway_host,
+    params.gateway_protocol,
+    ArweaveCache::new(),
+  );
+  let result = dry_run_source(
+    &arweave,
+    params.contract_source.as_bytes(),
+    params.contract_type,
+    params.initial_state,
+    &params.interactions,
+    &EvaluateOptions::default(),
+  )
+  .await
+  .map_err(RpcError::evaluation)?;
+
+  Ok(process_execution(result, params.show_validity))
+}
+
+fn get_cached_state(
+  params: GetCachedStateParams,
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+) -> Result<Value, RpcError> {
+  check_allowed(allowlist, &params.contract_id)?;
+
+  let cached =
+    ArweaveCache::new()
+      .find_state(params.contract_id)
+      .map_err(|err| {
+        RpcError::new(INTERNAL_ERROR, format!("Cache unavailable: {}", err))
+      })?;
+
+  match cached {
+    Some(cached) => Ok(serde_json::json!({
+      "state": cached.state,
+      "validity": cached.validity,
+      "transformed": cached.transformed,
+    })),
+    None => Ok(Value::Null),
+  }
+}
+
+async fn call(
+  method: &str,
+  params: Value,
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_metrics: &Option<Arc<Mutex<ContractMetrics>>>,
+) -> Result<Value, RpcError> {
+  match method {
+    "evaluateContract" => {
+      evaluate_contract(parse_params(params)?, allowlist, contract_metrics)
+        .await
+    }
+    "dryRun" => dry_run(parse_params(params)?).await,
+    "getCachedState" => get_cached_state(parse_params(params)?, allowlist),
+    _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+  }
+}
+
+async fn handle_request(
+  request: Value,
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_metrics: &Option<Arc<Mutex<ContractMetrics>>>,
+) -> Option<Value> {
+  // Requests without an id are notifications and get no response, unless
+  // they are too malformed to tell.
+  let id = request.get("id").cloned();
+  let result = match serde_json::from_value::<Request>(request) {
+    Ok(request) if request.jsonrpc == "2.0" => {
+      call(&request.method, request.params, allowlist, contract_metrics).await
+    }
+    _ => {
+      return Some(
+        RpcError::new(INVALID_REQUEST, "Invalid Request")
+          .into_response(id.unwrap_or(Value::Null)),
+      )
+    }
+  };
+
+  let id = id?;
+  match result {
+    Ok(result) => {
+      Some(serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": id }))
+    }
+    Err(err) => Some(err.into_response(id)),
+  }
+}
+
+/// Handles a JSON-RPC 2.0 request or batch and returns the response body,
+/// or `None` when there is nothing to respond with (only notifications).
+///
+/// Requests in a batch are evaluated one after the other, since contract
+/// runtimes can't be moved across threads.
+pub async fn handle(
+  body: &[u8],
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_metrics: &Option<Arc<Mutex<ContractMetrics>>>,
+) -> Option<Value> {
+  let request: Value = match serde_json::from_slice(body) {
+    Ok(request) => request,
+    Err(_) => {
+      return Some(
+        RpcError::new(PARSE_ERROR, "Parse error").into_response(Value::Null),
+      )
+    }
+  };
+
+  match request {
+    Value::Array(batch) if batch.is_empty() => Some(
+      RpcError::new(INVALID_REQUEST, "Invalid Request")
+        .into_response(Value::Null),
+    ),
+    Value::Array(batch) => {
+      let mut responses = vec![];
+      for request in batch {
+        if let Some(response) =
+          handle_request(request, allowlist, contract_metrics).await
+        {
+          responses.push(response);
+        }
+      }
+
+      if responses.is_empty() {
+        None
+      } else {
+        Some(Value::Array(responses))
+      }
+    }
+    request => handle_request(request, allowlist, contract_metrics).await,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::json_rpc::{error_kind, handle};
+  use deno_core::error::{generic_error, AnyError};
+  use serde_json::json;
+  use three_em_arweave::arweave::Error as ArweaveError;
+  use three_em_executor::executor::Error;
+
+  #[tokio::test]
+  async fn test_dry_run() {
+    let request = json!({
+      "jsonrpc": "2.0",
+      "method": "dryRun",
+      "params": {
+        "contractType": "JAVASCRIPT",
+        "contractSource":
+          include_str!("../../testdata/contracts/users_contract.js"),
+        "initialState": { "users": [] },
+        "interactions": [
+          {
+            "id": "tx1",
+            "caller": "ap-address",
+            "input": { "function": "add", "name": "Andres Pirela" }
+          }
+        ]
+      },
+      "id": 1
+    });
+
+    let response = handle(request.to_string().as_bytes(), &None, &None).await;
+    assert_eq!(
+      response,
+      Some(json!({
+        "jsonrpc": "2.0",
+        "result": { "users": ["Andres Pirela"] },
+        "id": 1
+      }))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_errors() {
+    let batch = json!([
+      { "jsonrpc": "2.0", "method": "mintTokens", "id": "a" },
+      { "jsonrpc": "2.0", "method": "getCachedState", "params": {}, "id": 2 },
+      { "jsonrpc": "2.0", "method": "mintTokens" },
+      { "method": "dryRun", "id": 3 },
+    ]);
+
+    let response = handle(batch.to_string().as_bytes(), &None, &None).await;
+    let response = response.unwrap();
+    let responses = response.as_array().unwrap();
+    // The notification gets no response.
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0]["error"]["code"], -32601);
+    assert_eq!(responses[0]["id"], "a");
+    assert_eq!(responses[1]["error"]["code"], -32602);
+    assert_eq!(responses[1]["id"], 2);
+    assert_eq!(responses[2]["error"]["code"], -32600);
+    assert_eq!(responses[2]["id"], 3);
+
+    let response = handle(b"{not json", &None, &None).await.unwrap();
+    assert_eq!(response["error"]["code"], -32700);
+    assert_eq!(response["id"], json!(null));
+  }
+
+  #[tokio::test]
+  async fn test_rejected_interaction() {
+    // The second interaction throws: it is rejected, the evaluation isn't.
+    let request = json!({
+      "jsonrpc": "2.0",
+      "method": "dryRun",
+      "params": {
+        "contractType": "JAVASCRIPT",
+        "contractSource":
+          include_str!("../../testdata/contracts/users_contract.js"),
+        "initialState": { "users": [] },
+        "interactions": [
+          {
+            "id": "tx1",
+            "caller": "ap-address",
+            "input": { "function": "add", "name": "Andres Pirela" }
+          },
+          {
+            "id": "tx2",
+            "caller": "divy-address",
+            "input": { "function": "none", "name": "Divy" }
+          }
+        ],
+        "showValidity": true
+      },
+      "id": 1
+    });
+
+    let response = handle(request.to_string().as_bytes(), &None, &None).await;
+    let response = response.unwrap();
+    assert_eq!(
+      response["result"]["state"],
+      json!({ "users": ["Andres Pirela"] })
+    );
+    assert_eq!(response["result"]["validity"]["tx1"], json!(true));
+    assert_ne!(response["result"]["validity"]["tx2"], json!(true));
+
+    // Failing evaluations report what failed them in `data.kind`.
+    let kinds = [
+      (
+        Error::InteractionRejected {
+          reason: String::from("Fee too low"),
+        },
+        "InteractionRejected",
+      ),
+      (
+        Error::MaxReadDepthExceeded { max_read_depth: 10 },
+        "MaxReadDepthExceeded",
+      ),
+      (
+        Error::UnmockedRead {
+          contract_id: String::from("tx"),
+        },
+        "UnmockedRead",
+      ),
+      (
+        Error::EngineNotEnabled {
+          contract_id: String::from("tx"),
+          engine: "wasm",
+        },
+        "EngineNotEnabled",
+      ),
+      (Error::StateMutated, "StateMutated"),
+      (
+        Error::TooManyForeignReads {
+          max_foreign_reads: 100,
+        },
+        "TooManyForeignReads",
+      ),
+    ];
+    for (error, kind) in kinds {
+      assert_eq!(error_kind(&AnyError::new(error)), kind);
+    }
+    assert_eq!(error_kind(&generic_error("Other")), "EvaluationFailed");
+  }
+
+  #[tokio::test]
+  async fn test_wrapped_error_kind() {
+    let rejected = AnyError::new(Error::InteractionRejected {
+      reason: String::from("Fee too low"),
+    })
+    .context("Could not evaluate the contract");
+    assert_eq!(error_kind(&rejected), "InteractionRejected");
+
+    let not_found = AnyError::new(ArweaveError::ContractNotFound {
+      id: String::from("tx"),
+    })
+    .context("Could not load the contract");
+    assert_eq!(error_kind(&not_found), "ContractNotFound");
+    let budget =
+      AnyError::new(ArweaveError::DownloadBudgetExceeded { limit: 1 });
+    assert_eq!(error_kind(&budget), "DownloadBudgetExceeded");
+    assert_eq!(
+      error_kind(&generic_error("Other").context("Wrapped")),
+      "EvaluationFailed"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_invalid_cursor() {
+    let batch = json!([
+      {
+        "jsonrpc": "2.0",
+        "method": "evaluateContract",
+        "params": { "contractId": "contract", "cursor": "abc.def" },
+        "id": 1
+      },
+      {
+        "jsonrpc": "2.0",
+        "method": "evaluateContract",
+        "params": { "contractId": "contract", "cursor": "abc", "state": {} },
+        "id": 2
+      },
+    ]);
+
+    // Rejected before anything is fetched.
+    let response = handle(batch.to_string().as_bytes(), &None, &None).await;
+    let response = response.unwrap();
+    let responses = response.as_array().unwrap();
+    assert_eq!(responses[0]["error"]["code"], -32602);
+    assert_eq!(responses[1]["error"]["code"], -32602);
+  }
+}
@@ -0,0 +1,279 @@
+use crate::exit_code;
+use deno_core::error::{generic_error, AnyError};
+use serde::Serialize;
+
+/// What happens to a flagged call when a contract is evaluated.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+  /// 3em replaces it with a deterministic version, other SmartWeave
+  /// clients may not.
+  Neutralized,
+  /// Its result depends on when and where the contract is evaluated.
+  Diverges,
+}
+
+struct Rule {
+  api: &'static str,
+  kind: Kind,
+  message: &'static str,
+}
+
+const RULES: &[Rule] = &[
+  Rule {
+    api: "Math.random",
+    kind: Kind::Neutralized,
+    message: "3em seeds it, other clients return different values",
+  },
+  Rule {
+    api: "Date.now",
+    kind: Kind::Neutralized,
+    message: "3em pins it to 2016-11-18, other clients return the wall clock",
+  },
+  Rule {
+    api: "new Date()",
+    kind: Kind::Neutralized,
+    message: "3em pins it to 2016-11-18, other clients return the wall clock",
+  },
+  Rule {
+    api: "performance.now",
+    kind: Kind::Neutralized,
+    message: "3em returns a fixed step counter, other clients a real timer",
+  },
+  Rule {
+    api: "crypto.getRandomValues",
+    kind: Kind::Neutralized,
+    message: "3em seeds it, other clients return different values",
+  },
+  Rule {
+    api: "crypto.randomUUID",
+    kind: Kind::Neutralized,
+    message: "3em seeds it, other clients return different values",
+  },
+  Rule {
+    api: "WeakRef",
+    kind: Kind::Neutralized,
+    message: "depends on garbage collection, 3em never collects its target",
+  },
+  Rule {
+    api: "FinalizationRegistry",
+    kind: Kind::Neutralized,
+    message: "depends on garbage collection, 3em never runs its callbacks",
+  },
+  Rule {
+    api: "fetch",
+    kind: Kind::Diverges,
+    message: "network access, responses change over time",
+  },
+  Rule {
+    api: "XMLHttpRequest",
+    kind: Kind::Diverges,
+    message: "network access, responses change over time",
+  },
+  Rule {
+    api: "WebSocket",
+    kind: Kind::Diverges,
+    message: "network access, responses change over time",
+  },
+  Rule {
+    api: "SmartWeave.unsafeClient",
+    kind: Kind::Diverges,
+    message: "reads whatever the gateway serves at evaluation time \
+              (needs --allow-unsafe-client)",
+  },
+  Rule {
+    api: "SmartWeave.arweave.wallets.getBalance",
+    kind: Kind::Diverges,
+    message: "returns the current balance, not the one at the evaluated \
+              height (needs --allow-wallet-balance)",
+  },
+  Rule {
+    api: "SmartWeave.extensions.randomBytes",
+    kind: Kind::Diverges,
+    message: "draws bytes from the operating system, disabled unless the \
+              node allows it",
+  },
+];
+
+/// A non-deterministic API used by a contract source.
+#[derive(Serialize, Debug, Clone)]
+pub struct Finding {
+  /// 1-based line and column of the first character of `api`.
+  pub line: usize,
+  pub column: usize,
+  pub api: &'static str,
+  pub kind: Kind,
+  pub message: &'static str,
+}
+
+/// Blanks out comments and the contents of string literals, keeping line
+/// breaks, so APIs are only matched in code. Template literal
+/// substitutions are blanked too.
+fn code_only(source: &str) -> Vec<char> {
+  let chars: Vec<char> = source.chars().collect();
+  let mut code = chars.clone();
+  let mut i = 0;
+
+  while i < chars.len() {
+    match chars[i] {
+      '/' if chars.get(i + 1) == Some(&'/') => {
+        let end = chars[i..]
+          .iter()
+          .position(|c| *c == '\n')
+          .map_or(chars.len(), |end| i + end);
+        blank(&mut code, i, end);
+        i = end;
+      }
+      '/' if chars.get(i + 1) == Some(&'*') => {
+        let end = (i + 2..chars.len())
+          .find(|j| chars[*j] == '*' && chars.get(j + 1) == Some(&'/'))
+          .map_or(chars.len(), |end| end + 2);
+        blank(&mut code, i, end);
+        i = end;
+      }
+      quote @ ('"' | '\'' | '`') => {
+        let mut end = i + 1;
+        while end < chars.len() && chars[end] != quote {
+          if chars[end] == '\\' {
+            end += 1;
+          }
+          end += 1;
+        }
+        blank(&mut code, i + 1, end);
+        i = end + 1;
+      }
+      _ => i += 1,
+    }
+  }
+
+  code
+}
+
+fn blank(code: &mut [char], from: usize, to: usize) {
+  let to = to.min(code.len());
+  for c in &mut code[from.min(to)..to] {
+    if *c != '\n' {
+      *c = ' ';
+    }
+  }
+}
+
+fn is_identifier(c: char) -> bool {
+  c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Scans a JavaScript contract source for non-deterministic APIs.
+///
+/// This is a textual check, not a parser: aliased or computed accesses
+/// (`const r = Math["random"]`) go unnoticed, and a local variable named
+/// like a flagged API is reported.
+pub fn lint_source(source: &str) -> Vec<Finding> {
+  let code = code_only(source);
+  let rules: Vec<(Vec<char>, &Rule)> = RULES
+    .iter()
+    .map(|rule| (rule.api.chars().collect(), rule))
+    .collect();
+  let mut findings = vec![];
+  let (mut line, mut column) = (1, 1);
+
+  for i in 0..code.len() {
+    let starts_identifier =
+      i == 0 || !(is_identifier(code[i - 1]) || code[i - 1] == '.');
+    if starts_identifier {
+      for (api, rule) in &rules {
+        let end = i + api.len();
+        let matches = code.get(i..end) == Some(&api[..])
+          && !code.get(end).map_or(false, |c| is_identifier(*c));
+        if matches {
+          findings.push(Finding {
+            line,
+            column,
+            api: rule.api,
+            kind: rule.kind,
+            message: rule.message,
+          });
+        }
+      }
+    }
+
+    if code[i] == '\n' {
+      line += 1;
+      column = 1;
+    } else {
+      column += 1;
+    }
+  }
+
+  findings
+}
+
+pub fn lint(file: String, json: bool) -> Result<i32, AnyError> {
+  let source = std::fs::read_to_string(&file).map_err(|err| {
+    generic_error(format!("Unable to read source {}: {}", file, err))
+  })?;
+  let findings = lint_source(&source);
+  let diverging = findings
+    .iter()
+    .filter(|finding| finding.kind == Kind::Diverges)
+    .count();
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&findings).unwrap());
+  } else {
+    for finding in &findings {
+      let kind = match finding.kind {
+        Kind::Neutralized => "neutralized",
+        Kind::Diverges => "diverges",
+      };
+      println!(
+        "{}:{}:{}: {}: {}: {}",
+        file, finding.line, finding.column, kind, finding.api, finding.message
+      );
+    }
+    println!(
+      "{} non-deterministic API uses, {} will diverge across nodes",
+      findings.len(),
+      diverging
+    );
+  }
+
+  if diverging > 0 {
+    return Ok(exit_code::FAILURE);
+  }
+
+  Ok(exit_code::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::lint::{lint_source, Kind};
+
+  #[tokio::test]
+  async fn test_lint_fixture() {
+    let source = include_str!("../../testdata/contracts/non_deterministic.js");
+    let findings = lint_source(source)
+      .into_iter()
+      .map(|finding| (finding.line, finding.column, finding.api, finding.kind))
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      findings,
+      vec![
+        (3, 18, "Math.random", Kind::Neutralized),
+        (4, 20, "Date.now", Kind::Neutralized),
+        (8, 28, "fetch", Kind::Diverges),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_lint_ignores_comments_and_strings() {
+    let source = r#"
+      // Math.random()
+      /* fetch(url) */
+      const name = "Date.now";
+      const random = state.Math.random + myfetch(1);
+    "#;
+    assert!(lint_source(source).is_empty());
+  }
+}
@@ -1,3 +1,5 @@
+use crate::allowlist::Allowlist;
+use crate::contract_metrics::{self, ContractMetrics};
 use deno_core::error::AnyError;
 use hyper::http::response::Parts;
 use hyper::service::{make_service_fn, service_fn};
@@ -10,31 +12,115 @@ use std::convert::Infallible;
 use std::net::{IpAddr, SocketAddr};
 use std::num::ParseIntError;
 use std::str::{FromStr, ParseBoolError};
-use three_em_arweave::arweave::Arweave;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use three_em_arweave::arweave::{try_cache, Arweave};
 use three_em_arweave::cache::{ArweaveCache, CacheExt};
-use three_em_executor::execute_contract;
-use three_em_executor::executor::ExecuteResult;
+use three_em_executor::execute_contract_with_counts;
+use three_em_executor::executor::{EvaluateOptions, ExecuteResult};
 use url::Url;
 
 pub struct ServerConfiguration {
   pub port: u16,
   pub host: IpAddr,
+  /// When set, only contracts on this list are evaluated.
+  pub allowlist: Option<Allowlist>,
+  /// Whether to expose the JSON-RPC endpoint at `POST /rpc`.
+  pub json_rpc: bool,
+  /// When set, `GET /metrics` also reports evaluations by contract.
+  pub contract_metrics: Option<ContractMetrics>,
 }
 
 pub fn build_error(message: &str) -> Response<Body> {
+  build_error_with_status(400, message)
+}
+
+pub fn build_error_with_status(status: u16, message: &str) -> Response<Body> {
   Response::builder()
-    .status(400)
+    .status(status)
     .body(Body::from(
       serde_json::json!({
-        "status": 400,
+        "status": status,
         "message": message})
       .to_string(),
     ))
     .unwrap()
 }
 
-async fn echo(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+/// Counters in the Prometheus text format, for `GET /metrics`.
+fn metrics(contract_metrics: &Option<Arc<Mutex<ContractMetrics>>>) -> String {
+  // Nothing was looked up before the first evaluation sets the cache.
+  let stats = try_cache(|cache| Ok(cache.stats())).unwrap_or_default();
+  let counters = [
+    (
+      "three_em_cache_hits_total",
+      "Cached state lookups that found a state.",
+      stats.hits,
+    ),
+    (
+      "three_em_cache_misses_total",
+      "Cached state lookups that found none.",
+      stats.misses,
+    ),
+  ];
+  counters
+    .iter()
+    .map(|(name, help, value)| {
+      format!(
+        "# HELP {0} {1}\n# TYPE {0} counter\n{0} {2}\n",
+        name, help, value
+      )
+    })
+    .chain(
+      contract_metrics
+        .iter()
+        .map(|metrics| metrics.lock().unwrap().render()),
+    )
+    .collect()
+}
+
+fn is_allowed(
+  allowlist: &Option<Arc<Mutex<Allowlist>>>,
+  contract_id: &str,
+) -> bool {
+  match allowlist {
+    Some(allowlist) => allowlist.lock().unwrap().is_allowed(contract_id),
+    None => true,
+  }
+}
+
+async fn echo(
+  req: Request<Body>,
+  allowlist: Option<Arc<Mutex<Allowlist>>>,
+  json_rpc: bool,
+  contract_metrics: Option<Arc<Mutex<ContractMetrics>>>,
+) -> Result<Response<Body>, hyper::Error> {
   match (req.method(), req.uri().path()) {
+    (&Method::POST, "/rpc") if json_rpc => {
+      let body = hyper::body::to_bytes(req.into_body()).await?;
+      match crate::json_rpc::handle(&body, &allowlist, &contract_metrics).await
+      {
+        Some(response) => Ok(
+          Response::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::from(response.to_string()))
+            .unwrap(),
+        ),
+        // Nothing to answer for notifications.
+        None => Ok(
+          Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap(),
+        ),
+      }
+    }
+    (&Method::GET, "/metrics") => Ok(
+      Response::builder()
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(metrics(&contract_metrics)))
+        .unwrap(),
+    ),
         (&Method::GET, "/evaluate") => {
             let params: HashMap<String, String> = req
                 .uri()
@@ -64,9 +150,19 @@ async fn echo(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
 
            if contract_id.is_none() {
               response_result = Some(build_error("contractId was not provided in query parameters. A contract id must be provided."));
+            } else if !is_allowed(&allowlist, contract_id.as_ref().unwrap()) {
+              response_result = Some(build_error_with_status(403, "contractId is not on this server's allowlist."));
             } else {
-                 let arweave = Arweave::new(port, gateway_host.to_owned(), gateway_protocol.to_owned(), ArweaveCache::new());
-                 let execute_result = execute_contract( contract_id.unwrap().to_owned(), height, cache, show_errors, None, None, &arweave).await;
+                 let contract_id = contract_id.unwrap();
+                 let start = Instant::now();
+                 let execute_result = match Arweave::new(port, gateway_host.to_owned(), gateway_protocol.to_owned(), ArweaveCache::new()).with_env_headers() {
+                     Ok(arweave) => execute_contract_with_counts( contract_id.to_owned(), height, cache, show_errors, None, None, &arweave, &EvaluateOptions::default()).await,
+                     Err(err) => Err(err),
+                 };
+                 let execute_result = execute_result.map(|(result, counts)| {
+                     contract_metrics::observe(&contract_metrics, &contract_id, start.elapsed(), counts.processed);
+                     result
+                 });
                 match execute_result {
                      Ok(result) => {
                          match result {
@@ -86,6 +182,20 @@ async fn echo(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
                                      )));
                                  }
                              },
+                             ExecuteResult::Bytes(state, validity) => {
+                                 let state = base64::encode(state);
+                                 let body = if show_validity {
+                                     serde_json::json!({
+                                         "state": state,
+                                         "validity": validity
+                                     })
+                                 } else {
+                                     serde_json::json!({
+                                         "state": state
+                                     })
+                                 };
+                                 response_result = Some(Response::new(Body::from(body.to_string())));
+                             },
                              ExecuteResult::Evm(_, _, _) => {
                                  response_result = Some(build_error("EVM evaluation is disabled"));
                              }
@@ -109,8 +219,20 @@ async fn echo(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
 
 pub async fn start_local_server(config: ServerConfiguration) {
   let addr = SocketAddr::from((config.host, config.port));
-  let service =
-    make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(echo)) });
+  let allowlist = config.allowlist.map(|list| Arc::new(Mutex::new(list)));
+  let json_rpc = config.json_rpc;
+  let contract_metrics = config
+    .contract_metrics
+    .map(|metrics| Arc::new(Mutex::new(metrics)));
+  let service = make_service_fn(move |_conn| {
+    let allowlist = allowlist.clone();
+    let contract_metrics = contract_metrics.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |req| {
+        echo(req, allowlist.clone(), json_rpc, contract_metrics.clone())
+      }))
+    }
+  });
 
   println!("Serving {}", addr.to_string());
   println!(
@@ -127,8 +249,21 @@ pub async fn start_local_server(config: ServerConfiguration) {
                ?showValidity   Whether validity table should be included in the JSON response   (Default: false)   [boolean]
                ?cache   Whether built-in cache system should be used during execution   (Default: true)   [boolean]
                ?showErrors   Whether server console should print out execution exceptions   (Default: false)   [boolean]
+         GET   /metrics   Cache hits and misses since the server started, and evaluations by contract with --metrics-top-contracts, in the Prometheus text format
       "}
   );
+  if json_rpc {
+    println!(
+      "{}",
+      indoc! {
+      "
+         POST  /rpc   JSON-RPC 2.0 endpoint, batches supported
+               evaluateContract   Same parameters as /evaluate, as a params object, and withCursor, cursor and state for polling
+               dryRun   Evaluates interactions against an inline contract source
+               getCachedState   Returns the cached state of a contract, or null
+        "}
+    );
+  }
 
   let server = Server::bind(&addr).executor(LocalExec).serve(service);
   server.await.unwrap();
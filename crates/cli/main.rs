@@ -1,15 +1,33 @@
+mod allowlist;
 mod cli;
+mod contract_metrics;
 mod core_nodes;
+mod diff;
+mod display;
 mod dry_run;
+mod exit_code;
+mod explain;
+mod json_rpc;
+mod lint;
 mod local_server;
+mod manifest;
 mod messages;
 mod node;
+mod node_config;
 mod node_crypto;
+mod prewarm;
 mod print_help;
+mod pst;
 mod run;
+mod schema;
 mod start;
+mod submit;
+mod template;
 mod utils;
+mod validate;
+mod verify;
 
+use crate::allowlist::Allowlist;
 use crate::cli::parse;
 use crate::cli::parse::{Flags, ParseResult};
 use deno_core::error::AnyError;
@@ -19,8 +37,31 @@ use std::env;
 use std::net::IpAddr;
 use std::ops::Deref;
 use std::str::FromStr;
+use three_em_executor::executor::Sample;
+use three_em_executor::executor::DEFAULT_MAX_FOREIGN_READS;
+use three_em_executor::executor::DEFAULT_MAX_READ_DEPTH;
 
-fn main() -> Result<(), AnyError> {
+fn main() {
+  let code = match run_command() {
+    Ok(code) => code,
+    Err(err) => {
+      eprintln!("Error: {:?}", err);
+      exit_code::of_error(&err)
+    }
+  };
+  std::process::exit(code);
+}
+
+/// Prints the help of `cmd` and what is wrong with its options.
+fn usage(cmd: &str, message: &str) -> i32 {
+  print_help::print_help(Some(cmd));
+  println!("{}", message);
+  exit_code::USAGE
+}
+
+/// Runs the command given on the command line, see [`exit_code`] for what
+/// it returns.
+fn run_command() -> Result<i32, AnyError> {
   let parse_result = parse::parse()?;
 
   let rt = tokio::runtime::Runtime::new()?;
@@ -29,14 +70,34 @@ fn main() -> Result<(), AnyError> {
     ParseResult::Help { cmd } => {
       print_help::print_help(Some(cmd.deref()));
     }
+    ParseResult::Unknown => {
+      print_help::print_help(None);
+      return Ok(exit_code::USAGE);
+    }
     ParseResult::Known { flag } => {
       match flag {
         Flags::Start {
           host,
           port,
           node_capacity,
+          config,
+          prewarm,
+          serve_during_prewarm,
         } => {
-          rt.block_on(crate::start::start(host, port, node_capacity))?;
+          if serve_during_prewarm && prewarm.is_none() {
+            return Ok(usage(
+              "start",
+              "Option '--serve-during-prewarm' requires '--prewarm'",
+            ));
+          }
+          rt.block_on(crate::start::start(
+            host,
+            port,
+            node_capacity,
+            config,
+            prewarm,
+            serve_during_prewarm,
+          ))?;
         }
         Flags::Run {
           port,
@@ -52,12 +113,128 @@ fn main() -> Result<(), AnyError> {
           height,
           no_cache,
           show_errors,
+          gateway_url,
+          gql_url,
+          allow_wallet_balance,
+          allow_unsafe_client,
+          manifest,
+          only_owners,
+          aliases,
+          bounded_memory,
+          extended_action,
+          compress_cache,
+          concurrency_safe,
+          cache_format,
+          max_cached_state_size,
+          cache_network,
+          max_download_bytes,
+          since,
+          init_state,
+          template,
+          vars,
+          genesis_interaction,
+          run_init,
+          wasi_sandbox,
+          sequential_load,
+          bytes_encoding,
+          max_input_bytes,
+          max_read_depth,
+          max_foreign_reads,
+          max_gas,
+          explain,
+          explain_all,
+          pst_summary,
+          trace_hashes,
+          dump_actions,
+          plan,
+          json,
+          max_display_depth,
+          max_array_len,
+          arlocal,
+          confirmations,
+          cache_confirmations,
+          as_of,
+          fail_on_invalid,
+          sample,
+          sample_seed,
+          schema,
         } => {
           if tx.is_none() {
-            print_help::print_help(Some("run"));
-            println!("{}", "Option '--contract-id' is required");
+            return Ok(usage("run", "Option '--contract-id' is required"));
+          } else if manifest && !save {
+            return Ok(usage("run", "Option '--manifest' requires '--save'"));
+          } else if manifest && init_state.is_some() {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--init-state'",
+            ));
+          } else if manifest && genesis_interaction {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--genesis-interaction'",
+            ));
+          } else if manifest && run_init {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--run-init'",
+            ));
+          } else if manifest && max_input_bytes != 1024 * 1024 {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--max-input-bytes'",
+            ));
+          } else if manifest
+            && max_read_depth
+              .map_or(false, |depth| depth != DEFAULT_MAX_READ_DEPTH)
+          {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--max-read-depth'",
+            ));
+          } else if manifest
+            && max_foreign_reads
+              .map_or(false, |max| max != DEFAULT_MAX_FOREIGN_READS)
+          {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--max-foreign-reads'",
+            ));
+          } else if manifest && max_gas.is_some() {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--max-gas'",
+            ));
+          } else if manifest && !aliases.is_empty() {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--alias'",
+            ));
+          } else if manifest && sample.is_some() {
+            return Ok(usage(
+              "run",
+              "Option '--manifest' cannot be used with '--sample'",
+            ));
+          } else if sample
+            .map_or(false, |fraction| !(0.0..=1.0).contains(&fraction))
+          {
+            return Ok(usage(
+              "run",
+              "Option '--sample' expects a fraction between 0 and 1",
+            ));
+          } else if sample_seed.is_some() && sample.is_none() {
+            return Ok(usage(
+              "run",
+              "Option '--sample-seed' requires '--sample'",
+            ));
+          } else if template && init_state.is_none() {
+            return Ok(usage(
+              "run",
+              "Option '--template' requires '--init-state'",
+            ));
+          } else if json && !plan {
+            return Ok(usage("run", "Option '--json' requires '--plan'"));
           } else {
-            rt.block_on(run::run(
+            return rt.block_on(run::run(
               port,
               host,
               protocol,
@@ -71,7 +248,54 @@ fn main() -> Result<(), AnyError> {
               height,
               no_cache,
               show_errors,
-            ))?;
+              gateway_url,
+              gql_url,
+              allow_wallet_balance,
+              allow_unsafe_client,
+              manifest,
+              only_owners,
+              aliases,
+              bounded_memory,
+              extended_action,
+              compress_cache,
+              concurrency_safe,
+              cache_format,
+              max_cached_state_size,
+              cache_network,
+              max_download_bytes,
+              since,
+              init_state,
+              template,
+              vars,
+              genesis_interaction,
+              run_init,
+              wasi_sandbox,
+              sequential_load,
+              bytes_encoding,
+              max_input_bytes,
+              max_read_depth,
+              max_foreign_reads,
+              max_gas,
+              explain,
+              explain_all,
+              pst_summary,
+              trace_hashes,
+              dump_actions,
+              plan,
+              json,
+              max_display_depth,
+              max_array_len,
+              arlocal,
+              confirmations,
+              cache_confirmations,
+              as_of,
+              fail_on_invalid,
+              sample.map(|fraction| Sample {
+                fraction,
+                seed: sample_seed.unwrap_or(0),
+              }),
+              schema,
+            ));
           }
         }
         Flags::DryRun {
@@ -81,45 +305,204 @@ fn main() -> Result<(), AnyError> {
           pretty_print,
           show_validity,
           file,
+          init_state,
+          template,
+          vars,
+          mock_contracts,
+          mock_only,
+          max_display_depth,
+          max_array_len,
+          arlocal,
+          submit,
+          wallet,
+          contract_id,
+          fail_on_invalid,
+          schema,
         } => {
           if file.is_none() {
-            print_help::print_help(Some("dry-run"));
-            println!("{}", "Option '--file' is required");
+            return Ok(usage("dry-run", "Option '--file' is required"));
+          } else if template && init_state.is_none() {
+            return Ok(usage(
+              "dry-run",
+              "Option '--template' requires '--init-state'",
+            ));
+          } else if submit && (wallet.is_none() || contract_id.is_none()) {
+            return Ok(usage(
+              "dry-run",
+              "Option '--submit' requires '--wallet' and '--contract-id'",
+            ));
           } else {
-            rt.block_on(dry_run::dry_run(
+            return rt.block_on(dry_run::dry_run(
               port,
               host,
               protocol,
               pretty_print,
               show_validity,
               file.unwrap(),
-            ))?;
+              init_state,
+              template,
+              vars,
+              mock_contracts,
+              mock_only,
+              max_display_depth,
+              max_array_len,
+              arlocal,
+              submit,
+              wallet,
+              contract_id,
+              fail_on_invalid,
+              schema,
+            ));
+          }
+        }
+        Flags::Validate {
+          host,
+          port,
+          protocol,
+          contract_id,
+          source,
+          init_state,
+          contract_type,
+        } => {
+          if contract_id.is_none() && source.is_none() {
+            return Ok(usage(
+              "validate",
+              "Option '--contract-id' or '--source' is required",
+            ));
+          } else if contract_id.is_none() && init_state.is_none() {
+            return Ok(usage(
+              "validate",
+              "Option '--init-state' is required with '--source'",
+            ));
+          } else {
+            return rt.block_on(validate::validate(
+              port,
+              host,
+              protocol,
+              contract_id,
+              source,
+              init_state,
+              contract_type,
+            ));
+          }
+        }
+        Flags::Lint { file, json } => {
+          if file.is_none() {
+            return Ok(usage("lint", "Option '--file' is required"));
+          } else {
+            return lint::lint(file.unwrap(), json);
+          }
+        }
+        Flags::Verify {
+          host,
+          port,
+          protocol,
+          contract_id,
+          height,
+          state_hash,
+          manifest,
+          state,
+        } => {
+          if contract_id.is_none() && manifest.is_none() {
+            return Ok(usage(
+              "verify",
+              "Option '--contract-id' or '--manifest' is required",
+            ));
+          } else {
+            return rt.block_on(verify::verify(
+              port,
+              host,
+              protocol,
+              contract_id,
+              height,
+              state_hash,
+              manifest,
+              state,
+            ));
+          }
+        }
+        Flags::Diff {
+          host,
+          port,
+          protocol,
+          contract_id,
+          height,
+          other_contract_id,
+          other_height,
+          json,
+          fail_on_diff,
+        } => {
+          if contract_id.is_none() {
+            return Ok(usage("diff", "Option '--contract-id' is required"));
+          } else if other_contract_id.is_none() && other_height.is_none() {
+            return Ok(usage(
+              "diff",
+              "Option '--other-contract-id' or '--other-height' is required",
+            ));
+          } else {
+            return rt.block_on(diff::diff(
+              port,
+              host,
+              protocol,
+              contract_id.unwrap(),
+              height,
+              other_contract_id,
+              other_height,
+              json,
+              fail_on_diff,
+            ));
           }
         }
         Flags::Serve {
           server_port,
           server_host,
+          allowlist,
+          json_rpc,
+          metrics_top_contracts,
+          prewarm,
+          serve_during_prewarm,
         } => {
           let ip_addr = IpAddr::from_str(server_host.as_str());
           if let Err(_) = ip_addr {
-            print_help::print_help(Some("serve"));
-            println!("{}", "Invalid IP Address provided in '--server-host'");
+            return Ok(usage(
+              "serve",
+              "Invalid IP Address provided in '--server-host'",
+            ));
+          } else if serve_during_prewarm && prewarm.is_none() {
+            return Ok(usage(
+              "serve",
+              "Option '--serve-during-prewarm' requires '--prewarm'",
+            ));
           } else {
+            let allowlist = allowlist.map(Allowlist::load).transpose()?;
+
             // Spawn the !Send future in the currently running
             // local task set.
             let local = tokio::task::LocalSet::new();
+            let server = start_local_server(ServerConfiguration {
+              host: ip_addr.unwrap(),
+              port: server_port,
+              allowlist,
+              json_rpc,
+              contract_metrics: metrics_top_contracts
+                .map(contract_metrics::ContractMetrics::new),
+            });
             local.block_on(
               &rt,
-              start_local_server(ServerConfiguration {
-                host: ip_addr.unwrap(),
-                port: server_port,
-              }),
-            );
+              prewarm::prewarm_then_serve(
+                prewarm,
+                serve_during_prewarm,
+                async {
+                  server.await;
+                  Ok(())
+                },
+              ),
+            )?;
           }
         }
       };
     }
   }
 
-  Ok(())
+  Ok(exit_code::SUCCESS)
 }
@@ -0,0 +1,104 @@
+use crate::utils::hasher;
+use deno_core::error::AnyError;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::miscellaneous::SORT_KEY_ALGORITHM;
+use three_em_executor::{InteractionCounts, ValidityTable};
+
+/// Everything needed to reproduce a `run`.
+///
+/// Two runs with equal manifests evaluated the same source over the same
+/// interactions with the same settings. When the state hashes differ, the
+/// remaining fields point at what changed between them.
+#[derive(Serialize)]
+pub struct Manifest {
+  pub three_em_version: String,
+  pub contract_id: String,
+  pub source_tx_id: String,
+  /// Height the interactions were fetched up to.
+  pub height: usize,
+  pub gateway: String,
+  pub sort_key_algorithm: String,
+  pub cache: bool,
+  pub allow_wallet_balance: bool,
+  pub allow_unsafe_client: bool,
+  pub extended_action: bool,
+  /// Owners the interactions were filtered down to. Empty for a full run.
+  pub only_owners: Vec<String>,
+  pub interactions: InteractionCounts,
+  /// Id of the last interaction in sort-key order.
+  pub last_interaction: Option<String>,
+  pub validity_hash: String,
+  pub state_hash: String,
+}
+
+impl Manifest {
+  #[allow(clippy::too_many_arguments)]
+  pub async fn new(
+    arweave: &Arweave,
+    contract_id: String,
+    height: usize,
+    cache: bool,
+    allow_wallet_balance: bool,
+    allow_unsafe_client: bool,
+    extended_action: bool,
+    only_owners: Vec<String>,
+    interactions: InteractionCounts,
+    validity: &ValidityTable,
+    state: &Value,
+  ) -> Result<Manifest, AnyError> {
+    let source_tx_id = arweave
+      .get_transaction(&contract_id)
+      .await?
+      .get_tag("Contract-Src")?;
+
+    Ok(Manifest {
+      three_em_version: env!("CARGO_PKG_VERSION").to_string(),
+      contract_id,
+      source_tx_id,
+      height,
+      gateway: arweave.get_host(),
+      sort_key_algorithm: SORT_KEY_ALGORITHM.to_string(),
+      cache,
+      allow_wallet_balance,
+      allow_unsafe_client,
+      extended_action,
+      only_owners,
+      interactions,
+      last_interaction: validity.keys().last().cloned(),
+      validity_hash: hash_json(validity)?,
+      state_hash: hash_json(state)?,
+    })
+  }
+
+  pub fn save(&self, save_path: &str) -> Result<(), AnyError> {
+    std::fs::write(manifest_path(save_path), serde_json::to_vec_pretty(self)?)?;
+    Ok(())
+  }
+}
+
+pub fn hash_json<T: Serialize>(value: &T) -> Result<String, AnyError> {
+  Ok(hex::encode(hasher(&serde_json::to_vec(value)?)))
+}
+
+/// `state.json` gets its manifest at `state.manifest.json`.
+pub fn manifest_path(save_path: &str) -> PathBuf {
+  Path::new(save_path).with_extension("manifest.json")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::manifest::manifest_path;
+  use std::path::PathBuf;
+
+  #[tokio::test]
+  async fn test_manifest_path() {
+    assert_eq!(
+      manifest_path("out/state.json"),
+      PathBuf::from("out/state.manifest.json")
+    );
+    assert_eq!(manifest_path("state"), PathBuf::from("state.manifest.json"));
+  }
+}
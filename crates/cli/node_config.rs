@@ -0,0 +1,202 @@
+use deno_core::error::{generic_error, AnyError};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Settings of a `start` node, read from the JSON file given with
+/// `--config`. Fields left out keep the value of their flag.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NodeConfig {
+  pub host: Option<String>,
+  pub port: Option<i32>,
+  pub node_limit: Option<i32>,
+}
+
+impl NodeConfig {
+  fn validate(&self) -> Result<(), AnyError> {
+    match self.node_limit {
+      Some(node_limit) if node_limit < 8 => {
+        Err(generic_error("At least 8 nodes are needed."))
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+/// How many peers a node serves at once. Shared with the accept loop, so
+/// that a reloaded limit applies to the next connection.
+#[derive(Clone)]
+pub struct NodeLimit {
+  limit: Arc<AtomicUsize>,
+  active: Arc<AtomicUsize>,
+}
+
+/// A connection counted against a [`NodeLimit`] until dropped.
+pub struct NodeSlot {
+  active: Arc<AtomicUsize>,
+}
+
+impl Drop for NodeSlot {
+  fn drop(&mut self) {
+    self.active.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl NodeLimit {
+  pub fn new(limit: usize) -> NodeLimit {
+    NodeLimit {
+      limit: Arc::new(AtomicUsize::new(limit)),
+      active: Arc::new(AtomicUsize::new(0)),
+    }
+  }
+
+  pub fn get(&self) -> usize {
+    self.limit.load(Ordering::SeqCst)
+  }
+
+  pub fn set(&self, limit: usize) {
+    self.limit.store(limit, Ordering::SeqCst);
+  }
+
+  /// A slot for one more connection, or `None` if the node is full.
+  /// Lowering the limit doesn't drop connections already served.
+  pub fn try_acquire(&self) -> Option<NodeSlot> {
+    self
+      .active
+      .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+        (active < self.get()).then(|| active + 1)
+      })
+      .ok()?;
+    Some(NodeSlot {
+      active: self.active.clone(),
+    })
+  }
+}
+
+/// The `--config` file of a running node.
+///
+/// The file is re-read whenever it changes on disk. The node limit is
+/// applied on the fly; the host and port are bound at startup, so changes
+/// to them are rejected until the node restarts. A file that fails to parse
+/// or validate is rejected as a whole, and the last good one is kept.
+pub struct NodeConfigFile {
+  path: PathBuf,
+  config: NodeConfig,
+  /// Modification time and length of the file when it was last read.
+  version: Option<(SystemTime, u64)>,
+}
+
+impl NodeConfigFile {
+  pub fn load(path: impl Into<PathBuf>) -> Result<NodeConfigFile, AnyError> {
+    let path = path.into();
+    let version = file_version(&path);
+    let config = read_config(&path)?;
+    Ok(NodeConfigFile {
+      path,
+      config,
+      version,
+    })
+  }
+
+  pub fn config(&self) -> &NodeConfig {
+    &self.config
+  }
+
+  /// Re-reads the file if it changed, and applies its new node limit to
+  /// `limit`.
+  pub fn reload(&mut self, limit: &NodeLimit) {
+    let version = file_version(&self.path);
+    if version == self.version {
+      return;
+    }
+    self.version = version;
+
+    let config = match read_config(&self.path) {
+      Ok(config) => config,
+      Err(err) => {
+        eprintln!(
+          "Failed to reload node config {}, keeping the last one: {}",
+          self.path.display(),
+          err
+        );
+        return;
+      }
+    };
+
+    if config.host != self.config.host || config.port != self.config.port {
+      eprintln!(
+        "Ignoring the host and port of {}: the node has to be restarted to \
+         bind another address",
+        self.path.display()
+      );
+    }
+    if config.node_limit != self.config.node_limit {
+      if let Some(node_limit) = config.node_limit {
+        limit.set(node_limit as usize);
+        println!("Node limit is now {}", node_limit);
+      }
+    }
+
+    self.config = NodeConfig {
+      host: self.config.host.clone(),
+      port: self.config.port,
+      ..config
+    };
+  }
+}
+
+fn read_config(path: &Path) -> Result<NodeConfig, AnyError> {
+  let config: NodeConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+  config.validate()?;
+  Ok(config)
+}
+
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+  let metadata = fs::metadata(path).ok()?;
+  Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::node_config::{NodeConfigFile, NodeLimit};
+  use std::fs;
+
+  #[tokio::test]
+  async fn test_reload_node_limit() {
+    let path = std::env::temp_dir().join("3em_test_node_config.json");
+    fs::write(&path, r#"{ "port": 8755, "nodeLimit": 8 }"#).unwrap();
+
+    let mut file = NodeConfigFile::load(&path).unwrap();
+    let limit = NodeLimit::new(file.config().node_limit.unwrap() as usize);
+    let slots: Vec<_> = (0..8).map(|_| limit.try_acquire().unwrap()).collect();
+    assert!(limit.try_acquire().is_none());
+
+    // The new limit lets two more peers in, the port keeps its old value.
+    fs::write(&path, r#"{ "port": 9000, "nodeLimit": 10 }"#).unwrap();
+    file.reload(&limit);
+    assert_eq!(limit.get(), 10);
+    assert_eq!(file.config().port, Some(8755));
+    let more: Vec<_> = (0..2).map(|_| limit.try_acquire().unwrap()).collect();
+    assert!(limit.try_acquire().is_none());
+
+    // Slots are given back as connections close.
+    drop(more);
+    drop(slots);
+    assert!(limit.try_acquire().is_some());
+
+    // Invalid files are rejected as a whole.
+    fs::write(&path, r#"{ "port": 8755, "nodeLimit": 2 }"#).unwrap();
+    file.reload(&limit);
+    assert_eq!(limit.get(), 10);
+    fs::write(&path, "{ \"nodeLimit\": ").unwrap();
+    file.reload(&limit);
+    assert_eq!(limit.get(), 10);
+    assert_eq!(file.config().node_limit, Some(10));
+
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -1,11 +1,14 @@
 #![allow(dead_code)]
 
 use crate::utils::hasher;
+use deno_core::error::{generic_error, AnyError};
 use deno_crypto::rand::rngs::OsRng;
 use rsa::pkcs1::{
   FromRsaPrivateKey, FromRsaPublicKey, ToRsaPrivateKey, ToRsaPublicKey,
 };
-use rsa::{PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use rsa::{BigUint, PaddingScheme, PublicKey, RsaPrivateKey, RsaPublicKey};
+use serde_json::Value;
+use sha2::Sha256;
 
 pub struct GeneratedPair {
   private_key: Vec<u8>,
@@ -91,6 +94,37 @@ pub fn verify(public_key: Vec<u8>, signature: Vec<u8>, data: &str) -> bool {
   verify.is_ok()
 }
 
+/// Reads an Arweave wallet keyfile, the JWK of an RSA key.
+pub fn jwk_to_private_key(jwk: &Value) -> Result<RsaPrivateKey, AnyError> {
+  let component = |name: &str| -> Result<BigUint, AnyError> {
+    let value = jwk.get(name).and_then(Value::as_str).ok_or_else(|| {
+      generic_error(format!("Wallet keyfile is missing '{}'", name))
+    })?;
+    let bytes = base64::decode_config(value, base64::URL_SAFE_NO_PAD)?;
+    Ok(BigUint::from_bytes_be(&bytes))
+  };
+
+  let mut private_key = RsaPrivateKey::from_components(
+    component("n")?,
+    component("e")?,
+    component("d")?,
+    vec![component("p")?, component("q")?],
+  );
+  private_key.validate()?;
+  private_key.precompute()?;
+  Ok(private_key)
+}
+
+/// Signs `data` the way Arweave transactions are signed: RSA-PSS over its
+/// SHA-256 hash, with a 32 byte salt.
+pub fn sign_pss(
+  private_key: &RsaPrivateKey,
+  data: &[u8],
+) -> Result<Vec<u8>, AnyError> {
+  let scheme = PaddingScheme::new_pss_with_salt::<Sha256, _>(OsRng, 32);
+  Ok(private_key.sign(scheme, &hasher(data))?)
+}
+
 impl GeneratedPair {
   pub async fn new() -> GeneratedPair {
     generate_keypair().await
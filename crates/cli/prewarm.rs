@@ -0,0 +1,200 @@
+use crate::allowlist::parse_contract_ids;
+use deno_core::error::AnyError;
+use std::fs;
+use std::future::Future;
+use std::time::Instant;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_executor::execute_contract;
+use three_em_executor::executor::EvaluateOptions;
+
+/// Evaluates `contract_ids` one after the other, caching their states, so
+/// that the first request for each of them is a cache hit. Progress is
+/// reported on stderr. A contract that fails to evaluate is reported and
+/// left cold, its first request evaluates it again.
+///
+/// Returns how many contracts were warmed.
+pub async fn prewarm(contract_ids: &[String], arweave: &Arweave) -> usize {
+  let mut warmed = 0;
+  for (index, contract_id) in contract_ids.iter().enumerate() {
+    let start = Instant::now();
+    let result = execute_contract(
+      contract_id.to_owned(),
+      None,
+      true,
+      false,
+      None,
+      None,
+      arweave,
+      &EvaluateOptions::default(),
+    )
+    .await;
+
+    let progress = format!("{}/{}", index + 1, contract_ids.len());
+    match result {
+      Ok(_) => {
+        warmed += 1;
+        eprintln!(
+          "Prewarmed {} ({}) in {} ms",
+          contract_id,
+          progress,
+          start.elapsed().as_millis()
+        );
+      }
+      Err(err) => {
+        eprintln!("Failed to prewarm {} ({}): {}", contract_id, progress, err)
+      }
+    }
+  }
+
+  eprintln!("Prewarmed {} of {} contracts", warmed, contract_ids.len());
+  warmed
+}
+
+/// Runs `serve` once the contracts listed in the `--prewarm` file at
+/// `list` are warmed, or right away while they are with `during`. The file
+/// has the format of an allowlist; the contracts are evaluated on
+/// arweave.net, the default gateway of requests.
+pub async fn prewarm_then_serve(
+  list: Option<String>,
+  during: bool,
+  serve: impl Future<Output = Result<(), AnyError>>,
+) -> Result<(), AnyError> {
+  let contract_ids: Vec<String> = match list {
+    Some(path) => parse_contract_ids(&fs::read_to_string(path)?).collect(),
+    None => return serve.await,
+  };
+  let arweave = Arweave::new(
+    443,
+    String::from("arweave.net"),
+    String::from("https"),
+    ArweaveCache::new(),
+  )
+  .with_env_headers()?;
+
+  if during {
+    let (_, served) = tokio::join!(prewarm(&contract_ids, &arweave), serve);
+    served
+  } else {
+    prewarm(&contract_ids, &arweave).await;
+    serve.await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::prewarm::prewarm;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use three_em_arweave::arweave::{Arweave, Tag, TransactionData};
+  use three_em_executor::execute_contract_with_counts;
+  use three_em_executor::executor::EvaluateOptions;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
+
+  /// A gateway serving `prewarm-test`, a contract without interactions,
+  /// and counting the requests it gets.
+  async fn mock_gateway(requests: Arc<AtomicUsize>) -> Arweave {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let tags = [
+      (
+        "Contract-Src-Inline",
+        format!("data:application/javascript;base64,{}", source),
+      ),
+      ("Init-State", String::from(r#"{"users":[]}"#)),
+    ];
+    let transaction = serde_json::to_vec(&TransactionData {
+      id: String::from("prewarm-test"),
+      tags: tags
+        .iter()
+        .map(|(name, value)| Tag {
+          name: base64::encode_config(name, base64::URL_SAFE_NO_PAD),
+          value: base64::encode(value),
+        })
+        .collect(),
+      ..Default::default()
+    })
+    .unwrap();
+
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        requests.fetch_add(1, Ordering::SeqCst);
+        let mut request = vec![0; 4096];
+        let len = socket.read(&mut request).await.unwrap();
+        let request = String::from_utf8_lossy(&request[..len]);
+        let (status, body) = match request.split(' ').nth(1) {
+          Some("/tx/prewarm-test") => ("200 OK", transaction.clone()),
+          Some("/graphql") => (
+            "200 OK",
+            br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+              .to_vec(),
+          ),
+          Some("/info") => (
+            "200 OK",
+            serde_json::json!({
+              "network": "arweave.N.1",
+              "version": 5,
+              "release": 1,
+              "height": 100,
+              "current": "",
+              "blocks": 100,
+              "peers": 0,
+              "queue_length": 0,
+              "node_state_latency": 0,
+            })
+            .to_string()
+            .into_bytes(),
+          ),
+          _ => ("404 Not Found", vec![]),
+        };
+        let head = format!(
+          "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+          status,
+          body.len()
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(&body).await.unwrap();
+      }
+    });
+
+    Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_prewarm() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let arweave = mock_gateway(requests.clone()).await;
+
+    // A contract that fails doesn't stop the others from being warmed.
+    let contract_ids = [
+      String::from("prewarm-missing"),
+      String::from("prewarm-test"),
+    ];
+    assert_eq!(prewarm(&contract_ids, &arweave).await, 1);
+
+    // The first request is answered from the cache, without evaluating
+    // anything or reaching the gateway.
+    let fetched = requests.load(Ordering::SeqCst);
+    let (result, counts) = execute_contract_with_counts(
+      String::from("prewarm-test"),
+      None,
+      true,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.state(), Some(&serde_json::json!({ "users": [] })));
+    assert_eq!(counts.processed, 0);
+    assert_eq!(requests.load(Ordering::SeqCst), fetched);
+  }
+}
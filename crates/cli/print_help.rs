@@ -14,6 +14,19 @@ pub fn print_help(sub_command: Option<&str>) {
                 --pretty-print   Whether state result should be in JSON prettified form   (Default: false)   [boolean]
                 --show-validity   Whether validity table should be included in output   (Default: false)   [boolean]
                 --file   Path to configuration file to be used   (Required)   [string]
+                --init-state   Path to an init state file replacing the configuration's initialState   [string]
+                --template   Fill in ${NAME} placeholders of the init state file from --var, built-ins (NOW) or environment   (Default: false)   [boolean]
+                --var   Placeholder value for --template as NAME=VALUE, can be repeated   [string]
+                --mock-contract   State returned to contracts reading another contract, as ID=PATH to a JSON file, can be repeated   [string]
+                --mock-only   Fail reads of contracts without --mock-contract instead of evaluating them   (Default: false)   [boolean]
+                --arlocal   Read other contracts from an arlocal test network at localhost:1984, ignoring host, port & protocol   (Default: false)   [boolean]
+                --submit   Sign the last interaction with --wallet and post it to --contract-id on the gateway, if the dry run found it valid   (Default: false)   [boolean]
+                --wallet   Path to the wallet keyfile (JWK) --submit signs with   [string]
+                --contract-id   Contract --submit posts the interaction to   [string]
+                --fail-on-invalid   Exit with code 6 when an interaction is invalid   (Default: false)   [boolean]
+                --schema   Path to a JSON Schema the final state must match, a state that doesn't exits with code 1 and lists the violations   [string]
+                --max-display-depth   Print nested arrays and objects deeper than this as a summary, the full state is still saved   [number]
+                --max-array-len   Print only the first entries of longer arrays and objects, the full state is still saved   [number]
     "},
     "run" => indoc! {"
             three_em run [options]
@@ -25,14 +38,113 @@ pub fn print_help(sub_command: Option<&str>) {
                 --host   Gateway url to be used by Executor & SmartWeave APIs   (Default: arweave.net)   [string]
                 --port   Gateway port to be used   (Default: 443)   [string]
                 --protocol   Protocol to be used for gateway communication (Default: https)   [http|https]
+                --gateway-url   Full gateway base URL, overrides host, port & protocol (e.g. https://gw.example.com/arweave)   [string]
+                --gql-url   Base URL of a separate gateway used for GraphQL queries (Default: same as data gateway)   [string]
+                --arlocal   Evaluate against an arlocal test network, at localhost:1984 unless --gateway-url is given, tolerating its GraphQL differences   (Default: false)   [boolean]
                 --pretty-print   Whether state result should be in JSON prettified form   (Default: false)   [boolean]
                 --show-validity   Whether validity table should be included in output   (Default: false)   [boolean]
                 --no-print   Whether no output should be displayed   (Default: false)   [boolean]
                 --benchmark   Whether execution time should be displayed   (Default: false)   [boolean]
                 --no-cache   Whether cache system should be used for evaluation   (Default: true)   [boolean]
                 --show-errors   Whether exceptions thrown during evaluation should be shown   (Default: false)   [boolean]
+                --allow-wallet-balance   Expose SmartWeave.arweave.wallets.getBalance to contracts (not height-pinned)   (Default: false)   [boolean]
+                --allow-unsafe-client   Expose SmartWeave.unsafeClient to contracts (arbitrary gateway reads)   (Default: false)   [boolean]
+                --wasi-sandbox   Give WASM contracts a WASI context without capabilities, WASI calls fail the interaction (bypasses cache)   (Default: false)   [boolean]
+                --fail-on-invalid   Exit with code 6 when an interaction is invalid   (Default: false)   [boolean]
+                --schema   Path to a JSON Schema the final state must match, a state that doesn't exits with code 1 and lists the violations   [string]
                 --save   Path to file where output will be saved   [string]
+                --bytes-encoding   How WASM states that aren't JSON are printed and saved   (Default: base64)   [base64|hex]
+                --max-input-bytes   Interactions with a longer input are invalid, other values than the default bypass cache   (Default: 1048576)   [number]
+                --max-read-depth   How deep contracts reading other contracts may nest, other values than the default bypass cache   (Default: 10)   [number]
+                --max-foreign-reads   How many distinct contracts an evaluation may read, other values than the default bypass cache   (Default: 100)   [number]
+                --max-gas   Gas a metered WASM interaction may consume, contracts' own Max-Gas tag applies under it (bypasses cache)   [number]
+                --explain   Print why each interaction was rejected, skipped from cache or filtered out, to stderr   (Default: false)   [boolean]
+                --explain-all   Like --explain, also listing the interactions applied successfully   (Default: false)   [boolean]
+                --pst-summary   Print the name, ticker, total supply and top holders of a PST state instead of the state   (Default: false)   [boolean]
+                --trace-hashes   Add a trace of the state hash after each interaction to the output (slow, bypasses cache)   (Default: false)   [boolean]
+                --dump-actions   Write the action each interaction is evaluated with to this file as JSON lines, - for stderr   [string]
+                --init-state   Path to an init state file to evaluate from instead of the contract's (counterfactual, bypasses cache)   [string]
+                --template   Fill in ${NAME} placeholders of the init state file from --var, built-ins (NOW) or environment   (Default: false)   [boolean]
+                --var   Placeholder value for --template as NAME=VALUE, can be repeated   [string]
+                --genesis-interaction   The first interaction starts from an empty state and its result replaces the init state (counterfactual, bypasses cache)   (Default: false)   [boolean]
+                --run-init   Call the contract's init export on the init state before the first interaction (counterfactual, bypasses cache)   (Default: false)   [boolean]
+                --since   Only evaluate interactions after this block timestamp, on top of a cached state (falls back to a full evaluation)   [number]
+                --extended-action   Also pass the interaction id, tags and block to contracts in the action object   (Default: false)   [boolean]
+                --only-owner   Only evaluate interactions from this wallet address, can be repeated (counterfactual, bypasses cache)   [string]
+                --sample   Only evaluate this fraction of the interactions, drawn pseudo-randomly (an approximation, bypasses cache)   [number]
+                --sample-seed   Seed of the interactions --sample draws, the same seed draws the same ones   (Default: 0)   [number]
+                --alias   Another id the contract had, whose interactions are part of its history, can be repeated (bypasses cache)   [string]
+                --bounded-memory   Fetch interactions page by page after sorting their keys, to cap memory on huge contracts (bypasses cache)   (Default: false)   [boolean]
+                --compress-cache   Compress new cache entries with zstd, existing entries stay readable   (Default: false)   [boolean]
+                --concurrency-safe   Lock cache entries so that several processes can share the cache directory   (Default: false)   [boolean]
+                --cache-format   Serialization of new cache entries, existing entries stay readable   (Default: json)   [json|cbor]
+                --max-cached-state-size   States larger than this many bytes are evaluated but not cached, with a warning   [number]
+                --cache-network   Network the cache keeps entries under, instead of one derived from the gateway URL   [string]
+                --max-download-bytes   Abort the evaluation once it has downloaded more than this many bytes from the gateway   [number]
+                --manifest   Write a reproducibility manifest next to the saved output   (Default: false)   [boolean]
+                --sequential-load   Debugging aid: load the contract, then fetch interactions, instead of both at once   (Default: false)   [boolean]
+                --plan   Load the contract, fetch and sort its interactions and print what would be evaluated, without evaluating   (Default: false)   [boolean]
+                --json   Print the plan as JSON, with --plan   (Default: false)   [boolean]
+                --max-display-depth   Print nested arrays and objects deeper than this as a summary, the full state is still saved   [number]
+                --max-array-len   Print only the first entries of longer arrays and objects, the full state is still saved   [number]
                 --height   Maximum height to be evaluated   [number]
+                --confirmations   Leave out interactions in this many blocks at the tip, which a reorg could drop (bypasses cache)   (Default: 0)   [number]
+                --cache-confirmations   Evaluate to the tip, but only cache the state up to this many blocks below it   (Default: 0)   [number]
+                --as-of   Evaluate up to the last block mined at an RFC 3339 date-time, instead of --height   [string]
+    "},
+    "validate" => indoc! {"
+            three_em validate [options]
+
+            Checks that a contract loads and initializes without evaluating any interaction. Exits with a non-zero code on failure.
+
+            Options:
+                --contract-id   ID of a deployed contract to be validated   [string]
+                --source   Path to a local contract source   [string]
+                --init-state   Path to the init state of a local contract   (Required with --source)   [string]
+                --contract-type   Type of a local contract   (Default: from the source extension)   [js|wasm|evm]
+                --host   Gateway url to be used   (Default: arweave.net)   [string]
+                --port   Gateway port to be used   (Default: 443)   [string]
+                --protocol   Protocol to be used for gateway communication (Default: https)   [http|https]
+    "},
+    "lint" => indoc! {"
+            three_em lint [options]
+
+            Statically checks a JavaScript contract for non-deterministic API usage. Exits with a non-zero code if any use will diverge across nodes.
+
+            Options:
+                --file   Path to the contract source   (Required)   [string]
+                --json   Print findings as JSON   (Default: false)   [boolean]
+    "},
+    "verify" => indoc! {"
+            three_em verify [options]
+
+            Evaluates a contract from scratch and compares its state hash with a claimed one. Exits with a non-zero code on mismatch.
+
+            Options:
+                --contract-id   ID of the contract to be verified   (Required without --manifest)   [string]
+                --height   Height the claimed state was evaluated at   (Required without --manifest)   [number]
+                --state-hash   Claimed SHA-256 of the state, as in a manifest   [string]
+                --manifest   Manifest written by 'run --manifest' to read the contract, height, settings and state hash from   [string]
+                --state   Path to the claimed state, hashed if --state-hash is missing and diffed on mismatch   [string]
+                --host   Gateway url to be used   (Default: arweave.net)   [string]
+                --port   Gateway port to be used   (Default: 443)   [string]
+                --protocol   Protocol to be used for gateway communication (Default: https)   [http|https]
+    "},
+    "diff" => indoc! {"
+            three_em diff [options]
+
+            Evaluates two contracts, or one contract at two heights, and prints where the second state differs from the first.
+
+            Options:
+                --contract-id   ID of the first contract   (Required)   [string]
+                --height   Height the first contract is evaluated at   (Default: latest)   [number]
+                --other-contract-id   ID of the second contract   (Default: --contract-id)   [string]
+                --other-height   Height the second contract is evaluated at   (Default: latest)   [number]
+                --json   Print the differences as a JSON array   (Default: false)   [boolean]
+                --fail-on-diff   Exit with a non-zero code when the states differ   (Default: false)   [boolean]
+                --host   Gateway url to be used   (Default: arweave.net)   [string]
+                --port   Gateway port to be used   (Default: 443)   [string]
+                --protocol   Protocol to be used for gateway communication (Default: https)   [http|https]
     "},
     "serve" => indoc! {"
             three_em serve [options]
@@ -42,6 +154,24 @@ pub fn print_help(sub_command: Option<&str>) {
             Options:
                 --host   Host to be used by the server   (Default: 127.0.0.1)   [string]
                 --port   Port to be used by the server   (Default: 5400)   [number]
+                --allowlist   File of contract ids the server will evaluate, one per line. Reloaded on change   [string]
+                --json-rpc   Also expose a JSON-RPC 2.0 endpoint at POST /rpc   (Default: false)   [boolean]
+                --metrics-top-contracts   Also report evaluation times and interactions on /metrics for this many of the costliest contracts, the others summed up as other   [number]
+                --prewarm   File of contract ids to evaluate and cache before serving, one per line   [string]
+                --serve-during-prewarm   Serve right away, while --prewarm evaluates in the background   (Default: false)   [boolean]
+    "},
+    "start" => indoc! {"
+            three_em start [options]
+
+            Starts a node and connects to the core nodes.
+
+            Options:
+                --host   Host the node listens on   (Default: 127.0.0.1)   [string]
+                --port   Port the node listens on   (Default: 8755)   [number]
+                --node-limit   Most peers served at once, at least 8   (Default: 8)   [number]
+                --config   JSON file overriding the flags above. Its node limit is reloaded on change   [string]
+                --prewarm   File of contract ids to evaluate and cache before listening, one per line   [string]
+                --serve-during-prewarm   Listen right away, while --prewarm evaluates in the background   (Default: false)   [boolean]
     "},
     "none" | _ => indoc! {"
             three_em <command> [options]
@@ -50,6 +180,11 @@ pub fn print_help(sub_command: Option<&str>) {
                 three_em run [options]   Evaluates the latest state of a deployed contract.
                 three_em dry-run [options]   Evaluates the latest state of a local contract.
                 three_em serve [options]   Spawns a local server with an endpoint to evaluate contracts.
+                three_em start [options]   Starts a node and connects to the core nodes.
+                three_em validate [options]   Checks that a contract loads without evaluating it.
+                three_em lint [options]   Checks a JavaScript contract for non-deterministic APIs.
+                three_em verify [options]   Replays a contract and checks a claimed state hash.
+                three_em diff [options]   Compares the states of two contracts or heights.
     "},
   };
 
@@ -0,0 +1,138 @@
+use serde_json::Value;
+
+/// Holders `run --pst-summary` lists.
+const TOP_HOLDERS: usize = 10;
+
+/// The gist of a profit-sharing token state, see [`PstSummary::from_state`].
+#[derive(Debug, PartialEq)]
+pub struct PstSummary {
+  pub name: Option<String>,
+  pub ticker: String,
+  pub total_supply: u128,
+  /// Addresses holding a non-zero balance.
+  pub holders: usize,
+  /// The largest balances, largest first. Equal balances are ordered by
+  /// address, so the list is the same on every run.
+  pub top_holders: Vec<(String, u64)>,
+}
+
+impl PstSummary {
+  /// Summarizes `state` if it has the standard PST shape: a `ticker`
+  /// string, a `balances` object mapping addresses to integer amounts and
+  /// optionally a `name` string. `None` for any other state.
+  pub fn from_state(state: &Value) -> Option<PstSummary> {
+    let ticker = state.get("ticker")?.as_str()?.to_owned();
+    let name = match state.get("name") {
+      Some(name) => Some(name.as_str()?.to_owned()),
+      None => None,
+    };
+    let mut balances = state
+      .get("balances")?
+      .as_object()?
+      .iter()
+      .map(|(address, balance)| Some((address.to_owned(), balance.as_u64()?)))
+      .collect::<Option<Vec<_>>>()?;
+
+    let total_supply =
+      balances.iter().map(|(_, balance)| *balance as u128).sum();
+    balances.retain(|(_, balance)| *balance > 0);
+    let holders = balances.len();
+    balances.sort_by(|(a, a_balance), (b, b_balance)| {
+      b_balance.cmp(a_balance).then_with(|| a.cmp(b))
+    });
+    balances.truncate(TOP_HOLDERS);
+
+    Some(PstSummary {
+      name,
+      ticker,
+      total_supply,
+      holders,
+      top_holders: balances,
+    })
+  }
+
+  /// The summary as printed by `run --pst-summary`.
+  pub fn lines(&self) -> Vec<String> {
+    let mut lines = vec![
+      match &self.name {
+        Some(name) => format!("{} ({})", name, self.ticker),
+        None => self.ticker.to_owned(),
+      },
+      format!("Total supply: {}", self.total_supply),
+      format!("Holders: {}", self.holders),
+    ];
+    if !self.top_holders.is_empty() {
+      lines.push(String::from("Top holders:"));
+    }
+    for (rank, (address, balance)) in self.top_holders.iter().enumerate() {
+      lines.push(format!(
+        "  {}. {} {} ({})",
+        rank + 1,
+        address,
+        balance,
+        share(*balance, self.total_supply)
+      ));
+    }
+    lines
+  }
+}
+
+/// `balance` as a percentage of `total_supply`, rounded down to two
+/// decimals.
+fn share(balance: u64, total_supply: u128) -> String {
+  let basis_points = balance as u128 * 10_000 / total_supply.max(1);
+  format!("{}.{:02}%", basis_points / 100, basis_points % 100)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::pst::PstSummary;
+  use serde_json::json;
+
+  #[tokio::test]
+  async fn test_pst_summary() {
+    let state = json!({
+      "name": "Verto",
+      "ticker": "VRT",
+      "balances": {
+        "alice": 500,
+        "bob": 250,
+        "carol": 250,
+        "dave": 0,
+        "erin": 3,
+      },
+      "settings": [],
+    });
+
+    let summary = PstSummary::from_state(&state).unwrap();
+    assert_eq!(summary.total_supply, 1003);
+    assert_eq!(summary.holders, 4);
+    assert_eq!(
+      summary.lines(),
+      vec![
+        "Verto (VRT)",
+        "Total supply: 1003",
+        "Holders: 4",
+        "Top holders:",
+        "  1. alice 500 (49.85%)",
+        "  2. bob 250 (24.92%)",
+        "  3. carol 250 (24.92%)",
+        "  4. erin 3 (0.29%)",
+      ]
+    );
+
+    // Anything else isn't summarized.
+    assert!(PstSummary::from_state(&json!({ "counter": 1 })).is_none());
+    assert!(PstSummary::from_state(&json!({
+      "ticker": "VRT",
+      "balances": { "alice": "500" },
+    }))
+    .is_none());
+    assert!(PstSummary::from_state(&json!({
+      "name": 1,
+      "ticker": "VRT",
+      "balances": {},
+    }))
+    .is_none());
+  }
+}
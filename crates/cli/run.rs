@@ -1,11 +1,76 @@
-use deno_core::error::AnyError;
+use crate::display::{print_value, DisplayLimits};
+use crate::exit_code;
+use crate::explain;
+use crate::manifest::Manifest;
+use crate::pst::PstSummary;
+use crate::schema::{self, Schema};
+use crate::template::read_init_state;
+use crate::utils::parse_rfc3339;
+use deno_core::error::{generic_error, AnyError};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
 use std::io::Write;
-use three_em_arweave::arweave::Arweave;
+use std::sync::{Arc, Mutex};
+use three_em_arweave::arweave::{try_cache, Arweave, ARLOCAL_URL};
 use three_em_arweave::cache::ArweaveCache;
 use three_em_arweave::cache::CacheExt;
-use three_em_executor::execute_contract;
-use three_em_executor::executor::ExecuteResult;
+use three_em_arweave::cache::CacheFormat;
+use three_em_executor::execute_contract_with_counts;
+use three_em_executor::executor::{EvaluateOptions, ExecuteResult, Sample};
+use three_em_executor::trace::Trace;
+use three_em_executor::utils::JsonLimits;
+use three_em_executor::{
+  plan_contract, ActionCallback, FilterCallback, Plan, RuntimeSettings,
+};
 
+/// Writes every action built for the contract to `path`, or stderr for
+/// `-`, as a line of JSON tagged with its interaction id.
+fn dump_actions_to(path: &str) -> Result<ActionCallback, AnyError> {
+  let out: Box<dyn Write + Send> = if path == "-" {
+    Box::new(std::io::stderr())
+  } else {
+    Box::new(File::create(path)?)
+  };
+  let out = Mutex::new(out);
+  Ok(Arc::new(move |id: &str, action: &Value| {
+    let line = serde_json::json!({ "interactionId": id, "action": action });
+    // A dump that can't be written doesn't fail the evaluation.
+    let _ = writeln!(out.lock().unwrap(), "{}", line);
+  }))
+}
+
+fn print_plan(plan: &Plan) {
+  println!(
+    "Contract {} ({:?}, source {})",
+    plan.contract_id, plan.contract_type, plan.source_tx_id
+  );
+  match plan.cached {
+    Some(cached) => println!("Cached state covers {} interactions", cached),
+    None => println!("No cached state"),
+  }
+  let pending = plan
+    .interactions
+    .iter()
+    .filter(|interaction| !interaction.cached)
+    .count();
+  println!(
+    "{} interactions, {} to evaluate, in order:",
+    plan.interactions.len(),
+    pending
+  );
+  for interaction in &plan.interactions {
+    println!(
+      "  {} {}{}",
+      interaction.sort_key,
+      interaction.id,
+      if interaction.cached { " (cached)" } else { "" }
+    );
+  }
+}
+
+/// Evaluates `tx` and prints its state, returning the code to exit with,
+/// see [`exit_code`].
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
   port: i32,
@@ -21,43 +86,370 @@ pub async fn run(
   height: Option<usize>,
   no_cache: bool,
   show_errors: bool,
-) -> Result<(), AnyError> {
-  let arweave = Arweave::new(port, host, protocol, ArweaveCache::new());
+  gateway_url: Option<String>,
+  gql_url: Option<String>,
+  allow_wallet_balance: bool,
+  allow_unsafe_client: bool,
+  manifest: bool,
+  only_owners: Vec<String>,
+  aliases: Vec<String>,
+  bounded_memory: bool,
+  extended_action: bool,
+  compress_cache: bool,
+  concurrency_safe: bool,
+  cache_format: String,
+  max_cached_state_size: Option<usize>,
+  cache_network: Option<String>,
+  max_download_bytes: Option<u64>,
+  since: Option<usize>,
+  init_state: Option<String>,
+  template: bool,
+  vars: Vec<String>,
+  genesis_interaction: bool,
+  run_init: bool,
+  wasi_sandbox: bool,
+  sequential_load: bool,
+  bytes_encoding: String,
+  max_input_bytes: usize,
+  max_read_depth: Option<usize>,
+  max_foreign_reads: Option<usize>,
+  max_gas: Option<usize>,
+  explain: bool,
+  explain_all: bool,
+  pst_summary: bool,
+  trace_hashes: bool,
+  dump_actions: Option<String>,
+  plan: bool,
+  json: bool,
+  max_display_depth: Option<usize>,
+  max_array_len: Option<usize>,
+  arlocal: bool,
+  confirmations: usize,
+  cache_confirmations: usize,
+  as_of: Option<String>,
+  fail_on_invalid: bool,
+  sample: Option<Sample>,
+  schema: Option<String>,
+) -> Result<i32, AnyError> {
+  let encode_bytes: fn(&[u8]) -> String = match bytes_encoding.as_str() {
+    "base64" => |state| base64::encode(state),
+    "hex" => |state| hex::encode(state),
+    _ => {
+      return Err(generic_error(format!(
+        "Invalid '--bytes-encoding {}', expected base64 or hex",
+        bytes_encoding
+      )))
+    }
+  };
+  let init_state = init_state
+    .map(|path| read_init_state(&path, template, &vars))
+    .transpose()?;
+  let as_of = as_of.map(|as_of| parse_rfc3339(&as_of)).transpose()?;
+  let schema = schema.as_deref().map(Schema::read).transpose()?;
+  if as_of.is_some() && height.is_some() {
+    return Err(generic_error("'--as-of' and '--height' can't be combined"));
+  }
+  let cache_format = match cache_format.as_str() {
+    "json" => CacheFormat::Json,
+    "cbor" => CacheFormat::Cbor,
+    _ => {
+      return Err(generic_error(format!(
+        "Invalid '--cache-format {}', expected json or cbor",
+        cache_format
+      )))
+    }
+  };
+  let cache = ArweaveCache::new().with_format(cache_format);
+  let cache = if compress_cache {
+    cache.with_compression(None)
+  } else {
+    cache
+  };
+  let cache = if concurrency_safe {
+    cache.with_locking()
+  } else {
+    cache
+  };
+  let cache = match max_cached_state_size {
+    Some(bytes) => cache.with_max_state_size(bytes),
+    None => cache,
+  };
+  // Otherwise the gateway decides, see `Arweave::new`.
+  let cache = match cache_network {
+    Some(network) if network.is_empty() => {
+      return Err(generic_error("'--cache-network' can't be empty"))
+    }
+    Some(network) => cache.with_network(&network),
+    None => cache,
+  };
+  // With only a GraphQL endpoint configured, it serves data requests too.
+  let arweave = match gateway_url.or_else(|| gql_url.clone()) {
+    Some(url) => Arweave::new_from_url(&url, cache)?,
+    None if arlocal => Arweave::new_from_url(ARLOCAL_URL, cache)?,
+    None => Arweave::new(port, host, protocol, cache),
+  };
+  let arweave = match gql_url {
+    Some(url) => arweave.with_gql_url(&url)?,
+    None => arweave,
+  }
+  .with_env_headers()?;
+  let arweave = if arlocal {
+    arweave.with_arlocal()
+  } else {
+    arweave
+  };
+  let arweave = match max_download_bytes {
+    Some(bytes) => arweave.with_download_budget(bytes),
+    None => arweave,
+  }
+  .with_max_body_input(max_input_bytes);
+  // Block granularity: everything in the last block mined by then counts.
+  let height = match as_of {
+    Some(timestamp) => Some(arweave.get_height_at(timestamp).await?),
+    None => height,
+  };
+  // A manifest has to name the height it was evaluated at, so pin it now
+  // rather than letting the gateway pick one while fetching.
+  let height = match height {
+    None if manifest => {
+      let tip = arweave.get_network_info().await?.height;
+      Some(tip.saturating_sub(confirmations))
+    }
+    height => height,
+  };
+  let explain = explain || explain_all;
+  // Interactions `--only-owner` and `--sample` leave out, for `--explain`.
+  let filtered = Arc::new(Mutex::new(Vec::new()));
+  let on_filtered: Option<FilterCallback> = if explain {
+    let filtered = filtered.clone();
+    Some(Arc::new(move |id: &str| {
+      filtered.lock().unwrap().push(id.to_owned());
+    }))
+  } else {
+    None
+  };
+  let trace = if trace_hashes {
+    Some(Trace::new())
+  } else {
+    None
+  };
+  let on_action = dump_actions.as_deref().map(dump_actions_to).transpose()?;
   let start = std::time::Instant::now();
+  let display_limits = DisplayLimits {
+    max_depth: max_display_depth,
+    max_array_len,
+  };
+
+  let options = EvaluateOptions {
+    runtime_settings: RuntimeSettings {
+      allow_wallet_balance,
+      allow_unsafe_client,
+      ..Default::default()
+    },
+    only_owners: if only_owners.is_empty() {
+      None
+    } else {
+      Some(only_owners.iter().cloned().collect())
+    },
+    on_filtered,
+    sample,
+    on_step: trace.as_ref().map(Trace::on_step),
+    on_action,
+    bounded_memory,
+    extended_action,
+    since,
+    init_state,
+    genesis_interaction,
+    run_init,
+    wasi_sandbox,
+    sequential_load,
+    json_limits: JsonLimits {
+      max_bytes: max_input_bytes,
+      ..Default::default()
+    },
+    max_read_depth,
+    max_foreign_reads,
+    max_gas,
+    confirmations,
+    cache_confirmations,
+    aliases: if aliases.is_empty() {
+      HashMap::new()
+    } else {
+      HashMap::from([(tx.to_owned(), aliases)])
+    },
+    ..Default::default()
+  };
 
-  let execution =
-    execute_contract(tx, height, !no_cache, show_errors, None, None, &arweave)
-      .await?;
+  if plan {
+    let plan =
+      plan_contract(tx.to_owned(), height, !no_cache, &arweave, &options)
+        .await?;
+    if json {
+      println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+    } else {
+      print_plan(&plan);
+    }
+    return Ok(exit_code::SUCCESS);
+  }
+
+  if options.only_owners.is_some() {
+    eprintln!(
+      "Only evaluating interactions from {}. The result is counterfactual \
+       and bypasses the cache.",
+      only_owners.join(", ")
+    );
+  }
+  if let Some(sample) = &sample {
+    eprintln!(
+      "Only evaluating a sample of {}% of the interactions (seed {}). The \
+       result is an approximation, not the contract's state, and bypasses \
+       the cache.",
+      sample.fraction * 100.0,
+      sample.seed
+    );
+  }
+
+  let (execution, counts) = execute_contract_with_counts(
+    tx.to_owned(),
+    height,
+    !no_cache,
+    show_errors,
+    None,
+    None,
+    &arweave,
+    &options,
+  )
+  .await?;
 
   if benchmark {
     let elapsed = start.elapsed();
     println!("Took {}ms to execute contract", elapsed.as_millis());
+    println!(
+      "Processed {} interactions, {} skipped from cache ({} total)",
+      counts.processed, counts.skipped_from_cache, counts.total
+    );
+    println!("Downloaded {} bytes", arweave.downloaded_bytes());
+    if let Some(stats) = try_cache(|cache| Ok(cache.stats())) {
+      println!(
+        "Cached state lookups: {} hits, {} misses",
+        stats.hits, stats.misses
+      );
+    }
+  }
+
+  if !counts.is_authoritative() {
+    eprintln!(
+      "{} interactions timed out. Whether they do depends on the node, so \
+       the result may differ on another one and wasn't cached.",
+      counts.timed_out
+    );
   }
 
+  let has_invalid = execution
+    .validity()
+    .values()
+    .any(|valid| valid != &Value::Bool(true));
+
+  // On stderr, so that the state printed to stdout stays parseable.
+  if explain {
+    let filtered = filtered.lock().unwrap();
+    let outcomes = explain::outcomes(execution.validity(), &counts, &filtered);
+    for line in explain::explain(&outcomes, explain_all) {
+      eprintln!("{}", line);
+    }
+  }
+
+  if schema.is_some() && !matches!(execution, ExecuteResult::V8(..)) {
+    return Err(generic_error(
+      "Only JSON states can be checked against '--schema'",
+    ));
+  }
+
+  let execution = match execution {
+    ExecuteResult::Bytes(state, validity_table) => {
+      ExecuteResult::V8(Value::String(encode_bytes(&state)), validity_table)
+    }
+    execution => execution,
+  };
+
   match execution {
-    ExecuteResult::V8(value, validity_table) => {
-      let value = if show_validity {
+    ExecuteResult::V8(state, validity_table) => {
+      let mut value = if show_validity {
         serde_json::json!({
-            "state": value,
-            "validity": validity_table
+            "state": state,
+            "validity": validity_table,
+            "interactions": counts
         })
+      } else if trace.is_some() {
+        serde_json::json!({ "state": state })
+      } else {
+        state.clone()
+      };
+      if let Some(trace) = &trace {
+        value["trace"] = serde_json::to_value(trace.entries())?;
+      }
+      // So that a saved sampled result can't pass for the real one.
+      if let (true, Some(sample)) = (show_validity, &sample) {
+        value["sample"] = serde_json::json!({
+          "fraction": sample.fraction,
+          "seed": sample.seed,
+        });
+      }
+
+      let summary = if pst_summary {
+        let summary = PstSummary::from_state(&state);
+        if summary.is_none() {
+          eprintln!(
+            "The state isn't a PST (no ticker and integer balances), \
+             printing it in full"
+          );
+        }
+        summary
       } else {
-        value
+        None
       };
 
       if !no_print {
-        if pretty_print {
-          println!("{}", serde_json::to_string_pretty(&value).unwrap());
-        } else {
-          println!("{}", value);
+        match summary {
+          Some(summary) => {
+            for line in summary.lines() {
+              println!("{}", line);
+            }
+          }
+          None => print_value(&value, pretty_print, &display_limits),
         }
       }
 
       if save {
-        let mut file = std::fs::File::create(save_path).unwrap();
+        let mut file = std::fs::File::create(&save_path).unwrap();
         file
           .write_all(serde_json::to_vec(&value).unwrap().as_slice())
           .unwrap();
+
+        if manifest {
+          Manifest::new(
+            &arweave,
+            tx,
+            height.unwrap(),
+            !no_cache,
+            allow_wallet_balance,
+            allow_unsafe_client,
+            extended_action,
+            only_owners,
+            counts,
+            &validity_table,
+            &state,
+          )
+          .await?
+          .save(&save_path)?;
+        }
+      }
+
+      // After printing and saving, so that the state can be looked into.
+      if let Some(schema) = &schema {
+        if !schema::report(schema, &state) {
+          return Ok(exit_code::FAILURE);
+        }
       }
     }
     ExecuteResult::Evm(store, result, validity_table) => {
@@ -68,7 +460,8 @@ pub async fn run(
         serde_json::json!({
           "result": result,
           "store": store,
-          "validity": validity_table
+          "validity": validity_table,
+          "interactions": counts
         })
       } else {
         serde_json::json!({
@@ -78,14 +471,14 @@ pub async fn run(
       };
 
       if !no_print {
-        if pretty_print {
-          println!("{}", serde_json::to_string_pretty(&value).unwrap());
-        } else {
-          println!("{}", value);
-        }
+        print_value(&value, pretty_print, &display_limits);
       }
     }
+    ExecuteResult::Bytes(..) => unreachable!(),
   }
 
-  Ok(())
+  if fail_on_invalid && has_invalid {
+    return Ok(exit_code::INVALID_INTERACTIONS);
+  }
+  Ok(exit_code::SUCCESS)
 }
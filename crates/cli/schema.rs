@@ -0,0 +1,624 @@
+//! Checks states against a JSON Schema, for `--schema`.
+//!
+//! Covers the keywords state shapes are described with: `type`, `enum`,
+//! `const`, the object, array, number and string constraints, the
+//! `allOf`/`anyOf`/`oneOf`/`not` combinators and `$ref`s within the schema
+//! (`#/definitions/...`, `#/$defs/...`). As the spec asks, other keywords
+//! are ignored rather than rejected.
+
+use deno_core::error::{generic_error, AnyError};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Prints every place `state` doesn't match `schema` to stderr, and
+/// returns whether it matches.
+pub fn report(schema: &Schema, state: &Value) -> bool {
+  let violations = schema.validate(state);
+  if !violations.is_empty() {
+    eprintln!("The state doesn't match the schema:");
+    for violation in &violations {
+      eprintln!("  {}", violation);
+    }
+  }
+  violations.is_empty()
+}
+
+/// How deep subschemas and `$ref`s may nest, so that a `$ref` cycle
+/// fails instead of recursing forever.
+const MAX_DEPTH: usize = 64;
+
+/// How far `number / multipleOf` may be from a whole number and still count
+/// as one, relative to its size: 0.3 / 0.1 is 2.9999999999999996.
+const MULTIPLE_TOLERANCE: f64 = 1e-9;
+
+/// A place where a state doesn't match the schema.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+  /// JSON Pointer to the failing value, empty for the whole state.
+  pub path: String,
+  pub message: String,
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let path = if self.path.is_empty() {
+      "(root)"
+    } else {
+      &self.path
+    };
+    write!(f, "{}: {}", path, self.message)
+  }
+}
+
+pub struct Schema {
+  root: Value,
+  /// Every `pattern` and `patternProperties` key, compiled up front.
+  patterns: HashMap<String, Regex>,
+}
+
+impl Schema {
+  /// Fails on patterns the `regex` crate can't parse, such as ECMA 262
+  /// lookarounds, rather than reporting them against every state.
+  pub fn new(root: Value) -> Result<Schema, AnyError> {
+    if !root.is_object() && !root.is_boolean() {
+      return Err(generic_error("A schema must be an object or a boolean"));
+    }
+    let mut schema = Schema {
+      root,
+      patterns: HashMap::new(),
+    };
+    let mut patterns = HashMap::new();
+    schema.compile(&schema.root, &mut HashSet::new(), &mut patterns)?;
+    schema.patterns = patterns;
+    Ok(schema)
+  }
+
+  /// Reads the schema at `path`.
+  pub fn read(path: &str) -> Result<Schema, AnyError> {
+    let schema = std::fs::read_to_string(path).map_err(|err| {
+      generic_error(format!("Unable to read schema {}: {}", path, err))
+    })?;
+    let schema = serde_json::from_str(&schema).map_err(|err| {
+      generic_error(format!("Schema {} is not valid JSON: {}", path, err))
+    })?;
+    Schema::new(schema)
+      .map_err(|err| generic_error(format!("Schema {}: {}", path, err)))
+  }
+
+  /// Every place `value` doesn't match the schema, sorted by path. Empty if
+  /// it conforms.
+  pub fn validate(&self, value: &Value) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    self.check(&self.root, value, "", 0, &mut violations);
+    violations.sort_by(|a, b| a.path.cmp(&b.path));
+    violations
+  }
+
+  fn check(
+    &self,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    depth: usize,
+    violations: &mut Vec<Violation>,
+  ) {
+    let mut fail = |message: String| {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message,
+      })
+    };
+    if depth > MAX_DEPTH {
+      fail(String::from(
+        "the schema nests too deeply, is a $ref cyclic?",
+      ));
+      return;
+    }
+    let schema = match schema {
+      Value::Bool(true) => return,
+      Value::Bool(false) => {
+        fail(String::from("no value is allowed here"));
+        return;
+      }
+      Value::Object(schema) => schema,
+      _ => {
+        fail(String::from("the schema isn't an object or a boolean"));
+        return;
+      }
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+      match self.resolve(reference) {
+        Some(target) => self.check(target, value, path, depth + 1, violations),
+        None => violations.push(Violation {
+          path: path.to_owned(),
+          message: format!("unresolvable $ref {}", reference),
+        }),
+      }
+    }
+
+    self.check_value(schema, value, path, violations);
+    match value {
+      Value::Object(object) => {
+        self.check_object(schema, object, path, depth, violations)
+      }
+      Value::Array(array) => {
+        self.check_array(schema, array, path, depth, violations)
+      }
+      _ => {}
+    }
+    self.check_combinators(schema, value, path, depth, violations);
+  }
+
+  /// Compiles the patterns of `schema` and of every subschema it reaches,
+  /// following each `$ref` once.
+  fn compile<'a>(
+    &'a self,
+    schema: &'a Value,
+    references: &mut HashSet<&'a str>,
+    patterns: &mut HashMap<String, Regex>,
+  ) -> Result<(), AnyError> {
+    let schema = match schema {
+      Value::Object(schema) => schema,
+      _ => return Ok(()),
+    };
+    let mut compile_pattern = |pattern: &str| {
+      if !patterns.contains_key(pattern) {
+        let regex = Regex::new(pattern).map_err(|err| {
+          generic_error(format!("Invalid pattern {}: {}", pattern, err))
+        })?;
+        patterns.insert(pattern.to_owned(), regex);
+      }
+      Ok::<_, AnyError>(())
+    };
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+      compile_pattern(pattern)?;
+    }
+    if let Some(Value::Object(properties)) = schema.get("patternProperties") {
+      for pattern in properties.keys() {
+        compile_pattern(pattern)?;
+      }
+    }
+
+    let mut children: Vec<&Value> = Vec::new();
+    for keyword in ["properties", "patternProperties", "definitions", "$defs"] {
+      if let Some(Value::Object(map)) = schema.get(keyword) {
+        children.extend(map.values());
+      }
+    }
+    for keyword in ["items", "allOf", "anyOf", "oneOf"] {
+      match schema.get(keyword) {
+        Some(Value::Array(subschemas)) => children.extend(subschemas),
+        Some(subschema) => children.push(subschema),
+        None => {}
+      }
+    }
+    for keyword in ["additionalProperties", "additionalItems", "not"] {
+      children.extend(schema.get(keyword));
+    }
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+      if references.insert(reference) {
+        children.extend(self.resolve(reference));
+      }
+    }
+    for child in children {
+      self.compile(child, references, patterns)?;
+    }
+    Ok(())
+  }
+
+  /// `#` or a JSON Pointer into the schema, `#/definitions/balance`.
+  fn resolve(&self, reference: &str) -> Option<&Value> {
+    self.root.pointer(reference.strip_prefix('#')?)
+  }
+
+  /// The keywords about `value` itself: its type, enum, const and number
+  /// and string constraints.
+  fn check_value(
+    &self,
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+    violations: &mut Vec<Violation>,
+  ) {
+    let mut fail = |message: String| {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message,
+      })
+    };
+
+    if let Some(types) = schema.get("type") {
+      let types: Vec<&str> = match types {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+      };
+      if !types.is_empty() && !types.iter().any(|name| is_type(value, name)) {
+        fail(format!(
+          "expected {}, got {}",
+          types.join(" or "),
+          type_name(value)
+        ));
+      }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+      if !allowed.contains(value) {
+        fail(format!(
+          "{} is not one of {}",
+          value,
+          Value::from(&allowed[..])
+        ));
+      }
+    }
+    if let Some(constant) = schema.get("const") {
+      if constant != value {
+        fail(format!("expected {}, got {}", constant, value));
+      }
+    }
+
+    if let Some(number) = value.as_f64() {
+      let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+      if let Some(minimum) = bound("minimum").filter(|min| number < *min) {
+        fail(format!("{} is less than the minimum {}", value, minimum));
+      }
+      if let Some(maximum) = bound("maximum").filter(|max| number > *max) {
+        fail(format!("{} is more than the maximum {}", value, maximum));
+      }
+      if let Some(minimum) =
+        bound("exclusiveMinimum").filter(|min| number <= *min)
+      {
+        fail(format!("{} is not more than {}", value, minimum));
+      }
+      if let Some(maximum) =
+        bound("exclusiveMaximum").filter(|max| number >= *max)
+      {
+        fail(format!("{} is not less than {}", value, maximum));
+      }
+      if let Some(divisor) = bound("multipleOf")
+        .filter(|divisor| *divisor > 0.0 && !is_multiple(number, *divisor))
+      {
+        fail(format!("{} is not a multiple of {}", value, divisor));
+      }
+    }
+
+    if let Value::String(string) = value {
+      let length = string.chars().count() as u64;
+      let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+      if let Some(min) = bound("minLength").filter(|min| length < *min) {
+        fail(format!("is shorter than {} characters", min));
+      }
+      if let Some(max) = bound("maxLength").filter(|max| length > *max) {
+        fail(format!("is longer than {} characters", max));
+      }
+      if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        let regex = self.patterns.get(pattern);
+        if regex.map_or(false, |regex| !regex.is_match(string)) {
+          fail(format!("{} doesn't match the pattern {}", value, pattern));
+        }
+      }
+    }
+  }
+
+  fn check_object(
+    &self,
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    depth: usize,
+    violations: &mut Vec<Violation>,
+  ) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+      for key in required.iter().filter_map(Value::as_str) {
+        if !object.contains_key(key) {
+          violations.push(Violation {
+            path: path.to_owned(),
+            message: format!("missing required property {:?}", key),
+          });
+        }
+      }
+    }
+    let count = object.len() as u64;
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+    if let Some(min) = bound("minProperties").filter(|min| count < *min) {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message: format!("has fewer than {} properties", min),
+      });
+    }
+    if let Some(max) = bound("maxProperties").filter(|max| count > *max) {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message: format!("has more than {} properties", max),
+      });
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let patterns: Vec<(&Regex, &Value)> = match schema.get("patternProperties")
+    {
+      Some(Value::Object(patterns)) => patterns
+        .iter()
+        .filter_map(|(pattern, schema)| {
+          Some((self.patterns.get(pattern)?, schema))
+        })
+        .collect(),
+      _ => vec![],
+    };
+    for (key, property) in object {
+      let property_path = format!("{}/{}", path, escape(key));
+      let mut matched = false;
+      if let Some(schema) =
+        properties.and_then(|properties| properties.get(key))
+      {
+        matched = true;
+        self.check(schema, property, &property_path, depth + 1, violations);
+      }
+      for (_, schema) in
+        patterns.iter().filter(|(regex, _)| regex.is_match(key))
+      {
+        matched = true;
+        self.check(schema, property, &property_path, depth + 1, violations);
+      }
+      match schema.get("additionalProperties") {
+        Some(Value::Bool(false)) if !matched => violations.push(Violation {
+          path: property_path,
+          message: String::from("is not an allowed property"),
+        }),
+        Some(additional) if !matched => self.check(
+          additional,
+          property,
+          &property_path,
+          depth + 1,
+          violations,
+        ),
+        _ => {}
+      }
+    }
+  }
+
+  fn check_array(
+    &self,
+    schema: &Map<String, Value>,
+    array: &[Value],
+    path: &str,
+    depth: usize,
+    violations: &mut Vec<Violation>,
+  ) {
+    let count = array.len() as u64;
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+    if let Some(min) = bound("minItems").filter(|min| count < *min) {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message: format!("has fewer than {} items", min),
+      });
+    }
+    if let Some(max) = bound("maxItems").filter(|max| count > *max) {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message: format!("has more than {} items", max),
+      });
+    }
+    if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+      for (index, item) in array.iter().enumerate() {
+        if array[..index].contains(item) {
+          violations.push(Violation {
+            path: format!("{}/{}", path, index),
+            message: format!("{} is a duplicate item", item),
+          });
+        }
+      }
+    }
+
+    for (index, item) in array.iter().enumerate() {
+      // `items` as an array describes each item by position, with the
+      // rest described by `additionalItems`.
+      let item_schema = match schema.get("items") {
+        Some(Value::Array(tuple)) => {
+          tuple.get(index).or_else(|| schema.get("additionalItems"))
+        }
+        items => items,
+      };
+      if let Some(item_schema) = item_schema {
+        let item_path = format!("{}/{}", path, index);
+        self.check(item_schema, item, &item_path, depth + 1, violations);
+      }
+    }
+  }
+
+  fn check_combinators(
+    &self,
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+    depth: usize,
+    violations: &mut Vec<Violation>,
+  ) {
+    let matches = |subschema: &Value| {
+      let mut inner = Vec::new();
+      self.check(subschema, value, path, depth + 1, &mut inner);
+      inner.is_empty()
+    };
+
+    for subschema in subschemas(schema, "allOf") {
+      self.check(subschema, value, path, depth + 1, violations);
+    }
+    let any_of = subschemas(schema, "anyOf");
+    if !any_of.is_empty() && !any_of.iter().any(matches) {
+      violations.push(Violation {
+        path: path.to_owned(),
+        message: String::from("matches none of the anyOf schemas"),
+      });
+    }
+    let one_of = subschemas(schema, "oneOf");
+    if !one_of.is_empty() {
+      let matching =
+        one_of.iter().filter(|subschema| matches(subschema)).count();
+      if matching != 1 {
+        violations.push(Violation {
+          path: path.to_owned(),
+          message: format!(
+            "matches {} of the oneOf schemas instead of exactly one",
+            matching
+          ),
+        });
+      }
+    }
+    if let Some(not) = schema.get("not") {
+      if matches(not) {
+        violations.push(Violation {
+          path: path.to_owned(),
+          message: String::from("matches the schema it must not"),
+        });
+      }
+    }
+  }
+}
+
+/// The schemas listed under `keyword`, like `anyOf`.
+fn subschemas<'a>(
+  schema: &'a Map<String, Value>,
+  keyword: &str,
+) -> &'a [Value] {
+  match schema.get(keyword) {
+    Some(Value::Array(subschemas)) => subschemas,
+    _ => &[],
+  }
+}
+
+fn is_multiple(number: f64, divisor: f64) -> bool {
+  let quotient = number / divisor;
+  (quotient - quotient.round()).abs()
+    <= MULTIPLE_TOLERANCE * quotient.abs().max(1.0)
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+  match name {
+    "null" => value.is_null(),
+    "boolean" => value.is_boolean(),
+    "object" => value.is_object(),
+    "array" => value.is_array(),
+    "string" => value.is_string(),
+    "number" => value.is_number(),
+    // 1.0 is an integer too.
+    "integer" => value.as_f64().map_or(false, |number| number.fract() == 0.0),
+    _ => false,
+  }
+}
+
+fn type_name(value: &Value) -> &'static str {
+  match value {
+    Value::Null => "null",
+    Value::Bool(_) => "boolean",
+    Value::Object(_) => "object",
+    Value::Array(_) => "array",
+    Value::String(_) => "string",
+    Value::Number(_) => "number",
+  }
+}
+
+/// A key as a JSON Pointer reference token.
+fn escape(key: &str) -> String {
+  key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::schema::Schema;
+  use serde_json::json;
+
+  #[tokio::test]
+  async fn test_schema() {
+    let schema = Schema::new(json!({
+      "type": "object",
+      "required": ["ticker", "balances"],
+      "properties": {
+        "ticker": { "type": "string", "pattern": "^[A-Z]+$" },
+        "balances": {
+          "type": "object",
+          "additionalProperties": { "$ref": "#/definitions/balance" }
+        },
+        "owners": {
+          "type": "array",
+          "items": { "type": "string" },
+          "uniqueItems": true
+        }
+      },
+      "additionalProperties": false,
+      "definitions": {
+        "balance": { "type": "integer", "minimum": 0 }
+      }
+    }))
+    .unwrap();
+
+    let conforming = json!({
+      "ticker": "PST",
+      "balances": { "alice": 100, "bob": 0 },
+      "owners": ["alice"]
+    });
+    assert!(schema.validate(&conforming).is_empty());
+
+    let drifted = json!({
+      "ticker": "pst",
+      "balances": { "alice": -1, "b/ob": "100" },
+      "owners": ["alice", "alice", 3],
+      "extra": true
+    });
+    let violations = schema
+      .validate(&drifted)
+      .iter()
+      .map(ToString::to_string)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      violations,
+      [
+        "/balances/alice: -1 is less than the minimum 0",
+        "/balances/b~1ob: expected integer, got string",
+        "/extra: is not an allowed property",
+        "/owners/1: \"alice\" is a duplicate item",
+        "/owners/2: expected string, got number",
+        "/ticker: \"pst\" doesn't match the pattern ^[A-Z]+$",
+      ]
+    );
+
+    let missing = schema.validate(&json!({ "ticker": "PST" }));
+    assert_eq!(
+      missing[0].to_string(),
+      "(root): missing required property \"balances\""
+    );
+
+    // A cyclic $ref fails instead of overflowing the stack.
+    let cyclic = Schema::new(json!({ "$ref": "#" })).unwrap();
+    assert_eq!(cyclic.validate(&json!(1)).len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_multiple_of() {
+    let schema = Schema::new(json!({ "multipleOf": 0.1 })).unwrap();
+    assert!(schema.validate(&json!(0.3)).is_empty());
+    assert!(schema.validate(&json!(12.7)).is_empty());
+    assert_eq!(schema.validate(&json!(0.35)).len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_invalid_pattern() {
+    // Lookaheads are ECMA 262 but not supported by the regex crate.
+    let err = Schema::new(json!({
+      "properties": { "ticker": { "pattern": "^(?=[A-Z])" } }
+    }))
+    .err()
+    .unwrap();
+    assert!(err.to_string().starts_with("Invalid pattern ^(?=[A-Z])"));
+
+    let err = Schema::new(json!({
+      "additionalProperties": { "$ref": "#/definitions/balances" },
+      "definitions": {
+        "balances": { "patternProperties": { "(?<=a)b": true } }
+      }
+    }))
+    .err()
+    .unwrap();
+    assert!(err.to_string().starts_with("Invalid pattern (?<=a)b"));
+  }
+}
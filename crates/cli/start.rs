@@ -1,12 +1,15 @@
 use crate::core_nodes::get_core_nodes;
 use crate::messages::get_addr::get_addr;
 use crate::node::{send_message, Node};
+use crate::node_config::{NodeConfigFile, NodeLimit};
+use crate::prewarm::prewarm_then_serve;
 use crate::utils::u8_array_to_usize;
 use deno_core::error::AnyError;
 use deno_core::futures::stream::unfold;
 use deno_core::futures::stream::Stream;
 use deno_core::futures::StreamExt;
 use std::pin::Pin;
+use std::time::Duration;
 use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
@@ -66,10 +69,48 @@ async fn send_discovery(nodes: &[Node]) {
   }
 }
 
+/// How often the `--config` file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 pub async fn start(
   host: String,
   port: i32,
-  _node_capacitiy: i32,
+  node_capacity: i32,
+  config: Option<String>,
+  prewarm: Option<String>,
+  serve_during_prewarm: bool,
+) -> Result<(), AnyError> {
+  let config = config.map(NodeConfigFile::load).transpose()?;
+  let (host, port, node_capacity) = match &config {
+    Some(file) => (
+      file.config().host.clone().unwrap_or(host),
+      file.config().port.unwrap_or(port),
+      file.config().node_limit.unwrap_or(node_capacity),
+    ),
+    None => (host, port, node_capacity),
+  };
+
+  let limit = NodeLimit::new(node_capacity as usize);
+  if let Some(mut file) = config {
+    let limit = limit.clone();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+      loop {
+        interval.tick().await;
+        file.reload(&limit);
+      }
+    });
+  }
+
+  prewarm_then_serve(prewarm, serve_during_prewarm, serve(host, port, limit))
+    .await
+}
+
+/// Connects to the core nodes and accepts peers up to `limit`.
+async fn serve(
+  host: String,
+  port: i32,
+  limit: NodeLimit,
 ) -> Result<(), AnyError> {
   let specifier = format!("{}:{}", host, port);
   let this_node = Node::new(&host, port);
@@ -87,8 +128,14 @@ pub async fn start(
 
   loop {
     let (socket, _) = listener.accept().await?;
+    // A full node turns peers away, they can connect to another one.
+    let slot = match limit.try_acquire() {
+      Some(slot) => slot,
+      None => continue,
+    };
 
     tokio::task::spawn(async move {
+      let _slot = slot;
       #[allow(clippy::for_loops_over_fallibles)]
       for data in handle_node(socket).next().await {
         process(data).await;
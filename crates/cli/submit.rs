@@ -0,0 +1,248 @@
+//! Signing and posting interactions, for `dry-run --submit`.
+//!
+//! Transactions are built in format 2 with no data and no transfer, and
+//! signed over their deep hash like arweave-js does.
+
+use crate::node_crypto::sign_pss;
+use crate::utils::hasher;
+use deno_core::error::AnyError;
+use rsa::{PublicKeyParts, RsaPrivateKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha384};
+use three_em_arweave::arweave::Arweave;
+
+/// `App-Version` of the interactions, the SmartWeave release whose tags
+/// they follow.
+pub const SMARTWEAVE_VERSION: &str = "0.3.0";
+
+/// A field of a transaction, as it goes into [`deep_hash`].
+pub enum DeepHashChunk {
+  Blob(Vec<u8>),
+  List(Vec<DeepHashChunk>),
+}
+
+fn sha384(parts: &[&[u8]]) -> Vec<u8> {
+  let mut hasher = Sha384::new();
+  for part in parts {
+    hasher.update(part);
+  }
+  hasher.finalize().to_vec()
+}
+
+/// Arweave's hash of nested byte strings, tagged with their kind and
+/// length so that no two structures hash the same.
+pub fn deep_hash(chunk: &DeepHashChunk) -> Vec<u8> {
+  match chunk {
+    DeepHashChunk::Blob(data) => {
+      let tag = format!("blob{}", data.len());
+      sha384(&[&sha384(&[tag.as_bytes()]), &sha384(&[data])])
+    }
+    DeepHashChunk::List(chunks) => {
+      let tag = format!("list{}", chunks.len());
+      chunks.iter().fold(sha384(&[tag.as_bytes()]), |acc, chunk| {
+        sha384(&[&acc, &deep_hash(chunk)])
+      })
+    }
+  }
+}
+
+fn encode(data: &[u8]) -> String {
+  base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+/// Address of the wallet `wallet` signs for.
+pub fn wallet_address(wallet: &RsaPrivateKey) -> String {
+  encode(&hasher(&wallet.n().to_bytes_be()))
+}
+
+/// Tags SmartWeave reads an interaction with `input` to `contract_id` from.
+pub fn interaction_tags(
+  contract_id: &str,
+  input: &Value,
+) -> Vec<(String, String)> {
+  vec![
+    (String::from("App-Name"), String::from("SmartWeaveAction")),
+    (
+      String::from("App-Version"),
+      String::from(SMARTWEAVE_VERSION),
+    ),
+    (String::from("Contract"), contract_id.to_owned()),
+    (String::from("Input"), input.to_string()),
+  ]
+}
+
+/// Builds a transaction with `tags` and signs it with `wallet`. Returns its
+/// id along with it, as `/tx` takes it.
+pub fn sign_transaction(
+  wallet: &RsaPrivateKey,
+  last_tx: &str,
+  reward: &str,
+  tags: &[(String, String)],
+) -> Result<(String, Value), AnyError> {
+  use DeepHashChunk::{Blob, List};
+
+  let owner = wallet.n().to_bytes_be();
+  let anchor = base64::decode_config(last_tx, base64::URL_SAFE_NO_PAD)?;
+  let signature_data = deep_hash(&List(vec![
+    Blob(b"2".to_vec()),
+    Blob(owner.clone()),
+    // Target and quantity, nothing is transferred.
+    Blob(vec![]),
+    Blob(b"0".to_vec()),
+    Blob(reward.as_bytes().to_vec()),
+    Blob(anchor),
+    List(
+      tags
+        .iter()
+        .map(|(name, value)| {
+          List(vec![
+            Blob(name.as_bytes().to_vec()),
+            Blob(value.as_bytes().to_vec()),
+          ])
+        })
+        .collect(),
+    ),
+    // Data size and root, there is no data.
+    Blob(b"0".to_vec()),
+    Blob(vec![]),
+  ]));
+
+  let signature = sign_pss(wallet, &signature_data)?;
+  let id = encode(&hasher(&signature));
+  let tags: Vec<Value> = tags
+    .iter()
+    .map(|(name, value)| {
+      json!({
+        "name": encode(name.as_bytes()),
+        "value": encode(value.as_bytes()),
+      })
+    })
+    .collect();
+  let transaction = json!({
+    "format": 2,
+    "id": id,
+    "last_tx": last_tx,
+    "owner": encode(&owner),
+    "tags": tags,
+    "target": "",
+    "quantity": "0",
+    "data": "",
+    "data_size": "0",
+    "data_root": "",
+    "reward": reward,
+    "signature": encode(&signature),
+  });
+  Ok((id, transaction))
+}
+
+/// Signs an interaction with `input` to `contract_id` and posts it to the
+/// gateway of `arweave`. Returns the id of the transaction.
+pub async fn submit_interaction(
+  arweave: &Arweave,
+  wallet: &RsaPrivateKey,
+  contract_id: &str,
+  input: &Value,
+) -> Result<String, AnyError> {
+  let last_tx = arweave.get_tx_anchor().await?;
+  let reward = arweave.get_price(0).await?;
+  let tags = interaction_tags(contract_id, input);
+  let (id, transaction) =
+    sign_transaction(wallet, last_tx.trim(), reward.trim(), &tags)?;
+  arweave.post_transaction(&transaction).await?;
+  Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::submit::{deep_hash, DeepHashChunk};
+
+  #[tokio::test]
+  async fn test_deep_hash() {
+    use DeepHashChunk::{Blob, List};
+
+    assert_eq!(
+      hex::encode(deep_hash(&Blob(vec![]))),
+      "fbf00cc444f5fea9dc3bedf62a13fba8ae87e7445fc910567a23bec4\
+       eb82fadb1143c433069314d8362983dc3c2e4a38"
+    );
+
+    // A list hashes differently from its only element, and from the
+    // list of its element's halves.
+    let blob = deep_hash(&Blob(b"ab".to_vec()));
+    let list = deep_hash(&List(vec![Blob(b"ab".to_vec())]));
+    let split =
+      deep_hash(&List(vec![Blob(b"a".to_vec()), Blob(b"b".to_vec())]));
+    assert_ne!(blob, list);
+    assert_ne!(list, split);
+  }
+
+  // Submits to a spawned arlocal, when `arlocal` is on the PATH:
+  // `cargo test -p three_em_cli --features arlocal-tests`.
+  #[cfg(feature = "arlocal-tests")]
+  #[tokio::test]
+  async fn test_submit_interaction() {
+    use crate::submit::{submit_interaction, wallet_address};
+    use deno_crypto::rand::rngs::OsRng;
+    use rsa::RsaPrivateKey;
+    use serde_json::json;
+    use std::io::ErrorKind;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+    use three_em_arweave::arweave::Arweave;
+
+    let port = 1986;
+    let mut arlocal = match Command::new("arlocal")
+      .args([&port.to_string(), "--hidelogs"])
+      .stdout(Stdio::null())
+      .spawn()
+    {
+      Ok(child) => child,
+      Err(err) if err.kind() == ErrorKind::NotFound => {
+        eprintln!("arlocal is not installed, skipping");
+        return;
+      }
+      Err(err) => panic!("Unable to start arlocal: {}", err),
+    };
+
+    let url = format!("http://localhost:{}", port);
+    let client = reqwest::Client::new();
+    for _ in 0..50 {
+      if client.get(format!("{}/info", url)).send().await.is_ok() {
+        break;
+      }
+      tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    let wallet = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+    client
+      .get(format!(
+        "{}/mint/{}/1000000000000",
+        url,
+        wallet_address(&wallet)
+      ))
+      .send()
+      .await
+      .unwrap();
+
+    let arweave = Arweave::new_from_url_no_cache(&url).unwrap().with_arlocal();
+    let contract_id = "submit-test-contract";
+    let input = json!({ "function": "add", "name": "Divy" });
+    let id = submit_interaction(&arweave, &wallet, contract_id, &input).await;
+    client.get(format!("{}/mine", url)).send().await.unwrap();
+    let interactions = arweave
+      .get_interactions(contract_id.to_owned(), None, false)
+      .await;
+    let _ = arlocal.kill();
+
+    let id = id.unwrap();
+    let (interactions, _, _) = interactions.unwrap();
+    assert_eq!(interactions.len(), 1);
+    let node = &interactions[0].node;
+    assert_eq!(node.id, id);
+    let tag = node.tags.iter().find(|tag| tag.name == "Input").unwrap();
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&tag.value).unwrap(),
+      input
+    );
+  }
+}
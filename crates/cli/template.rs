@@ -0,0 +1,158 @@
+use deno_core::error::{generic_error, AnyError};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Reads a local init state file. With `template`, its `${NAME}`
+/// placeholders are filled in first, see [`substitute`].
+pub fn read_init_state(
+  path: &str,
+  template: bool,
+  vars: &[String],
+) -> Result<String, AnyError> {
+  let init_state = std::fs::read_to_string(path).map_err(|err| {
+    generic_error(format!("Unable to read init state {}: {}", path, err))
+  })?;
+  if !template {
+    return Ok(init_state);
+  }
+
+  let vars = parse_vars(vars)?;
+  substitute(&init_state, |name| {
+    vars
+      .get(name)
+      .cloned()
+      .or_else(|| builtin(name))
+      .or_else(|| std::env::var(name).ok())
+  })
+}
+
+/// Parses `--var NAME=VALUE` flags.
+fn parse_vars(vars: &[String]) -> Result<HashMap<String, String>, AnyError> {
+  vars
+    .iter()
+    .map(|var| match var.split_once('=') {
+      Some((name, value)) if is_placeholder_name(name) => {
+        Ok((name.to_owned(), value.to_owned()))
+      }
+      _ => Err(generic_error(format!(
+        "Invalid '--var {}', expected NAME=VALUE",
+        var
+      ))),
+    })
+    .collect()
+}
+
+/// Placeholders filled in without a flag or environment variable.
+fn builtin(name: &str) -> Option<String> {
+  match name {
+    "NOW" => Some(
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        .to_string(),
+    ),
+    _ => None,
+  }
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+  let mut chars = name.chars();
+  matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces every `${NAME}` in `template` with `lookup(NAME)`, verbatim.
+/// Values are not quoted, so a string value goes inside quotes in the
+/// template: `"owner": "${CALLER}"`.
+///
+/// `${` not followed by a valid name and a closing `}` is left as is. Fails
+/// listing every placeholder `lookup` has no value for.
+pub fn substitute(
+  template: &str,
+  lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, AnyError> {
+  let mut result = String::with_capacity(template.len());
+  let mut missing: Vec<&str> = vec![];
+  let mut rest = template;
+
+  while let Some(start) = rest.find("${") {
+    result.push_str(&rest[..start]);
+    let after = &rest[start + 2..];
+    let name = after
+      .find('}')
+      .map(|end| &after[..end])
+      .filter(|name| is_placeholder_name(name));
+
+    match name {
+      Some(name) => {
+        match lookup(name) {
+          Some(value) => result.push_str(&value),
+          None if !missing.contains(&name) => missing.push(name),
+          None => {}
+        }
+        rest = &after[name.len() + 1..];
+      }
+      None => {
+        result.push_str("${");
+        rest = after;
+      }
+    }
+  }
+  result.push_str(rest);
+
+  if missing.is_empty() {
+    Ok(result)
+  } else {
+    Err(generic_error(format!(
+      "No value for init state placeholder(s) {}, pass '--var NAME=VALUE' \
+       or set them as environment variables",
+      missing
+        .iter()
+        .map(|name| format!("${{{}}}", name))
+        .collect::<Vec<String>>()
+        .join(", ")
+    )))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::template::{parse_vars, substitute};
+
+  #[tokio::test]
+  async fn test_substitute() {
+    let lookup = |name: &str| match name {
+      "CALLER" => Some(String::from("abc")),
+      "SUPPLY" => Some(String::from("1000")),
+      _ => None,
+    };
+
+    assert_eq!(
+      substitute(
+        r#"{"owner":"${CALLER}","supply":${SUPPLY},"balances":{"${CALLER}":${SUPPLY}}}"#,
+        lookup
+      )
+      .unwrap(),
+      r#"{"owner":"abc","supply":1000,"balances":{"abc":1000}}"#
+    );
+    // Not placeholders.
+    assert_eq!(
+      substitute(r#"{"a":"${not valid}","b":"${x"}"#, lookup).unwrap(),
+      r#"{"a":"${not valid}","b":"${x"}"#
+    );
+
+    let err = substitute(r#"["${A}","${CALLER}","${B}","${A}"]"#, lookup)
+      .unwrap_err()
+      .to_string();
+    assert!(err.contains("${A}, ${B}"), "{}", err);
+  }
+
+  #[tokio::test]
+  async fn test_parse_vars() {
+    let vars = parse_vars(&[String::from("CALLER=a=b")]).unwrap();
+    assert_eq!(vars["CALLER"], "a=b");
+    assert!(parse_vars(&[String::from("CALLER")]).is_err());
+    assert!(parse_vars(&[String::from("1X=2")]).is_err());
+  }
+}
@@ -0,0 +1,121 @@
+// Runs the `three_em` binary and checks the code it exits with for each
+// class of failure, see exit_code.rs.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::thread;
+
+fn three_em(args: &[&str]) -> i32 {
+  Command::new(env!("CARGO_BIN_EXE_three_em"))
+    .args(args)
+    .current_dir(env!("CARGO_MANIFEST_DIR"))
+    .output()
+    .unwrap()
+    .status
+    .code()
+    .unwrap()
+}
+
+/// A gateway that knows no transaction and has no interactions, on the
+/// port it returns.
+fn empty_gateway() -> u16 {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let port = listener.local_addr().unwrap().port();
+  thread::spawn(move || {
+    for socket in listener.incoming() {
+      let mut socket = socket.unwrap();
+      let mut request = vec![0; 4096];
+      let len = socket.read(&mut request).unwrap();
+      let request = String::from_utf8_lossy(&request[..len]);
+      let (status, body): (&str, &[u8]) = if request.starts_with("POST") {
+        (
+          "200 OK",
+          br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#,
+        )
+      } else {
+        ("404 Not Found", b"")
+      };
+      let head = format!(
+        "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        body.len()
+      );
+      let _ = socket.write_all(head.as_bytes());
+      let _ = socket.write_all(body);
+    }
+  });
+  port
+}
+
+#[test]
+fn test_exit_codes() {
+  assert_eq!(three_em(&["--help"]), 0);
+
+  // Usage errors.
+  assert_eq!(three_em(&[]), 2);
+  assert_eq!(three_em(&["bogus"]), 2);
+  assert_eq!(three_em(&["run"]), 2);
+  assert_eq!(three_em(&["run", "--contract-id", "tx", "--port", "x"]), 2);
+  assert_eq!(
+    three_em(&["run", "--contract-id", "tx", "--height", "x"]),
+    2
+  );
+  assert_eq!(
+    three_em(&["run", "--contract-id", "tx", "--sample", "2"]),
+    2
+  );
+
+  // Nothing listens on a port that was just freed.
+  let closed = TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap()
+    .port()
+    .to_string();
+  let gateway = ["--host", "127.0.0.1", "--protocol", "http", "--port"];
+  let run = ["run", "--contract-id", "tx", "--height", "1", "--no-cache"];
+  assert_eq!(three_em(&[&run[..], &gateway, &[&closed]].concat()), 3);
+  let port = empty_gateway().to_string();
+  assert_eq!(three_em(&[&run[..], &gateway, &[&port]].concat()), 4);
+
+  let dir = std::env::temp_dir();
+  let source = dir.join("3em_test_exit_codes.js");
+  let init_state = dir.join("3em_test_exit_codes.json");
+  std::fs::write(&source, "export function handle(state {").unwrap();
+  std::fs::write(&init_state, "{}").unwrap();
+  assert_eq!(
+    three_em(&[
+      "validate",
+      "--source",
+      source.to_str().unwrap(),
+      "--init-state",
+      init_state.to_str().unwrap(),
+    ]),
+    5
+  );
+
+  // Its third interaction throws.
+  let dry_run = [
+    "dry-run",
+    "--file",
+    "../../testdata/contracts/dry_run_users_contract.json",
+  ];
+  assert_eq!(three_em(&dry_run), 0);
+  assert_eq!(
+    three_em(&[&dry_run[..], &["--fail-on-invalid"]].concat()),
+    6
+  );
+
+  // Its state is a list of names.
+  let schema = dir.join("3em_test_exit_codes_schema.json");
+  let schema_flag = ["--schema", schema.to_str().unwrap()];
+  std::fs::write(
+    &schema,
+    r#"{"properties":{"users":{"items":{"type":"string"}}}}"#,
+  )
+  .unwrap();
+  assert_eq!(three_em(&[&dry_run[..], &schema_flag].concat()), 0);
+  std::fs::write(&schema, r#"{"required":["balances"]}"#).unwrap();
+  assert_eq!(three_em(&[&dry_run[..], &schema_flag].concat()), 1);
+}
@@ -1,4 +1,5 @@
 use crate::node::Node;
+use deno_core::error::{generic_error, AnyError};
 use sha2::Digest;
 
 pub fn parse_node_ip(node: &Node) -> String {
@@ -23,9 +24,141 @@ pub fn hasher(data: &[u8]) -> Vec<u8> {
   hasher.finalize()[..].to_vec()
 }
 
+/// Seconds since the epoch of an RFC 3339 date-time, like
+/// `2022-03-01T12:00:00Z` or `2022-03-01T14:00:00.5+02:00`. Fractions of a
+/// second are dropped.
+pub fn parse_rfc3339(value: &str) -> Result<u64, AnyError> {
+  let invalid =
+    || generic_error(format!("Invalid RFC 3339 date-time '{}'", value));
+  let bytes = value.as_bytes();
+  let digits = |start: usize, len: usize| -> Result<i64, AnyError> {
+    match bytes.get(start..start + len) {
+      Some(part) if part.iter().all(u8::is_ascii_digit) => {
+        Ok(part.iter().fold(0, |n, d| n * 10 + (d - b'0') as i64))
+      }
+      _ => Err(invalid()),
+    }
+  };
+
+  let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+  if bytes.len() < 20
+    || separators.iter().any(|(at, byte)| bytes[*at] != *byte)
+    || !matches!(bytes[10], b'T' | b't' | b' ')
+  {
+    return Err(invalid());
+  }
+  let (year, month, day) = (digits(0, 4)?, digits(5, 2)?, digits(8, 2)?);
+  let (hour, minute, second) = (digits(11, 2)?, digits(14, 2)?, digits(17, 2)?);
+  if !(1..=12).contains(&month)
+    || day < 1
+    || day > days_in_month(year, month)
+    || hour > 23
+    || minute > 59
+    // Leap seconds are 60.
+    || second > 60
+  {
+    return Err(invalid());
+  }
+
+  let mut zone = 19;
+  if bytes[zone] == b'.' {
+    let fraction = bytes[zone + 1..]
+      .iter()
+      .take_while(|byte| byte.is_ascii_digit())
+      .count();
+    if fraction == 0 {
+      return Err(invalid());
+    }
+    zone += 1 + fraction;
+  }
+  let offset = match &bytes[zone..] {
+    b"Z" | b"z" => 0,
+    [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+      let (hours, minutes) = (digits(zone + 1, 2)?, digits(zone + 4, 2)?);
+      if hours > 23 || minutes > 59 {
+        return Err(invalid());
+      }
+      let offset = hours * 3600 + minutes * 60;
+      if *sign == b'-' {
+        -offset
+      } else {
+        offset
+      }
+    }
+    _ => return Err(invalid()),
+  };
+
+  let seconds = days_from_civil(year, month, day) * 86400
+    + hour * 3600
+    + minute * 60
+    + second
+    - offset;
+  u64::try_from(seconds)
+    .map_err(|_| generic_error(format!("'{}' is before 1970", value)))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+  match month {
+    2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  // Counted in eras of 400 years starting in March, so that leap days
+  // fall at the end of a year.
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month_from_march = (month + 9) % 12;
+  let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+  let day_of_era =
+    year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::utils::{u8_array_to_usize, usize_to_u8_array};
+  use crate::utils::{parse_rfc3339, u8_array_to_usize, usize_to_u8_array};
+
+  #[tokio::test]
+  async fn test_parse_rfc3339() {
+    assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z").unwrap(), 0);
+    assert_eq!(
+      parse_rfc3339("2022-03-01T12:00:00Z").unwrap(),
+      1_646_136_000
+    );
+    // The same instant with an offset, a fraction or a lowercase zone.
+    assert_eq!(
+      parse_rfc3339("2022-03-01T14:00:00.75+02:00").unwrap(),
+      1_646_136_000
+    );
+    assert_eq!(
+      parse_rfc3339("2022-03-01t12:00:00z").unwrap(),
+      1_646_136_000
+    );
+    assert_eq!(
+      parse_rfc3339("2020-02-29T23:59:59-00:30").unwrap(),
+      1_583_022_599
+    );
+
+    for invalid in [
+      "2022-03-01",
+      "2022-03-01T12:00:00",
+      "2022-02-29T12:00:00Z",
+      "2022-13-01T12:00:00Z",
+      "2022-03-01T24:00:00Z",
+      "2022-03-01T12:00:00.Z",
+      "2022-03-01T12:00:00+0200",
+      "+022-03-01T12:00:00Z",
+      "1969-12-31T23:59:59Z",
+    ] {
+      assert!(parse_rfc3339(invalid).is_err(), "{}", invalid);
+    }
+  }
 
   #[tokio::test]
   async fn test_usize_to_u8_array() {
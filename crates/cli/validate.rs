@@ -0,0 +1,119 @@
+use crate::exit_code;
+use deno_core::error::{generic_error, AnyError};
+use three_em_arweave::arweave::{Arweave, LoadedContract};
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::executor::validate_contract;
+use three_em_executor::test_util::generate_fake_loaded_contract_data;
+
+fn parse_contract_type(
+  contract_type: Option<String>,
+  source: &str,
+) -> Result<ContractType, AnyError> {
+  let contract_type =
+    contract_type.unwrap_or_else(|| match source.rsplit('.').next() {
+      Some("wasm") => String::from("wasm"),
+      _ => String::from("js"),
+    });
+
+  match &contract_type.to_lowercase()[..] {
+    "js" | "javascript" => Ok(ContractType::JAVASCRIPT),
+    "wasm" => Ok(ContractType::WASM),
+    "evm" => Ok(ContractType::EVM),
+    _ => Err(generic_error(format!(
+      "Unknown contract type '{}'",
+      contract_type
+    ))),
+  }
+}
+
+fn load_local_contract(
+  source: String,
+  init_state: String,
+  contract_type: Option<String>,
+) -> Result<LoadedContract, AnyError> {
+  let contract_type = parse_contract_type(contract_type, &source)?;
+  let source = std::fs::read(&source).map_err(|err| {
+    generic_error(format!("Unable to read source {}: {}", source, err))
+  })?;
+  let init_state = std::fs::read_to_string(&init_state).map_err(|err| {
+    generic_error(format!("Unable to read init state {}: {}", init_state, err))
+  })?;
+
+  Ok(generate_fake_loaded_contract_data(
+    &source,
+    contract_type,
+    init_state,
+  ))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn validate(
+  port: i32,
+  host: String,
+  protocol: String,
+  contract_id: Option<String>,
+  source: Option<String>,
+  init_state: Option<String>,
+  contract_type: Option<String>,
+) -> Result<i32, AnyError> {
+  let arweave =
+    Arweave::new_no_cache(port, host, protocol).with_env_headers()?;
+
+  let loaded_contract = match (contract_id, source) {
+    (Some(contract_id), _) => {
+      arweave
+        .load_contract(contract_id, None, None, None, false, false, false)
+        .await
+    }
+    (None, Some(source)) => match init_state {
+      Some(init_state) => load_local_contract(source, init_state, contract_type),
+      None => Err(generic_error("Option '--init-state' is required")),
+    },
+    (None, None) => Err(generic_error(
+      "Either '--contract-id' or '--source' is required",
+    )),
+  };
+
+  let result = match loaded_contract {
+    Ok(loaded_contract) => validate_contract(loaded_contract, &arweave).await,
+    Err(err) => Err(err),
+  };
+
+  match result {
+    Ok(()) => {
+      println!("Contract is valid");
+      Ok(exit_code::SUCCESS)
+    }
+    Err(err) => {
+      eprintln!("Contract is invalid: {}", err);
+      // A contract that doesn't load for lack of a gateway isn't invalid.
+      Ok(match exit_code::of_error(&err) {
+        exit_code::FAILURE => exit_code::EVALUATION,
+        code => code,
+      })
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::validate::parse_contract_type;
+  use three_em_arweave::miscellaneous::ContractType;
+
+  #[tokio::test]
+  async fn test_parse_contract_type() {
+    assert!(matches!(
+      parse_contract_type(None, "contract.wasm").unwrap(),
+      ContractType::WASM
+    ));
+    assert!(matches!(
+      parse_contract_type(None, "contract.js").unwrap(),
+      ContractType::JAVASCRIPT
+    ));
+    assert!(matches!(
+      parse_contract_type(Some(String::from("EVM")), "contract").unwrap(),
+      ContractType::EVM
+    ));
+    assert!(parse_contract_type(Some(String::from("lua")), "").is_err());
+  }
+}
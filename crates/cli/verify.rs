@@ -0,0 +1,270 @@
+use crate::exit_code;
+use crate::manifest::hash_json;
+use deno_core::error::{generic_error, AnyError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use three_em_arweave::arweave::Arweave;
+use three_em_executor::delta;
+use three_em_executor::execute_contract;
+use three_em_executor::executor::{process_execution, EvaluateOptions};
+use three_em_executor::RuntimeSettings;
+
+/// What a node claims to have computed. A manifest written by
+/// `run --manifest` deserializes into one, its other fields are ignored.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Claim {
+  pub contract_id: Option<String>,
+  pub height: Option<usize>,
+  pub state_hash: Option<String>,
+  pub allow_wallet_balance: bool,
+  pub allow_unsafe_client: bool,
+  pub extended_action: bool,
+  pub only_owners: Vec<String>,
+}
+
+/// The outcome of replaying a [`Claim`].
+pub struct Verification {
+  pub claimed_hash: String,
+  pub computed_hash: String,
+  /// The state the replay ended with, rendered like `run` prints it.
+  pub state: Value,
+}
+
+impl Verification {
+  pub fn matches(&self) -> bool {
+    self.claimed_hash.eq_ignore_ascii_case(&self.computed_hash)
+  }
+}
+
+/// Evaluates the claimed contract from scratch, without the cache, and
+/// hashes its state the way manifests do.
+pub async fn check(
+  arweave: &Arweave,
+  claim: &Claim,
+) -> Result<Verification, AnyError> {
+  let (contract_id, height, claimed_hash) =
+    match (&claim.contract_id, claim.height, &claim.state_hash) {
+      (Some(contract_id), Some(height), Some(state_hash)) => {
+        (contract_id, height, state_hash)
+      }
+      _ => {
+        return Err(generic_error(
+          "A contract id, a height and a state hash are required",
+        ))
+      }
+    };
+
+  let options = EvaluateOptions {
+    runtime_settings: RuntimeSettings {
+      allow_wallet_balance: claim.allow_wallet_balance,
+      allow_unsafe_client: claim.allow_unsafe_client,
+      ..Default::default()
+    },
+    only_owners: if claim.only_owners.is_empty() {
+      None
+    } else {
+      Some(claim.only_owners.iter().cloned().collect())
+    },
+    extended_action: claim.extended_action,
+    ..Default::default()
+  };
+  let result = execute_contract(
+    contract_id.to_owned(),
+    Some(height),
+    false,
+    false,
+    None,
+    None,
+    arweave,
+    &options,
+  )
+  .await?;
+  let state = process_execution(result, false);
+
+  Ok(Verification {
+    claimed_hash: claimed_hash.trim().to_owned(),
+    computed_hash: hash_json(&state)?,
+    state,
+  })
+}
+
+/// A path where two states differ. `before` is missing for values only in
+/// the second state, `after` for values only in the first one.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Difference {
+  pub path: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub before: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub after: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match (&self.before, &self.after) {
+      (Some(before), Some(after)) => {
+        write!(f, "~ {}: {} -> {}", self.path, before, after)
+      }
+      (Some(before), None) => write!(f, "- {}: {}", self.path, before),
+      (None, Some(after)) => write!(f, "+ {}: {}", self.path, after),
+      (None, None) => write!(f, "  {}", self.path),
+    }
+  }
+}
+
+/// Lists where `computed` differs from `claimed`, one line per path:
+/// `-` for values only claimed, `+` for values only computed and `~` for
+/// changed values.
+pub fn diff_states(claimed: &Value, computed: &Value) -> Vec<String> {
+  diff_values(claimed, computed)
+    .iter()
+    .map(ToString::to_string)
+    .collect()
+}
+
+/// Lists the paths where `before` and `after` differ, from `state`, see
+/// [`delta::diff_values`].
+pub fn diff_values(before: &Value, after: &Value) -> Vec<Difference> {
+  delta::diff_values(before, after)
+    .into_iter()
+    .map(|change| Difference {
+      path: delta::display_path("state", &change.path),
+      before: change.before,
+      after: change.after,
+    })
+    .collect()
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(
+  path: &str,
+  what: &str,
+) -> Result<T, AnyError> {
+  let contents = std::fs::read(path).map_err(|err| {
+    generic_error(format!("Unable to read {} {}: {}", what, path, err))
+  })?;
+  serde_json::from_slice(&contents).map_err(|err| {
+    generic_error(format!("Unable to parse {} {}: {}", what, path, err))
+  })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn verify(
+  port: i32,
+  host: String,
+  protocol: String,
+  contract_id: Option<String>,
+  height: Option<usize>,
+  state_hash: Option<String>,
+  manifest: Option<String>,
+  state: Option<String>,
+) -> Result<i32, AnyError> {
+  let mut claim: Claim = match manifest {
+    Some(path) => read_json(&path, "manifest")?,
+    None => Claim::default(),
+  };
+  claim.contract_id = contract_id.or(claim.contract_id);
+  claim.height = height.or(claim.height);
+  claim.state_hash = state_hash.or(claim.state_hash);
+
+  let claimed_state: Option<Value> = state
+    .map(|path| read_json(&path, "claimed state"))
+    .transpose()?;
+  if let Some(claimed_state) = &claimed_state {
+    let hash = hash_json(claimed_state)?;
+    match &claim.state_hash {
+      Some(state_hash) if !state_hash.trim().eq_ignore_ascii_case(&hash) => {
+        return Err(generic_error(format!(
+          "The claimed state hashes to {}, not to the claimed hash {}",
+          hash, state_hash
+        )));
+      }
+      _ => claim.state_hash = Some(hash),
+    }
+  }
+
+  let arweave =
+    Arweave::new_no_cache(port, host, protocol).with_env_headers()?;
+  let verification = check(&arweave, &claim).await?;
+
+  if verification.matches() {
+    println!("State hash matches: {}", verification.computed_hash);
+    return Ok(exit_code::SUCCESS);
+  }
+
+  println!(
+    "State hash mismatch: claimed {}, computed {}",
+    verification.claimed_hash, verification.computed_hash
+  );
+  if let Some(claimed_state) = &claimed_state {
+    for diff in diff_states(claimed_state, &verification.state) {
+      println!("{}", diff);
+    }
+  }
+  Ok(exit_code::FAILURE)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::verify::{check, diff_states, Claim};
+  use serde_json::json;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::cache::ArweaveCache;
+
+  #[tokio::test]
+  async fn test_check() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let mut claim = Claim {
+      contract_id: Some(String::from(
+        "KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ",
+      )),
+      height: Some(822062),
+      state_hash: Some("0".repeat(64)),
+      ..Default::default()
+    };
+
+    let wrong = check(&arweave, &claim).await.unwrap();
+    assert!(!wrong.matches());
+    assert_eq!(wrong.computed_hash.len(), 64);
+
+    claim.state_hash = Some(wrong.computed_hash.to_uppercase());
+    let matching = check(&arweave, &claim).await.unwrap();
+    assert!(matching.matches());
+  }
+
+  #[tokio::test]
+  async fn test_check_requires_claim() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    assert!(check(&arweave, &Claim::default()).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_diff_states() {
+    let claimed =
+      json!({ "owner": "a", "balances": { "a": 1, "b": 2 }, "log": [1] });
+    let computed =
+      json!({ "owner": "a", "balances": { "a": 1, "c": 3 }, "log": [2, 3] });
+
+    assert_eq!(
+      diff_states(&claimed, &computed),
+      vec![
+        "- state.balances.b: 2",
+        "+ state.balances.c: 3",
+        "~ state.log[0]: 1 -> 2",
+        "+ state.log[1]: 3",
+      ]
+    );
+    assert!(diff_states(&claimed, &claimed).is_empty());
+  }
+}
@@ -0,0 +1,152 @@
+//! Tokens marking how far an evaluation got, for API consumers that poll a
+//! contract and only want to evaluate what is new since their last request.
+//!
+//! A [`Cursor`] comes with the state of an evaluation. Given back with that
+//! state, it resumes the evaluation after the interactions the state
+//! reflects, see [`Cursor::resume`].
+
+use crate::executor::{EvaluateOptions, ExecuteResult, Resume};
+use deno_core::error::{generic_error, AnyError};
+use deno_core::serde_json;
+use deno_core::serde_json::Value;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Bytes of the SHA-256 of the payload a token carries, to catch tokens
+/// mangled on their way back.
+const CHECKSUM_LEN: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Cursor {
+  pub contract_id: String,
+  /// Id of the last interaction the state reflects, `None` if it reflects
+  /// none.
+  pub interaction_id: Option<String>,
+  /// How many interactions, in evaluation order, the state reflects.
+  pub applied: usize,
+  /// The height the state was evaluated up to, `None` for the tip.
+  pub height: Option<usize>,
+}
+
+impl Cursor {
+  /// The cursor of `result`, evaluated up to `height` with `options`.
+  pub fn after(
+    contract_id: &str,
+    height: Option<usize>,
+    result: &ExecuteResult,
+    options: &EvaluateOptions,
+  ) -> Cursor {
+    // The validity table is in evaluation order, and only holds what this
+    // run evaluated when it was resumed.
+    let validity = result.validity();
+    let resume = options.resume.as_ref();
+    Cursor {
+      contract_id: contract_id.to_owned(),
+      interaction_id: validity
+        .keys()
+        .last()
+        .cloned()
+        .or_else(|| resume.and_then(|resume| resume.after.clone())),
+      applied: resume.map_or(0, |resume| resume.applied) + validity.len(),
+      height,
+    }
+  }
+
+  /// The cursor as an opaque token. The same cursor always gives the same
+  /// token.
+  pub fn encode(&self) -> String {
+    let payload = serde_json::to_vec(self).unwrap();
+    format!("{}.{}", encode(&payload), encode(&checksum(&payload)))
+  }
+
+  /// Reads a token from [`Cursor::encode`]. Fails if it was altered.
+  pub fn decode(token: &str) -> Result<Cursor, AnyError> {
+    let invalid = || generic_error("Invalid cursor");
+    let (payload, sum) = token.split_once('.').ok_or_else(invalid)?;
+    let payload = decode(payload).ok_or_else(invalid)?;
+    if decode(sum).as_deref() != Some(&checksum(&payload)[..]) {
+      return Err(invalid());
+    }
+    serde_json::from_slice(&payload).map_err(|_| invalid())
+  }
+
+  /// Resumes an evaluation of `contract_id` up to `height` from this
+  /// cursor, on top of `state`, the state it came with. Fails if the cursor
+  /// belongs to another contract or to a later height.
+  ///
+  /// The evaluation itself fails if the interaction the cursor ends at is
+  /// no longer where it was, e.g. after a reorg.
+  pub fn resume(
+    &self,
+    contract_id: &str,
+    height: Option<usize>,
+    state: Value,
+  ) -> Result<Resume, AnyError> {
+    if self.contract_id != contract_id {
+      return Err(generic_error(format!(
+        "Cursor belongs to contract {}",
+        self.contract_id
+      )));
+    }
+    if let (Some(cursor_height), Some(height)) = (self.height, height) {
+      if height < cursor_height {
+        return Err(generic_error(format!(
+          "Cursor is at height {}, past {}",
+          cursor_height, height
+        )));
+      }
+    }
+
+    Ok(Resume {
+      state,
+      applied: self.applied,
+      after: self.interaction_id.clone(),
+    })
+  }
+}
+
+fn checksum(payload: &[u8]) -> Vec<u8> {
+  Sha256::digest(payload)[..CHECKSUM_LEN].to_vec()
+}
+
+fn encode(data: &[u8]) -> String {
+  base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(data: &str) -> Option<Vec<u8>> {
+  base64::decode_config(data, base64::URL_SAFE_NO_PAD).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::cursor::Cursor;
+  use deno_core::serde_json::json;
+
+  #[tokio::test]
+  async fn test_cursor_token() {
+    let cursor = Cursor {
+      contract_id: String::from("contract"),
+      interaction_id: Some(String::from("tx2")),
+      applied: 2,
+      height: Some(900000),
+    };
+    let token = cursor.encode();
+    assert_eq!(token, cursor.clone().encode());
+    assert_eq!(Cursor::decode(&token).unwrap(), cursor);
+
+    // A token with any byte changed is rejected.
+    let mut altered = token.clone().into_bytes();
+    altered[4] = if altered[4] == b'A' { b'B' } else { b'A' };
+    let altered = String::from_utf8(altered).unwrap();
+    assert!(Cursor::decode(&altered).is_err());
+    assert!(Cursor::decode("").is_err());
+    assert!(Cursor::decode(token.split('.').next().unwrap()).is_err());
+
+    let resume = cursor.resume("contract", None, json!({})).unwrap();
+    assert_eq!(resume.applied, 2);
+    assert_eq!(resume.after.as_deref(), Some("tx2"));
+    assert!(cursor.resume("other", None, json!({})).is_err());
+    assert!(cursor.resume("contract", Some(899999), json!({})).is_err());
+  }
+}
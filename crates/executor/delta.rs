@@ -0,0 +1,265 @@
+//! Structural differences between states, and deltas built from them that
+//! bring one state to the other.
+//!
+//! A client syncing a contract already holds its state at some height, and
+//! only needs what changed since to reach a later one. See
+//! [`crate::execute_contract_delta`].
+
+use deno_core::error::{generic_error, AnyError};
+use deno_core::serde_json::Value;
+use serde::{Deserialize, Serialize};
+
+/// A step of the path to a value: a key of an object or an index of an
+/// array.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PathSegment {
+  Key(String),
+  Index(usize),
+}
+
+/// A path where two states differ. `before` is missing for values only in
+/// the second state, `after` for values only in the first one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+  pub path: Vec<PathSegment>,
+  pub before: Option<Value>,
+  pub after: Option<Value>,
+}
+
+/// Walks `before` and `after` together and lists the paths where they
+/// differ. Objects are compared key by key and arrays index by index;
+/// other values are compared as a whole.
+pub fn diff_values(before: &Value, after: &Value) -> Vec<Change> {
+  let mut changes = vec![];
+  diff_json(&mut Vec::new(), before, after, &mut changes);
+  changes
+}
+
+fn diff_json(
+  path: &mut Vec<PathSegment>,
+  before: &Value,
+  after: &Value,
+  changes: &mut Vec<Change>,
+) {
+  let children: Vec<(PathSegment, Option<&Value>, Option<&Value>)> =
+    match (before, after) {
+      (Value::Object(before), Value::Object(after)) => before
+        .keys()
+        .chain(after.keys().filter(|key| !before.contains_key(*key)))
+        .map(|key| {
+          (
+            PathSegment::Key(key.to_owned()),
+            before.get(key),
+            after.get(key),
+          )
+        })
+        .collect(),
+      (Value::Array(before), Value::Array(after)) => {
+        let len = before.len().max(after.len());
+        (0..len)
+          .map(|i| (PathSegment::Index(i), before.get(i), after.get(i)))
+          .collect()
+      }
+      _ => {
+        if before != after {
+          changes.push(Change {
+            path: path.clone(),
+            before: Some(before.clone()),
+            after: Some(after.clone()),
+          });
+        }
+        return;
+      }
+    };
+
+  for (segment, before, after) in children {
+    path.push(segment);
+    match (before, after) {
+      (Some(before), Some(after)) => diff_json(path, before, after, changes),
+      (before, after) => changes.push(Change {
+        path: path.clone(),
+        before: before.cloned(),
+        after: after.cloned(),
+      }),
+    }
+    path.pop();
+  }
+}
+
+/// `path` as `root.key[index]`.
+pub fn display_path(root: &str, path: &[PathSegment]) -> String {
+  let mut display = root.to_owned();
+  for segment in path {
+    match segment {
+      PathSegment::Key(key) => {
+        display.push('.');
+        display.push_str(key);
+      }
+      PathSegment::Index(index) => display.push_str(&format!("[{}]", index)),
+    }
+  }
+  display
+}
+
+/// One step of a delta, see [`delta`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Patch {
+  /// Sets the value at `path`, adding it if it is missing.
+  Set {
+    path: Vec<PathSegment>,
+    value: Value,
+  },
+  Remove {
+    path: Vec<PathSegment>,
+  },
+}
+
+/// The patches that bring `base` to `target` when applied in order with
+/// [`apply_delta`]. Only the values that differ are carried, not what they
+/// were in `base`.
+pub fn delta(base: &Value, target: &Value) -> Vec<Patch> {
+  diff_values(base, target)
+    .into_iter()
+    .map(|change| match change.after {
+      Some(value) => Patch::Set {
+        path: change.path,
+        value,
+      },
+      None => Patch::Remove { path: change.path },
+    })
+    .collect()
+}
+
+/// Applies the patches of a [`delta`] to `state`, which must be the state
+/// the delta was computed from. Fails if a patch doesn't fit `state`; it is
+/// then left partly patched.
+pub fn apply_delta(state: &mut Value, delta: &[Patch]) -> Result<(), AnyError> {
+  for patch in delta {
+    let (path, value) = match patch {
+      Patch::Set { path, value } => (path, Some(value)),
+      Patch::Remove { path } => (path, None),
+    };
+    let (last, parents) = match path.split_last() {
+      Some(split) => split,
+      None => {
+        *state = value
+          .cloned()
+          .ok_or_else(|| generic_error("The delta removes the whole state"))?;
+        continue;
+      }
+    };
+
+    let mut parent = &mut *state;
+    for (depth, segment) in parents.iter().enumerate() {
+      parent = match (parent, segment) {
+        (Value::Object(map), PathSegment::Key(key)) => map.get_mut(key),
+        (Value::Array(items), PathSegment::Index(index)) => {
+          items.get_mut(*index)
+        }
+        _ => None,
+      }
+      .ok_or_else(|| mismatch(&path[..=depth]))?;
+    }
+
+    match (parent, last, value) {
+      (Value::Object(map), PathSegment::Key(key), Some(value)) => {
+        map.insert(key.to_owned(), value.clone());
+      }
+      (Value::Object(map), PathSegment::Key(key), None) => {
+        map.remove(key).ok_or_else(|| mismatch(path))?;
+      }
+      (Value::Array(items), PathSegment::Index(index), Some(value)) => {
+        match items.get_mut(*index) {
+          Some(item) => *item = value.clone(),
+          None if *index == items.len() => items.push(value.clone()),
+          None => return Err(mismatch(path)),
+        }
+      }
+      // Arrays only lose items at their end, so removing an item removes
+      // those after it, which the following patches remove again.
+      (Value::Array(items), PathSegment::Index(index), None) => {
+        items.truncate(*index);
+      }
+      _ => return Err(mismatch(path)),
+    }
+  }
+  Ok(())
+}
+
+fn mismatch(path: &[PathSegment]) -> AnyError {
+  generic_error(format!(
+    "The delta doesn't apply to this state, it has nothing at {}",
+    display_path("state", path)
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::delta::{apply_delta, delta, diff_values, display_path};
+  use crate::delta::{Patch, PathSegment};
+  use deno_core::serde_json;
+  use deno_core::serde_json::json;
+
+  #[tokio::test]
+  async fn test_delta() {
+    let base = json!({
+      "balances": { "alice": 10, "bob": 5 },
+      "votes": [1, 2, 3],
+      "owners": ["alice"],
+      "settings": { "paused": false },
+      "ticker": "VRT",
+    });
+    let target = json!({
+      "balances": { "alice": 7, "carol": 3 },
+      "votes": [1],
+      "owners": ["alice", "bob", "carol"],
+      "settings": null,
+      "ticker": "VRT",
+      "name": "Verto",
+    });
+
+    let changes = diff_values(&base, &target);
+    let mut paths: Vec<String> = changes
+      .iter()
+      .map(|change| display_path("state", &change.path))
+      .collect();
+    paths.sort();
+    assert_eq!(
+      paths,
+      [
+        "state.balances.alice",
+        "state.balances.bob",
+        "state.balances.carol",
+        "state.name",
+        "state.owners[1]",
+        "state.owners[2]",
+        "state.settings",
+        "state.votes[1]",
+        "state.votes[2]",
+      ]
+    );
+
+    // What a client receives, and the target it rebuilds from it.
+    let patches = delta(&base, &target);
+    let received: Vec<Patch> =
+      serde_json::from_str(&serde_json::to_string(&patches).unwrap()).unwrap();
+    assert_eq!(received, patches);
+    let mut state = base.clone();
+    apply_delta(&mut state, &received).unwrap();
+    assert_eq!(state, target);
+
+    let mut state = json!(1);
+    apply_delta(&mut state, &delta(&json!(1), &json!({ "a": [] }))).unwrap();
+    assert_eq!(state, json!({ "a": [] }));
+
+    // Not the base it was computed from.
+    let mut other = json!({ "votes": [] });
+    assert!(apply_delta(&mut other, &patches).is_err());
+    let remove = [Patch::Remove {
+      path: vec![PathSegment::Key(String::from("missing"))],
+    }];
+    assert!(apply_delta(&mut json!({}), &remove).is_err());
+  }
+}
@@ -0,0 +1,564 @@
+//! The engines contracts are evaluated with.
+//!
+//! Each engine wraps a runtime in a [`ContractRuntime`], so that the
+//! interaction loop of [`crate::executor::raw_execute_contract`] is the same
+//! for all of them. Validating inputs, the validity table and step reports
+//! belong to the loop; an engine only calls the contract handler and keeps
+//! its state.
+//!
+//! [`JsEngine`] needs the `js` feature and [`WasmEngine`] the `wasm` one.
+
+#[cfg(any(feature = "js", feature = "wasm"))]
+use crate::executor::Error;
+#[cfg(feature = "js")]
+use crate::executor::{arweave_info, op_smartweave_read_state, ReadOptions};
+use crate::executor::{ExecuteResult, ValidityTable};
+#[cfg(any(feature = "js", feature = "wasm"))]
+use crate::limits::Limits;
+use async_trait::async_trait;
+use deno_core::error::{generic_error, AnyError};
+use deno_core::serde_json;
+use deno_core::serde_json::Value;
+#[cfg(feature = "js")]
+use three_em_arweave::arweave::Arweave;
+#[cfg(feature = "js")]
+use three_em_js::{CallResult, Runtime, DEFAULT_HEAP_LIMIT};
+#[cfg(feature = "wasm")]
+use three_em_smartweave::DeterminismContext;
+use three_em_smartweave::InteractionContext;
+#[cfg(feature = "js")]
+use three_em_smartweave::{ContractInfo, RuntimeSettings};
+#[cfg(feature = "wasm")]
+use three_em_wasm::WasmRuntime;
+
+/// What a successful call did to the state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateChange {
+  /// The state is the same as before the call, like when the handler only
+  /// returns a `result` or gives back the state untouched. The interaction
+  /// is still valid. Only reported after
+  /// [`ContractRuntime::track_changes`], and by readonly calls.
+  Unchanged,
+  /// The state may have changed.
+  Changed,
+}
+
+/// A contract instance that interactions are evaluated against, one at a
+/// time and in order.
+#[async_trait(?Send)]
+pub trait ContractRuntime {
+  /// Runs the contract's `init` export, if it has one, on the current
+  /// state, which the state it derives replaces. Called once before the
+  /// first interaction, and only when starting from the init state.
+  async fn init(&mut self) -> Result<(), AnyError> {
+    Ok(())
+  }
+
+  /// Makes calls tell [`StateChange::Unchanged`] apart, which costs a
+  /// comparison of the whole state after each of them. Until then, every
+  /// successful call reports [`StateChange::Changed`].
+  fn track_changes(&mut self) {}
+
+  /// Calls the handler with an action object. If the call fails, the state
+  /// is left as it was before.
+  async fn call(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError>;
+
+  /// Like [`ContractRuntime::call`], but the handler is given an empty
+  /// state and the one it leaves behind replaces the current state. See
+  /// [`crate::executor::EvaluateOptions::genesis_interaction`].
+  async fn call_genesis(
+    &mut self,
+    _action: Value,
+    _context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    Err(generic_error("Genesis interactions are not supported"))
+  }
+
+  /// Like [`ContractRuntime::call`], but a call that changes the state
+  /// fails with [`crate::executor::Error::StateMutated`] and is undone. See
+  /// [`crate::executor::EvaluateOptions::readonly`].
+  async fn call_readonly(
+    &mut self,
+    _action: Value,
+    _context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    Err(generic_error("Readonly evaluation is not supported"))
+  }
+
+  /// The current state, as reported to
+  /// [`crate::executor::EvaluateOptions::on_step`].
+  fn state(&mut self) -> Value;
+
+  /// Ends the evaluation with the validity table the loop built.
+  fn finish(self, validity: ValidityTable) -> ExecuteResult;
+}
+
+/// Evaluates JavaScript contracts on V8.
+#[cfg(feature = "js")]
+pub struct JsEngine<'a> {
+  rt: Runtime,
+  /// The current source, which changes when the contract evolves.
+  source: String,
+  /// The state after the last valid interaction. Handlers receive the live
+  /// state object, so one that fails halfway through leaves its mutations
+  /// behind; this is what it is rolled back to.
+  state: Value,
+  contract: ContractInfo,
+  shared_client: &'a Arweave,
+  settings: RuntimeSettings,
+  reads: ReadOptions,
+  limits: Limits,
+  /// See [`ContractRuntime::track_changes`].
+  track_changes: bool,
+}
+
+#[cfg(feature = "js")]
+impl<'a> JsEngine<'a> {
+  pub async fn new(
+    contract: ContractInfo,
+    source: String,
+    state: Value,
+    shared_client: &'a Arweave,
+    settings: RuntimeSettings,
+    reads: ReadOptions,
+    limits: Limits,
+  ) -> Result<JsEngine<'a>, AnyError> {
+    let rt = new_js_runtime(
+      &source,
+      state.clone(),
+      &contract,
+      shared_client,
+      &settings,
+      reads.clone(),
+      &limits,
+    )
+    .await?;
+
+    Ok(Self {
+      rt,
+      source,
+      state,
+      contract,
+      shared_client,
+      settings,
+      reads,
+      limits,
+      track_changes: false,
+    })
+  }
+
+  async fn evaluate(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+    genesis: bool,
+    track_changes: bool,
+  ) -> Result<StateChange, AnyError> {
+    if genesis {
+      let empty = serde_json::json!({});
+      set_state(&mut self.rt, &empty, self.reads.big_integers)?;
+    }
+
+    let result = match self.rt.call(action, Some(context)).await {
+      Ok(Some(CallResult::Evolve(evolve))) => self.evolve(evolve).await,
+      Ok(_) => Ok(()),
+      Err(err) => Err(err),
+    }
+    // Handlers can leave values behind that JSON can't hold, like BigInts.
+    .and_then(|()| {
+      // Kept to roll back the next interaction if it fails.
+      let state =
+        get_state(&mut self.rt, &self.state, self.reads.big_integers)?;
+      if track_changes && state == self.state {
+        return Ok(StateChange::Unchanged);
+      }
+      self.state = state;
+      Ok(StateChange::Changed)
+    });
+
+    if let Err(err) = &result {
+      if err.downcast_ref::<three_em_js::Error>().is_some() {
+        // A terminated isolate, out of memory or past its timeout, cannot
+        // run any more code. If it can't be rebuilt either, the next
+        // interactions fail the same way.
+        if let Ok(rt) = new_js_runtime(
+          &self.source,
+          self.state.clone(),
+          &self.contract,
+          self.shared_client,
+          &self.settings,
+          self.reads.clone(),
+          &self.limits,
+        )
+        .await
+        {
+          self.rt = rt;
+        }
+      } else {
+        set_state(&mut self.rt, &self.state, self.reads.big_integers)?;
+      }
+    }
+
+    result
+  }
+
+  /// Switches to the source the contract evolved to, keeping its state.
+  async fn evolve(&mut self, evolve: String) -> Result<(), AnyError> {
+    let contract = self
+      .shared_client
+      .load_contract(
+        self.contract.id.clone(),
+        Some(evolve),
+        None,
+        None,
+        true,
+        false,
+        false,
+      )
+      .await?;
+
+    let state = get_state(&mut self.rt, &self.state, self.reads.big_integers)?;
+    let source = String::from_utf8_lossy(&contract.contract_src).into_owned();
+    self.rt = new_js_runtime(
+      &source,
+      state,
+      &self.contract,
+      self.shared_client,
+      &self.settings,
+      self.reads.clone(),
+      &self.limits,
+    )
+    .await?;
+    self.source = source;
+
+    Ok(())
+  }
+}
+
+#[cfg(feature = "js")]
+async fn new_js_runtime(
+  source: &str,
+  state: Value,
+  contract: &ContractInfo,
+  shared_client: &Arweave,
+  settings: &RuntimeSettings,
+  reads: ReadOptions,
+  limits: &Limits,
+) -> Result<Runtime, AnyError> {
+  // With big integers, the state is set below so that they become BigInts.
+  let init = if reads.big_integers {
+    Value::Null
+  } else {
+    state.clone()
+  };
+  let mut rt = Runtime::with_heap_limit(
+    source,
+    init,
+    arweave_info(shared_client),
+    op_smartweave_read_state,
+    settings.clone(),
+    limits.memory.unwrap_or(DEFAULT_HEAP_LIMIT),
+  )
+  .await?;
+  rt.set_timeout(limits.timeout);
+  if reads.big_integers {
+    rt.set_json_state(&state)?;
+  }
+  rt.set_contract_info(contract.clone())?;
+  rt.set_determinism(reads.determinism);
+  rt.op_state().borrow_mut().put(reads);
+  rt.op_state().borrow_mut().put(shared_client.clone());
+  Ok(rt)
+}
+
+/// The state a JavaScript contract left behind. `previous` is the state it
+/// was given, see [`keep_big_integers`].
+#[cfg(feature = "js")]
+fn get_state(
+  rt: &mut Runtime,
+  previous: &Value,
+  big_integers: bool,
+) -> Result<Value, AnyError> {
+  if big_integers {
+    return rt.json_state();
+  }
+  let mut state: Value = rt.get_contract_state()?;
+  keep_big_integers(previous, &mut state);
+  Ok(state)
+}
+
+#[cfg(feature = "js")]
+fn set_state(
+  rt: &mut Runtime,
+  state: &Value,
+  big_integers: bool,
+) -> Result<(), AnyError> {
+  if big_integers {
+    rt.set_json_state(state)
+  } else {
+    rt.set_contract_state(state)
+  }
+}
+
+/// A number can't hold every integer beyond 2^53, so those of a state come
+/// back from the contract rounded, even the ones it never touched. Puts
+/// back the exact integers of `previous` wherever `state` still holds them
+/// as far as a number can tell.
+#[cfg(feature = "js")]
+fn keep_big_integers(previous: &Value, state: &mut Value) {
+  const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+  match (previous, state) {
+    (Value::Number(exact), Value::Number(number)) => {
+      let unsafe_integer = exact.as_i64().map_or(exact.is_u64(), |n| {
+        !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n)
+      });
+      if unsafe_integer && exact.as_f64() == number.as_f64() {
+        *number = exact.clone();
+      }
+    }
+    (Value::Array(previous), Value::Array(state)) => {
+      for (previous, item) in previous.iter().zip(state.iter_mut()) {
+        keep_big_integers(previous, item);
+      }
+    }
+    (Value::Object(previous), Value::Object(state)) => {
+      for (key, item) in state.iter_mut() {
+        if let Some(previous) = previous.get(key) {
+          keep_big_integers(previous, item);
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+#[cfg(feature = "js")]
+#[async_trait(?Send)]
+impl<'a> ContractRuntime for JsEngine<'a> {
+  async fn init(&mut self) -> Result<(), AnyError> {
+    if self.rt.init().await? {
+      self.state =
+        get_state(&mut self.rt, &self.state, self.reads.big_integers)?;
+    }
+    Ok(())
+  }
+
+  fn track_changes(&mut self) {
+    self.track_changes = true;
+  }
+
+  async fn call(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    self
+      .evaluate(action, context, false, self.track_changes)
+      .await
+  }
+
+  async fn call_genesis(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    self
+      .evaluate(action, context, true, self.track_changes)
+      .await
+  }
+
+  async fn call_readonly(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    let (state, source) = (self.state.clone(), self.source.clone());
+    let change = self.evaluate(action, context, false, true).await?;
+    if change == StateChange::Unchanged && self.source == source {
+      return Ok(change);
+    }
+
+    if self.source != source {
+      self.rt = new_js_runtime(
+        &source,
+        state.clone(),
+        &self.contract,
+        self.shared_client,
+        &self.settings,
+        self.reads.clone(),
+        &self.limits,
+      )
+      .await?;
+      self.source = source;
+    } else {
+      set_state(&mut self.rt, &state, self.reads.big_integers)?;
+    }
+    self.state = state;
+    Err(AnyError::new(Error::StateMutated))
+  }
+
+  fn state(&mut self) -> Value {
+    self.state.clone()
+  }
+
+  fn finish(self, validity: ValidityTable) -> ExecuteResult {
+    ExecuteResult::V8(self.state, validity)
+  }
+}
+
+/// Evaluates WASM contracts, on V8 or Wasmtime depending on the build.
+#[cfg(feature = "wasm")]
+pub struct WasmEngine {
+  rt: WasmRuntime,
+  /// The state as the handler returned it, usually JSON.
+  state: Vec<u8>,
+  /// What the runtime was built from, to rebuild it after a call that left
+  /// it unusable.
+  wasm: Vec<u8>,
+  sandbox_wasi: bool,
+  determinism: DeterminismContext,
+  limits: Limits,
+  /// See [`ContractRuntime::track_changes`].
+  track_changes: bool,
+}
+
+#[cfg(feature = "wasm")]
+impl WasmEngine {
+  pub fn new(
+    wasm: &[u8],
+    state: Vec<u8>,
+    sandbox_wasi: bool,
+    determinism: DeterminismContext,
+    limits: Limits,
+  ) -> Result<WasmEngine, AnyError> {
+    let rt = new_wasm_runtime(wasm, sandbox_wasi, determinism, &limits)?;
+    Ok(Self {
+      rt,
+      state,
+      wasm: wasm.to_vec(),
+      sandbox_wasi,
+      determinism,
+      limits,
+      track_changes: false,
+    })
+  }
+
+  fn evaluate(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+    track_changes: bool,
+  ) -> Result<StateChange, AnyError> {
+    let mut action = serde_json::to_vec(&action)?;
+    // The V8 engine moves the state it is given into the contract memory.
+    let mut input = self.state.clone();
+    let state = match self.rt.call(&mut input, &mut action, context) {
+      Ok(state) => state,
+      Err(err) => {
+        // The memory of the contract never shrinks, and a terminated
+        // isolate runs no more code, so each later call would fail too.
+        if err.downcast_ref::<three_em_wasm::Error>().is_some() {
+          if let Ok(rt) = new_wasm_runtime(
+            &self.wasm,
+            self.sandbox_wasi,
+            self.determinism,
+            &self.limits,
+          ) {
+            self.rt = rt;
+          }
+        }
+        return Err(err);
+      }
+    };
+    if track_changes && state == self.state {
+      return Ok(StateChange::Unchanged);
+    }
+    self.state = state;
+    Ok(StateChange::Changed)
+  }
+}
+
+/// A runtime of `wasm` enforcing `limits`.
+#[cfg(feature = "wasm")]
+pub fn new_wasm_runtime(
+  wasm: &[u8],
+  sandbox_wasi: bool,
+  determinism: DeterminismContext,
+  limits: &Limits,
+) -> Result<WasmRuntime, AnyError> {
+  let mut rt = WasmRuntime::with_wasi_sandbox(wasm, sandbox_wasi)?;
+  rt.set_determinism(determinism);
+  rt.set_gas_limit(limits.gas);
+  rt.set_timeout(limits.timeout);
+  rt.set_memory_limit(limits.memory);
+  Ok(rt)
+}
+
+#[cfg(feature = "wasm")]
+#[async_trait(?Send)]
+impl ContractRuntime for WasmEngine {
+  async fn init(&mut self) -> Result<(), AnyError> {
+    if let Some(state) = self.rt.init(&mut self.state.clone())? {
+      self.state = state;
+    }
+    Ok(())
+  }
+
+  fn track_changes(&mut self) {
+    self.track_changes = true;
+  }
+
+  async fn call(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    self.evaluate(action, context, self.track_changes)
+  }
+
+  async fn call_readonly(
+    &mut self,
+    action: Value,
+    context: InteractionContext,
+  ) -> Result<StateChange, AnyError> {
+    // Each call is handed the state, so keeping the old one undoes it.
+    let state = self.state.clone();
+    match self.evaluate(action, context, true)? {
+      StateChange::Unchanged => Ok(StateChange::Unchanged),
+      StateChange::Changed => {
+        self.state = state;
+        Err(AnyError::new(Error::StateMutated))
+      }
+    }
+  }
+
+  fn state(&mut self) -> Value {
+    wasm_state(&self.state)
+  }
+
+  fn finish(self, validity: ValidityTable) -> ExecuteResult {
+    wasm_result(self.state, validity)
+  }
+}
+
+/// A WASM state as JSON, or as a base64 string when it isn't JSON.
+#[cfg(feature = "wasm")]
+fn wasm_state(state: &[u8]) -> Value {
+  serde_json::from_slice(state)
+    .unwrap_or_else(|_| Value::String(base64::encode(state)))
+}
+
+/// Binary states are returned as is. They aren't cached, the cache only
+/// holds JSON.
+#[cfg(feature = "wasm")]
+pub fn wasm_result(state: Vec<u8>, validity: ValidityTable) -> ExecuteResult {
+  match serde_json::from_slice(&state) {
+    Ok(state) => ExecuteResult::V8(state, validity),
+    Err(_) => ExecuteResult::Bytes(state, validity),
+  }
+}
@@ -1,12 +1,34 @@
-use crate::{get_input_from_interaction, nop_cost_fn};
-use deno_core::error::AnyError;
+#[cfg(feature = "js")]
+use crate::engine::JsEngine;
+#[cfg(feature = "wasm")]
+use crate::engine::{new_wasm_runtime, wasm_result, WasmEngine};
+use crate::engine::{ContractRuntime, StateChange};
+use crate::get_input_from_interaction;
+use crate::input_cache::DECODED_INPUTS;
+use crate::limits::{count_timeout, timeouts, DefaultLimits, Limits};
+#[cfg(feature = "evm")]
+use crate::nop_cost_fn;
+use crate::pause::PauseToken;
+use crate::utils::{check_input_size, parse_json_with_limits, JsonLimits};
+#[cfg(feature = "wasm")]
+use crate::wasm_parallel::{WasmCall, WasmWorkers};
+use deno_core::error::{generic_error, AnyError};
+use deno_core::futures::future::LocalBoxFuture;
+use deno_core::futures::stream::{self, Stream, StreamExt};
 use deno_core::serde_json;
 use deno_core::serde_json::Value;
 use deno_core::OpState;
 use indexmap::map::IndexMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::rc::Rc;
-use three_em_arweave::arweave::get_cache;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use three_em_arweave::arweave::LoadedContract;
 use three_em_arweave::arweave::{Arweave, ArweaveProtocol};
 use three_em_arweave::cache::ArweaveCache;
@@ -16,25 +38,869 @@ use three_em_arweave::gql_result::{
   GQLAmountInterface, GQLEdgeInterface, GQLNodeInterface,
 };
 use three_em_arweave::miscellaneous::ContractType;
+#[cfg(feature = "evm")]
 use three_em_evm::{ExecutionState, Machine, Storage};
-use three_em_js::CallResult;
+#[cfg(feature = "js")]
 use three_em_js::Runtime;
 use three_em_smartweave::{
-  InteractionBlock, InteractionContext, InteractionTx,
+  ContractInfo, DeterminismContext, InteractionBlock, InteractionContext,
+  InteractionTx, RuntimeSettings, Waiting,
 };
+#[cfg(feature = "wasm")]
 use three_em_wasm::WasmRuntime;
+use tracing::{Instrument, Span};
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+  /// The contract's content type doesn't match any engine.
+  UnsupportedContractType {
+    contract_id: String,
+    content_type: String,
+  },
+  /// The interaction comes after the contract's
+  /// [`LoadedContract::max_interactions`].
+  InteractionCapReached { max_interactions: usize },
+  /// [`EvaluateOptions::validator`] rejected the interaction.
+  InteractionRejected { reason: String },
+  /// A foreign read would go deeper than
+  /// [`EvaluateOptions::max_read_depth`].
+  MaxReadDepthExceeded { max_read_depth: usize },
+  /// A contract without a mock is read under [`MockReads::strict`].
+  UnmockedRead { contract_id: String },
+  /// The contract's engine was left out of this build, see the `js`, `wasm`
+  /// and `evm` features.
+  EngineNotEnabled {
+    contract_id: String,
+    engine: &'static str,
+  },
+  /// The interaction changed the state under [`EvaluateOptions::readonly`].
+  StateMutated,
+  /// A read would take the number of distinct contracts read over
+  /// [`EvaluateOptions::max_foreign_reads`].
+  TooManyForeignReads { max_foreign_reads: usize },
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::UnsupportedContractType {
+        contract_id,
+        content_type,
+      } => write!(
+        f,
+        "Contract {} has unsupported content type '{}'",
+        contract_id, content_type
+      ),
+      Error::InteractionCapReached { max_interactions } => write!(
+        f,
+        "Contract accepts at most {} interactions",
+        max_interactions
+      ),
+      Error::InteractionRejected { reason } => write!(f, "{}", reason),
+      Error::MaxReadDepthExceeded { max_read_depth } => write!(
+        f,
+        "Foreign reads can't be nested more than {} deep",
+        max_read_depth
+      ),
+      Error::UnmockedRead { contract_id } => {
+        write!(f, "Contract {} is read but has no mock state", contract_id)
+      }
+      Error::EngineNotEnabled {
+        contract_id,
+        engine,
+      } => write!(
+        f,
+        "Contract {} needs the {} engine, which is not enabled in this build",
+        contract_id, engine
+      ),
+      Error::StateMutated => {
+        write!(f, "Interaction changed the state of a readonly evaluation")
+      }
+      Error::TooManyForeignReads { max_foreign_reads } => write!(
+        f,
+        "An evaluation can't read more than {} distinct contracts",
+        max_foreign_reads
+      ),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+/// Fails with [`Error::UnsupportedContractType`] unless an engine can run
+/// `loaded_contract`, or with [`Error::EngineNotEnabled`] if that engine
+/// isn't part of this build.
+pub fn ensure_supported_contract(
+  loaded_contract: &LoadedContract,
+) -> Result<(), AnyError> {
+  match &loaded_contract.contract_type {
+    ContractType::UNSUPPORTED(content_type) => {
+      Err(AnyError::new(Error::UnsupportedContractType {
+        contract_id: loaded_contract.id.to_owned(),
+        content_type: content_type.to_owned(),
+      }))
+    }
+    contract_type => match disabled_engine(contract_type) {
+      Some(engine) => Err(AnyError::new(Error::EngineNotEnabled {
+        contract_id: loaded_contract.id.to_owned(),
+        engine,
+      })),
+      None => Ok(()),
+    },
+  }
+}
+
+/// The feature `contract_type` is evaluated with, if it is disabled.
+pub(crate) fn disabled_engine(
+  contract_type: &ContractType,
+) -> Option<&'static str> {
+  match contract_type {
+    #[cfg(not(feature = "js"))]
+    ContractType::JAVASCRIPT => Some("js"),
+    #[cfg(not(feature = "wasm"))]
+    ContractType::WASM => Some("wasm"),
+    #[cfg(not(feature = "evm"))]
+    ContractType::EVM => Some("evm"),
+    _ => None,
+  }
+}
+
+/// Validity of each interaction, in evaluation order. Serializing it always
+/// gives the same bytes for the same evaluation.
 pub type ValidityTable = IndexMap<String, Value>;
+
+/// `validity` sorted by interaction id, for comparing tables regardless of
+/// the order interactions were evaluated in.
+pub fn sorted_validity(validity: &ValidityTable) -> BTreeMap<&str, &Value> {
+  validity
+    .iter()
+    .map(|(id, valid)| (id.as_str(), valid))
+    .collect()
+}
 pub type CachedState = Option<Value>;
 
 #[derive(Clone)]
 pub enum ExecuteResult {
   V8(Value, ValidityTable),
+  #[cfg(feature = "evm")]
   Evm(Storage, Vec<u8>, ValidityTable),
+  /// A WASM contract state that isn't JSON, as the contract returned it.
+  Bytes(Vec<u8>, ValidityTable),
+}
+
+impl ExecuteResult {
+  pub fn validity(&self) -> &ValidityTable {
+    match self {
+      ExecuteResult::V8(_, validity) => validity,
+      #[cfg(feature = "evm")]
+      ExecuteResult::Evm(_, _, validity) => validity,
+      ExecuteResult::Bytes(_, validity) => validity,
+    }
+  }
+
+  /// The final state, if it is JSON.
+  pub fn state(&self) -> Option<&Value> {
+    match self {
+      ExecuteResult::V8(state, _) => Some(state),
+      _ => None,
+    }
+  }
+
+  /// The final state, deserialized into `T`. Fails if the state doesn't
+  /// have the shape of `T`, or isn't JSON: EVM storage and binary WASM
+  /// states can't be deserialized.
+  ///
+  /// ```
+  /// use deno_core::serde_json::json;
+  /// use serde::Deserialize;
+  /// use three_em_executor::{ExecuteResult, ValidityTable};
+  ///
+  /// #[derive(Deserialize)]
+  /// struct Users {
+  ///   users: Vec<String>,
+  /// }
+  ///
+  /// let state = json!({ "users": ["Divy", "Andres"] });
+  /// let result = ExecuteResult::V8(state, ValidityTable::new());
+  ///
+  /// let state: Users = result.state_as().unwrap();
+  /// assert_eq!(state.users, ["Divy", "Andres"]);
+  /// assert!(result.state_as::<Vec<String>>().is_err());
+  /// ```
+  pub fn state_as<T: DeserializeOwned>(&self) -> Result<T, AnyError> {
+    match self.state() {
+      Some(state) => Ok(T::deserialize(state)?),
+      None => Err(generic_error("The contract state isn't JSON")),
+    }
+  }
+}
+
+/// How much of an evaluation was served from the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct InteractionCounts {
+  /// Interactions evaluated during this run.
+  pub processed: usize,
+  /// Interactions already reflected in the cached state.
+  pub skipped_from_cache: usize,
+  pub total: usize,
+  /// Interactions terminated for running past their
+  /// [`crate::limits::Limits::timeout`], those of the contracts read
+  /// included. Whether one does depends on the node, so the result isn't
+  /// authoritative when any did: another node may find them valid. Such
+  /// results are never cached. Other evaluations on the same thread may
+  /// add to this, never take from it.
+  pub timed_out: usize,
+}
+
+impl InteractionCounts {
+  pub fn new(processed: usize, total: usize) -> InteractionCounts {
+    InteractionCounts {
+      processed,
+      skipped_from_cache: total.saturating_sub(processed),
+      total,
+      timed_out: 0,
+    }
+  }
+
+  /// Whether every interaction evaluated the same as it would on any other
+  /// node, see [`InteractionCounts::timed_out`].
+  pub fn is_authoritative(&self) -> bool {
+    self.timed_out == 0
+  }
 }
 
 pub type OnCached = dyn Fn() -> ExecuteResult;
 
+/// Orders two interactions. See [`EvaluateOptions::sort`].
+pub type InteractionComparator = Arc<
+  dyn Fn(&GQLEdgeInterface, &GQLEdgeInterface) -> Ordering + Send + Sync,
+>;
+
+/// Receives the id of an evaluated interaction, the state it left behind and
+/// its validity entry. See [`EvaluateOptions::on_step`].
+pub type StepCallback = Arc<dyn Fn(&str, &Value, &Value) + Send + Sync>;
+
+/// Receives the id of an interaction left out of the evaluation. See
+/// [`EvaluateOptions::on_filtered`].
+pub type FilterCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Receives the id of an interaction and the action it is evaluated with.
+/// See [`EvaluateOptions::on_action`].
+pub type ActionCallback = Arc<dyn Fn(&str, &Value) + Send + Sync>;
+
+/// Receives the id of an evaluated interaction and the state it left
+/// behind, and persists it. See [`EvaluateOptions::state_sink`].
+pub type StateSink =
+  Arc<dyn Fn(String, Value) -> LocalBoxFuture<'static, ()> + Send + Sync>;
+
+/// Rewrites a state right before it is cached. See
+/// [`EvaluateOptions::cache_transform`].
+pub type StateTransform = Arc<dyn Fn(&mut Value) + Send + Sync>;
+
+/// Accepts an interaction, or rejects it with a reason. See
+/// [`EvaluateOptions::validator`].
+pub type InteractionValidator =
+  Arc<dyn Fn(&GQLNodeInterface) -> Result<(), String> + Send + Sync>;
+
+/// Derives the `caller` of the action an interaction is evaluated with. See
+/// [`EvaluateOptions::caller`].
+pub type CallerResolver =
+  Arc<dyn Fn(&GQLNodeInterface) -> String + Send + Sync>;
+
+/// A point an embedder has already evaluated a contract up to. See
+/// [`EvaluateOptions::resume`].
+#[derive(Clone, Debug)]
+pub struct Resume {
+  /// The contract state after the first `applied` interactions.
+  pub state: Value,
+  /// How many interactions, in evaluation order, `state` reflects.
+  pub applied: usize,
+  /// Id of the last interaction `state` reflects, if known. Resuming fails
+  /// if another interaction comes at that place, e.g. after a reorg. See
+  /// [`crate::cursor::Cursor`].
+  pub after: Option<String>,
+}
+
+/// A reproducible share of the interactions of a contract. See
+/// [`EvaluateOptions::sample`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+  /// The share of interactions kept, between 0 and 1.
+  pub fraction: f64,
+  /// Picks the interactions making up the share: the same seed keeps the
+  /// same ones, on every run and every node.
+  pub seed: u64,
+}
+
+impl Sample {
+  /// Whether the interaction `id` is part of the sample. Each interaction
+  /// is drawn on its own, from a hash of the seed and its id, so the draw
+  /// doesn't depend on the order or the number of interactions: new ones
+  /// never change whether older ones are kept.
+  pub fn keeps(&self, id: &str) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(self.seed.to_le_bytes());
+    hasher.update(id.as_bytes());
+    let mut draw = [0; 8];
+    draw.copy_from_slice(&hasher.finalize()[..8]);
+    // The 53 high bits, as many as an f64 holds exactly: a draw in [0, 1).
+    let draw = (u64::from_le_bytes(draw) >> 11) as f64 / (1u64 << 53) as f64;
+    draw < self.fraction
+  }
+}
+
+/// See [`EvaluateOptions::max_read_depth`].
+pub const DEFAULT_MAX_READ_DEPTH: usize = 10;
+
+/// See [`EvaluateOptions::max_foreign_reads`].
+pub const DEFAULT_MAX_FOREIGN_READS: usize = 100;
+
+/// The distinct contracts read within one top-level evaluation, shared by
+/// the evaluations its reads start. See
+/// [`EvaluateOptions::max_foreign_reads`].
+#[derive(Clone, Debug, Default)]
+pub struct ForeignContracts(Arc<Mutex<HashSet<String>>>);
+
+impl ForeignContracts {
+  /// Counts a read of `contract_id`. Fails if it is a new contract and
+  /// `max` were already read.
+  fn record(&self, contract_id: &str, max: usize) -> Result<(), AnyError> {
+    let mut read = self.0.lock().unwrap();
+    if read.contains(contract_id) {
+      return Ok(());
+    }
+    if read.len() >= max {
+      return Err(AnyError::new(Error::TooManyForeignReads {
+        max_foreign_reads: max,
+      }));
+    }
+    read.insert(contract_id.to_owned());
+    Ok(())
+  }
+
+  /// Number of distinct contracts read so far.
+  pub fn len(&self) -> usize {
+    self.0.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+/// States handed to contracts reading other contracts, in place of
+/// evaluating them. See [`EvaluateOptions::mock_reads`].
+#[derive(Clone, Debug, Default)]
+pub struct MockReads {
+  /// Mock states by contract id, whatever the height read at.
+  pub states: HashMap<String, Value>,
+  /// Fail reads of contracts without a mock with [`Error::UnmockedRead`],
+  /// instead of evaluating them.
+  pub strict: bool,
+}
+
+/// How foreign reads behave in an evaluation, and where it stands in a
+/// chain of contracts reading each other's state. Kept in the op state of
+/// its JavaScript runtimes.
+#[derive(Clone, Debug)]
+pub struct ReadOptions {
+  /// Number of foreign reads the evaluation is nested in.
+  pub depth: usize,
+  pub max_depth: usize,
+  pub max_foreign_reads: usize,
+  /// Shared with the evaluations nested in the same top-level one.
+  pub contracts_read: ForeignContracts,
+  pub mocks: Option<MockReads>,
+  /// Contracts read see the same time and randomness as the reader.
+  pub determinism: DeterminismContext,
+  /// Contracts read run under the same limits as the reader.
+  pub default_limits: DefaultLimits,
+  pub contract_limits: HashMap<String, Limits>,
+  /// Contracts read are fetched across the same ids.
+  pub aliases: HashMap<String, Vec<String>>,
+  pub big_integers: bool,
+}
+
+/// Options that change how a single evaluation is carried out.
+///
+/// `Default` matches the behaviour of a plain `three_em run`.
+#[derive(Clone, Default)]
+pub struct EvaluateOptions {
+  /// Host capabilities exposed to JavaScript contracts. Allowing wallet
+  /// balances or the unsafe client is
+  /// [counterfactual](Self::is_counterfactual).
+  pub runtime_settings: RuntimeSettings,
+  /// Number of threads used to verify WASM interactions that leave the
+  /// state untouched. `0` and `1` keep the serial interaction loop.
+  ///
+  /// See [`crate::wasm_parallel`] for when this is safe to enable.
+  pub wasm_read_workers: usize,
+  /// Give WASM contracts a WASI context without any capability, see
+  /// [`three_em_wasm::V8WasmRuntime::with_wasi_sandbox`]. Interactions
+  /// that make a WASI call fail instead of reaching the no-op stubs, which
+  /// is [counterfactual](Self::is_counterfactual).
+  pub wasi_sandbox: bool,
+  /// Load the contract, then fetch its interactions, instead of doing both
+  /// at once. The result is the same, only slower; this is a debugging aid
+  /// that keeps the loader's and the fetcher's requests and logs apart.
+  pub sequential_load: bool,
+  /// Limits applied to interaction inputs before they are parsed. Other
+  /// limits than the defaults are [counterfactual](Self::is_counterfactual).
+  pub json_limits: JsonLimits,
+  /// Reuse the parsed inputs of interactions already evaluated in this
+  /// process, see [`crate::input_cache`]. Worth it when the same contracts
+  /// are evaluated repeatedly; results are the same either way.
+  pub cache_inputs: bool,
+  /// Replaces the SmartWeave sort-key ordering of interactions, for
+  /// protocols that order them differently (by nonce, by a tag, ...).
+  ///
+  /// **The order of interactions determines the resulting state.** With a
+  /// custom comparator a contract evaluates to a different state than in any
+  /// standard SmartWeave client, so it is
+  /// [counterfactual](Self::is_counterfactual).
+  pub sort: Option<InteractionComparator>,
+  /// Only evaluate interactions sent by these wallet addresses, as if no
+  /// one else had interacted with the contract.
+  ///
+  /// The resulting state is [counterfactual](Self::is_counterfactual).
+  pub only_owners: Option<HashSet<String>>,
+  /// Called with the id of every interaction `only_owners` or `sample`
+  /// leaves out, for tools that account for each interaction of a
+  /// contract. Never called when the whole result comes from the in-memory
+  /// result cache.
+  pub on_filtered: Option<FilterCallback>,
+  /// Only evaluate a pseudo-random share of the interactions, drawn by
+  /// [`Sample::keeps`], for a quick look at contracts too large to replay
+  /// in full.
+  ///
+  /// **The result is a sampled approximation, not the contract's state.**
+  /// As far as the contract knows the interactions left out never
+  /// happened, so those evaluated may fail or act differently than they
+  /// did, so the result is [counterfactual](Self::is_counterfactual).
+  pub sample: Option<Sample>,
+  /// Deployment-specific rules an interaction must pass to be evaluated,
+  /// e.g. a minimum fee or a given `App-Version` tag.
+  ///
+  /// Called on each interaction before its input is parsed. A rejected
+  /// interaction never reaches the contract, and its validity entry is the
+  /// reason, whether or not errors are shown. The resulting state is
+  /// [counterfactual](Self::is_counterfactual).
+  pub validator: Option<InteractionValidator>,
+  /// Derives the `caller` handed to contracts from the interaction, for
+  /// ecosystems that identify callers by something other than the owner's
+  /// wallet address, e.g. a name carried in a tag. The owner's address,
+  /// as in standard SmartWeave clients, if `None`.
+  ///
+  /// Contracts keying balances or permissions by caller evaluate to another
+  /// state with a resolver, so it is [counterfactual](Self::is_counterfactual).
+  pub caller: Option<CallerResolver>,
+  /// Called with every action built for a contract, `input` and `caller`
+  /// plus the fields of `extended_action`, right before it is handed to
+  /// the engine. A debugging aid for contracts that misread their input.
+  ///
+  /// Interactions rejected before their input is parsed have no action.
+  /// EVM contracts take raw call data instead, so this isn't called for
+  /// them.
+  pub on_action: Option<ActionCallback>,
+  /// Fetch interactions in two phases to keep peak memory bounded.
+  ///
+  /// The sort keys of every interaction (id, owner, block height and id)
+  /// are fetched and sorted first. Full interactions, tags and inputs
+  /// included, are then loaded one gateway page at a time while the
+  /// contract is evaluated, so only a page of them is held in memory. This
+  /// costs one extra round of GraphQL requests, and the key list and
+  /// validity table still grow with the number of interactions.
+  ///
+  /// Evaluations in this mode neither read nor write the interaction and
+  /// state caches, and a custom `sort` is not supported since it needs the
+  /// full interactions to compare.
+  pub bounded_memory: bool,
+  /// Add the interaction's `id`, `tags` and `block` (`height`, `id`,
+  /// `timestamp`) to the action handed to contracts, next to the standard
+  /// `input` and `caller`.
+  ///
+  /// Standard SmartWeave clients don't pass these, so contracts relying on
+  /// them only evaluate correctly with this enabled, which is
+  /// [counterfactual](Self::is_counterfactual).
+  pub extended_action: bool,
+  /// Called after every interaction evaluated in this run, malformed ones
+  /// included, for tools that watch the state evolve.
+  ///
+  /// Handing out the state means reading it back from the engine on every
+  /// interaction, so this is much slower than a plain evaluation. It turns
+  /// off the parallel WASM workers, and isn't called for EVM contracts.
+  pub on_step: Option<StepCallback>,
+  /// Awaited after every interaction evaluated in this run, malformed ones
+  /// included, to push the running state into an external store.
+  ///
+  /// This costs what `on_step` does, and then some: the state is read back
+  /// from the engine and cloned for every interaction, and the next one
+  /// isn't evaluated until the sink's future completes, so a store taking
+  /// a millisecond per write adds a millisecond per interaction. Like
+  /// `on_step`, it turns off the parallel WASM workers and isn't called for
+  /// EVM contracts.
+  pub state_sink: Option<StateSink>,
+  /// Rewrites every state right before it goes into the state cache, e.g.
+  /// to strip large ephemeral fields or redact data from a cache shared
+  /// with others. The state the evaluation returns is left untouched.
+  ///
+  /// **A transformed state is not the contract's state.** Its cache entry
+  /// is marked [`StateResult::transformed`]: evaluations never continue
+  /// from it, so with a transform the cache only saves work for whoever
+  /// reads it with its own tools, and whoever serves it must not pass it
+  /// off as the state of the contract. `getCachedState` flags it.
+  pub cache_transform: Option<StateTransform>,
+  /// Resume from the state cached as of this block timestamp (in seconds)
+  /// and only evaluate interactions whose block timestamp is later.
+  ///
+  /// Every cached evaluation keeps a snapshot of the resulting state, taken
+  /// at the timestamp of the last interaction it covers. The latest
+  /// snapshot taken at or before `since` is used as the base, but only if
+  /// its validity table holds every interaction with a block timestamp up
+  /// to and including `since`. Otherwise, or when the cache is disabled,
+  /// all interactions are evaluated as usual. Either way the result is the
+  /// contract's full state.
+  ///
+  /// Ignored with `bounded_memory`, and by
+  /// [counterfactual](Self::is_counterfactual) evaluations since they bypass
+  /// the state cache.
+  pub since: Option<usize>,
+  /// Evaluate from this init state instead of the one the contract was
+  /// deployed with, e.g. a local file with test values.
+  ///
+  /// The resulting state is [counterfactual](Self::is_counterfactual).
+  pub init_state: Option<String>,
+  /// Continue from a state the caller evaluated and stored itself, skipping
+  /// the interactions it already reflects.
+  ///
+  /// Interactions are sorted, filtered and deduplicated as usual, then the
+  /// first `applied` are skipped and the rest are evaluated on top of
+  /// `state`. The validity table only holds the interactions evaluated in
+  /// this run. Fails if `applied` is more than there are interactions, and
+  /// for EVM contracts.
+  ///
+  /// 3em can't check that `state` is what the skipped interactions lead to,
+  /// so the result is [counterfactual](Self::is_counterfactual).
+  pub resume: Option<Resume>,
+  /// Treat the first sorted interaction as the contract's genesis.
+  ///
+  /// Its handler is called with an empty state object instead of the init
+  /// state, and the state it leaves behind replaces the init state
+  /// outright: init state fields the handler doesn't set are gone, rather
+  /// than carried over as they are when a handler mutates the state it
+  /// receives. Every later interaction is evaluated as usual. If the first
+  /// interaction is invalid, evaluation continues from the init state.
+  ///
+  /// With `resume`, this only applies if nothing was applied yet. Only
+  /// JavaScript contracts support it. Standard SmartWeave clients don't, so
+  /// it is [counterfactual](Self::is_counterfactual).
+  pub genesis_interaction: bool,
+  /// Call the contract's `init` export, if it has one, on the init state
+  /// before the first interaction, and evaluate from the state it derives.
+  ///
+  /// Cached and resumed states went through it already, so it only runs
+  /// when evaluating from the init state. JavaScript and WASM contracts
+  /// only. Other SmartWeave clients never call `init`, so it is
+  /// [counterfactual](Self::is_counterfactual).
+  pub run_init: bool,
+  /// How deep contracts reading each other's state, which read others in
+  /// turn, may go. [`DEFAULT_MAX_READ_DEPTH`] if `None`.
+  ///
+  /// This bounds acyclic but deep chains as much as cycles. The read that would
+  /// go deeper fails with [`Error::MaxReadDepthExceeded`], which invalidates
+  /// the reading interaction unless the contract catches it. Another limit than
+  /// the default is [counterfactual](Self::is_counterfactual).
+  pub max_read_depth: Option<usize>,
+  /// Number of foreign reads this evaluation is nested in. Foreign reads
+  /// set it for the evaluations they start; leave it at `0`.
+  pub read_depth: usize,
+  /// How many distinct contracts one top-level evaluation may read, its
+  /// nested evaluations' reads included. [`DEFAULT_MAX_FOREIGN_READS`] if
+  /// `None`.
+  ///
+  /// This bounds the fan-out of contracts reading many others, whatever
+  /// the depth. Reading a contract already read, at any height, doesn't
+  /// count again, and neither do memoized reads; mocked reads do. The read
+  /// that would go over fails with [`Error::TooManyForeignReads`], which
+  /// invalidates the reading interaction unless the contract catches it.
+  /// As with `max_read_depth`, another limit than the default is
+  /// [counterfactual](Self::is_counterfactual).
+  pub max_foreign_reads: Option<usize>,
+  /// The contracts read by the top-level evaluation this one is nested in.
+  /// Foreign reads set it for the evaluations they start; leave it `None`.
+  pub contracts_read: Option<ForeignContracts>,
+  /// Answer `SmartWeave.contracts.readContractState` with these states
+  /// instead of evaluating the contracts read, to test contracts that read
+  /// others without deploying them. Contracts without a mock are evaluated
+  /// as usual, mocks applying to their reads too, unless the mocks are
+  /// strict.
+  ///
+  /// The resulting state is [counterfactual](Self::is_counterfactual).
+  pub mock_reads: Option<MockReads>,
+  /// What contracts read the time and draw random numbers from, in every
+  /// engine. Tests can pin both to get byte-identical results.
+  ///
+  /// Contracts that depend on them evaluate differently with anything but
+  /// the default, which is [counterfactual](Self::is_counterfactual).
+  pub determinism: DeterminismContext,
+  /// Reject every interaction that changes the state, as
+  /// [`Error::StateMutated`], and put back the state it started from.
+  ///
+  /// This enforces that interactions are views, e.g. untrusted queries run
+  /// against a contract, whatever the contract does with them; it doesn't rely
+  /// on handlers only returning a `result`. An interaction evolving the
+  /// contract counts as a change. Not supported for EVM contracts or with
+  /// `genesis_interaction`. Rejected interactions are valid in the contract's
+  /// history, so it is [counterfactual](Self::is_counterfactual).
+  pub readonly: bool,
+  /// Leave out the interactions mined in the last `confirmations` blocks
+  /// of the chain, which a reorg could still drop, so that evaluations
+  /// near the tip don't change from one run to the next. `0` evaluates up
+  /// to the tip.
+  ///
+  /// The tip is the current network height, so this costs a request to the
+  /// gateway. A height given explicitly is lowered the same way, see
+  /// [`crate::confirmed_height`]. Cached states may cover interactions since
+  /// dropped, so any confirmations are
+  /// [counterfactual](Self::is_counterfactual).
+  pub confirmations: usize,
+  /// Evaluate up to the tip, but cache states and interactions only up to
+  /// `cache_confirmations` blocks below it, so that a reorg never leaves
+  /// the cache holding interactions it dropped. `0` caches up to the tip.
+  ///
+  /// The interactions in the last blocks are fetched and evaluated on top
+  /// of the cached state on every call, and the in-memory result cache,
+  /// which would hold them, is bypassed. The result is the same as without
+  /// this, so it doesn't bypass the state cache; a cache written without
+  /// it may hold interactions at the tip, though. Only applies to
+  /// evaluations of a single contract to the tip, with the cache: with a
+  /// height, or anything that bypasses the state cache, everything is
+  /// evaluated as usual.
+  pub cache_confirmations: usize,
+  /// Gas a WASM interaction may consume, as counted by the
+  /// `3em.consumeGas` calls of metered modules. An interaction going over
+  /// it is invalid. Contracts declaring a [`LoadedContract::max_gas`] get
+  /// the lower of the two; `None` leaves them to their own, if any.
+  ///
+  /// Unmetered modules never consume gas. A limit is
+  /// [counterfactual](Self::is_counterfactual).
+  pub max_gas: Option<usize>,
+  /// The limits interactions run under, by contract type; see
+  /// [`crate::limits`]. `Default` holds a timeout that no real handler
+  /// comes near, so that a contract looping forever only fails its own
+  /// interactions. Other limits are [counterfactual](Self::is_counterfactual).
+  pub default_limits: DefaultLimits,
+  /// Limits of single contracts by id, over the `default_limits` of their
+  /// type: those they leave unset keep the default. Setting any is
+  /// [counterfactual](Self::is_counterfactual).
+  pub contract_limits: HashMap<String, Limits>,
+  /// The other ids of contracts known by more than one, by the id they are
+  /// evaluated as. The interactions naming any of a contract's ids make up
+  /// its history, see [`EvaluateOptions::lineage`].
+  ///
+  /// The ids aren't derived from the contracts: an evolve only replaces
+  /// the source of a contract, so nothing on the chain links an id to
+  /// those the contract had before. Whoever moved the contract lists them.
+  /// Not used by [`crate::execute_contracts`]. States are cached under a
+  /// single id, so setting any is
+  /// [counterfactual](Self::is_counterfactual).
+  pub aliases: HashMap<String, Vec<String>>,
+  /// Hand the integers of a JavaScript contract's state beyond 2^53 to the
+  /// contract as `BigInt`s, and read `BigInt`s it leaves in the state back as
+  /// integers, failing the interaction if one doesn't fit in 64 bits. This is a
+  /// 3em extension that other SmartWeave clients don't have: without it, such
+  /// integers are numbers, as everywhere else, and only those the contract
+  /// leaves alone keep all their digits. Contracts read follow the reader.
+  /// Enabling it is [counterfactual](Self::is_counterfactual).
+  pub big_integers: bool,
+  /// Checked between interactions; once paused, the evaluation stops and
+  /// leaves a checkpoint on the token to resume from, see
+  /// [`crate::pause`]. The result then holds the state and validity of the
+  /// interactions evaluated so far.
+  ///
+  /// Only JavaScript and WASM contracts evaluated one interaction at a
+  /// time can be paused: this turns off the parallel WASM workers, and
+  /// EVM contracts ignore it. The result of a paused evaluation is never
+  /// cached.
+  pub pause: Option<PauseToken>,
+}
+
+/// The [`EvaluateOptions`] that make an evaluation counterfactual. Those
+/// without a meaningful equality, callbacks mostly, count as set or not.
+#[derive(PartialEq)]
+struct HistoryOptions<'a> {
+  sort: bool,
+  only_owners: Option<&'a HashSet<String>>,
+  sample: Option<Sample>,
+  validator: bool,
+  caller: bool,
+  init_state: Option<&'a str>,
+  resume: bool,
+  genesis_interaction: bool,
+  run_init: bool,
+  wasi_sandbox: bool,
+  json_limits: JsonLimits,
+  mock_reads: bool,
+  max_read_depth: usize,
+  max_foreign_reads: usize,
+  determinism: DeterminismContext,
+  readonly: bool,
+  confirmations: usize,
+  max_gas: Option<usize>,
+  default_limits: DefaultLimits,
+  contract_limits: &'a HashMap<String, Limits>,
+  aliases: &'a HashMap<String, Vec<String>>,
+  big_integers: bool,
+  extended_action: bool,
+  allow_wallet_balance: bool,
+  allow_unsafe_client: bool,
+}
+
+impl EvaluateOptions {
+  /// Whether these options evaluate something other than the contract's
+  /// real history, as standard SmartWeave clients evaluate it: whether any
+  /// option that changes which interactions are evaluated, from what state,
+  /// in what order or with what outcome differs from its default. Options
+  /// that only change how the evaluation is carried out, e.g.
+  /// `wasm_read_workers` or `on_step`, don't count.
+  ///
+  /// States are cached by contract id alone, so counterfactual evaluations
+  /// neither read nor write the state and result caches.
+  pub fn is_counterfactual(&self) -> bool {
+    self.history() != EvaluateOptions::default().history()
+  }
+
+  /// The options [`EvaluateOptions::is_counterfactual`] compares to their
+  /// defaults.
+  fn history(&self) -> HistoryOptions {
+    HistoryOptions {
+      sort: self.sort.is_some(),
+      only_owners: self.only_owners.as_ref(),
+      sample: self.sample,
+      validator: self.validator.is_some(),
+      caller: self.caller.is_some(),
+      init_state: self.init_state.as_deref(),
+      resume: self.resume.is_some(),
+      genesis_interaction: self.genesis_interaction,
+      run_init: self.run_init,
+      wasi_sandbox: self.wasi_sandbox,
+      json_limits: self.json_limits,
+      mock_reads: self.mock_reads.is_some(),
+      max_read_depth: self.max_read_depth.unwrap_or(DEFAULT_MAX_READ_DEPTH),
+      max_foreign_reads: self
+        .max_foreign_reads
+        .unwrap_or(DEFAULT_MAX_FOREIGN_READS),
+      determinism: self.determinism,
+      readonly: self.readonly,
+      confirmations: self.confirmations,
+      max_gas: self.max_gas,
+      default_limits: self.default_limits,
+      contract_limits: &self.contract_limits,
+      aliases: &self.aliases,
+      big_integers: self.big_integers,
+      extended_action: self.extended_action,
+      allow_wallet_balance: self.runtime_settings.allow_wallet_balance,
+      allow_unsafe_client: self.runtime_settings.allow_unsafe_client,
+    }
+  }
+
+  /// The gas limit of each interaction of a contract declaring `declared`,
+  /// capped by [`EvaluateOptions::max_gas`].
+  pub fn gas_limit(&self, declared: Option<usize>) -> Option<usize> {
+    match (declared, self.max_gas) {
+      (Some(declared), Some(max_gas)) => Some(declared.min(max_gas)),
+      (declared, max_gas) => declared.or(max_gas),
+    }
+  }
+
+  /// The limits `contract_id`, a contract of `contract_type` declaring
+  /// `declared` gas, runs under: its `contract_limits` over the
+  /// `default_limits` of its type. The gas from
+  /// [`EvaluateOptions::gas_limit`], if any, wins over both.
+  pub fn limits(
+    &self,
+    contract_id: &str,
+    contract_type: &ContractType,
+    declared: Option<usize>,
+  ) -> Limits {
+    let defaults = self.default_limits.get(contract_type);
+    let limits = match self.contract_limits.get(contract_id) {
+      Some(limits) => limits.or(defaults),
+      None => defaults,
+    };
+    Limits {
+      gas: self.gas_limit(declared).or(limits.gas),
+      ..limits
+    }
+  }
+
+  /// The ids whose interactions make up the history of `contract_id`:
+  /// itself, then its [`EvaluateOptions::aliases`].
+  pub fn lineage(&self, contract_id: &str) -> Vec<String> {
+    let mut lineage = vec![contract_id.to_owned()];
+    if let Some(aliases) = self.aliases.get(contract_id) {
+      lineage.extend(aliases.iter().filter(|id| *id != contract_id).cloned());
+    }
+    lineage
+  }
+
+  /// Whether the evaluation was stopped by [`EvaluateOptions::pause`].
+  pub(crate) fn paused(&self) -> bool {
+    self.pause.as_ref().map_or(false, PauseToken::is_paused)
+  }
+
+  /// Whether evaluated interactions are handed out, to `on_step` or
+  /// `state_sink`.
+  pub(crate) fn reports_steps(&self) -> bool {
+    self.on_step.is_some() || self.state_sink.is_some()
+  }
+
+  /// Whether some interactions are left out, by `only_owners` or `sample`.
+  pub(crate) fn filters_interactions(&self) -> bool {
+    self.only_owners.is_some() || self.sample.is_some()
+  }
+
+  pub fn read_options(&self) -> ReadOptions {
+    ReadOptions {
+      depth: self.read_depth,
+      max_depth: self.max_read_depth.unwrap_or(DEFAULT_MAX_READ_DEPTH),
+      max_foreign_reads: self
+        .max_foreign_reads
+        .unwrap_or(DEFAULT_MAX_FOREIGN_READS),
+      contracts_read: self.contracts_read.clone().unwrap_or_default(),
+      mocks: self.mock_reads.clone(),
+      determinism: self.determinism,
+      default_limits: self.default_limits,
+      contract_limits: self.contract_limits.clone(),
+      aliases: self.aliases.clone(),
+      big_integers: self.big_integers,
+    }
+  }
+}
+
+/// Hands an evaluated interaction to [`EvaluateOptions::on_step`] and
+/// [`EvaluateOptions::state_sink`]. The state is only read when there is
+/// one of them, and only once for a run of interactions that leave it
+/// unchanged: `reported` holds it until then.
+async fn report_step<R: ContractRuntime>(
+  options: &EvaluateOptions,
+  id: &str,
+  valid: &Value,
+  rt: &mut R,
+  reported: &mut Option<Value>,
+) {
+  if !options.reports_steps() {
+    return;
+  }
+  let state = reported.get_or_insert_with(|| rt.state());
+  if let Some(on_step) = &options.on_step {
+    on_step(id, state, valid);
+  }
+  if let Some(state_sink) = &options.state_sink {
+    state_sink(id.to_owned(), state.clone()).await;
+  }
+}
+
 pub fn process_execution(
   execute_result: ExecuteResult,
   show_validity: bool,
@@ -50,6 +916,7 @@ pub fn process_execution(
         value
       }
     }
+    #[cfg(feature = "evm")]
     ExecuteResult::Evm(store, result, validity_table) => {
       let store = hex::encode(store.raw());
       let result = hex::encode(result);
@@ -67,25 +934,127 @@ pub fn process_execution(
         })
       }
     }
+    ExecuteResult::Bytes(state, validity_table) => {
+      let state = base64::encode(state);
+
+      if show_validity {
+        serde_json::json!({
+            "state": state,
+            "validity": validity_table
+        })
+      } else {
+        Value::String(state)
+      }
+    }
   }
 }
+/// Foreign contract states read during the lifetime of a runtime, i.e. one
+/// top-level evaluation, keyed by contract id and height.
+///
+/// Interactions up to a given height never change, so neither does the
+/// state they lead to. Reads without a height follow the chain tip and are
+/// never memoized.
+#[derive(Default)]
+struct ForeignReads(HashMap<(String, usize), ExecuteResult>);
+
 pub async fn op_smartweave_read_state(
   state: Rc<RefCell<OpState>>,
   (contract_id, height, show_validity): (String, Option<usize>, Option<bool>),
   _: (),
 ) -> Result<Value, AnyError> {
-  let op_state = state.borrow();
-  let info = op_state.borrow::<three_em_smartweave::ArweaveInfo>();
-  let cl = Arweave::new(
-    info.port,
-    info.host.clone(),
-    info.protocol.clone(),
-    ArweaveCache::new(),
-  );
-  let state =
-    crate::execute_contract(contract_id, height, true, false, None, None, &cl)
-      .await?;
-  Ok(process_execution(state, show_validity.unwrap_or(false)))
+  let show_validity = show_validity.unwrap_or(false);
+  let memo_key = height.map(|height| (contract_id.clone(), height));
+
+  let (cl, reads) = {
+    let op_state = state.borrow();
+    let memoized = memo_key.as_ref().and_then(|key| {
+      op_state.try_borrow::<ForeignReads>()?.0.get(key).cloned()
+    });
+    if let Some(result) = memoized {
+      return Ok(process_execution(result, show_validity));
+    }
+
+    let reads = op_state.borrow::<ReadOptions>().clone();
+    reads
+      .contracts_read
+      .record(&contract_id, reads.max_foreign_reads)?;
+    if let Some(mocks) = &reads.mocks {
+      match mocks.states.get(&contract_id) {
+        Some(state) => {
+          let result = ExecuteResult::V8(state.clone(), ValidityTable::new());
+          return Ok(process_execution(result, show_validity));
+        }
+        None if mocks.strict => {
+          return Err(AnyError::new(Error::UnmockedRead { contract_id }));
+        }
+        None => {}
+      }
+    }
+    if reads.depth >= reads.max_depth {
+      return Err(AnyError::new(Error::MaxReadDepthExceeded {
+        max_read_depth: reads.max_depth,
+      }));
+    }
+
+    // Contracts are read with the client of the evaluation: the same
+    // gateway, headers and download budget.
+    let cl = match op_state.try_borrow::<Arweave>() {
+      Some(cl) => cl.clone(),
+      None => {
+        let info = op_state.borrow::<three_em_smartweave::ArweaveInfo>();
+        Arweave::new(
+          info.port,
+          info.host.clone(),
+          info.protocol.clone(),
+          ArweaveCache::new(),
+        )
+      }
+    };
+    (cl, reads)
+  };
+  let options = EvaluateOptions {
+    max_read_depth: Some(reads.max_depth),
+    read_depth: reads.depth + 1,
+    max_foreign_reads: Some(reads.max_foreign_reads),
+    contracts_read: Some(reads.contracts_read),
+    mock_reads: reads.mocks,
+    determinism: reads.determinism,
+    default_limits: reads.default_limits,
+    contract_limits: reads.contract_limits,
+    aliases: reads.aliases,
+    big_integers: reads.big_integers,
+    ..Default::default()
+  };
+  // The reader's timeout only counts its own code.
+  let waiting = state.borrow().try_borrow::<Waiting>().map(Waiting::enter);
+  let result = crate::execute_contract(
+    contract_id,
+    height,
+    true,
+    false,
+    None,
+    None,
+    &cl,
+    &options,
+  )
+  .await;
+  drop(waiting);
+  let result = result?;
+
+  // No op holds a borrow of the op state across an await, so this can't
+  // clash with other reads or requests of the contract still in flight.
+  if let Some(key) = memo_key {
+    let mut op_state = state.borrow_mut();
+    if !op_state.has::<ForeignReads>() {
+      op_state.put(ForeignReads::default());
+    }
+    op_state
+      .borrow_mut::<ForeignReads>()
+      .0
+      .insert(key, result.clone());
+  }
+
+  Ok(process_execution(result, show_validity))
 }
 
 pub fn generate_interaction_context(
@@ -127,205 +1096,602 @@ pub fn generate_interaction_context(
   }
 }
 
-#[allow(clippy::too_many_arguments)]
-pub async fn raw_execute_contract<
-  CachedCallBack: FnOnce(ValidityTable, CachedState) -> ExecuteResult,
->(
+/// What a contract sees of itself as `SmartWeave.contract`.
+pub fn generate_contract_info(
   contract_id: String,
-  loaded_contract: LoadedContract,
-  interactions: Vec<GQLEdgeInterface>,
-  mut validity: IndexMap<String, Value>,
-  cache_state: Option<Value>,
-  needs_processing: bool,
-  show_errors: bool,
-  on_cached: CachedCallBack,
-  shared_client: &Arweave,
-) -> ExecuteResult {
-  let transaction = (&loaded_contract.contract_transaction).to_owned();
-  let cache = cache_state.is_some();
-  let arweave_info = (
+  loaded_contract: &LoadedContract,
+) -> ContractInfo {
+  ContractInfo {
+    id: contract_id,
+    block: loaded_contract
+      .block
+      .as_ref()
+      .map(|block| InteractionBlock {
+        indep_hash: block.id.to_owned(),
+        height: block.height,
+        timestamp: block.timestamp,
+      }),
+  }
+}
+
+/// The action object a contract handler is called with.
+fn action_object(
+  tx: &GQLNodeInterface,
+  input: Value,
+  options: &EvaluateOptions,
+) -> Value {
+  let caller = match &options.caller {
+    Some(caller) => caller(tx),
+    None => tx.owner.address.to_owned(),
+  };
+  let mut action = serde_json::json!({
+    "input": input,
+    "caller": caller,
+  });
+
+  if options.extended_action {
+    action["id"] = serde_json::json!(tx.id);
+    action["tags"] = serde_json::json!(tx.tags);
+    action["block"] = serde_json::json!({
+      "height": tx.block.height,
+      "id": tx.block.id,
+      "timestamp": tx.block.timestamp,
+    });
+  }
+
+  if let Some(on_action) = &options.on_action {
+    on_action(&tx.id, &action);
+  }
+  action
+}
+
+/// Parses the input of an interaction.
+fn decode_input(
+  tx: &GQLNodeInterface,
+  options: &EvaluateOptions,
+) -> Result<Value, AnyError> {
+  let input = get_input_from_interaction(tx);
+  if options.cache_inputs {
+    DECODED_INPUTS
+      .lock()
+      .unwrap()
+      .decode(&tx.id, input, &options.json_limits)
+  } else {
+    parse_json_with_limits(input, &options.json_limits)
+  }
+}
+
+#[cfg(feature = "wasm")]
+fn wasm_call_input(
+  tx: &GQLNodeInterface,
+  options: &EvaluateOptions,
+) -> Result<Vec<u8>, AnyError> {
+  let wasm_input = decode_input(tx, options)?;
+  let call_input = action_object(tx, wasm_input, options);
+
+  Ok(deno_core::serde_json::to_vec(&call_input)?)
+}
+
+/// The state a WASM contract's `init` export derives from `state`, or
+/// `state` itself if it has none. See [`ContractRuntime::init`].
+#[cfg(feature = "wasm")]
+fn wasm_init_state(
+  wasm: &[u8],
+  state: Vec<u8>,
+  limits: &Limits,
+  options: &EvaluateOptions,
+) -> Result<Vec<u8>, AnyError> {
+  let mut rt =
+    new_wasm_runtime(wasm, options.wasi_sandbox, options.determinism, limits)?;
+  Ok(rt.init(&mut state.clone())?.unwrap_or(state))
+}
+
+/// Rejects an interaction once `seen` interactions, valid or not, are
+/// already in the validity table and the contract declares a cap of at most
+/// that many. Excess interactions are never evaluated.
+fn check_interaction_cap(
+  max_interactions: Option<usize>,
+  seen: usize,
+) -> Result<(), AnyError> {
+  match max_interactions {
+    Some(max_interactions) if seen >= max_interactions => {
+      Err(AnyError::new(Error::InteractionCapReached { max_interactions }))
+    }
+    _ => Ok(()),
+  }
+}
+
+/// Runs [`EvaluateOptions::validator`] on `tx`.
+fn validate_interaction(
+  tx: &GQLNodeInterface,
+  options: &EvaluateOptions,
+) -> Result<(), AnyError> {
+  match &options.validator {
+    Some(validator) => validator(tx)
+      .map_err(|reason| AnyError::new(Error::InteractionRejected { reason })),
+    None => Ok(()),
+  }
+}
+
+/// Validity table entry for an interaction that failed.
+fn invalid_interaction(err: &AnyError, show_errors: bool) -> Value {
+  count_timeout(err);
+  if show_errors {
+    println!("{}", err);
+    serde_json::Value::String(err.to_string())
+  } else if let Some(Error::InteractionRejected { reason }) =
+    err.downcast_ref::<Error>()
+  {
+    serde_json::Value::String(reason.to_owned())
+  } else {
+    serde_json::Value::Bool(false)
+  }
+}
+
+/// The gateway a JavaScript runtime reads foreign contracts from.
+pub fn arweave_info(shared_client: &Arweave) -> (i32, String, String) {
+  (
     shared_client.port.to_owned(),
     shared_client.host.to_owned(),
     match shared_client.protocol.to_owned() {
       ArweaveProtocol::HTTPS => String::from("https"),
       ArweaveProtocol::HTTP => String::from("http"),
     },
-  );
+  )
+}
 
+#[cfg(any(feature = "js", feature = "wasm"))]
+fn parse_init_state(init_state: &str) -> Result<Value, AnyError> {
+  deno_core::serde_json::from_str(init_state)
+    .map_err(|err| generic_error(format!("Invalid init state: {}", err)))
+}
+
+/// Checks that a contract loads and initializes without running any
+/// interaction.
+///
+/// The init state must parse, and the source must compile in the runtime
+/// matching its contract type.
+pub async fn validate_contract(
+  loaded_contract: LoadedContract,
+  shared_client: &Arweave,
+) -> Result<(), AnyError> {
   match loaded_contract.contract_type {
+    #[cfg(feature = "js")]
     ContractType::JAVASCRIPT => {
-      if needs_processing {
-        let state: Value = cache_state.unwrap_or_else(|| {
-          deno_core::serde_json::from_str(&loaded_contract.init_state).unwrap()
-        });
-
-        let mut rt = Runtime::new(
-          &(String::from_utf8(loaded_contract.contract_src).unwrap()),
-          state,
-          arweave_info.to_owned(),
-          op_smartweave_read_state,
-        )
-        .await
-        .unwrap();
-
-        for interaction in interactions {
-          let tx = interaction.node;
-
-          let input = get_input_from_interaction(&tx);
-
-          // TODO: has_multiple_interactions
-          // https://github.com/ArweaveTeam/SmartWeave/blob/4d09c66d832091805f583ba73e8da96cde2c0190/src/contract-read.ts#L68
-          let js_input: Value = deno_core::serde_json::from_str(input).unwrap();
-
-          let call_input = serde_json::json!({
-            "input": js_input,
-            "caller": tx.owner.address
-          });
-
-          let interaction_context = generate_interaction_context(&tx);
-
-          let valid = match rt.call(call_input, Some(interaction_context)).await
-          {
-            Ok(None) => serde_json::Value::Bool(true),
-            Ok(Some(CallResult::Evolve(evolve))) => {
-              let contract = shared_client
-                .load_contract(
-                  contract_id.clone(),
-                  Some(evolve),
-                  None,
-                  None,
-                  true,
-                  false,
-                  false,
-                )
-                .await
-                .unwrap();
-
-              let state: Value = rt.get_contract_state().unwrap();
-              rt = Runtime::new(
-                &(String::from_utf8_lossy(&contract.contract_src)),
-                state,
-                arweave_info.to_owned(),
-                op_smartweave_read_state,
-              )
-              .await
-              .unwrap();
-
-              serde_json::Value::Bool(true)
-            }
-            Ok(Some(CallResult::Result(_))) => serde_json::Value::Bool(true),
-            Err(err) => {
-              if show_errors {
-                println!("{}", err);
-              }
-
-              if show_errors {
-                serde_json::Value::String(err.to_string())
-              } else {
-                serde_json::Value::Bool(false)
-              }
-            }
-          };
-
-          validity.insert(tx.id, valid);
-        }
+      let init_state = parse_init_state(&loaded_contract.init_state)?;
+      let source = String::from_utf8(loaded_contract.contract_src)
+        .map_err(|_| generic_error("Contract source is not valid UTF-8"))?;
 
-        let state_val: Value = rt.get_contract_state().unwrap();
-
-        if cache {
-          get_cache().lock().unwrap().cache_states(
-            contract_id,
-            StateResult {
-              state: state_val.clone(),
-              validity: validity.clone(),
-            },
-          );
-        }
+      let mut rt = Runtime::new(
+        &source,
+        init_state,
+        arweave_info(shared_client),
+        op_smartweave_read_state,
+        RuntimeSettings::default(),
+      )
+      .await?;
 
-        ExecuteResult::V8(state_val, validity)
-      } else {
-        on_cached(validity, cache_state)
+      if !rt.has_handler() {
+        return Err(generic_error("Contract does not export a handle function"));
       }
     }
+    #[cfg(feature = "wasm")]
     ContractType::WASM => {
-      if needs_processing {
-        let wasm = loaded_contract.contract_src.as_slice();
+      parse_init_state(&loaded_contract.init_state)?;
+      WasmRuntime::new(&loaded_contract.contract_src)?;
+    }
+    #[cfg(feature = "evm")]
+    ContractType::EVM => {
+      hex::decode(loaded_contract.contract_src.as_slice()).map_err(|err| {
+        generic_error(format!("Invalid contract bytecode: {}", err))
+      })?;
+      hex::decode(loaded_contract.init_state.as_bytes()).map_err(|err| {
+        generic_error(format!("Invalid account state: {}", err))
+      })?;
+    }
+    // Unsupported types, and engines left out of this build.
+    #[allow(unreachable_patterns)]
+    _ => {
+      ensure_supported_contract(&loaded_contract)?;
+    }
+  }
 
-        let init_state_wasm = if cache_state.is_some() {
-          let cache_state_unwrapped = cache_state.unwrap();
-          let state_str = cache_state_unwrapped.to_string();
-          state_str.as_bytes().to_vec()
-        } else {
-          loaded_contract.init_state.as_bytes().to_vec()
-        };
+  Ok(())
+}
 
-        let mut state = init_state_wasm;
-        let mut rt = WasmRuntime::new(wasm).unwrap();
-
-        for interaction in interactions {
-          let tx = interaction.node;
-
-          let input = get_input_from_interaction(&tx);
-          let wasm_input: Value =
-            deno_core::serde_json::from_str(input).unwrap();
-          let call_input = serde_json::json!({
-            "input": wasm_input,
-            "caller": tx.owner.address,
-          });
-          let interaction_context = generate_interaction_context(&tx);
-
-          let mut input = deno_core::serde_json::to_vec(&call_input).unwrap();
-          let exec = rt.call(&mut state, &mut input, interaction_context);
-          let valid_with_result = match exec {
-            Ok(result) => (serde_json::Value::Bool(true), Some(result)),
-            Err(err) => {
-              if show_errors {
-                println!("{}", err);
-              }
+/// Evaluates interactions one after the other on any engine.
+///
+/// Interactions over the contract's cap or with an input that doesn't
+/// parse are rejected without reaching the handler. With `genesis`, the
+/// first interaction is evaluated with [`ContractRuntime::call_genesis`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn evaluate_interactions<
+  R: ContractRuntime,
+  S: Stream<Item = GQLEdgeInterface> + Unpin,
+>(
+  mut rt: R,
+  mut interactions: S,
+  mut validity: ValidityTable,
+  mut genesis: bool,
+  max_interactions: Option<usize>,
+  show_errors: bool,
+  options: &EvaluateOptions,
+) -> ExecuteResult {
+  // The state last reported to `on_step`, until an interaction changes it.
+  let mut reported: Option<Value> = None;
+  if options.reports_steps() {
+    rt.track_changes();
+  }
+  while let Some(interaction) = interactions.next().await {
+    if let Some(pause) = &options.pause {
+      if pause.is_requested() {
+        pause.stop(rt.state(), &validity, options.resume.as_ref());
+        break;
+      }
+    }
+    let tx = interaction.node;
+    // Only the first interaction can be the genesis one, even if it is
+    // rejected.
+    let is_genesis = std::mem::take(&mut genesis);
 
-              if show_errors {
-                (serde_json::Value::String(err.to_string()), None)
-              } else {
-                (serde_json::Value::Bool(false), None)
-              }
-            }
-          };
-          let valid = valid_with_result.0;
+    let span = tracing::info_span!(
+      "interaction",
+      interaction_id = %tx.id,
+      duration_ms = tracing::field::Empty,
+    );
+    let valid = timed(span, async {
+      // TODO: has_multiple_interactions
+      // https://github.com/ArweaveTeam/SmartWeave/blob/4d09c66d832091805f583ba73e8da96cde2c0190/src/contract-read.ts#L68
+      let input = check_interaction_cap(max_interactions, validity.len())
+        .and_then(|_| validate_interaction(&tx, options))
+        .and_then(|_| decode_input(&tx, options));
+      let action = match input {
+        Ok(input) => action_object(&tx, input, options),
+        Err(err) => return invalid_interaction(&err, show_errors),
+      };
+      let interaction_context = generate_interaction_context(&tx);
 
-          if valid.is_boolean() && valid.as_bool().unwrap() {
-            state = valid_with_result.1.unwrap();
+      let result = if options.readonly {
+        rt.call_readonly(action, interaction_context).await
+      } else if is_genesis {
+        rt.call_genesis(action, interaction_context).await
+      } else {
+        rt.call(action, interaction_context).await
+      };
+      match result {
+        Ok(change) => {
+          if change == StateChange::Changed {
+            reported = None;
           }
-          validity.insert(tx.id, valid);
+          serde_json::Value::Bool(true)
         }
+        Err(err) => invalid_interaction(&err, show_errors),
+      }
+    })
+    .await;
 
-        let state: Value = deno_core::serde_json::from_slice(&state).unwrap();
+    report_step(options, &tx.id, &valid, &mut rt, &mut reported).await;
+    validity.insert(tx.id, valid);
+  }
 
-        if cache {
-          get_cache().lock().unwrap().cache_states(
-            contract_id,
-            StateResult {
-              state: state.clone(),
-              validity: validity.clone(),
-            },
-          );
-        }
+  rt.finish(validity)
+}
 
-        ExecuteResult::V8(state, validity)
-      } else {
-        on_cached(validity, cache_state)
-      }
-    }
-    ContractType::EVM => {
-      // Contract source bytes.
-      let bytecode = hex::decode(loaded_contract.contract_src.as_slice())
-        .expect("Failed to decode contract bytecode");
-      let store = hex::decode(loaded_contract.init_state.as_bytes())
-        .expect("Failed to decode account state");
+/// Runs `future` in `span`, then records how long it took as the span's
+/// `duration_ms` field.
+pub(crate) async fn timed<F: Future>(span: Span, future: F) -> F::Output {
+  let start = Instant::now();
+  let output = future.instrument(span.clone()).await;
+  span.record("duration_ms", &(start.elapsed().as_millis() as u64));
+  output
+}
+
+/// The cache entry of `state`, after the interactions of `validity`,
+/// rewritten by [`EvaluateOptions::cache_transform`] if there is one.
+pub(crate) fn cache_entry(
+  state: &Value,
+  validity: &ValidityTable,
+  options: &EvaluateOptions,
+) -> StateResult {
+  let mut state = state.clone();
+  let transformed = match &options.cache_transform {
+    Some(transform) => {
+      transform(&mut state);
+      true
+    }
+    None => false,
+  };
+  StateResult {
+    state,
+    validity: validity.clone(),
+    transformed,
+  }
+}
+
+/// Caches the state an evaluation ended with. Binary WASM states are
+/// skipped, and so are states over [`CacheExt::max_state_size`].
+fn cache_result(
+  contract_id: String,
+  result: &ExecuteResult,
+  shared_client: &Arweave,
+  options: &EvaluateOptions,
+) {
+  if let ExecuteResult::V8(state, validity) = result {
+    shared_client.try_cache(|cache| {
+      let state = cache_entry(state, validity, options);
+      if !fits_cache(&contract_id, &state, cache.max_state_size())? {
+        return Ok(());
+      }
+      cache.cache_states(contract_id, state)
+    });
+  }
+}
+
+/// Whether `state` is at most `max_state_size` bytes as JSON. Warns when it
+/// isn't, since it is then evaluated again on every run.
+pub(crate) fn fits_cache(
+  contract_id: &str,
+  state: &StateResult,
+  max_state_size: Option<usize>,
+) -> Result<bool, AnyError> {
+  let max_state_size = match max_state_size {
+    Some(max_state_size) => max_state_size,
+    None => return Ok(true),
+  };
+  let size = serde_json::to_vec(state)?.len();
+  if size > max_state_size {
+    tracing::warn!(
+      "Not caching the state of {}: {} bytes is over the {} byte limit",
+      contract_id,
+      size,
+      max_state_size
+    );
+    return Ok(false);
+  }
+  Ok(true)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn raw_execute_contract<
+  CachedCallBack: FnOnce(ValidityTable, CachedState) -> ExecuteResult,
+>(
+  contract_id: String,
+  loaded_contract: LoadedContract,
+  interactions: Vec<GQLEdgeInterface>,
+  validity: IndexMap<String, Value>,
+  cache_state: Option<Value>,
+  needs_processing: bool,
+  show_errors: bool,
+  on_cached: CachedCallBack,
+  shared_client: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
+  raw_execute_contract_stream(
+    contract_id,
+    loaded_contract,
+    stream::iter(interactions),
+    validity,
+    cache_state,
+    needs_processing,
+    show_errors,
+    on_cached,
+    shared_client,
+    options,
+  )
+  .await
+}
+
+/// Same as [`raw_execute_contract`], but pulls interactions from a stream
+/// so they don't all have to be in memory at once.
+///
+/// The stream must yield interactions already in evaluation order. The
+/// parallel WASM path still collects every interaction before running.
+#[allow(clippy::too_many_arguments)]
+pub async fn raw_execute_contract_stream<
+  CachedCallBack: FnOnce(ValidityTable, CachedState) -> ExecuteResult,
+  S: Stream<Item = GQLEdgeInterface> + Unpin,
+>(
+  contract_id: String,
+  loaded_contract: LoadedContract,
+  mut interactions: S,
+  mut validity: IndexMap<String, Value>,
+  cache_state: Option<Value>,
+  needs_processing: bool,
+  show_errors: bool,
+  on_cached: CachedCallBack,
+  shared_client: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
+  let cache = cache_state.is_some();
+  let max_interactions = loaded_contract.max_interactions();
+  #[cfg(any(feature = "js", feature = "wasm"))]
+  let timeouts_before = timeouts();
+  // Cached and resumed states already went through the contract's `init`.
+  #[cfg(any(feature = "js", feature = "wasm"))]
+  let from_init_state = cache_state.is_none()
+    && !options
+      .resume
+      .as_ref()
+      .map_or(false, |resume| resume.applied > 0);
+
+  match loaded_contract.contract_type {
+    #[cfg(feature = "js")]
+    ContractType::JAVASCRIPT => {
+      if needs_processing {
+        let genesis = options.genesis_interaction && from_init_state;
+        let state: Value = cache_state.unwrap_or_else(|| {
+          deno_core::serde_json::from_str(&loaded_contract.init_state).unwrap()
+        });
+
+        let contract =
+          generate_contract_info(contract_id.clone(), &loaded_contract);
+        let limits =
+          options.limits(&contract_id, &ContractType::JAVASCRIPT, None);
+        let source = String::from_utf8(loaded_contract.contract_src).unwrap();
+        let mut rt = JsEngine::new(
+          contract,
+          source,
+          state,
+          shared_client,
+          options.runtime_settings.clone(),
+          options.read_options(),
+          limits,
+        )
+        .await?;
+        if from_init_state && options.run_init {
+          rt.init().await?;
+        }
+
+        let result = evaluate_interactions(
+          rt,
+          interactions,
+          validity,
+          genesis,
+          max_interactions,
+          show_errors,
+          options,
+        )
+        .await;
+
+        if cache && !options.paused() && timeouts() == timeouts_before {
+          cache_result(contract_id, &result, shared_client, options);
+        }
+
+        Ok(result)
+      } else {
+        Ok(on_cached(validity, cache_state))
+      }
+    }
+    #[cfg(feature = "wasm")]
+    ContractType::WASM => {
+      if needs_processing {
+        let wasm = loaded_contract.contract_src.as_slice();
+        let limits = options.limits(
+          &contract_id,
+          &ContractType::WASM,
+          loaded_contract.max_gas(),
+        );
+
+        let init_state_wasm = if cache_state.is_some() {
+          let cache_state_unwrapped = cache_state.unwrap();
+          let state_str = cache_state_unwrapped.to_string();
+          state_str.as_bytes().to_vec()
+        } else {
+          loaded_contract.init_state.as_bytes().to_vec()
+        };
+
+        // The workers don't report the state after each interaction, nor
+        // undo the ones that change it, nor stop halfway.
+        let result = if options.wasm_read_workers > 1
+          && !options.reports_steps()
+          && !options.readonly
+          && options.pause.is_none()
+        {
+          let workers = WasmWorkers::new(
+            wasm,
+            options.wasm_read_workers,
+            options.wasi_sandbox,
+            options.determinism,
+            limits,
+          )?;
+          // Interactions rejected before the call never reach the contract
+          // and leave the state untouched, so they are kept out of the
+          // workers and merged back in order afterwards.
+          let mut entries: Vec<(String, Option<AnyError>)> = vec![];
+          let mut calls: Vec<WasmCall> = vec![];
+          while let Some(interaction) = interactions.next().await {
+            let tx = interaction.node;
+            let seen = validity.len() + entries.len();
+            match check_interaction_cap(max_interactions, seen)
+              .and_then(|_| validate_interaction(&tx, options))
+              .and_then(|_| wasm_call_input(&tx, options))
+            {
+              Ok(action) => {
+                calls.push(WasmCall {
+                  action,
+                  context: generate_interaction_context(&tx),
+                });
+                entries.push((tx.id, None));
+              }
+              Err(err) => entries.push((tx.id, Some(err))),
+            }
+          }
+
+          let init_state_wasm = if from_init_state && options.run_init {
+            wasm_init_state(wasm, init_state_wasm, &limits, options)?
+          } else {
+            init_state_wasm
+          };
+          let (state, outcomes) = workers.run(init_state_wasm, calls);
+
+          let mut outcomes = outcomes.into_iter();
+          for (id, rejected) in entries {
+            let outcome = match rejected {
+              Some(err) => Err(err),
+              None => outcomes.next().unwrap(),
+            };
+            let valid = match outcome {
+              Ok(()) => serde_json::Value::Bool(true),
+              Err(err) => invalid_interaction(&err, show_errors),
+            };
+            validity.insert(id, valid);
+          }
+
+          wasm_result(state, validity)
+        } else {
+          let mut rt = WasmEngine::new(
+            wasm,
+            init_state_wasm,
+            options.wasi_sandbox,
+            options.determinism,
+            limits,
+          )?;
+          if from_init_state && options.run_init {
+            rt.init().await?;
+          }
+
+          evaluate_interactions(
+            rt,
+            interactions,
+            validity,
+            false,
+            max_interactions,
+            show_errors,
+            options,
+          )
+          .await
+        };
+
+        if cache && !options.paused() && timeouts() == timeouts_before {
+          cache_result(contract_id, &result, shared_client, options);
+        }
+
+        Ok(result)
+      } else {
+        Ok(on_cached(validity, cache_state))
+      }
+    }
+    #[cfg(feature = "evm")]
+    ContractType::EVM => {
+      // Contract source bytes.
+      let bytecode = hex::decode(loaded_contract.contract_src.as_slice())
+        .expect("Failed to decode contract bytecode");
+      let store = hex::decode(loaded_contract.init_state.as_bytes())
+        .expect("Failed to decode account state");
 
       let mut account_store = Storage::from_raw(&store);
       let mut result = vec![];
-      for interaction in interactions {
+      while let Some(interaction) = interactions.next().await {
         let tx = interaction.node;
+        if check_interaction_cap(max_interactions, validity.len()).is_err() {
+          validity.insert(tx.id, serde_json::Value::Bool(false));
+          continue;
+        }
+        if let Err(err) = validate_interaction(&tx, options) {
+          validity.insert(tx.id, invalid_interaction(&err, show_errors));
+          continue;
+        }
+
         let block_info =
           shared_client.get_transaction_block(&tx.id).await.unwrap();
 
@@ -340,6 +1706,10 @@ pub async fn raw_execute_contract<
         };
 
         let input = get_input_from_interaction(&tx);
+        if let Err(err) = check_input_size(input, &options.json_limits) {
+          validity.insert(tx.id, invalid_interaction(&err, show_errors));
+          continue;
+        }
         let call_data = hex::decode(input).expect("Failed to decode input");
 
         let mut machine = Machine::new_with_data(nop_cost_fn, call_data);
@@ -377,29 +1747,51 @@ pub async fn raw_execute_contract<
         }
       }
 
-      ExecuteResult::Evm(account_store, result, validity)
+      Ok(ExecuteResult::Evm(account_store, result, validity))
     }
+    // Unsupported types, and engines left out of this build. Entry points
+    // reject these before fetching interactions.
+    #[allow(unreachable_patterns)]
+    _ => Err(ensure_supported_contract(&loaded_contract).unwrap_err()),
   }
 }
 
-#[cfg(test)]
+// Most tests evaluate JavaScript contracts, a few WASM ones.
+#[cfg(all(test, feature = "js", feature = "wasm"))]
 mod tests {
-  use crate::executor::{raw_execute_contract, ExecuteResult};
+  use crate::engine::{ContractRuntime, JsEngine, StateChange};
+  use crate::executor::{
+    ensure_supported_contract, evaluate_interactions, process_execution,
+    raw_execute_contract, sorted_validity, validate_contract, ActionCallback,
+    CallerResolver, Error, EvaluateOptions, ExecuteResult,
+    InteractionValidator, MockReads, Resume, ValidityTable,
+    DEFAULT_MAX_FOREIGN_READS, DEFAULT_MAX_READ_DEPTH,
+  };
+  use crate::limits::{timeouts, DefaultLimits, Limits};
   use crate::test_util::{
     generate_fake_interaction, generate_fake_loaded_contract_data,
   };
+  use crate::utils::JsonLimits;
+  use async_trait::async_trait;
+  use deno_core::error::{generic_error, AnyError};
+  use deno_core::futures::stream;
   use deno_core::serde_json;
   use deno_core::serde_json::Value;
   use indexmap::map::IndexMap;
+  use std::collections::{HashMap, HashSet};
+  use std::sync::Arc;
   use three_em_arweave::arweave::Arweave;
-  use three_em_arweave::arweave::{LoadedContract, TransactionData};
+  use three_em_arweave::arweave::{LoadedContract, Tag, TransactionData};
   use three_em_arweave::cache::ArweaveCache;
   use three_em_arweave::cache::CacheExt;
   use three_em_arweave::gql_result::{
     GQLAmountInterface, GQLBlockInterface, GQLEdgeInterface, GQLNodeInterface,
     GQLOwnerInterface, GQLTagInterface,
   };
-  use three_em_arweave::miscellaneous::ContractType;
+  use three_em_arweave::miscellaneous::{get_contract_type_raw, ContractType};
+  use three_em_smartweave::{
+    ContractInfo, InteractionContext, RuntimeSettings,
+  };
 
   #[tokio::test]
   async fn test_globals_js() {
@@ -452,8 +1844,10 @@ mod tests {
         String::from("https"),
         ArweaveCache::new(),
       ),
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await
+    .unwrap();
 
     if let ExecuteResult::V8(value, validity) = result {
       assert_eq!(
@@ -516,8 +1910,10 @@ mod tests {
         String::from("https"),
         ArweaveCache::new(),
       ),
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await
+    .unwrap();
 
     if let ExecuteResult::V8(value, validity) = result {
       let x = serde_json::json!({
@@ -531,67 +1927,39 @@ mod tests {
   }
 
   #[tokio::test]
-  pub async fn test_executor_js() {
-    let init_state = serde_json::json!({
-      "users": []
-    });
-
-    let fake_contract = generate_fake_loaded_contract_data(
-      include_bytes!("../../testdata/contracts/users_contract.js"),
-      ContractType::JAVASCRIPT,
-      init_state.to_string(),
-    );
-    let fake_interactions = vec![
-      generate_fake_interaction(
-        serde_json::json!({
-          "function": "add",
-          "name": "Andres"
-        }),
-        "tx1",
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-      ),
-      generate_fake_interaction(
-        serde_json::json!({
-          "function": "none",
-          "name": "Tate"
-        }),
-        "tx2",
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-      ),
-      generate_fake_interaction(
-        serde_json::json!({
-          "function": "add",
-          "name": "Divy"
-        }),
-        "tx3",
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-      ),
-    ];
+  async fn test_genesis_interaction() {
+    let evaluate = |genesis_interaction| async move {
+      let fake_contract = generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/genesis.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"deployer":"placeholder","supply":0,"balances":{}}"#),
+      );
+      let fake_interactions = vec![
+        (
+          serde_json::json!({"function": "genesis", "supply": 100}),
+          "tx1",
+        ),
+        (serde_json::json!({"function": "mint", "qty": 5}), "tx2"),
+      ]
+      .into_iter()
+      .map(|(input, id)| {
+        generate_fake_interaction(
+          input,
+          id,
+          None,
+          None,
+          Some(String::from("alice")),
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect();
 
-    let execute = || async {
-      let result = raw_execute_contract(
-        String::new(),
+      raw_execute_contract(
+        String::from("genesis"),
         fake_contract,
         fake_interactions,
         IndexMap::new(),
@@ -607,99 +1975,1849 @@ mod tests {
           String::from("https"),
           ArweaveCache::new(),
         ),
+        &EvaluateOptions {
+          genesis_interaction,
+          ..Default::default()
+        },
       )
-      .await;
-
-      result
+      .await
+      .unwrap()
     };
 
-    if let ExecuteResult::V8(value, validity) = execute().await {
-      assert_eq!(validity.len(), 3);
-      let (tx1, tx2, tx3) = (
-        validity.get("tx1"),
-        validity.get("tx2"),
-        validity.get("tx3"),
-      );
-      assert_eq!(tx1.is_some(), true);
-      assert_eq!(tx2.is_some(), true);
-      assert_eq!(tx3.is_some(), true);
-      assert_eq!((tx1.unwrap()).to_owned(), true);
-      assert_eq!((tx2.unwrap()).to_owned(), false);
-      assert_eq!((tx3.unwrap()).to_owned(), true);
-
-      let value_state = value.get("users");
-      assert_eq!(value_state.is_some(), true);
-      let users = value_state
-        .unwrap()
-        .to_owned()
-        .as_array()
-        .unwrap()
-        .to_owned();
+    if let ExecuteResult::V8(value, validity) = evaluate(true).await {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
       assert_eq!(
-        users.get(0).unwrap().to_owned(),
-        serde_json::json!("Andres")
+        value,
+        serde_json::json!({
+          "owner": "alice",
+          "supply": 105,
+          "balances": {"alice": 105},
+        })
       );
-      assert_eq!(users.get(1).unwrap().to_owned(), serde_json::json!("Divy"));
     } else {
-      panic!("Failed");
+      panic!("Unexpected entry");
+    }
+
+    // By default the first interaction only adds to the init state.
+    if let ExecuteResult::V8(value, _) = evaluate(false).await {
+      assert_eq!(value["deployer"], serde_json::json!("placeholder"));
+      assert_eq!(value["supply"], serde_json::json!(105));
+    } else {
+      panic!("Unexpected entry");
     }
   }
 
   #[tokio::test]
-  async fn test_contract_evolve() {
-    let init_state = serde_json::json!({
-      "canEvolve": true,
-      "v": 0,
-      "i": 0,
-      "pi": 0
-    });
-
-    let fake_contract = generate_fake_loaded_contract_data(
-      include_bytes!("../../testdata/evolve/evolve1.js"),
-      ContractType::JAVASCRIPT,
-      init_state.to_string(),
+  async fn test_init_export() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
     );
-
-    let fake_interactions = vec![
-      generate_fake_interaction(
-        serde_json::json!({
-          "function": "evolve",
-          // Contract Source for "Zwp7r7Z10O0TuF6lmFApB7m5lJIrE5RbLAVWg_WKNcU"
-          "value": "C0F9QvOOJNR2DDIicWeL9B-C5vFrtczmOjpW_3FCQBQ",
-        }),
+    let transfer = || {
+      vec![generate_fake_interaction(
+        serde_json::json!({"function": "transfer", "qty": 10, "target": "bob"}),
         "tx1",
         None,
         None,
+        Some(String::from("alice")),
         None,
         None,
         None,
         None,
         None,
+      )]
+    };
+    let evaluate = |init_state: Value, options: EvaluateOptions| async move {
+      raw_execute_contract(
+        String::from("init"),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/contracts/init_contract.js"),
+          ContractType::JAVASCRIPT,
+          init_state.to_string(),
+        ),
+        transfer(),
+        IndexMap::new(),
         None,
-      ),
-      generate_fake_interaction(
-        serde_json::json!({
-          "function": "contribute",
-        }),
-        "tx2",
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-      ),
-    ];
+        true,
+        false,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &arweave,
+        &options,
+      )
+      .await
+      .unwrap()
+    };
 
-    let result = raw_execute_contract(
-      String::from("Zwp7r7Z10O0TuF6lmFApB7m5lJIrE5RbLAVWg_WKNcU"),
+    let init_state =
+      serde_json::json!({ "holders": ["alice", "bob"], "supply": 100 });
+    let options = EvaluateOptions {
+      run_init: true,
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    if let ExecuteResult::V8(value, validity) =
+      evaluate(init_state.clone(), options).await
+    {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(
+        value["balances"],
+        serde_json::json!({"alice": 40, "bob": 60})
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // Without `run_init`, `init` is never called.
+    if let ExecuteResult::V8(value, validity) =
+      evaluate(init_state.clone(), EvaluateOptions::default()).await
+    {
+      assert_eq!(validity.get("tx1").unwrap(), &false);
+      assert_eq!(value, init_state);
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // A resumed state went through `init` already.
+    let state = serde_json::json!({ "balances": { "alice": 10, "bob": 0 } });
+    let options = EvaluateOptions {
+      resume: Some(Resume {
+        state: state.clone(),
+        applied: 1,
+        after: None,
+      }),
+      run_init: true,
+      ..Default::default()
+    };
+    if let ExecuteResult::V8(value, _) = evaluate(state, options).await {
+      assert_eq!(
+        value["balances"],
+        serde_json::json!({"alice": 0, "bob": 10})
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // WASM contracts, on one engine or split across workers.
+    for (workers, run_init) in [(0, true), (4, true), (0, false), (4, false)] {
+      let result = raw_execute_contract(
+        String::from("init"),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/08_wasm/08_wasm.wasm"),
+          ContractType::WASM,
+          init_state.to_string(),
+        ),
+        transfer(),
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &arweave,
+        &EvaluateOptions {
+          wasm_read_workers: workers,
+          run_init,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+
+      if let ExecuteResult::V8(value, validity) = result {
+        assert_eq!(validity.get("tx1").unwrap(), &true);
+        if run_init {
+          assert_eq!(value, serde_json::json!({ "init": init_state }));
+        } else {
+          assert_eq!(value, init_state);
+        }
+      } else {
+        panic!("Unexpected entry");
+      }
+    }
+
+    // An `init` that throws fails the evaluation instead of panicking.
+    let result = raw_execute_contract(
+      String::from("init"),
+      generate_fake_loaded_contract_data(
+        b"export function init() { throw new Error('bad init'); }
+          export function handle(state) { return { state }; }",
+        ContractType::JAVASCRIPT,
+        init_state.to_string(),
+      ),
+      transfer(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &EvaluateOptions {
+        run_init: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_big_integer_state() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let transfer = |id: &str, caller: &str, qty: u64| {
+      generate_fake_interaction(
+        serde_json::json!({"function": "transfer", "qty": qty, "target": "bob"}),
+        id,
+        None,
+        None,
+        Some(String::from(caller)),
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    };
+
+    // Past 2^53, where doubles start skipping integers.
+    let init_state = r#"{
+      "balances": { "alice": 100, "whale": 9007199254740993 },
+      "supply": 18446744073709551615
+    }"#;
+    let result = raw_execute_contract(
+      String::from("big"),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/big_balances.js"),
+        ContractType::JAVASCRIPT,
+        String::from(init_state),
+      ),
+      vec![transfer("tx1", "alice", 10), transfer("tx2", "whale", 3)],
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &EvaluateOptions {
+        big_integers: true,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(validity.get("tx2").unwrap(), &true);
+      assert_eq!(
+        value,
+        serde_json::json!({
+          "balances": {
+            "alice": 90,
+            "bob": 13,
+            "whale": 9007199254740990u64,
+          },
+          "supply": u64::MAX,
+        })
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_big_integers_as_numbers() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let mint = generate_fake_interaction(
+      serde_json::json!({"function": "mint", "qty": 5}),
+      "tx1",
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+    );
+
+    // The contract only ever sees numbers, but the integers it leaves alone
+    // keep all their digits.
+    let init_state = r#"{
+      "owner": "alice",
+      "supply": 100,
+      "balances": { "alice": 100, "whale": 9007199254740993 },
+      "cap": 18446744073709551615
+    }"#;
+    let result = raw_execute_contract(
+      String::from("numbers"),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/genesis.js"),
+        ContractType::JAVASCRIPT,
+        String::from(init_state),
+      ),
+      vec![mint],
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(value["balances"]["alice"], 105);
+      assert_eq!(value["balances"]["whale"], 9007199254740993u64);
+      assert_eq!(value["cap"], u64::MAX);
+      assert_eq!(value["supply"], 105);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_readonly() {
+    let evaluate = |readonly| async move {
+      let fake_contract = generate_fake_loaded_contract_data(
+        br#"
+          export async function handle(state, action) {
+            if (action.input.function === "count") {
+              return { result: state.users.length };
+            }
+            state.users.push(action.input.name);
+            return { state };
+          }
+        "#,
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      );
+      let fake_interactions = vec![
+        (serde_json::json!({"function": "count"}), "tx1"),
+        (
+          serde_json::json!({"function": "add", "name": "Divy"}),
+          "tx2",
+        ),
+        (serde_json::json!({"function": "count"}), "tx3"),
+      ]
+      .into_iter()
+      .map(|(input, id)| {
+        generate_fake_interaction(
+          input, id, None, None, None, None, None, None, None, None,
+        )
+      })
+      .collect();
+
+      raw_execute_contract(
+        String::from("readonly"),
+        fake_contract,
+        fake_interactions,
+        IndexMap::new(),
+        None,
+        true,
+        true,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &Arweave::new_no_cache(
+          1,
+          String::from("127.0.0.1"),
+          String::from("http"),
+        ),
+        &EvaluateOptions {
+          readonly,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap()
+    };
+
+    if let ExecuteResult::V8(value, validity) = evaluate(true).await {
+      assert_eq!(value, serde_json::json!({ "users": [] }));
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(
+        validity.get("tx2").unwrap(),
+        &serde_json::json!(Error::StateMutated.to_string())
+      );
+      assert_eq!(validity.get("tx3").unwrap(), &true);
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    if let ExecuteResult::V8(value, validity) = evaluate(false).await {
+      assert_eq!(value, serde_json::json!({ "users": ["Divy"] }));
+      assert!(validity.values().all(|valid| valid == &true));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_js_read_contract_memoized() {
+    let fake_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/contracts/read_sibling.js"),
+      ContractType::JAVASCRIPT,
+      String::from("{}"),
+    );
+    let fake_interactions = ["tx1", "tx2", "tx3"]
+      .iter()
+      .map(|id| {
+        generate_fake_interaction(
+          serde_json::json!({}),
+          id,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect();
+
+    let result = raw_execute_contract(
+      String::from("read_sibling"),
       fake_contract,
       fake_interactions,
       IndexMap::new(),
       None,
       true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert!(validity.values().all(|valid| valid == &true));
+      assert_eq!(value["reads"], serde_json::json!(3));
+      assert!(value["siblingKeys"].as_u64().unwrap() > 0);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_js_read_contract_mocked() {
+    let evaluate = |source: &[u8], mock_reads: MockReads| {
+      let fake_contract = generate_fake_loaded_contract_data(
+        source,
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      );
+      let fake_interactions = ["tx1", "tx2"]
+        .iter()
+        .map(|id| {
+          generate_fake_interaction(
+            serde_json::json!({}),
+            id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect();
+
+      async move {
+        raw_execute_contract(
+          String::from("read_mocked"),
+          fake_contract,
+          fake_interactions,
+          IndexMap::new(),
+          None,
+          true,
+          true,
+          |_, _| {
+            panic!("not implemented");
+          },
+          &Arweave::new(
+            443,
+            "arweave.net".to_string(),
+            String::from("https"),
+            ArweaveCache::new(),
+          ),
+          &EvaluateOptions {
+            mock_reads: Some(mock_reads),
+            ..Default::default()
+          },
+        )
+        .await
+        .unwrap()
+      }
+    };
+
+    let sibling = "0zplqhFARjHyR-dBdNEY1TpZuKL0mWm--RFq6LByoew";
+    let mock_reads = MockReads {
+      states: HashMap::from([(
+        String::from(sibling),
+        serde_json::json!({ "a": 1, "b": 2 }),
+      )]),
+      strict: true,
+    };
+    let result = evaluate(
+      include_bytes!("../../testdata/contracts/read_sibling.js"),
+      mock_reads,
+    )
+    .await;
+    if let ExecuteResult::V8(value, validity) = result {
+      assert!(validity.values().all(|valid| valid == &true));
+      assert_eq!(value, serde_json::json!({ "reads": 2, "siblingKeys": 2 }));
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // Strict mocks without the sibling fail the read instead of evaluating
+    // it.
+    let mock_reads = MockReads {
+      strict: true,
+      ..Default::default()
+    };
+    let result = evaluate(
+      include_bytes!("../../testdata/contracts/read_contact.js"),
+      mock_reads,
+    )
+    .await;
+    if let ExecuteResult::V8(value, _) = result {
+      assert_eq!(
+        value,
+        serde_json::json!(format!(
+          "Error: Contract {} is read but has no mock state",
+          sibling
+        ))
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_max_foreign_reads() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let mock_reads = MockReads {
+      states: (0..5)
+        .map(|i| (format!("sibling-{}", i), serde_json::json!({})))
+        .collect(),
+      strict: true,
+    };
+    let evaluate = |max_foreign_reads: Option<usize>| {
+      let options = EvaluateOptions {
+        mock_reads: Some(mock_reads.clone()),
+        max_foreign_reads,
+        ..Default::default()
+      };
+      let arweave = &arweave;
+      async move {
+        raw_execute_contract(
+          String::from("read_many"),
+          generate_fake_loaded_contract_data(
+            include_bytes!("../../testdata/contracts/read_many.js"),
+            ContractType::JAVASCRIPT,
+            String::from("{}"),
+          ),
+          vec![generate_fake_interaction(
+            serde_json::json!({ "count": 5 }),
+            "tx1",
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )],
+          IndexMap::new(),
+          None,
+          true,
+          true,
+          |_, _| panic!("not implemented"),
+          arweave,
+          &options,
+        )
+        .await
+        .unwrap()
+      }
+    };
+
+    // Each sibling only counts once, however often it is read.
+    for max_foreign_reads in [None, Some(5)] {
+      let result = evaluate(max_foreign_reads).await;
+      assert_eq!(result.validity().get("tx1").unwrap(), &true);
+      assert_eq!(result.state().unwrap(), &serde_json::json!({ "read": 5 }));
+    }
+
+    let result = evaluate(Some(3)).await;
+    let error = Error::TooManyForeignReads {
+      max_foreign_reads: 3,
+    };
+    let valid = result.validity().get("tx1").unwrap();
+    assert!(valid.as_str().unwrap().contains(&error.to_string()));
+    assert_eq!(result.state().unwrap(), &serde_json::json!({}));
+  }
+
+  #[tokio::test]
+  async fn test_js_wallet_balance() {
+    let init_state = serde_json::json!({});
+    let interaction = || {
+      vec![generate_fake_interaction(
+        serde_json::json!({}),
+        "tx1",
+        None,
+        None,
+        Some(String::from("vh-NTHVvlKZqRxc8LyyTNok65yQ55a_PJ1zWLb9G2JI")),
+        None,
+        None,
+        None,
+        None,
+        None,
+      )]
+    };
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/wallet_balance.js"),
+        ContractType::JAVASCRIPT,
+        init_state.to_string(),
+      ),
+      interaction(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &false);
+      assert_eq!(value, init_state);
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // Balances are read as they are now, not at the evaluated height.
+    let options = EvaluateOptions {
+      runtime_settings: RuntimeSettings {
+        allow_wallet_balance: true,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/wallet_balance.js"),
+        ContractType::JAVASCRIPT,
+        init_state.to_string(),
+      ),
+      interaction(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &options,
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      let balance = value.get("balance").unwrap().as_str().unwrap();
+      assert!(balance.chars().all(|c| c.is_ascii_digit()));
+      assert_eq!(value.get("memoized").unwrap(), &true);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_js_unsafe_client() {
+    let init_state = serde_json::json!({});
+    let interaction = || {
+      vec![generate_fake_interaction(
+        serde_json::json!({
+          "id": "YzVdaDBnaiGToFQJAnJCGtyJwJZbaCASotWEPFhBgBY"
+        }),
+        "tx1",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )]
+    };
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/unsafe_client.js"),
+        ContractType::JAVASCRIPT,
+        init_state.to_string(),
+      ),
+      interaction(),
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    if let ExecuteResult::V8(value, validity) = result {
+      let error = validity.get("tx1").unwrap().as_str().unwrap();
+      assert!(error.contains("unsafeClient is disabled"));
+      assert_eq!(value, init_state);
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // The gateway serves whatever it has at evaluation time.
+    let options = EvaluateOptions {
+      runtime_settings: RuntimeSettings {
+        allow_unsafe_client: true,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/unsafe_client.js"),
+        ContractType::JAVASCRIPT,
+        init_state.to_string(),
+      ),
+      interaction(),
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &options,
+    )
+    .await
+    .unwrap();
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert!(value.get("length").unwrap().as_u64().unwrap() > 0);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_js_extensions() {
+    let interaction = |id: &str, input: Value| {
+      generate_fake_interaction(
+        input, id, None, None, None, None, None, None, None, None,
+      )
+    };
+    // RFC 8032, test 1: the signature of an empty message.
+    let public_key =
+      "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    let signature = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065\
+                     224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24\
+                     655141438e7a100b";
+    let interactions = vec![
+      interaction(
+        "tx1",
+        serde_json::json!({ "function": "hash", "data": "abc" }),
+      ),
+      interaction(
+        "tx2",
+        serde_json::json!({
+          "function": "verify",
+          "publicKey": public_key,
+          "message": "",
+          "signature": signature,
+        }),
+      ),
+      interaction("tx3", serde_json::json!({ "function": "random" })),
+    ];
+    let arweave =
+      Arweave::new_no_cache(1, String::from("127.0.0.1"), String::from("http"));
+    let evaluate = |interactions: Vec<GQLEdgeInterface>,
+                    runtime_settings: RuntimeSettings| {
+      let options = EvaluateOptions {
+        runtime_settings,
+        ..Default::default()
+      };
+      let arweave = &arweave;
+      async move {
+        raw_execute_contract(
+          String::new(),
+          generate_fake_loaded_contract_data(
+            include_bytes!("../../testdata/contracts/extensions.js"),
+            ContractType::JAVASCRIPT,
+            String::from("{}"),
+          ),
+          interactions,
+          IndexMap::new(),
+          None,
+          true,
+          false,
+          |_, _| panic!("not implemented"),
+          arweave,
+          &options,
+        )
+        .await
+        .unwrap()
+      }
+    };
+
+    let result =
+      evaluate(interactions.clone(), RuntimeSettings::default()).await;
+    let validity = result.validity();
+    assert_eq!(validity.get("tx1").unwrap(), &true);
+    assert_eq!(validity.get("tx2").unwrap(), &true);
+    // Random bytes are off by default.
+    assert_eq!(validity.get("tx3").unwrap(), &false);
+    assert_eq!(
+      result.state().unwrap(),
+      &serde_json::json!({
+        "keccak256":
+          "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+        "sha256":
+          "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        "verified": true,
+      })
+    );
+
+    // Another message doesn't verify with the same signature.
+    let tampered = interaction(
+      "tx4",
+      serde_json::json!({
+        "function": "verify",
+        "publicKey": public_key,
+        "message": "tampered",
+        "signature": signature,
+      }),
+    );
+    let result = evaluate(
+      vec![tampered, interactions[2].clone()],
+      RuntimeSettings {
+        allow_random_extension: true,
+        ..Default::default()
+      },
+    )
+    .await;
+    assert_eq!(result.validity().get("tx3").unwrap(), &true);
+    assert_eq!(
+      result.state().unwrap(),
+      &serde_json::json!({ "verified": false, "random": 8 })
+    );
+  }
+
+  #[tokio::test]
+  pub async fn test_executor_js() {
+    let init_state = serde_json::json!({
+      "users": []
+    });
+
+    let fake_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/contracts/users_contract.js"),
+      ContractType::JAVASCRIPT,
+      init_state.to_string(),
+    );
+    let fake_interactions = vec![
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": "Andres"
+        }),
+        "tx1",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "none",
+          "name": "Tate"
+        }),
+        "tx2",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": "Divy"
+        }),
+        "tx3",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+    ];
+
+    let execute = || async {
+      let result = raw_execute_contract(
+        String::new(),
+        fake_contract,
+        fake_interactions,
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &Arweave::new(
+          443,
+          "arweave.net".to_string(),
+          String::from("https"),
+          ArweaveCache::new(),
+        ),
+        &EvaluateOptions::default(),
+      )
+      .await
+      .unwrap();
+
+      result
+    };
+
+    if let ExecuteResult::V8(value, validity) = execute().await {
+      assert_eq!(validity.len(), 3);
+      let (tx1, tx2, tx3) = (
+        validity.get("tx1"),
+        validity.get("tx2"),
+        validity.get("tx3"),
+      );
+      assert_eq!(tx1.is_some(), true);
+      assert_eq!(tx2.is_some(), true);
+      assert_eq!(tx3.is_some(), true);
+      assert_eq!((tx1.unwrap()).to_owned(), true);
+      assert_eq!((tx2.unwrap()).to_owned(), false);
+      assert_eq!((tx3.unwrap()).to_owned(), true);
+
+      let value_state = value.get("users");
+      assert_eq!(value_state.is_some(), true);
+      let users = value_state
+        .unwrap()
+        .to_owned()
+        .as_array()
+        .unwrap()
+        .to_owned();
+      assert_eq!(
+        users.get(0).unwrap().to_owned(),
+        serde_json::json!("Andres")
+      );
+      assert_eq!(users.get(1).unwrap().to_owned(), serde_json::json!("Divy"));
+    } else {
+      panic!("Failed");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_contract_evolve() {
+    let init_state = serde_json::json!({
+      "canEvolve": true,
+      "v": 0,
+      "i": 0,
+      "pi": 0
+    });
+
+    let fake_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/evolve/evolve1.js"),
+      ContractType::JAVASCRIPT,
+      init_state.to_string(),
+    );
+
+    let fake_interactions = vec![
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "evolve",
+          // Contract Source for "Zwp7r7Z10O0TuF6lmFApB7m5lJIrE5RbLAVWg_WKNcU"
+          "value": "C0F9QvOOJNR2DDIicWeL9B-C5vFrtczmOjpW_3FCQBQ",
+        }),
+        "tx1",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "contribute",
+        }),
+        "tx2",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+    ];
+
+    let result = raw_execute_contract(
+      String::from("Zwp7r7Z10O0TuF6lmFApB7m5lJIrE5RbLAVWg_WKNcU"),
+      fake_contract,
+      fake_interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      // tx1 is the evolve action. This must not fail.
+      // All network calls happen here and runtime is
+      // re-initialized.
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      // tx2 is the interaction to the evolved source.
+      // In this case, the pi contract.
+      assert_eq!(validity.get("tx2").unwrap(), &true);
+
+      let value_state = value.get("canEvolve");
+      assert_eq!(value_state.is_some(), true);
+
+      assert_eq!(value.get("v").unwrap(), 0.6666666666666667_f64);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_wasm_contract_interactions_context() {
+    let init_state = serde_json::json!({
+      "txId": "",
+      "owner": "",
+      "height": 0
+    });
+
+    let fake_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/03_wasm/03_wasm.wasm"),
+      ContractType::WASM,
+      init_state.to_string(),
+    );
+
+    let fake_interactions = vec![
+      generate_fake_interaction(
+        serde_json::json!({}),
+        "POCAHONTAS",
+        None,
+        Some(100),
+        Some(String::from("ADDRESS")),
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+      generate_fake_interaction(
+        serde_json::json!({}),
+        "STARWARS",
+        None,
+        Some(200),
+        Some(String::from("ADDRESS2")),
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+    ];
+
+    let result = raw_execute_contract(
+      String::from("WHATEVA"),
+      fake_contract,
+      fake_interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(value.get("txId").unwrap(), "STARWARS");
+      assert_eq!(value.get("owner").unwrap(), "ADDRESS2");
+      assert_eq!(value.get("height").unwrap(), 200);
+    } else {
+      panic!("Invalid operation");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_validate_contract() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+
+    validate_contract(
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      &arweave,
+    )
+    .await
+    .unwrap();
+    validate_contract(
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/03_wasm/03_wasm.wasm"),
+        ContractType::WASM,
+        String::from(r#"{"txId":"","owner":"","height":0}"#),
+      ),
+      &arweave,
+    )
+    .await
+    .unwrap();
+
+    let err = validate_contract(
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from("{"),
+      ),
+      &arweave,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().starts_with("Invalid init state"));
+
+    let err = validate_contract(
+      generate_fake_loaded_contract_data(
+        b"export function handle(state, action) {",
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      ),
+      &arweave,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("SyntaxError"));
+
+    let err = validate_contract(
+      generate_fake_loaded_contract_data(
+        b"export const state = {};",
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      ),
+      &arweave,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Contract does not export a handle function");
+
+    assert!(validate_contract(
+      generate_fake_loaded_contract_data(
+        b"not wasm",
+        ContractType::WASM,
+        String::from("{}"),
+      ),
+      &arweave,
+    )
+    .await
+    .is_err());
+  }
+
+  #[tokio::test]
+  async fn test_unsupported_contract_type() {
+    let loaded_contract = generate_fake_loaded_contract_data(
+      b"<html></html>",
+      get_contract_type_raw(String::from("text/html")),
+      String::from("{}"),
+    );
+
+    let err = ensure_supported_contract(&loaded_contract).unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<Error>(),
+      Some(&Error::UnsupportedContractType {
+        contract_id: String::from("test"),
+        content_type: String::from("text/html"),
+      })
+    );
+    assert_eq!(
+      err.to_string(),
+      "Contract test has unsupported content type 'text/html'"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_failed_interaction_rolls_back() {
+    let interaction = |id: &str, name: &str, fail: bool| {
+      generate_fake_interaction(
+        serde_json::json!({
+          "name": name,
+          "fail": fail
+        }),
+        id,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    };
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/throw_mid_mutation.js"),
+        ContractType::JAVASCRIPT,
+        serde_json::json!({ "counter": 0, "log": [] }).to_string(),
+      ),
+      vec![
+        interaction("tx1", "a", false),
+        interaction("tx2", "b", true),
+        interaction("tx3", "c", false),
+      ],
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(validity.get("tx2").unwrap(), &false);
+      assert_eq!(validity.get("tx3").unwrap(), &true);
+      assert_eq!(value, serde_json::json!({ "counter": 2, "log": ["a", "c"] }));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_validity_serialization() {
+    let validity = || {
+      let mut validity = IndexMap::new();
+      for (id, valid) in [("tx3", true), ("tx1", false), ("tx2", true)] {
+        validity.insert(String::from(id), Value::Bool(valid));
+      }
+      validity
+    };
+
+    // Evaluation order, identical bytes every time.
+    let first = serde_json::to_vec(&validity()).unwrap();
+    let second = serde_json::to_vec(&validity()).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(first, br#"{"tx3":true,"tx1":false,"tx2":true}"#);
+
+    let mut reordered = validity();
+    reordered.sort_keys();
+    assert_eq!(sorted_validity(&validity()), sorted_validity(&reordered));
+    assert_eq!(
+      serde_json::to_vec(&sorted_validity(&validity())).unwrap(),
+      br#"{"tx1":false,"tx2":true,"tx3":true}"#
+    );
+  }
+
+  #[tokio::test]
+  async fn test_interaction_cap() {
+    let mut loaded_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/contracts/users_contract.js"),
+      ContractType::JAVASCRIPT,
+      String::from(r#"{"users":[]}"#),
+    );
+    loaded_contract.contract_transaction.tags.push(Tag {
+      name: base64::encode_config("Max-Interactions", base64::URL_SAFE_NO_PAD),
+      value: base64::encode("2"),
+    });
+    assert_eq!(loaded_contract.max_interactions(), Some(2));
+
+    let interactions = ["Andres", "Divy", "Tate"]
+      .iter()
+      .enumerate()
+      .map(|(i, name)| {
+        generate_fake_interaction(
+          serde_json::json!({
+            "function": "add",
+            "name": name
+          }),
+          &format!("tx{}", i),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect();
+
+    let result = raw_execute_contract(
+      String::new(),
+      loaded_contract,
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(value, serde_json::json!({ "users": ["Andres", "Divy"] }));
+      assert_eq!(validity.get("tx0").unwrap(), &true);
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(
+        validity.get("tx2").unwrap(),
+        "Contract accepts at most 2 interactions"
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_extended_action() {
+    let interactions = || {
+      vec![
+        generate_fake_interaction(
+          serde_json::json!({}),
+          "tx1",
+          None,
+          Some(1),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        ),
+        generate_fake_interaction(
+          serde_json::json!({}),
+          "tx2",
+          None,
+          Some(2),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        ),
+      ]
+    };
+    let init_state = serde_json::json!({ "ids": [], "heights": [] });
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+
+    let mut states = vec![];
+    for extended_action in [false, true] {
+      let options = EvaluateOptions {
+        extended_action,
+        ..Default::default()
+      };
+      assert_eq!(options.is_counterfactual(), extended_action);
+      let result = raw_execute_contract(
+        String::new(),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/contracts/action_id.js"),
+          ContractType::JAVASCRIPT,
+          init_state.to_string(),
+        ),
+        interactions(),
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &arweave,
+        &options,
+      )
+      .await
+      .unwrap();
+
+      if let ExecuteResult::V8(value, validity) = result {
+        states.push((value, validity));
+      } else {
+        panic!("Unexpected entry");
+      }
+    }
+
+    // Without the flag the action only has `input` and `caller`.
+    assert_eq!(states[0].0, init_state);
+    assert_eq!(states[0].1.get("tx1").unwrap(), &false);
+
+    assert_eq!(
+      states[1].0,
+      serde_json::json!({ "ids": ["tx1", "tx2"], "heights": [1, 2] })
+    );
+    assert_eq!(states[1].1.get("tx2").unwrap(), &true);
+  }
+
+  #[tokio::test]
+  async fn test_dump_actions() {
+    let inputs = [("tx1", r#"{"function":"a"}"#), ("tx2", "{"), ("tx3", "1")];
+    let interactions = inputs
+      .iter()
+      .map(|(id, input)| {
+        let mut interaction = generate_fake_interaction(
+          serde_json::json!({}),
+          id,
+          None,
+          Some(7),
+          Some(String::from("ADDR")),
+          None,
+          None,
+          None,
+          None,
+          None,
+        );
+        interaction.node.tags[0].value = input.to_string();
+        interaction
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+
+    let dumped = Arc::new(std::sync::Mutex::new(vec![]));
+    let dump = dumped.clone();
+    let on_action: ActionCallback =
+      Arc::new(move |id: &str, action: &Value| {
+        dump.lock().unwrap().push((id.to_owned(), action.clone()));
+      });
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/echo_action.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"actions":[]}"#),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| panic!("not implemented"),
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions {
+        extended_action: true,
+        on_action: Some(on_action),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    // `tx2` has no action, its input doesn't parse.
+    let dumped = dumped.lock().unwrap();
+    let ids: Vec<&str> = dumped.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, ["tx1", "tx3"]);
+    assert_eq!(dumped[0].1["input"], serde_json::json!({ "function": "a" }));
+    assert_eq!(dumped[1].1["input"], serde_json::json!(1));
+    assert_eq!(dumped[1].1["caller"], "ADDR");
+    assert_eq!(dumped[1].1["block"]["height"], 7);
+
+    // Exactly what the contract received.
+    let received = &result.state().unwrap()["actions"];
+    let sent: Vec<Value> =
+      dumped.iter().map(|(_, action)| action.clone()).collect();
+    assert_eq!(received, &Value::Array(sent));
+  }
+
+  #[tokio::test]
+  async fn test_caller_resolver() {
+    let interactions = || {
+      [("tx1", "ADDR1", Some("alice.ar")), ("tx2", "ADDR2", None)]
+        .iter()
+        .map(|(id, owner, identity)| {
+          generate_fake_interaction(
+            serde_json::json!({}),
+            id,
+            None,
+            None,
+            Some(owner.to_string()),
+            None,
+            identity.map(|identity| GQLTagInterface {
+              name: String::from("Identity"),
+              value: String::from(identity),
+            }),
+            None,
+            None,
+            None,
+          )
+        })
+        .collect::<Vec<GQLEdgeInterface>>()
+    };
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    // Callers are named by their `Identity` tag, if they have one.
+    let resolver: CallerResolver = Arc::new(|tx: &GQLNodeInterface| {
+      tx.tags
+        .iter()
+        .find(|tag| tag.name == "Identity")
+        .map_or_else(|| tx.owner.address.to_owned(), |tag| tag.value.clone())
+    });
+
+    let mut states = vec![];
+    for caller in [None, Some(resolver)] {
+      let options = EvaluateOptions {
+        caller,
+        ..Default::default()
+      };
+      let result = raw_execute_contract(
+        String::new(),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/contracts/callers.js"),
+          ContractType::JAVASCRIPT,
+          String::from(r#"{"callers":[]}"#),
+        ),
+        interactions(),
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| panic!("not implemented"),
+        &arweave,
+        &options,
+      )
+      .await
+      .unwrap();
+      states.push(result.state().unwrap().clone());
+    }
+
+    assert_eq!(
+      states[0],
+      serde_json::json!({ "callers": ["ADDR1", "ADDR2"] })
+    );
+    assert_eq!(
+      states[1],
+      serde_json::json!({ "callers": ["alice.ar", "ADDR2"] })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_nested_input_rejected() {
+    let mut bomb = generate_fake_interaction(
+      serde_json::json!({}),
+      "tx1",
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+    );
+    bomb.node.tags[0].value =
+      format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    let interactions = vec![
+      bomb,
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": "Divy"
+        }),
+        "tx2",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ),
+    ];
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        serde_json::json!({ "users": [] }).to_string(),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &false);
+      assert_eq!(validity.get("tx2").unwrap(), &true);
+      assert_eq!(value, serde_json::json!({ "users": ["Divy"] }));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_oversized_input_rejected() {
+    let interaction = |id: &str, name: String| {
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": name
+        }),
+        id,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    };
+    let interactions = vec![
+      interaction("tx1", "D".repeat(1_000)),
+      interaction("tx2", String::from("Divy")),
+    ];
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        serde_json::json!({ "users": [] }).to_string(),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions {
+        json_limits: JsonLimits {
+          max_bytes: 256,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      let reason = validity.get("tx1").unwrap().as_str().unwrap();
+      assert!(reason.contains("exceeds the limit of 256 bytes"));
+      assert_eq!(validity.get("tx2").unwrap(), &true);
+      assert_eq!(value, serde_json::json!({ "users": ["Divy"] }));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_contract_block() {
+    let interaction = |id: &str, height: usize| {
+      generate_fake_interaction(
+        serde_json::json!({}),
+        id,
+        None,
+        Some(height),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    };
+    let mut contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/contracts/contract_age.js"),
+      ContractType::JAVASCRIPT,
+      serde_json::json!({}).to_string(),
+    );
+    contract.block = Some(GQLBlockInterface {
+      id: String::from("deployment"),
+      timestamp: 1640000000,
+      height: 850000,
+      previous: None,
+    });
+
+    let result = raw_execute_contract(
+      String::new(),
+      contract,
+      vec![interaction("tx1", 850010), interaction("tx2", 851000)],
+      IndexMap::new(),
+      None,
+      true,
       false,
       |_, _| {
         panic!("not implemented");
@@ -710,70 +3828,419 @@ mod tests {
         String::from("https"),
         ArweaveCache::new(),
       ),
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await
+    .unwrap();
 
     if let ExecuteResult::V8(value, validity) = result {
-      // tx1 is the evolve action. This must not fail.
-      // All network calls happen here and runtime is
-      // re-initialized.
-      assert_eq!(validity.get("tx1").unwrap(), &true);
-      // tx2 is the interaction to the evolved source.
-      // In this case, the pi contract.
       assert_eq!(validity.get("tx2").unwrap(), &true);
-
-      let value_state = value.get("canEvolve");
-      assert_eq!(value_state.is_some(), true);
-
-      assert_eq!(value.get("v").unwrap(), 0.6666666666666667_f64);
+      assert_eq!(
+        value,
+        serde_json::json!({ "age": 1000, "deployedAt": 1640000000 })
+      );
+    } else {
+      panic!("Unexpected entry");
     }
   }
 
   #[tokio::test]
-  async fn test_wasm_contract_interactions_context() {
-    let init_state = serde_json::json!({
-      "txId": "",
-      "owner": "",
-      "height": 0
-    });
-
-    let fake_contract = generate_fake_loaded_contract_data(
-      include_bytes!("../../testdata/03_wasm/03_wasm.wasm"),
-      ContractType::WASM,
-      init_state.to_string(),
-    );
-
-    let fake_interactions = vec![
+  async fn test_validator() {
+    let interaction = |id: &str, name: &str, fee: &str| {
       generate_fake_interaction(
-        serde_json::json!({}),
-        "POCAHONTAS",
+        serde_json::json!({
+          "function": "add",
+          "name": name
+        }),
+        id,
+        None,
         None,
-        Some(100),
-        Some(String::from("ADDRESS")),
         None,
         None,
         None,
         None,
+        Some(GQLAmountInterface {
+          winston: Some(fee.to_owned()),
+          ar: None,
+        }),
         None,
+      )
+    };
+    let interactions = vec![
+      interaction("tx1", "Divy", "1000"),
+      interaction("tx2", "Andres", "10"),
+      interaction("tx3", "Tate", "500"),
+    ];
+    let min_fee: InteractionValidator = Arc::new(|tx| {
+      let fee = tx
+        .fee
+        .as_ref()
+        .and_then(|fee| fee.winston.as_ref())
+        .and_then(|winston| winston.parse::<u64>().ok())
+        .unwrap_or(0);
+      if fee >= 500 {
+        Ok(())
+      } else {
+        Err(format!("Fee of {} winston is below 500", fee))
+      }
+    });
+    let options = EvaluateOptions {
+      validator: Some(min_fee),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        serde_json::json!({ "users": [] }).to_string(),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
       ),
+      &options,
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(validity.get("tx1").unwrap(), &true);
+      assert_eq!(
+        validity.get("tx2").unwrap(),
+        "Fee of 10 winston is below 500"
+      );
+      assert_eq!(validity.get("tx3").unwrap(), &true);
+      assert_eq!(value, serde_json::json!({ "users": ["Divy", "Tate"] }));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  /// Counts the calls that succeed, fails those to `fail`.
+  struct CountingEngine(usize);
+
+  #[async_trait(?Send)]
+  impl ContractRuntime for CountingEngine {
+    async fn call(
+      &mut self,
+      action: Value,
+      _context: InteractionContext,
+    ) -> Result<StateChange, AnyError> {
+      if action["input"]["function"] == "fail" {
+        return Err(generic_error("failed"));
+      }
+      self.0 += 1;
+      Ok(StateChange::Changed)
+    }
+
+    fn state(&mut self) -> Value {
+      serde_json::json!(self.0)
+    }
+
+    fn finish(self, validity: ValidityTable) -> ExecuteResult {
+      ExecuteResult::V8(serde_json::json!(self.0), validity)
+    }
+  }
+
+  #[tokio::test]
+  async fn test_unchanged_state() {
+    let source = r#"
+      export function handle(state, action) {
+        switch (action.input.function) {
+          case "add":
+            state.n += 1;
+            return { state };
+          case "noop":
+            return { state };
+          default:
+            return { result: state.n };
+        }
+      }
+    "#;
+    let arweave =
+      Arweave::new_no_cache(1, String::from("127.0.0.1"), String::from("http"));
+    let new_engine = || {
+      JsEngine::new(
+        ContractInfo::default(),
+        String::from(source),
+        serde_json::json!({ "n": 0 }),
+        &arweave,
+        RuntimeSettings::default(),
+        EvaluateOptions::default().read_options(),
+        Limits::default(),
+      )
+    };
+
+    let mut rt = new_engine().await.unwrap();
+    rt.track_changes();
+    for (function, change) in [
+      ("add", StateChange::Changed),
+      ("noop", StateChange::Unchanged),
+      ("view", StateChange::Unchanged),
+    ] {
+      let action = serde_json::json!({ "input": { "function": function } });
+      let result = rt.call(action, InteractionContext::default()).await;
+      assert_eq!(result.unwrap(), change);
+    }
+    assert_eq!(rt.state(), serde_json::json!({ "n": 1 }));
+
+    // Without tracking, the state isn't compared.
+    let mut rt = new_engine().await.unwrap();
+    let action = serde_json::json!({ "input": { "function": "noop" } });
+    let result = rt.call(action, InteractionContext::default()).await;
+    assert_eq!(result.unwrap(), StateChange::Changed);
+
+    let steps = Arc::new(std::sync::Mutex::new(vec![]));
+    let steps_copy = steps.clone();
+    let options = EvaluateOptions {
+      on_step: Some(Arc::new(move |id: &str, state: &Value, _: &Value| {
+        steps_copy
+          .lock()
+          .unwrap()
+          .push((id.to_owned(), state.clone()));
+      })),
+      ..Default::default()
+    };
+    let interactions = ["add", "noop", "view"]
+      .into_iter()
+      .map(|function| {
+        generate_fake_interaction(
+          serde_json::json!({ "function": function }),
+          function,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<_>>();
+    let result = evaluate_interactions(
+      new_engine().await.unwrap(),
+      stream::iter(interactions),
+      IndexMap::new(),
+      false,
+      None,
+      true,
+      &options,
+    )
+    .await;
+
+    assert_eq!(
+      process_execution(result, true),
+      serde_json::json!({
+        "state": { "n": 1 },
+        "validity": { "add": true, "noop": true, "view": true },
+      })
+    );
+    let n = serde_json::json!({ "n": 1 });
+    assert_eq!(
+      *steps.lock().unwrap(),
+      vec![
+        (String::from("add"), n.clone()),
+        (String::from("noop"), n.clone()),
+        (String::from("view"), n),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_evaluate_interactions() {
+    let interaction = |id: &str, function: &str| {
       generate_fake_interaction(
-        serde_json::json!({}),
-        "STARWARS",
+        serde_json::json!({ "function": function }),
+        id,
         None,
-        Some(200),
-        Some(String::from("ADDRESS2")),
         None,
         None,
         None,
         None,
         None,
-      ),
+        None,
+        None,
+      )
+    };
+    let interactions = || {
+      stream::iter(vec![
+        interaction("tx1", "add"),
+        interaction("tx2", "fail"),
+        interaction("tx3", "a".repeat(64).as_str()),
+        interaction("tx4", "add"),
+      ])
+    };
+    let options = EvaluateOptions {
+      json_limits: JsonLimits {
+        max_bytes: 64,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+
+    let result = evaluate_interactions(
+      CountingEngine(0),
+      interactions(),
+      IndexMap::new(),
+      false,
+      None,
+      false,
+      &options,
+    )
+    .await;
+    let validity = result.validity();
+    assert_eq!(
+      validity.values().collect::<Vec<_>>(),
+      vec![&true, &false, &false, &true]
+    );
+    if let ExecuteResult::V8(state, _) = &result {
+      assert_eq!(state, &serde_json::json!(2));
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    // Engines without genesis support reject the first interaction, and
+    // the cap applies before the handler is reached.
+    let result = evaluate_interactions(
+      CountingEngine(0),
+      interactions(),
+      IndexMap::new(),
+      true,
+      Some(3),
+      true,
+      &options,
+    )
+    .await;
+    let validity = result.validity();
+    assert_eq!(
+      validity["tx1"],
+      "Genesis interactions are not supported".to_string()
+    );
+    assert_eq!(validity["tx2"], "failed".to_string());
+    assert!(validity["tx4"].as_str().is_some());
+  }
+
+  #[tokio::test]
+  async fn test_wasm_parallel_reads() {
+    let init_state = serde_json::json!({ "v": "a" });
+    // Reads with a write every few interactions, including a write that
+    // does not change the value.
+    let ids = [
+      "r1", "r2", "wb", "r3", "r4", "r5", "wb2", "r6", "wc", "wd", "r7", "r8",
+      "r9", "we", "r10",
     ];
+    let interactions = || {
+      ids
+        .iter()
+        .map(|id| {
+          generate_fake_interaction(
+            serde_json::json!({}),
+            id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect::<Vec<GQLEdgeInterface>>()
+    };
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+
+    let mut results = vec![];
+    for workers in [0, 4] {
+      let result = raw_execute_contract(
+        String::from("WHATEVA"),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/04_wasm/04_wasm.wasm"),
+          ContractType::WASM,
+          init_state.to_string(),
+        ),
+        interactions(),
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &arweave,
+        &EvaluateOptions {
+          wasm_read_workers: workers,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+
+      if let ExecuteResult::V8(value, validity) = result {
+        assert_eq!(value, serde_json::json!({ "v": "e" }));
+        assert_eq!(validity.len(), ids.len());
+        results.push(validity);
+      } else {
+        panic!("Invalid operation");
+      }
+    }
+
+    assert_eq!(results[0], results[1]);
+  }
+
+  #[tokio::test]
+  async fn test_wasm_bytes_state() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let interactions = ["a", "b", "c"]
+      .iter()
+      .map(|id| {
+        generate_fake_interaction(
+          serde_json::json!({}),
+          id,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
 
     let result = raw_execute_contract(
       String::from("WHATEVA"),
-      fake_contract,
-      fake_interactions,
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/06_wasm/06_wasm.wasm"),
+        ContractType::WASM,
+        String::new(),
+      ),
+      interactions,
       IndexMap::new(),
       None,
       true,
@@ -781,21 +4248,237 @@ mod tests {
       |_, _| {
         panic!("not implemented");
       },
-      &Arweave::new(
-        443,
-        "arweave.net".to_string(),
-        String::from("https"),
-        ArweaveCache::new(),
-      ),
+      &arweave,
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await
+    .unwrap();
 
-    if let ExecuteResult::V8(value, validity) = result {
-      assert_eq!(value.get("txId").unwrap(), "STARWARS");
-      assert_eq!(value.get("owner").unwrap(), "ADDRESS2");
-      assert_eq!(value.get("height").unwrap(), 200);
+    if let ExecuteResult::Bytes(state, validity) = result.clone() {
+      assert_eq!(state, vec![0xff, 0xff, 0xff]);
+      assert_eq!(validity.len(), 3);
     } else {
       panic!("Invalid operation");
     }
+    assert_eq!(process_execution(result, false), serde_json::json!("////"));
+  }
+
+  #[tokio::test]
+  async fn test_wasm_gas_limit() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    // The contract consumes 100 gas per byte of the action.
+    let mut loaded_contract = generate_fake_loaded_contract_data(
+      include_bytes!("../../testdata/09_wasm/09_wasm.wasm"),
+      ContractType::WASM,
+      String::from("{}"),
+    );
+    loaded_contract.contract_transaction.tags.push(Tag {
+      name: base64::encode_config("Max-Gas", base64::URL_SAFE_NO_PAD),
+      value: base64::encode("20000"),
+    });
+    assert_eq!(loaded_contract.max_gas(), Some(20000));
+
+    let interactions = || {
+      [("light", String::new()), ("heavy", "x".repeat(1000))]
+        .iter()
+        .map(|(id, data)| {
+          generate_fake_interaction(
+            serde_json::json!({ "data": data }),
+            id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect::<Vec<GQLEdgeInterface>>()
+    };
+    let mut results = vec![];
+    for (workers, max_gas) in
+      [(0, None), (4, None), (0, Some(1_000_000)), (0, Some(100))]
+    {
+      let result = raw_execute_contract(
+        String::from("WHATEVA"),
+        loaded_contract.clone(),
+        interactions(),
+        IndexMap::new(),
+        None,
+        true,
+        true,
+        |_, _| {
+          panic!("not implemented");
+        },
+        &arweave,
+        &EvaluateOptions {
+          wasm_read_workers: workers,
+          max_gas,
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+      results.push(result.validity().clone());
+    }
+
+    // The contract's own limit applies without a global one, and under a
+    // looser one.
+    for validity in &results[..3] {
+      assert_eq!(validity.get("light").unwrap(), &true);
+      let heavy = validity.get("heavy").unwrap().as_str().unwrap();
+      assert!(heavy.contains("gas limit of 20000"), "{}", heavy);
+    }
+
+    // A tighter global limit wins.
+    let light = results[3].get("light").unwrap().as_str().unwrap();
+    assert!(light.contains("gas limit of 100"), "{}", light);
+
+    let options = EvaluateOptions {
+      max_gas: Some(5000),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    assert_eq!(options.gas_limit(None), Some(5000));
+    assert_eq!(options.gas_limit(Some(20000)), Some(5000));
+    let options = EvaluateOptions::default();
+    assert_eq!(options.gas_limit(Some(20000)), Some(20000));
+  }
+
+  #[tokio::test]
+  async fn test_counterfactual_options() {
+    assert!(!EvaluateOptions::default().is_counterfactual());
+    // Defaults given explicitly, and options that only change how the
+    // evaluation is carried out, evaluate the real history.
+    let options = EvaluateOptions {
+      max_read_depth: Some(DEFAULT_MAX_READ_DEPTH),
+      max_foreign_reads: Some(DEFAULT_MAX_FOREIGN_READS),
+      wasm_read_workers: 4,
+      cache_inputs: true,
+      on_step: Some(Arc::new(|_: &str, _: &Value, _: &Value| {})),
+      ..Default::default()
+    };
+    assert!(!options.is_counterfactual());
+
+    let options = EvaluateOptions {
+      max_read_depth: Some(DEFAULT_MAX_READ_DEPTH + 1),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    let options = EvaluateOptions {
+      only_owners: Some(HashSet::new()),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+  }
+
+  #[tokio::test]
+  async fn test_default_limits() {
+    // Without explicit limits, every contract gets those of its type.
+    let options = EvaluateOptions::default();
+    assert!(!options.is_counterfactual());
+    for contract_type in [ContractType::JAVASCRIPT, ContractType::WASM] {
+      assert_eq!(
+        options.limits("any", &contract_type, None),
+        DefaultLimits::default().get(&contract_type)
+      );
+    }
+    // Only the time is bounded: memory and gas would change which
+    // interactions are valid.
+    let wasm = options.limits("any", &ContractType::WASM, None);
+    assert!(wasm.timeout.is_some());
+    assert_eq!((wasm.memory, wasm.gas), (None, None));
+    assert_eq!(
+      options.limits("any", &ContractType::WASM, Some(20000)).gas,
+      Some(20000)
+    );
+
+    // An explicit limit wins over the default of its contract only.
+    let timeout = std::time::Duration::from_millis(200);
+    let options = EvaluateOptions {
+      contract_limits: HashMap::from([(
+        String::from("WHATEVA"),
+        Limits {
+          timeout: Some(timeout),
+          ..Default::default()
+        },
+      )]),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+    let limits = options.limits("WHATEVA", &ContractType::JAVASCRIPT, None);
+    assert_eq!(limits.timeout, Some(timeout));
+    assert_eq!(limits.memory, DefaultLimits::default().javascript.memory);
+    assert_eq!(
+      options.limits("other", &ContractType::JAVASCRIPT, None),
+      DefaultLimits::default().javascript
+    );
+
+    let source = br#"
+      export async function handle(state, action) {
+        if (action.input.function === "spin") {
+          while (true) {}
+        }
+        state.n += 1;
+        return { state };
+      }
+    "#;
+    let fake_contract = generate_fake_loaded_contract_data(
+      source,
+      ContractType::JAVASCRIPT,
+      String::from(r#"{"n": 0}"#),
+    );
+    let interactions = ["spin", "add"]
+      .iter()
+      .map(|function| {
+        generate_fake_interaction(
+          serde_json::json!({ "function": function }),
+          &format!("{}-tx", function),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+    let arweave =
+      Arweave::new_no_cache(1, String::from("127.0.0.1"), String::from("http"));
+    let timeouts_before = timeouts();
+    let result = raw_execute_contract(
+      String::from("WHATEVA"),
+      fake_contract,
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| {
+        panic!("not implemented");
+      },
+      &arweave,
+      &options,
+    )
+    .await
+    .unwrap();
+
+    // The spinning interaction is terminated, and the contract goes on.
+    let validity = result.validity();
+    let spin = validity.get("spin-tx").unwrap().as_str().unwrap();
+    assert!(spin.contains("timed out after 200 ms"), "{}", spin);
+    assert_eq!(validity.get("add-tx").unwrap(), &true);
+    assert_eq!(result.state(), Some(&serde_json::json!({ "n": 1 })));
+    // Which keeps the result out of the caches.
+    assert_eq!(timeouts(), timeouts_before + 1);
   }
 }
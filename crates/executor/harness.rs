@@ -0,0 +1,238 @@
+//! Evaluation of contracts given as raw bytes, for fuzzers.
+//!
+//! [`evaluate_raw`] runs the same interaction loop as a normal evaluation,
+//! but everything comes from its arguments: there is no gateway, no cache
+//! and no clock. Malformed sources, states and inputs must come back as
+//! errors or invalid interactions; a panic is a bug.
+
+#[cfg(any(feature = "js", feature = "wasm"))]
+use crate::engine::ContractRuntime;
+#[cfg(feature = "js")]
+use crate::engine::JsEngine;
+#[cfg(feature = "wasm")]
+use crate::engine::WasmEngine;
+use crate::executor::{
+  disabled_engine, evaluate_interactions, Error, EvaluateOptions,
+  ExecuteResult, ValidityTable,
+};
+use crate::test_util::generate_fake_interaction;
+use deno_core::error::{generic_error, AnyError};
+use deno_core::futures::stream;
+use deno_core::serde_json::{self, Value};
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_arweave::miscellaneous::{get_contract_type_raw, ContractType};
+use three_em_smartweave::ContractInfo;
+
+/// The id contracts see as `SmartWeave.contract.id`.
+const CONTRACT_ID: &str = "fuzz";
+
+/// Evaluates `inputs`, the raw `Input` tags of interactions
+/// `interaction-0`, `interaction-1`, ..., against the contract in `source`
+/// with `content_type`, starting from `init_state`. The contract's `init`
+/// export, if any, is called first, as with [`EvaluateOptions::run_init`].
+///
+/// Reading other contracts fails, there is no gateway to load them from.
+/// EVM contracts are not supported. JavaScript handlers are not given a
+/// time limit, fuzzers should bound each run themselves.
+pub async fn evaluate_raw(
+  source: &[u8],
+  content_type: &str,
+  init_state: &[u8],
+  inputs: &[Vec<u8>],
+) -> Result<ExecuteResult, AnyError> {
+  let contract_type = get_contract_type_raw(content_type.to_owned());
+  let init_state = std::str::from_utf8(init_state)?;
+
+  // Nothing listens on port 1, so any request fails right away.
+  let client =
+    Arweave::new_no_cache(1, String::from("127.0.0.1"), String::from("http"));
+  let interactions = stream::iter(fake_interactions(inputs));
+  let options = EvaluateOptions::default();
+
+  let result = match contract_type {
+    #[cfg(feature = "js")]
+    ContractType::JAVASCRIPT => {
+      let state: Value = serde_json::from_str(init_state)?;
+      let source = String::from_utf8(source.to_vec())?;
+      let contract = ContractInfo {
+        id: String::from(CONTRACT_ID),
+        block: None,
+      };
+      let mut rt = JsEngine::new(
+        contract,
+        source,
+        state,
+        &client,
+        options.runtime_settings.clone(),
+        options.read_options(),
+        options.limits(CONTRACT_ID, &ContractType::JAVASCRIPT, None),
+      )
+      .await?;
+      rt.init().await?;
+
+      evaluate_interactions(
+        rt,
+        interactions,
+        ValidityTable::new(),
+        false,
+        None,
+        true,
+        &options,
+      )
+      .await
+    }
+    #[cfg(feature = "wasm")]
+    ContractType::WASM => {
+      let mut rt = WasmEngine::new(
+        source,
+        init_state.as_bytes().to_vec(),
+        true,
+        options.determinism,
+        options.limits(CONTRACT_ID, &ContractType::WASM, None),
+      )?;
+      rt.init().await?;
+
+      evaluate_interactions(
+        rt,
+        interactions,
+        ValidityTable::new(),
+        false,
+        None,
+        true,
+        &options,
+      )
+      .await
+    }
+    ContractType::EVM => {
+      return Err(generic_error("EVM contracts need a gateway to evaluate"))
+    }
+    ContractType::UNSUPPORTED(content_type) => {
+      return Err(AnyError::new(Error::UnsupportedContractType {
+        contract_id: String::from(CONTRACT_ID),
+        content_type,
+      }))
+    }
+    // Engines left out of this build.
+    #[allow(unreachable_patterns)]
+    contract_type => {
+      return Err(AnyError::new(Error::EngineNotEnabled {
+        contract_id: String::from(CONTRACT_ID),
+        engine: disabled_engine(&contract_type).unwrap_or_default(),
+      }))
+    }
+  };
+
+  Ok(result)
+}
+
+fn fake_interactions(inputs: &[Vec<u8>]) -> Vec<GQLEdgeInterface> {
+  inputs
+    .iter()
+    .enumerate()
+    .map(|(i, input)| {
+      let id = format!("interaction-{}", i);
+      let mut interaction = generate_fake_interaction(
+        Value::Null,
+        &id,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      );
+      // Inputs don't have to be JSON, or even UTF-8.
+      interaction.node.tags[0].value =
+        String::from_utf8_lossy(input).into_owned();
+      interaction
+    })
+    .collect()
+}
+
+#[cfg(all(test, feature = "js", feature = "wasm"))]
+mod tests {
+  use crate::executor::ExecuteResult;
+  use crate::harness::evaluate_raw;
+  use deno_core::serde_json::json;
+
+  #[tokio::test]
+  async fn test_evaluate_raw() {
+    let source = br#"
+      export function handle(state, action) {
+        state.n += 1;
+        if (action.input.function === "throw") {
+          throw new Error("Mutated, then failed");
+        }
+        return { state };
+      }
+    "#;
+    let result = evaluate_raw(
+      source,
+      "application/javascript",
+      br#"{"n":0}"#,
+      &[
+        br#"{"function":"add"}"#.to_vec(),
+        br#"{"function":"throw"}"#.to_vec(),
+        b"\xff{".to_vec(),
+      ],
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(state, validity) = result {
+      // The failed interaction's mutation is rolled back.
+      assert_eq!(state, json!({ "n": 1 }));
+      assert_eq!(validity.get("interaction-0").unwrap(), &json!(true));
+      assert_ne!(validity.get("interaction-1").unwrap(), &json!(true));
+      assert_ne!(validity.get("interaction-2").unwrap(), &json!(true));
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_evaluate_raw_malformed() {
+    // Without a handle export, every interaction is invalid.
+    let result = evaluate_raw(
+      b"export const x = 1;",
+      "application/javascript",
+      b"{}",
+      &[b"{}".to_vec()],
+    )
+    .await
+    .unwrap();
+    if let ExecuteResult::V8(state, validity) = result {
+      assert_eq!(state, json!({}));
+      assert_ne!(validity.get("interaction-0").unwrap(), &json!(true));
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    assert!(evaluate_raw(
+      b"export function handle(",
+      "application/javascript",
+      b"{}",
+      &[],
+    )
+    .await
+    .is_err());
+
+    assert!(evaluate_raw(
+      b"export function handle() {}",
+      "application/javascript",
+      b"{",
+      &[],
+    )
+    .await
+    .is_err());
+
+    assert!(evaluate_raw(b"\0asm\x01", "application/wasm", b"{}", &[])
+      .await
+      .is_err());
+
+    assert!(evaluate_raw(b"", "text/plain", b"{}", &[]).await.is_err());
+  }
+}
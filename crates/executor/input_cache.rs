@@ -0,0 +1,146 @@
+//! Decoded interaction inputs, shared by every evaluation in the process.
+//!
+//! An interaction's `Input` tag never changes, yet it is parsed again each
+//! time its contract is evaluated. Processes that evaluate the same
+//! contracts over and over, like step debuggers or a server behind a
+//! development UI, can opt into reusing the parsed `Value` with
+//! [`crate::executor::EvaluateOptions::cache_inputs`].
+//!
+//! Entries are keyed by interaction id, but a hit also requires the raw
+//! input and the [`JsonLimits`] to match, so fake interactions reusing an
+//! id (dry runs, tests) never see another interaction's input. Inputs that
+//! fail to parse are not cached.
+
+use crate::utils::{parse_json_with_limits, JsonLimits};
+use deno_core::error::AnyError;
+use deno_core::serde_json::Value;
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// Total length of the raw inputs kept by [`DECODED_INPUTS`].
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+pub static DECODED_INPUTS: Lazy<Mutex<InputCache>> =
+  Lazy::new(|| Mutex::new(InputCache::new(DEFAULT_MAX_BYTES)));
+
+struct Entry {
+  input: String,
+  limits: JsonLimits,
+  value: Value,
+}
+
+/// A least recently used cache of parsed inputs, bounded by the total
+/// length of the raw inputs it holds.
+pub struct InputCache {
+  entries: LruCache<String, Entry>,
+  bytes: usize,
+  max_bytes: usize,
+}
+
+impl InputCache {
+  pub fn new(max_bytes: usize) -> Self {
+    Self {
+      entries: LruCache::unbounded(),
+      bytes: 0,
+      max_bytes,
+    }
+  }
+
+  /// Parses `input`, the input of interaction `id`, like
+  /// [`parse_json_with_limits`] does, reusing an earlier result if there is
+  /// one.
+  pub fn decode(
+    &mut self,
+    id: &str,
+    input: &str,
+    limits: &JsonLimits,
+  ) -> Result<Value, AnyError> {
+    let key = id.to_owned();
+    if let Some(entry) = self.entries.get(&key) {
+      if entry.input == input && entry.limits == *limits {
+        return Ok(entry.value.clone());
+      }
+    }
+
+    let value = parse_json_with_limits(input, limits)?;
+    if input.len() <= self.max_bytes {
+      let entry = Entry {
+        input: input.to_owned(),
+        limits: *limits,
+        value: value.clone(),
+      };
+      if let Some(replaced) = self.entries.put(key, entry) {
+        self.bytes -= replaced.input.len();
+      }
+      self.bytes += input.len();
+
+      while self.bytes > self.max_bytes {
+        match self.entries.pop_lru() {
+          Some((_, evicted)) => self.bytes -= evicted.input.len(),
+          None => break,
+        }
+      }
+    }
+
+    Ok(value)
+  }
+
+  /// Number of cached inputs.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::input_cache::InputCache;
+  use crate::utils::JsonLimits;
+  use deno_core::serde_json::json;
+
+  #[tokio::test]
+  async fn test_input_cache() {
+    let mut cache = InputCache::new(1024);
+    let limits = JsonLimits::default();
+
+    let input = r#"{"function":"add","name":"Divy"}"#;
+    let value = cache.decode("tx1", input, &limits).unwrap();
+    assert_eq!(value, json!({ "function": "add", "name": "Divy" }));
+    assert_eq!(cache.decode("tx1", input, &limits).unwrap(), value);
+    assert_eq!(cache.len(), 1);
+
+    // Same id, different input: parsed again and replaced.
+    let value = cache.decode("tx1", "[1]", &limits).unwrap();
+    assert_eq!(value, json!([1]));
+    assert_eq!(cache.len(), 1);
+
+    // A hit still honors stricter limits.
+    let strict = JsonLimits {
+      max_depth: 0,
+      ..Default::default()
+    };
+    assert!(cache.decode("tx1", "[1]", &strict).is_err());
+
+    assert!(cache.decode("tx2", "{", &limits).is_err());
+    assert_eq!(cache.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_input_cache_bounded() {
+    let mut cache = InputCache::new(16);
+    let limits = JsonLimits::default();
+
+    cache.decode("tx1", "[1,2,3,4]", &limits).unwrap();
+    cache.decode("tx2", "[5,6,7,8]", &limits).unwrap();
+    assert_eq!(cache.len(), 1);
+
+    // Longer than the whole cache, never kept.
+    let long = format!("[{}]", "1,".repeat(16) + "1");
+    cache.decode("tx3", &long, &limits).unwrap();
+    assert_eq!(cache.len(), 1);
+  }
+}
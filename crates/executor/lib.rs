@@ -1,30 +1,136 @@
+pub mod cursor;
+pub mod delta;
+pub mod engine;
 pub mod executor;
+pub mod harness;
+pub mod input_cache;
+pub mod limits;
+pub mod pause;
+pub mod preview;
 pub mod test_util;
+pub mod trace;
 pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm_parallel;
 
-use crate::executor::raw_execute_contract;
+use crate::delta::Patch;
+use crate::executor::{
+  cache_entry, ensure_supported_contract, fits_cache, raw_execute_contract,
+  raw_execute_contract_stream, timed,
+};
+pub use crate::executor::ActionCallback;
+pub use crate::executor::CallerResolver;
+pub use crate::executor::EvaluateOptions;
 pub use crate::executor::ExecuteResult;
+pub use crate::executor::FilterCallback;
+pub use crate::executor::InteractionComparator;
+pub use crate::executor::InteractionCounts;
+pub use crate::executor::InteractionValidator;
+pub use crate::executor::MockReads;
+pub use crate::executor::Resume;
+pub use crate::executor::Sample;
+pub use crate::executor::StateTransform;
 pub use crate::executor::ValidityTable;
+use crate::limits::timeouts;
+use crate::preview::{Candidate, Preview};
 use deno_core::error::{generic_error, AnyError};
+use deno_core::futures::channel::mpsc;
+use deno_core::futures::future::{join_all, try_join_all};
+use deno_core::futures::stream::{self, Stream, StreamExt};
 use deno_core::serde_json::Value;
 pub use indexmap::map::IndexMap;
 use lru::LruCache;
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::ffi::CString;
-use std::sync::Mutex;
-use three_em_arweave::arweave::get_cache;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use three_em_arweave::arweave::Arweave;
 use three_em_arweave::arweave::LoadedContract;
+use three_em_arweave::arweave::MAX_REQUEST;
+use three_em_arweave::cache::{CacheLock, StateResult};
 use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_arweave::gql_result::GQLKeyEdgeInterface;
 use three_em_arweave::gql_result::GQLNodeInterface;
 use three_em_arweave::miscellaneous::get_sort_key;
+use three_em_arweave::miscellaneous::ContractType;
+#[cfg(feature = "evm")]
 use three_em_evm::Instruction;
+#[cfg(feature = "evm")]
 use three_em_evm::U256;
+pub use three_em_smartweave::RuntimeSettings;
+pub use three_em_smartweave::{Clock, DeterminismContext, Seed};
 
-static LRU_CACHE: Lazy<Mutex<LruCache<String, ExecuteResult>>> =
+/// Results by network, see [`Arweave::network`], and contract id.
+static LRU_CACHE: Lazy<Mutex<LruCache<(String, String), ExecuteResult>>> =
   Lazy::new(|| Mutex::new(LruCache::unbounded()));
 
+/// Contracts this process evaluates under an evaluation lock.
+static EVALUATING: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+/// How long to wait for another process evaluating the same contract before
+/// evaluating it anyway. Two processes each evaluating a contract that
+/// reads the other's would otherwise wait forever.
+const EVALUATION_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct EvaluationLock {
+  contract_id: String,
+  _lock: CacheLock,
+}
+
+impl Drop for EvaluationLock {
+  fn drop(&mut self) {
+    EVALUATING.lock().unwrap().remove(&self.contract_id);
+  }
+}
+
+/// With a cache shared between processes, waits for any other process
+/// evaluating `contract_id` and keeps others waiting until the lock is
+/// dropped, so that a state is cached once instead of being computed by
+/// each of them.
+async fn lock_evaluation(
+  contract_id: &str,
+  arweave: &Arweave,
+) -> Option<EvaluationLock> {
+  let path = arweave
+    .try_cache(|cache| Ok(cache.evaluation_lock_file(contract_id.to_owned())))
+    .flatten()?;
+  // A contract reading its own state must not wait on itself.
+  if !EVALUATING.lock().unwrap().insert(contract_id.to_owned()) {
+    return None;
+  }
+
+  let deadline = Instant::now() + EVALUATION_LOCK_TIMEOUT;
+  let lock = loop {
+    match CacheLock::try_acquire(&path) {
+      Ok(Some(lock)) => break Some(lock),
+      Ok(None) if Instant::now() < deadline => {
+        tokio::time::sleep(Duration::from_millis(100)).await
+      }
+      Ok(None) => break None,
+      Err(err) => {
+        tracing::warn!("Cache unavailable, continuing without it: {}", err);
+        break None;
+      }
+    }
+  };
+
+  match lock {
+    Some(lock) => Some(EvaluationLock {
+      contract_id: contract_id.to_owned(),
+      _lock: lock,
+    }),
+    None => {
+      EVALUATING.lock().unwrap().remove(contract_id);
+      None
+    }
+  }
+}
+
 pub async fn simulate_contract(
   contract_id: String,
   contract_init_state: Option<String>,
@@ -51,10 +157,12 @@ pub async fn simulate_contract(
   })
   .0;
 
-  if loaded_contract.is_ok() {
+  if let Ok(loaded_contract) = loaded_contract {
+    ensure_supported_contract(&loaded_contract)?;
+
     let execute = raw_execute_contract(
       contract_id,
-      loaded_contract.unwrap(),
+      loaded_contract,
       interactions,
       IndexMap::new(),
       None,
@@ -64,8 +172,9 @@ pub async fn simulate_contract(
         ExecuteResult::V8(cache_state.unwrap(), validity_table)
       },
       arweave,
+      &EvaluateOptions::default(),
     )
-    .await;
+    .await?;
 
     Ok(execute)
   } else {
@@ -73,7 +182,7 @@ pub async fn simulate_contract(
   }
 }
 
-#[async_recursion::async_recursion(?Send)]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_contract(
   contract_id: String,
   height: Option<usize>,
@@ -82,169 +191,1354 @@ pub async fn execute_contract(
   contract_src_tx: Option<String>,
   contract_content_type: Option<String>,
   arweave: &Arweave,
+  options: &EvaluateOptions,
 ) -> Result<ExecuteResult, AnyError> {
-  if let Some(result) = LRU_CACHE.lock().unwrap().get(&contract_id) {
-    return Ok(result.clone());
+  let (result, _) = execute_contract_with_counts(
+    contract_id,
+    height,
+    cache,
+    show_errors,
+    contract_src_tx,
+    contract_content_type,
+    arweave,
+    options,
+  )
+  .await?;
+
+  Ok(result)
+}
+
+/// Evaluates `contract_id` from `base`, its state at `base_height`, up to
+/// `height`, and returns the [`delta::delta`] that brings `base` to the
+/// state at `height` rather than that state.
+///
+/// The interactions up to `base_height` aren't evaluated again: evaluation
+/// resumes after them, see [`EvaluateOptions::resume`], which must be
+/// unset. `base` has to be the state they led to under the same `options`
+/// for the delta to be right.
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_contract_delta(
+  contract_id: String,
+  base: Value,
+  base_height: usize,
+  height: Option<usize>,
+  cache: bool,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<Vec<Patch>, AnyError> {
+  if options.resume.is_some() {
+    return Err(generic_error(
+      "A delta can't be evaluated from a resume point",
+    ));
+  }
+  if let Some(height) = height.filter(|height| *height < base_height) {
+    return Err(generic_error(format!(
+      "Cannot evaluate a delta back from height {} to {}",
+      base_height, height
+    )));
+  }
+
+  // The interactions `base` reflects, in the order they are evaluated in.
+  let (mut interactions, _, _) = arweave
+    .get_lineage_interactions(&options.lineage(&contract_id), height, cache)
+    .await?;
+  sort_interactions_with(&mut interactions, options);
+  filter_interactions_with(&mut interactions, options);
+  dedup_interactions(&mut interactions, &IndexMap::new());
+  let applied = interactions
+    .iter()
+    .take_while(|interaction| interaction.node.block.height <= base_height)
+    .count();
+  let after = applied
+    .checked_sub(1)
+    .map(|last| interactions[last].node.id.to_owned());
+
+  let options = EvaluateOptions {
+    resume: Some(Resume {
+      state: base.clone(),
+      applied,
+      after,
+    }),
+    ..options.clone()
+  };
+  let result = execute_contract(
+    contract_id,
+    height,
+    cache,
+    show_errors,
+    None,
+    None,
+    arweave,
+    &options,
+  )
+  .await?;
+  let state = result
+    .state()
+    .ok_or_else(|| generic_error("Only JSON states have a delta"))?;
+
+  Ok(delta::delta(&base, state))
+}
+
+/// Evaluates `contract_id` up to `height`, and previews `candidates` on
+/// the state it reaches, see [`preview::preview_state`]. They are mined in
+/// the block after `height`, or after the current network height without
+/// one.
+#[allow(clippy::too_many_arguments)]
+pub async fn preview_contract(
+  contract_id: String,
+  height: Option<usize>,
+  candidates: &[Candidate],
+  cache: bool,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<Preview, AnyError> {
+  let base = execute_contract(
+    contract_id.to_owned(),
+    height,
+    cache,
+    show_errors,
+    None,
+    None,
+    arweave,
+    options,
+  )
+  .await?;
+  let state = base
+    .state()
+    .cloned()
+    .ok_or_else(|| generic_error("Only JSON states can be previewed"))?;
+  let height =
+    match confirmed_height(arweave, height, options.confirmations).await? {
+      Some(height) => height,
+      None => arweave.get_network_info().await?.height,
+    };
+  let loaded_contract = arweave
+    .load_contract(
+      contract_id.to_owned(),
+      None,
+      None,
+      None,
+      cache,
+      false,
+      false,
+    )
+    .await?;
+
+  preview::preview_state(
+    contract_id,
+    loaded_contract,
+    state,
+    base.validity().clone(),
+    candidates,
+    height + 1,
+    show_errors,
+    arweave,
+    options,
+  )
+  .await
+}
+
+/// An interaction evaluated by [`execute_contract_steps`].
+#[derive(Clone, Debug)]
+pub struct Step {
+  pub interaction_id: String,
+  /// The contract state right after this interaction.
+  pub state: Value,
+  /// The interaction's entry in the validity table.
+  pub valid: Value,
+}
+
+/// Same as [`execute_contract`], but yields the state after every
+/// interaction instead of only the final one.
+///
+/// This is the slow path for step debuggers and state explorers: every step
+/// clones the state, and the state and result caches are bypassed so every
+/// interaction is evaluated. An evaluation error is yielded as the last
+/// item. EVM contracts yield no steps.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_contract_steps<'a>(
+  contract_id: String,
+  height: Option<usize>,
+  cache: bool,
+  show_errors: bool,
+  contract_src_tx: Option<String>,
+  contract_content_type: Option<String>,
+  arweave: &'a Arweave,
+  options: &EvaluateOptions,
+) -> impl Stream<Item = Result<Step, AnyError>> + 'a {
+  let (sender, receiver) = mpsc::unbounded();
+  let options = EvaluateOptions {
+    on_step: Some(Arc::new(move |id: &str, state: &Value, valid: &Value| {
+      // Only fails once the stream has been dropped.
+      let _ = sender.unbounded_send(Step {
+        interaction_id: id.to_owned(),
+        state: state.clone(),
+        valid: valid.clone(),
+      });
+    })),
+    ..options.clone()
+  };
+
+  // The sender lives in `options`, so the receiver ends with the
+  // evaluation. Its error is held back until the steps before it are out.
+  let error: Rc<RefCell<Option<AnyError>>> = Rc::new(RefCell::new(None));
+  let evaluation_error = error.clone();
+  let evaluation = stream::once(async move {
+    let result = execute_contract(
+      contract_id,
+      height,
+      cache,
+      show_errors,
+      contract_src_tx,
+      contract_content_type,
+      arweave,
+      &options,
+    )
+    .await;
+    if let Err(err) = result {
+      *evaluation_error.borrow_mut() = Some(err);
+    }
+  })
+  .filter_map(|_| async { None });
+
+  stream::select(receiver.map(Ok), evaluation).chain(
+    stream::once(async move { error.borrow_mut().take() })
+      .filter_map(|err| async move { err.map(Err) }),
+  )
+}
+
+/// Same as [`execute_contract`], but also reports how many interactions were
+/// evaluated and how many were already covered by a cached state.
+#[allow(clippy::too_many_arguments)]
+#[async_recursion::async_recursion(?Send)]
+pub async fn execute_contract_with_counts(
+  contract_id: String,
+  height: Option<usize>,
+  cache: bool,
+  show_errors: bool,
+  contract_src_tx: Option<String>,
+  contract_content_type: Option<String>,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<(ExecuteResult, InteractionCounts), AnyError> {
+  // Counterfactual states must not mix with standard ones, and a cached
+  // result has no steps to report.
+  let cache_results = !options.is_counterfactual() && !options.reports_steps();
+  // With `cache_confirmations`, results cover interactions at the tip that
+  // the in-memory cache must not hold past a reorg.
+  let cache_in_memory = cache_results && options.cache_confirmations == 0;
+  // Nor do results in which an interaction timed out.
+  let timeouts_before = timeouts();
+
+  let memory_key = (arweave.network(), contract_id.to_owned());
+  if cache_in_memory {
+    if let Some(result) = LRU_CACHE.lock().unwrap().get(&memory_key) {
+      let counts = InteractionCounts::new(0, result.validity().len());
+      return Ok((result.clone(), counts));
+    }
+  }
+
+  let height = confirmed_height(arweave, height, options.confirmations).await?;
+  let max_height = height.filter(|_| options.confirmations > 0);
+  // The height the cached state and interactions stop at, see
+  // `EvaluateOptions::cache_confirmations`.
+  let cache_height = if cache
+    && cache_results
+    && height.is_none()
+    && !options.bounded_memory
+    && options.cache_confirmations > 0
+  {
+    confirmed_height(arweave, None, options.cache_confirmations).await?
+  } else {
+    None
+  };
+
+  if options.bounded_memory {
+    let result = execute_contract_bounded(
+      contract_id.to_owned(),
+      height,
+      show_errors,
+      contract_src_tx,
+      contract_content_type,
+      arweave,
+      options,
+    )
+    .await?;
+
+    if cache_in_memory && !options.paused() && timeouts() == timeouts_before {
+      LRU_CACHE.lock().unwrap().put(memory_key, result.clone());
+    }
+
+    let total = result.validity().len();
+    let counts = InteractionCounts {
+      timed_out: timeouts() - timeouts_before,
+      ..InteractionCounts::new(total, total)
+    };
+    return Ok((result, counts));
   }
 
+  // Whoever held the lock cached its result, which is picked up below.
+  let _evaluation_lock = if cache && cache_results {
+    lock_evaluation(&contract_id, arweave).await
+  } else {
+    None
+  };
+
   let contract_id_copy = contract_id.to_owned();
   let contract_id_copy2 = contract_id.to_owned();
   let shared_id = contract_id.clone();
-  let (loaded_contract, interactions) = tokio::join!(
-    async move {
-      let contract: Result<LoadedContract, AnyError> = arweave
-        .load_contract(
-          shared_id,
-          contract_src_tx,
-          contract_content_type,
-          None,
-          cache,
-          false,
-          false,
-        )
-        .await;
+  let load_span = tracing::info_span!(
+    "load_contract",
+    contract_id = %contract_id,
+    duration_ms = tracing::field::Empty,
+  );
+  let load = timed(load_span, async move {
+    let contract: Result<LoadedContract, AnyError> = arweave
+      .load_contract(
+        shared_id,
+        contract_src_tx,
+        contract_content_type,
+        None,
+        cache,
+        false,
+        false,
+      )
+      .await;
 
-      contract
-    },
-    async move {
-      let interactions: Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> =
-        arweave
-          .get_interactions(contract_id_copy2, height, cache)
-          .await;
-      let (
-        result_interactions,
-        new_interaction_index,
-        are_there_new_interactions,
-      ) = interactions?;
+    contract
+  });
+  let fetch = async move {
+    let lineage = options.lineage(&contract_id_copy2);
+    let fetch_span = tracing::info_span!(
+      "get_interactions",
+      contract_id = %contract_id_copy2,
+      duration_ms = tracing::field::Empty,
+    );
+    let interactions: Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError> =
+      match cache_height {
+        Some(cache_height) => {
+          timed(
+            fetch_span,
+            arweave.get_confirmed_interactions(
+              contract_id_copy2.to_owned(),
+              cache_height,
+              cache,
+            ),
+          )
+          .await
+        }
+        None => {
+          timed(
+            fetch_span,
+            arweave.get_lineage_interactions(&lineage, height, cache),
+          )
+          .await
+        }
+      };
+    let (
+      result_interactions,
+      new_interaction_index,
+      are_there_new_interactions,
+    ) = interactions?;
 
-      let mut interactions = result_interactions;
+    let mut interactions = result_interactions;
 
-      sort_interactions(&mut interactions);
+    let sort_span = tracing::info_span!(
+      "sort",
+      contract_id = %contract_id_copy2,
+      interactions = interactions.len(),
+      duration_ms = tracing::field::Empty,
+    );
+    timed(sort_span, async {
+      sort_interactions_with(&mut interactions, options);
+      filter_interactions_with(&mut interactions, options);
+      drop_unconfirmed(&mut interactions, max_height);
+    })
+    .await;
 
-      Ok((
-        interactions,
-        new_interaction_index,
-        are_there_new_interactions,
-      )) as Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError>
-    }
-  );
+    Ok((
+      interactions,
+      new_interaction_index,
+      are_there_new_interactions,
+    )) as Result<(Vec<GQLEdgeInterface>, usize, bool), AnyError>
+  };
+  let (loaded_contract, interactions) = if options.sequential_load {
+    (load.await, fetch.await)
+  } else {
+    tokio::join!(load, fetch)
+  };
 
-  let loaded_contract = loaded_contract?;
+  let mut loaded_contract = loaded_contract?;
+  override_init_state(&mut loaded_contract, options)?;
+  ensure_supported_contract(&loaded_contract)?;
   let (result_interactions, new_interaction_index, are_there_new_interactions) =
     interactions?;
+  // EVM states can't be evaluated further, and are never cached anyway.
+  let cache_height = cache_height
+    .filter(|_| !matches!(loaded_contract.contract_type, ContractType::EVM));
 
   let mut interactions = result_interactions;
+  let is_cached = |interaction: &GQLEdgeInterface| {
+    cache_height.map_or(true, |height| interaction.node.block.height <= height)
+  };
+  let last_timestamp = interactions
+    .iter()
+    .rev()
+    .find(|interaction| is_cached(interaction))
+    .map(|interaction| interaction.node.block.timestamp);
 
   let mut validity: IndexMap<String, Value> = IndexMap::new();
 
   let mut needs_processing = true;
   let mut cache_state: Option<Value> = None;
 
-  if cache {
-    let get_cached_state = get_cache()
-      .lock()
-      .unwrap()
-      .find_state(contract_id_copy.to_owned());
+  let since_snapshot = match options.since {
+    Some(since) if cache && cache_results => arweave
+      .try_cache(|cache| {
+        cache.find_state_at(contract_id_copy.to_owned(), since)
+      })
+      .flatten()
+      .filter(|(_, snapshot)| !snapshot.transformed)
+      .and_then(|(_, snapshot)| {
+        let rest = interactions_since(&interactions, since, &snapshot)?;
+        Some((snapshot, rest))
+      }),
+    _ => None,
+  };
+  if let (Some(since), None) = (options.since, &since_snapshot) {
+    tracing::warn!(
+      "No cached state of {} covers timestamp {}, evaluating all interactions",
+      contract_id_copy,
+      since
+    );
+  }
+
+  if let Some((snapshot, rest)) = since_snapshot {
+    cache_state = Some(snapshot.state);
+    validity = snapshot.validity;
+    interactions = rest;
+  } else if cache && cache_results {
+    // A failing cache reads as a miss: everything is evaluated again, and
+    // so does a transformed state, which isn't one the contract evaluates to.
+    let get_cached_state = arweave
+      .try_cache(|cache| cache.find_state(contract_id_copy.to_owned()))
+      .flatten()
+      .filter(|cached_state| !cached_state.transformed);
 
     if let Some(cached_state) = get_cached_state {
       cache_state = Some(cached_state.state);
       validity = cached_state.validity;
-      needs_processing = are_there_new_interactions;
+      if cache_height.is_some() {
+        // The cached state stops short of the interactions at the tip of
+        // earlier calls, the validity table tells which it covers.
+        interactions
+          .retain(|interaction| !validity.contains_key(&interaction.node.id));
+      } else {
+        needs_processing = are_there_new_interactions;
+
+        if are_there_new_interactions {
+          interactions = (&interactions[new_interaction_index..]).to_vec();
+        }
+      }
     }
   }
+  let had_cached_state = cache_state.is_some();
 
-  let is_cache_state_present = cache_state.is_some();
+  if needs_processing {
+    // Interactions already in a cached validity table count as seen.
+    let duplicates = dedup_interactions(&mut interactions, &validity);
+    if duplicates > 0 {
+      tracing::warn!(
+        "Dropped {} duplicate interactions of {}",
+        duplicates,
+        contract_id_copy
+      );
+    }
+  }
 
-  if cache && is_cache_state_present && are_there_new_interactions {
-    interactions = (&interactions[new_interaction_index..]).to_vec();
+  if let Some(resume) = &options.resume {
+    skip_applied(&mut interactions, resume, |interaction| {
+      &interaction.node.id
+    })?;
   }
 
-  let result = raw_execute_contract(
-    contract_id_copy.to_owned(),
-    loaded_contract,
-    interactions,
-    validity,
-    cache_state,
-    needs_processing,
-    show_errors,
-    |validity_table, cache_state| {
-      ExecuteResult::V8(cache_state.unwrap(), validity_table)
-    },
-    arweave,
-  )
-  .await;
+  let processed = if needs_processing {
+    interactions.len()
+  } else {
+    0
+  };
+  // Evaluated after the state that is cached, on top of it.
+  let at_tip = match interactions
+    .iter()
+    .position(|interaction| !is_cached(interaction))
+  {
+    Some(confirmed) => interactions.split_off(confirmed),
+    None => Vec::new(),
+  };
+  let tip_contract = if at_tip.is_empty() {
+    None
+  } else {
+    Some(loaded_contract.clone())
+  };
 
-  LRU_CACHE.lock().unwrap().put(contract_id, result.clone());
+  let execute_span = tracing::info_span!(
+    "execute",
+    contract_id = %contract_id,
+    interactions = interactions.len(),
+    duration_ms = tracing::field::Empty,
+  );
+  let result = timed(
+    execute_span,
+    raw_execute_contract(
+      contract_id_copy.to_owned(),
+      loaded_contract,
+      interactions,
+      validity,
+      cache_state,
+      needs_processing,
+      show_errors,
+      |validity_table, cache_state| {
+        ExecuteResult::V8(cache_state.unwrap(), validity_table)
+      },
+      arweave,
+      options,
+    ),
+  )
+  .await?;
 
-  Ok(result)
-}
+  // A paused evaluation covers only part of the interactions.
+  let cache_results =
+    cache_results && !options.paused() && timeouts() == timeouts_before;
+  if cache && cache_results && needs_processing {
+    if let (ExecuteResult::V8(state, validity), Some(timestamp)) =
+      (&result, last_timestamp)
+    {
+      arweave.try_cache(|cache| {
+        let state = cache_entry(state, validity, options);
+        if !fits_cache(&contract_id, &state, cache.max_state_size())? {
+          return Ok(());
+        }
+        // `raw_execute_contract` only caches the state it ends with when
+        // it starts from a cached one.
+        if cache_height.is_some() && !had_cached_state {
+          cache.cache_states(contract_id.to_owned(), state.clone())?;
+        }
+        cache.cache_state_at(contract_id.to_owned(), timestamp, state)
+      });
+    }
+  }
 
-pub fn get_input_from_interaction(interaction_tx: &GQLNodeInterface) -> &str {
-  let tag = &interaction_tx
-    .tags
-    .iter()
-    .find(|data| &data.name == "Input");
+  let result = match (result, tip_contract) {
+    (result, _) if options.paused() => result,
+    (result, None) => result,
+    (ExecuteResult::V8(state, validity), Some(tip_contract)) => {
+      execute_at_tip(
+        contract_id_copy,
+        tip_contract,
+        state,
+        validity,
+        at_tip,
+        show_errors,
+        arweave,
+        options,
+      )
+      .await?
+    }
+    (_, Some(_)) => {
+      return Err(generic_error(
+        "Only JSON states can be evaluated past the cached height",
+      ))
+    }
+  };
 
-  match tag {
-    Some(data) => &data.value,
-    None => "",
+  if cache_results && cache_in_memory && timeouts() == timeouts_before {
+    LRU_CACHE.lock().unwrap().put(memory_key, result.clone());
   }
+
+  let counts = InteractionCounts {
+    timed_out: timeouts() - timeouts_before,
+    ..InteractionCounts::new(processed, result.validity().len())
+  };
+  Ok((result, counts))
 }
 
-pub fn has_multiple_interactions(interaction_tx: &GQLNodeInterface) -> bool {
-  let tags = (&interaction_tx.tags).to_owned();
-  let count = tags
-    .iter()
-    .filter(|data| data.name == *"Contract")
-    .cloned()
-    .count();
+/// What [`execute_contract`] would evaluate, see [`plan_contract`].
+#[derive(Serialize, Debug)]
+pub struct Plan {
+  pub contract_id: String,
+  pub contract_type: ContractType,
+  pub source_tx_id: String,
+  /// Interactions in evaluation order.
+  pub interactions: Vec<PlannedInteraction>,
+  /// How many interactions the cached state covers, `None` without one.
+  pub cached: Option<usize>,
+}
 
-  count > 1
+#[derive(Serialize, Debug)]
+pub struct PlannedInteraction {
+  pub id: String,
+  pub sort_key: String,
+  /// Already reflected in the cached state, so it won't be evaluated.
+  pub cached: bool,
 }
 
-// String locale compare
-fn strcoll(s1: &str, s2: &str) -> Ordering {
-  let c1 = CString::new(s1).unwrap_or_default();
-  let c2 = CString::new(s2).unwrap_or_default();
-  let cmp = unsafe { libc::strcoll(c1.as_ptr(), c2.as_ptr()) };
-  cmp.cmp(&0)
+/// Loads a contract and fetches, sorts and filters its interactions the
+/// way [`execute_contract`] does, but evaluates none of them.
+pub async fn plan_contract(
+  contract_id: String,
+  height: Option<usize>,
+  cache: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<Plan, AnyError> {
+  let height = confirmed_height(arweave, height, options.confirmations).await?;
+  let load = arweave.load_contract(
+    contract_id.to_owned(),
+    None,
+    None,
+    None,
+    cache,
+    false,
+    false,
+  );
+  let lineage = options.lineage(&contract_id);
+  let fetch = arweave.get_lineage_interactions(&lineage, height, cache);
+  let (loaded_contract, interactions) = if options.sequential_load {
+    (load.await, fetch.await)
+  } else {
+    tokio::join!(load, fetch)
+  };
+  let loaded_contract = loaded_contract?;
+  let (mut interactions, _, _) = interactions?;
+  sort_interactions_with(&mut interactions, options);
+  filter_interactions_with(&mut interactions, options);
+  drop_unconfirmed(
+    &mut interactions,
+    height.filter(|_| options.confirmations > 0),
+  );
+
+  let cached_validity = if cache && !options.is_counterfactual() {
+    arweave
+      .try_cache(|cache| cache.find_state(contract_id.to_owned()))
+      .flatten()
+      .filter(|cached_state| !cached_state.transformed)
+      .map(|cached_state| cached_state.validity)
+  } else {
+    None
+  };
+
+  let interactions = interactions
+    .into_iter()
+    .map(|interaction| {
+      let node = interaction.node;
+      PlannedInteraction {
+        sort_key: get_sort_key(&node.block.height, &node.block.id, &node.id),
+        cached: cached_validity
+          .as_ref()
+          .map_or(false, |validity| validity.contains_key(&node.id)),
+        id: node.id,
+      }
+    })
+    .collect();
+
+  Ok(Plan {
+    contract_id,
+    contract_type: loaded_contract.contract_type,
+    source_tx_id: loaded_contract.contract_src_tx_id,
+    interactions,
+    cached: cached_validity.map(|validity| validity.len()),
+  })
 }
 
-pub fn sort_interactions(interactions: &mut Vec<GQLEdgeInterface>) {
-  interactions.sort_by(|a, b| {
-    let a_sort_key =
-      get_sort_key(&a.node.block.height, &a.node.block.id, &a.node.id);
-    let b_sort_key =
-      get_sort_key(&b.node.block.height, &b.node.block.id, &b.node.id);
+/// Fetches the interactions with a contract up to `height` and sorts them
+/// by SmartWeave sort key, in the order [`execute_contract`] evaluates them
+/// with the default [`EvaluateOptions`]. Nothing is evaluated.
+///
+/// With `cache`, the interaction cache is read and brought up to date the
+/// same way an evaluation does.
+pub async fn fetch_sorted_interactions(
+  arweave: &Arweave,
+  contract_id: String,
+  height: Option<usize>,
+  cache: bool,
+) -> Result<Vec<GQLEdgeInterface>, AnyError> {
+  let (mut interactions, _, _) =
+    arweave.get_interactions(contract_id, height, cache).await?;
+  sort_interactions(&mut interactions);
 
-    strcoll(&a_sort_key, &b_sort_key)
-  });
+  Ok(interactions)
 }
 
-fn nop_cost_fn(_: &Instruction) -> U256 {
-  U256::zero()
+/// Picks the interactions naming `contract_id` in a `Contract` tag out of
+/// a shared fetch, see [`Arweave::get_shared_interactions`].
+pub fn interactions_for(
+  contract_id: &str,
+  interactions: &[GQLEdgeInterface],
+) -> Vec<GQLEdgeInterface> {
+  interactions
+    .iter()
+    .filter(|interaction| {
+      interaction
+        .node
+        .tags
+        .iter()
+        .any(|tag| tag.name == "Contract" && tag.value == contract_id)
+    })
+    .cloned()
+    .collect()
 }
 
-#[cfg(test)]
-mod test {
-  use crate::test_util::generate_fake_interaction;
-  use crate::ExecuteResult;
-  use crate::{execute_contract, sort_interactions};
-  use deno_core::serde_json;
-  use deno_core::serde_json::value::Value::Null;
-  use deno_core::serde_json::Value;
-  use indexmap::map::IndexMap;
-  use serde::Deserialize;
-  use serde::Serialize;
-  use std::collections::HashMap;
-  use three_em_arweave::arweave::Arweave;
-  use three_em_arweave::cache::ArweaveCache;
-  use three_em_arweave::cache::CacheExt;
-  use three_em_arweave::gql_result::GQLEdgeInterface;
+/// Evaluates contracts whose interactions overlap, as when transactions
+/// carry several `Contract` tags, fetching their interactions only once.
+///
+/// Each contract still sees only its own sorted interactions. The shared
+/// query matches an interaction when any of its `Contract` tags names one
+/// of `contract_ids`, so it returns the union of the per-contract queries.
+/// [`interactions_for`] then hands each contract the interactions whose
+/// `Contract` tags name it, exactly what its own query would have returned,
+/// and only that subset is sorted and filtered for it.
+///
+/// Results come back in the order of `contract_ids`. The state cache is
+/// neither read nor written.
+pub async fn execute_contracts(
+  contract_ids: &[String],
+  height: Option<usize>,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<Vec<ExecuteResult>, AnyError> {
+  let height = confirmed_height(arweave, height, options.confirmations).await?;
+  let loads = contract_ids.iter().map(|contract_id| {
+    arweave.load_contract(
+      contract_id.to_owned(),
+      None,
+      None,
+      None,
+      false,
+      false,
+      false,
+    )
+  });
+  let (loaded_contracts, shared) = tokio::join!(
+    join_all(loads),
+    arweave.get_shared_interactions(contract_ids, height)
+  );
+  let shared = shared?;
 
-  #[derive(Deserialize, Serialize)]
-  struct People {
-    username: String,
+  let mut results = Vec::with_capacity(contract_ids.len());
+  for (contract_id, loaded) in contract_ids.iter().zip(loaded_contracts) {
+    let mut loaded_contract = loaded?;
+    override_init_state(&mut loaded_contract, options)?;
+    ensure_supported_contract(&loaded_contract)?;
+
+    let mut interactions = interactions_for(contract_id, &shared);
+    sort_interactions_with(&mut interactions, options);
+    filter_interactions_with(&mut interactions, options);
+    dedup_interactions(&mut interactions, &IndexMap::new());
+
+    let result = raw_execute_contract(
+      contract_id.to_owned(),
+      loaded_contract,
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      show_errors,
+      |_, _| unreachable!("nothing is cached"),
+      arweave,
+      options,
+    )
+    .await?;
+    results.push(result);
+  }
+
+  Ok(results)
+}
+
+/// Evaluates a contract from scratch with the two-phase fetch described in
+/// [`EvaluateOptions::bounded_memory`].
+#[allow(clippy::too_many_arguments)]
+async fn execute_contract_bounded(
+  contract_id: String,
+  height: Option<usize>,
+  show_errors: bool,
+  contract_src_tx: Option<String>,
+  contract_content_type: Option<String>,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
+  if options.sort.is_some() {
+    return Err(generic_error(
+      "A custom sort cannot be used with bounded-memory fetching",
+    ));
+  }
+
+  let load = arweave.load_contract(
+    contract_id.to_owned(),
+    contract_src_tx,
+    contract_content_type,
+    None,
+    false,
+    false,
+    false,
+  );
+  let lineage = options.lineage(&contract_id);
+  let fetch = try_join_all(
+    lineage
+      .iter()
+      .map(|id| arweave.get_interaction_keys(id.to_owned(), height)),
+  );
+  let (loaded_contract, keys) = if options.sequential_load {
+    (load.await, fetch.await)
+  } else {
+    tokio::join!(load, fetch)
+  };
+  let mut loaded_contract = loaded_contract?;
+  override_init_state(&mut loaded_contract, options)?;
+  ensure_supported_contract(&loaded_contract)?;
+  // An interaction naming several ids of the lineage is dropped below.
+  let mut keys: Vec<GQLKeyEdgeInterface> =
+    keys?.into_iter().flatten().collect();
+
+  sort_interaction_keys(&mut keys);
+  let mut seen = HashSet::new();
+  let before = keys.len();
+  keys.retain(|key| seen.insert(key.node.id.to_owned()));
+  if keys.len() < before {
+    tracing::warn!(
+      "Dropped {} duplicate interactions of {}",
+      before - keys.len(),
+      contract_id
+    );
+  }
+  if options.filters_interactions() {
+    keys.retain(|key| is_kept(&key.node.id, &key.node.owner.address, options));
+  }
+  if let Some(resume) = &options.resume {
+    skip_applied(&mut keys, resume, |key| &key.node.id)?;
+  }
+
+  // Only the ids are needed from here on.
+  let pages: Vec<Vec<String>> = keys
+    .chunks(MAX_REQUEST)
+    .map(|page| page.iter().map(|key| key.node.id.to_owned()).collect())
+    .collect();
+  drop(keys);
+
+  let fetch_error: Rc<RefCell<Option<AnyError>>> = Rc::new(RefCell::new(None));
+  let interactions = Box::pin(stream::unfold(
+    (pages.into_iter(), Vec::new().into_iter()),
+    |(mut pages, mut page)| {
+      let fetch_error = fetch_error.clone();
+      async move {
+        loop {
+          if let Some(interaction) = page.next() {
+            return Some((interaction, (pages, page)));
+          }
+
+          let ids = pages.next()?;
+          match arweave.get_interactions_by_id(&ids).await {
+            Ok(interactions) => page = interactions.into_iter(),
+            Err(err) => {
+              *fetch_error.borrow_mut() = Some(err);
+              return None;
+            }
+          }
+        }
+      }
+    },
+  ));
+
+  let result = raw_execute_contract_stream(
+    contract_id,
+    loaded_contract,
+    interactions,
+    IndexMap::new(),
+    None,
+    true,
+    show_errors,
+    |validity_table, cache_state| {
+      ExecuteResult::V8(cache_state.unwrap(), validity_table)
+    },
+    arweave,
+    options,
+  )
+  .await;
+
+  // A page that failed to load ends the stream early, so the result is
+  // only usable if every page came through.
+  let fetch_error = fetch_error.borrow_mut().take();
+  match fetch_error {
+    Some(err) => Err(err),
+    None => result,
+  }
+}
+
+/// Evaluates a contract on interactions as they arrive, for real-time
+/// applications that feed it from a live source instead of the gateway.
+/// The contract is loaded from the gateway, see [`evaluate_live`].
+pub async fn execute_contract_live<S>(
+  contract_id: String,
+  interactions: S,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError>
+where
+  S: Stream<Item = GQLEdgeInterface> + Unpin,
+{
+  let loaded_contract = arweave
+    .load_contract(contract_id.to_owned(), None, None, None, true, false, false)
+    .await?;
+
+  evaluate_live(
+    contract_id,
+    loaded_contract,
+    interactions,
+    show_errors,
+    arweave,
+    options,
+  )
+  .await
+}
+
+/// Evaluates `loaded_contract` on each interaction `interactions` yields,
+/// as it comes, until the stream ends. The running state is handed out
+/// through [`EvaluateOptions::on_step`] or
+/// [`EvaluateOptions::state_sink`], and the result is the state the last
+/// interaction left.
+///
+/// **Interactions are never reordered.** The caller must yield them in
+/// evaluation order: by SmartWeave sort key, or by
+/// [`EvaluateOptions::sort`] if set. An interaction arriving after one it
+/// sorts before would have changed every state since, so it ends the
+/// stream and the evaluation fails with an error rather than returning a
+/// state no other client agrees with. Interactions already evaluated are
+/// dropped, and [`EvaluateOptions::only_owners`] filters as usual.
+///
+/// Nothing is read from or written to the state cache.
+pub async fn evaluate_live<S>(
+  contract_id: String,
+  mut loaded_contract: LoadedContract,
+  interactions: S,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError>
+where
+  S: Stream<Item = GQLEdgeInterface> + Unpin,
+{
+  override_init_state(&mut loaded_contract, options)?;
+  ensure_supported_contract(&loaded_contract)?;
+
+  let order_error: Rc<RefCell<Option<AnyError>>> = Rc::new(RefCell::new(None));
+  let interactions = Box::pin(stream::unfold(
+    (interactions, None::<GQLEdgeInterface>, HashSet::new()),
+    |(mut interactions, mut last, mut seen)| {
+      let order_error = order_error.clone();
+      async move {
+        loop {
+          let interaction = interactions.next().await?;
+          let node = &interaction.node;
+          if !seen.insert(node.id.to_owned()) {
+            continue;
+          }
+          if let Some(last) = &last {
+            if compare_interactions(last, &interaction, options)
+              == Ordering::Greater
+            {
+              *order_error.borrow_mut() = Some(generic_error(format!(
+                "Interaction {} arrived after {}, but sorts before it",
+                node.id, last.node.id
+              )));
+              return None;
+            }
+          }
+          last = Some(interaction.clone());
+          if is_kept(&node.id, &node.owner.address, options) {
+            return Some((interaction, (interactions, last, seen)));
+          }
+        }
+      }
+    },
+  ));
+
+  let result = raw_execute_contract_stream(
+    contract_id,
+    loaded_contract,
+    interactions,
+    IndexMap::new(),
+    None,
+    true,
+    show_errors,
+    |_, _| unreachable!("nothing is cached"),
+    arweave,
+    options,
+  )
+  .await;
+
+  let order_error = order_error.borrow_mut().take();
+  match order_error {
+    Some(err) => Err(err),
+    None => result,
+  }
+}
+
+/// The raw input of an interaction: its `Input` tag, or else the data it
+/// carries it in, see [`GQLNodeInterface::body_input`].
+pub fn get_input_from_interaction(interaction_tx: &GQLNodeInterface) -> &str {
+  let tag = &interaction_tx
+    .tags
+    .iter()
+    .find(|data| &data.name == "Input");
+
+  match (tag, &interaction_tx.body_input) {
+    (Some(data), _) => &data.value,
+    (None, Some(body)) => body,
+    (None, None) => "",
+  }
+}
+
+pub fn has_multiple_interactions(interaction_tx: &GQLNodeInterface) -> bool {
+  let tags = (&interaction_tx.tags).to_owned();
+  let count = tags
+    .iter()
+    .filter(|data| data.name == *"Contract")
+    .cloned()
+    .count();
+
+  count > 1
+}
+
+// String locale compare
+fn strcoll(s1: &str, s2: &str) -> Ordering {
+  let c1 = CString::new(s1).unwrap_or_default();
+  let c2 = CString::new(s2).unwrap_or_default();
+  let cmp = unsafe { libc::strcoll(c1.as_ptr(), c2.as_ptr()) };
+  cmp.cmp(&0)
+}
+
+pub fn sort_interactions(interactions: &mut Vec<GQLEdgeInterface>) {
+  interactions.sort_by(|a, b| {
+    let a_sort_key =
+      get_sort_key(&a.node.block.height, &a.node.block.id, &a.node.id);
+    let b_sort_key =
+      get_sort_key(&b.node.block.height, &b.node.block.id, &b.node.id);
+
+    strcoll(&a_sort_key, &b_sort_key)
+  });
+}
+
+/// Sorts interaction keys the same way [`sort_interactions`] sorts full
+/// interactions.
+pub fn sort_interaction_keys(keys: &mut Vec<GQLKeyEdgeInterface>) {
+  keys.sort_by(|a, b| {
+    let a_sort_key =
+      get_sort_key(&a.node.block.height, &a.node.block.id, &a.node.id);
+    let b_sort_key =
+      get_sort_key(&b.node.block.height, &b.node.block.id, &b.node.id);
+
+    strcoll(&a_sort_key, &b_sort_key)
+  });
+}
+
+/// Sorts interactions with the comparator from `options`, falling back to
+/// the SmartWeave sort key.
+pub fn sort_interactions_with(
+  interactions: &mut Vec<GQLEdgeInterface>,
+  options: &EvaluateOptions,
+) {
+  match &options.sort {
+    Some(compare) => interactions.sort_by(|a, b| compare(a, b)),
+    None => sort_interactions(interactions),
+  }
+}
+
+/// The order [`sort_interactions_with`] puts `a` and `b` in.
+fn compare_interactions(
+  a: &GQLEdgeInterface,
+  b: &GQLEdgeInterface,
+  options: &EvaluateOptions,
+) -> Ordering {
+  match &options.sort {
+    Some(compare) => compare(a, b),
+    None => {
+      let a_sort_key =
+        get_sort_key(&a.node.block.height, &a.node.block.id, &a.node.id);
+      let b_sort_key =
+        get_sort_key(&b.node.block.height, &b.node.block.id, &b.node.id);
+
+      strcoll(&a_sort_key, &b_sort_key)
+    }
+  }
+}
+
+/// Drops interactions whose id was already seen, either earlier in
+/// `interactions` or in `validity`, keeping the first occurrence. Applying
+/// an interaction twice would corrupt the state.
+///
+/// Returns how many interactions were dropped.
+pub fn dedup_interactions(
+  interactions: &mut Vec<GQLEdgeInterface>,
+  validity: &ValidityTable,
+) -> usize {
+  let before = interactions.len();
+  let mut seen = HashSet::new();
+  interactions.retain(|interaction| {
+    let id = &interaction.node.id;
+    !validity.contains_key(id) && seen.insert(id.to_owned())
+  });
+
+  before - interactions.len()
+}
+
+/// Interactions left to evaluate on top of `snapshot` for
+/// [`EvaluateOptions::since`]: those with a block timestamp after `since`
+/// that `snapshot` doesn't already cover.
+///
+/// Returns `None` if an interaction at or before `since` is missing from
+/// the snapshot, as it can't serve as the base then.
+pub fn interactions_since(
+  interactions: &[GQLEdgeInterface],
+  since: usize,
+  snapshot: &StateResult,
+) -> Option<Vec<GQLEdgeInterface>> {
+  let mut rest = Vec::new();
+  for interaction in interactions {
+    let covered = snapshot.validity.contains_key(&interaction.node.id);
+    if interaction.node.block.timestamp <= since {
+      if !covered {
+        return None;
+      }
+    } else if !covered {
+      rest.push(interaction.clone());
+    }
+  }
+
+  Some(rest)
+}
+
+/// Evaluates `interactions`, those mined past the height of
+/// [`EvaluateOptions::cache_confirmations`], on top of `state`, the state
+/// of the contract after the interactions of `validity`. Nothing is
+/// cached.
+#[allow(clippy::too_many_arguments)]
+async fn execute_at_tip(
+  contract_id: String,
+  mut loaded_contract: LoadedContract,
+  state: Value,
+  validity: ValidityTable,
+  interactions: Vec<GQLEdgeInterface>,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<ExecuteResult, AnyError> {
+  // Without interactions before, `state` is what the init state becomes,
+  // which is evaluated again instead.
+  let applied = validity.len();
+  let resume = Some(Resume {
+    state,
+    applied,
+    after: validity.keys().last().cloned(),
+  });
+  let options = EvaluateOptions {
+    resume: resume.filter(|_| applied > 0),
+    ..options.clone()
+  };
+  override_init_state(&mut loaded_contract, &options)?;
+
+  raw_execute_contract(
+    contract_id,
+    loaded_contract,
+    interactions,
+    validity,
+    None,
+    true,
+    show_errors,
+    |_, _| unreachable!("interactions at the tip are always evaluated"),
+    arweave,
+    &options,
+  )
+  .await
+}
+
+/// Replaces the init state of `loaded_contract` with the one evaluation
+/// starts from under [`EvaluateOptions::init_state`] or
+/// [`EvaluateOptions::resume`]. Fails if the contract can't start from
+/// [`EvaluateOptions::genesis_interaction`], or can't be evaluated
+/// [`EvaluateOptions::readonly`].
+fn override_init_state(
+  loaded_contract: &mut LoadedContract,
+  options: &EvaluateOptions,
+) -> Result<(), AnyError> {
+  if options.readonly {
+    if let ContractType::EVM = loaded_contract.contract_type {
+      return Err(generic_error("EVM contracts cannot be evaluated readonly"));
+    }
+    if options.genesis_interaction {
+      return Err(generic_error(
+        "A genesis interaction cannot be evaluated readonly",
+      ));
+    }
+  }
+  if options.genesis_interaction
+    && !matches!(loaded_contract.contract_type, ContractType::JAVASCRIPT)
+  {
+    return Err(generic_error(
+      "Only JavaScript contracts support a genesis interaction",
+    ));
+  }
+  if let Some(resume) = &options.resume {
+    if let ContractType::EVM = loaded_contract.contract_type {
+      return Err(generic_error("EVM contracts cannot be resumed"));
+    }
+    loaded_contract.init_state = resume.state.to_string();
+  } else if let Some(init_state) = &options.init_state {
+    loaded_contract.init_state = init_state.to_owned();
+  }
+
+  Ok(())
+}
+
+/// Drops the first `applied` interactions for [`EvaluateOptions::resume`].
+fn skip_applied<T>(
+  interactions: &mut Vec<T>,
+  resume: &Resume,
+  id: impl Fn(&T) -> &String,
+) -> Result<(), AnyError> {
+  let applied = resume.applied;
+  if applied > interactions.len() {
+    return Err(generic_error(format!(
+      "Cannot resume after {} interactions, the contract only has {}",
+      applied,
+      interactions.len()
+    )));
+  }
+  if let Some(after) = &resume.after {
+    let last = applied.checked_sub(1).map(|last| id(&interactions[last]));
+    if last != Some(after) {
+      return Err(generic_error(format!(
+        "Cannot resume after {}, it is no longer interaction {}",
+        after, applied
+      )));
+    }
+  }
+  interactions.drain(..applied);
+
+  Ok(())
+}
+
+/// The height to evaluate up to under [`EvaluateOptions::confirmations`]:
+/// `height`, but no higher than `confirmations` blocks below the current
+/// network height. With no confirmations, `height` as is and no request.
+pub async fn confirmed_height(
+  arweave: &Arweave,
+  height: Option<usize>,
+  confirmations: usize,
+) -> Result<Option<usize>, AnyError> {
+  if confirmations == 0 {
+    return Ok(height);
+  }
+  let tip = arweave.get_network_info().await?.height;
+  let max_height = tip.saturating_sub(confirmations);
+  let height = height.map_or(max_height, |height| height.min(max_height));
+  Ok(Some(height))
+}
+
+/// Drops the interactions mined above `max_height`. The gateway leaves them
+/// out of a fetch at that height, but cached interactions may have been
+/// fetched closer to the tip.
+fn drop_unconfirmed(
+  interactions: &mut Vec<GQLEdgeInterface>,
+  max_height: Option<usize>,
+) {
+  if let Some(max_height) = max_height {
+    interactions
+      .retain(|interaction| interaction.node.block.height <= max_height);
+  }
+}
+
+/// Drops the interactions excluded by [`EvaluateOptions::only_owners`] and
+/// [`EvaluateOptions::sample`].
+pub fn filter_interactions_with(
+  interactions: &mut Vec<GQLEdgeInterface>,
+  options: &EvaluateOptions,
+) {
+  if options.filters_interactions() {
+    interactions.retain(|interaction| {
+      let node = &interaction.node;
+      is_kept(&node.id, &node.owner.address, options)
+    });
+  }
+}
+
+/// Whether [`EvaluateOptions::only_owners`] and [`EvaluateOptions::sample`]
+/// keep the interaction `id` sent by `owner`. Those they don't are reported
+/// to [`EvaluateOptions::on_filtered`].
+fn is_kept(id: &str, owner: &str, options: &EvaluateOptions) -> bool {
+  let kept = options
+    .only_owners
+    .as_ref()
+    .map_or(true, |owners| owners.contains(owner))
+    && options.sample.map_or(true, |sample| sample.keeps(id));
+  if let (false, Some(on_filtered)) = (kept, &options.on_filtered) {
+    on_filtered(id);
+  }
+  kept
+}
+
+#[cfg(feature = "evm")]
+fn nop_cost_fn(_: &Instruction) -> U256 {
+  U256::zero()
+}
+
+#[cfg(all(test, feature = "js", feature = "wasm", feature = "evm"))]
+mod test {
+  use crate::cursor::Cursor;
+  use crate::delta::apply_delta;
+  use crate::executor::{process_execution, raw_execute_contract};
+  use crate::limits::Limits;
+  use crate::test_util::generate_fake_interaction;
+  use crate::test_util::generate_fake_loaded_contract_data;
+  use crate::RuntimeSettings;
+  use crate::Sample;
+  use crate::{
+    confirmed_height, dedup_interactions, evaluate_live, execute_contract,
+    execute_contract_bounded, execute_contract_delta, execute_contract_steps,
+    execute_contract_with_counts, execute_contracts, fetch_sorted_interactions,
+    filter_interactions_with, interactions_for, interactions_since,
+    plan_contract, sort_interactions, sort_interactions_with, Step,
+  };
+  use crate::{Clock, DeterminismContext, Seed};
+  use crate::{EvaluateOptions, ExecuteResult, InteractionCounts, Resume};
+  use deno_core::error::AnyError;
+  use deno_core::futures::channel::mpsc;
+  use deno_core::futures::{stream, FutureExt, StreamExt};
+  use deno_core::serde_json;
+  use deno_core::serde_json::value::Value::Null;
+  use deno_core::serde_json::Value;
+  use indexmap::map::IndexMap;
+  use serde::Deserialize;
+  use serde::Serialize;
+  use std::collections::{HashMap, HashSet};
+  use std::sync::{Arc, Mutex};
+  use std::time::{SystemTime, UNIX_EPOCH};
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::arweave::{get_cache, Tag, TransactionData};
+  use three_em_arweave::cache::ArweaveCache;
+  use three_em_arweave::cache::CacheExt;
+  use three_em_arweave::cache::StateResult;
+  use three_em_arweave::gql_result::GQLEdgeInterface;
+  use three_em_arweave::gql_result::GQLTagInterface;
+  use three_em_arweave::miscellaneous::ContractType;
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+  use tokio::net::TcpListener;
+
+  #[derive(Deserialize, Serialize)]
+  struct People {
+    username: String,
   }
 
   #[tokio::test]
@@ -452,6 +1746,7 @@ mod test {
       None,
       None,
       &arweave,
+      &EvaluateOptions::default(),
     )
     .await
     .unwrap();
@@ -497,6 +1792,7 @@ mod test {
       None,
       None,
       &arweave,
+      &EvaluateOptions::default(),
     )
     .await
     .unwrap();
@@ -517,37 +1813,2523 @@ mod test {
   }
 
   #[tokio::test]
-  async fn test_execute_javascript() {
+  async fn test_execute_contract_steps() {
     let arweave = Arweave::new(
       80,
       String::from("arweave.net"),
       String::from("https"),
       ArweaveCache::new(),
     );
-    let result = execute_contract(
-      String::from("t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE"),
+    let steps: Vec<Step> = execute_contract_steps(
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ"),
+      Some(822062),
+      false,
+      false,
+      None,
       None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .map(|step| step.unwrap())
+    .collect()
+    .await;
+
+    let result = execute_contract(
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ"),
+      Some(822062),
       false,
       false,
       None,
       None,
       &arweave,
+      &EvaluateOptions::default(),
     )
     .await
     .unwrap();
-    if let ExecuteResult::V8(value, _validity) = result {
-      assert!(!(value.is_null()));
-      assert!(value.get("people").is_some());
-      assert!(value.get("people").unwrap().is_array());
-      let people = value.get("people").unwrap();
-      let people_struct: Vec<People> =
-        serde_json::from_value(people.to_owned()).unwrap();
-      let is_marton_here = people_struct
-        .iter()
-        .find(|data| data.username == *"martonlederer");
-      assert!(is_marton_here.is_some());
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(steps.len(), validity.len());
+      for (step, (id, valid)) in steps.iter().zip(validity.iter()) {
+        assert_eq!(&step.interaction_id, id);
+        assert_eq!(&step.valid, valid);
+      }
+      assert_eq!(steps.last().unwrap().state, value);
     } else {
-      assert!(false);
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_resume_matches_full_run() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+    let steps: Vec<Step> = execute_contract_steps(
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .map(|step| step.unwrap())
+    .collect()
+    .await;
+
+    let resume = |applied: usize| EvaluateOptions {
+      resume: Some(Resume {
+        state: steps[applied - 1].state.clone(),
+        applied,
+        after: None,
+      }),
+      ..Default::default()
+    };
+    let applied = steps.len() / 2;
+    let (result, counts) = execute_contract_with_counts(
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &resume(applied),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(value, steps.last().unwrap().state);
+      assert_eq!(counts.processed, steps.len() - applied);
+      assert_eq!(
+        validity.get_index(0).unwrap().0,
+        &steps[applied].interaction_id
+      );
+    } else {
+      panic!("Unexpected entry");
+    }
+
+    let mut too_far = resume(steps.len());
+    too_far.resume.as_mut().unwrap().applied += 1;
+    assert!(execute_contract_with_counts(
+      contract_id,
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &too_far,
+    )
+    .await
+    .is_err());
+  }
+
+  #[tokio::test]
+  async fn test_delta_rebuilds_target_state() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+    let evaluate = |height: usize| {
+      let arweave = &arweave;
+      let contract_id = contract_id.to_owned();
+      async move {
+        let result = execute_contract(
+          contract_id,
+          Some(height),
+          false,
+          false,
+          None,
+          None,
+          arweave,
+          &EvaluateOptions::default(),
+        )
+        .await
+        .unwrap();
+        result.state().unwrap().clone()
+      }
+    };
+
+    // A client holding the state halfway through the interactions.
+    let interactions = fetch_sorted_interactions(
+      &arweave,
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+    )
+    .await
+    .unwrap();
+    let base_height = interactions[interactions.len() / 2].node.block.height;
+    let base = evaluate(base_height).await;
+    let target = evaluate(822062).await;
+    assert_ne!(base, target);
+
+    let patches = execute_contract_delta(
+      contract_id.to_owned(),
+      base.clone(),
+      base_height,
+      Some(822062),
+      false,
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert!(!patches.is_empty());
+    let mut state = base;
+    apply_delta(&mut state, &patches).unwrap();
+    assert_eq!(state, target);
+
+    assert!(execute_contract_delta(
+      contract_id,
+      target,
+      822062,
+      Some(base_height),
+      false,
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .is_err());
+  }
+
+  #[tokio::test]
+  async fn test_dedup_interactions() {
+    let ids = [("txA0", "Andres"), ("txA1", "Divy"), ("txA0", "Andres")];
+    let mut interactions = ids
+      .iter()
+      .map(|(id, name)| {
+        generate_fake_interaction(
+          serde_json::json!({
+            "function": "add",
+            "name": name
+          }),
+          id,
+          Some(String::from("AAAA")),
+          Some(1),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+
+    sort_interactions(&mut interactions);
+    assert_eq!(dedup_interactions(&mut interactions, &IndexMap::new()), 1);
+    assert_eq!(interactions.len(), 2);
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      interactions.clone(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| panic!("not implemented"),
+      &Arweave::new(
+        443,
+        String::from("arweave.net"),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      let users = value["users"].as_array().unwrap();
+      assert_eq!(users.len(), 2);
+      assert_eq!(users.iter().filter(|user| *user == "Andres").count(), 1);
+      assert_eq!(validity.len(), 2);
+
+      // Ids already in a cached validity table are not applied again.
+      assert_eq!(dedup_interactions(&mut interactions, &validity), 2);
+      assert!(interactions.is_empty());
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_live_stream() {
+    let arweave = Arweave::new(
+      443,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract = || {
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      )
+    };
+    let mut interactions = ["tx00", "tx01", "tx02", "tx03", "tx04"]
+      .iter()
+      .map(|id| {
+        generate_fake_interaction(
+          serde_json::json!({ "function": "add", "name": id }),
+          id,
+          Some(String::from("AAAA")),
+          Some(1),
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+    sort_interactions(&mut interactions);
+
+    let batch = raw_execute_contract(
+      String::new(),
+      contract(),
+      interactions.clone(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| panic!("not implemented"),
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // Interactions arrive one at a time while the evaluation runs, one of
+    // them twice.
+    let (sender, receiver) = mpsc::unbounded();
+    let mut arriving = interactions.clone();
+    arriving.insert(2, interactions[1].clone());
+    let feed = async move {
+      for interaction in arriving {
+        sender.unbounded_send(interaction).unwrap();
+        tokio::task::yield_now().await;
+      }
+    };
+    let steps = Arc::new(Mutex::new(0));
+    let counted = steps.clone();
+    let options = EvaluateOptions {
+      on_step: Some(Arc::new(move |_: &str, _: &Value, _: &Value| {
+        *counted.lock().unwrap() += 1;
+      })),
+      ..Default::default()
+    };
+    let live = evaluate_live(
+      String::new(),
+      contract(),
+      receiver,
+      false,
+      &arweave,
+      &options,
+    );
+    let (_, live) = tokio::join!(feed, live);
+    let live = live.unwrap();
+    assert_eq!(*steps.lock().unwrap(), 5);
+    assert_eq!(live.state(), batch.state());
+    assert_eq!(live.validity(), batch.validity());
+
+    // An interaction arriving too late can't be applied any more.
+    let mut late = interactions.clone();
+    late.swap(1, 3);
+    let late = evaluate_live(
+      String::new(),
+      contract(),
+      stream::iter(late),
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await;
+    assert!(late.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_interactions_since() {
+    let interactions = [("txA", 100), ("txB", 200), ("txC", 300)]
+      .iter()
+      .enumerate()
+      .map(|(height, (id, timestamp))| {
+        generate_fake_interaction(
+          serde_json::json!({}),
+          id,
+          Some(String::from("AAAA")),
+          Some(height),
+          None,
+          None,
+          None,
+          None,
+          None,
+          Some(*timestamp),
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+    let snapshot = |ids: &[&str]| StateResult {
+      state: serde_json::json!({}),
+      validity: ids
+        .iter()
+        .map(|id| (id.to_string(), Value::Bool(true)))
+        .collect(),
+      transformed: false,
+    };
+    let ids = |rest: Vec<GQLEdgeInterface>| {
+      rest
+        .into_iter()
+        .map(|interaction| interaction.node.id)
+        .collect::<Vec<String>>()
+    };
+
+    let rest =
+      interactions_since(&interactions, 200, &snapshot(&["txA", "txB"]));
+    assert_eq!(ids(rest.unwrap()), vec!["txC"]);
+
+    // Nothing happened between 200 and 250, so the same snapshot covers 250.
+    let rest =
+      interactions_since(&interactions, 250, &snapshot(&["txA", "txB"]));
+    assert_eq!(ids(rest.unwrap()), vec!["txC"]);
+
+    // txB is at or before `since` but missing from the snapshot.
+    assert!(
+      interactions_since(&interactions, 200, &snapshot(&["txA"])).is_none()
+    );
+    let rest = interactions_since(&interactions, 50, &snapshot(&[]));
+    assert_eq!(ids(rest.unwrap()), vec!["txA", "txB", "txC"]);
+  }
+
+  #[tokio::test]
+  async fn test_only_owners() {
+    let owners = [("Andres", "alice"), ("Divy", "bob"), ("Tate", "alice")];
+    let mut interactions = owners
+      .iter()
+      .enumerate()
+      .map(|(height, (name, owner))| {
+        generate_fake_interaction(
+          serde_json::json!({
+            "function": "add",
+            "name": name
+          }),
+          &format!("txA{}", height),
+          Some(String::from("AAAA")),
+          Some(height),
+          Some(owner.to_string()),
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect::<Vec<GQLEdgeInterface>>();
+    let filtered = Arc::new(Mutex::new(vec![]));
+    let on_filtered = filtered.clone();
+    let options = EvaluateOptions {
+      only_owners: Some(HashSet::from([String::from("alice")])),
+      on_filtered: Some(Arc::new(move |id: &str| {
+        on_filtered.lock().unwrap().push(id.to_owned());
+      })),
+      ..Default::default()
+    };
+    assert!(options.is_counterfactual());
+
+    sort_interactions_with(&mut interactions, &options);
+    filter_interactions_with(&mut interactions, &options);
+    assert_eq!(interactions.len(), 2);
+    assert_eq!(*filtered.lock().unwrap(), vec![String::from("txA1")]);
+
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| panic!("not implemented"),
+      &Arweave::new(
+        443,
+        String::from("arweave.net"),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &options,
+    )
+    .await
+    .unwrap();
+
+    if let ExecuteResult::V8(value, validity) = result {
+      assert_eq!(value, serde_json::json!({ "users": ["Andres", "Tate"] }));
+      assert!(validity.get("txA1").is_none());
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_sample() {
+    let sampled = |fraction: f64, seed: u64| {
+      let mut interactions = (0..1000)
+        .map(|i| {
+          generate_fake_interaction(
+            serde_json::json!({}),
+            &format!("tx{}", i),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect::<Vec<GQLEdgeInterface>>();
+      let options = EvaluateOptions {
+        sample: Some(Sample { fraction, seed }),
+        ..Default::default()
+      };
+      assert!(options.is_counterfactual());
+      filter_interactions_with(&mut interactions, &options);
+      interactions
+        .into_iter()
+        .map(|interaction| interaction.node.id)
+        .collect::<Vec<String>>()
+    };
+
+    // The same seed selects the same subset, another seed another one.
+    let subset = sampled(0.1, 42);
+    assert_eq!(sampled(0.1, 42), subset);
+    assert_ne!(sampled(0.1, 7), subset);
+    assert!((50..150).contains(&subset.len()), "{}", subset.len());
+
+    // A larger share of the same seed keeps what a smaller one does.
+    let larger = sampled(0.5, 42);
+    assert!(subset.iter().all(|id| larger.contains(id)));
+    assert_eq!(sampled(1.0, 42).len(), 1000);
+    assert!(sampled(0.0, 42).is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_custom_sort() {
+    let interactions = || {
+      ["Andres", "Divy", "Tate"]
+        .iter()
+        .enumerate()
+        .map(|(height, name)| {
+          generate_fake_interaction(
+            serde_json::json!({
+              "function": "add",
+              "name": name
+            }),
+            // Sort keys need base64url ids.
+            &format!("txA{}", height),
+            Some(String::from("AAAA")),
+            Some(height),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect::<Vec<GQLEdgeInterface>>()
+    };
+    let arweave = Arweave::new(
+      443,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+
+    let default_options = EvaluateOptions::default();
+    let reverse_options = EvaluateOptions {
+      sort: Some(Arc::new(|a: &GQLEdgeInterface, b: &GQLEdgeInterface| {
+        b.node.block.height.cmp(&a.node.block.height)
+      })),
+      ..Default::default()
+    };
+
+    let mut states = vec![];
+    for options in [&default_options, &reverse_options] {
+      let mut sorted = interactions();
+      sort_interactions_with(&mut sorted, options);
+
+      let result = raw_execute_contract(
+        String::new(),
+        generate_fake_loaded_contract_data(
+          include_bytes!("../../testdata/contracts/users_contract.js"),
+          ContractType::JAVASCRIPT,
+          String::from(r#"{"users":[]}"#),
+        ),
+        sorted,
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| panic!("not implemented"),
+        &arweave,
+        options,
+      )
+      .await
+      .unwrap();
+
+      if let ExecuteResult::V8(value, _) = result {
+        states.push(value);
+      } else {
+        panic!("Unexpected entry");
+      }
+    }
+
+    assert_eq!(
+      states[0],
+      serde_json::json!({ "users": ["Andres", "Divy", "Tate"] })
+    );
+    assert_eq!(
+      states[1],
+      serde_json::json!({ "users": ["Tate", "Divy", "Andres"] })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_interaction_counts() {
+    assert_eq!(
+      InteractionCounts::new(3, 10),
+      InteractionCounts {
+        processed: 3,
+        skipped_from_cache: 7,
+        total: 10,
+        timed_out: 0,
+      }
+    );
+
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let (result, counts) = execute_contract_with_counts(
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ"),
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(counts.total, result.validity().len());
+    assert_eq!(counts.processed + counts.skipped_from_cache, counts.total);
+    assert!(counts.is_authoritative());
+  }
+
+  #[tokio::test]
+  async fn test_bounded_memory_matches_full_fetch() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+
+    // Called directly to stay clear of the in-process result cache.
+    let bounded = execute_contract_bounded(
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    let full = execute_contract(
+      contract_id,
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let (
+      ExecuteResult::V8(bounded_state, bounded_validity),
+      ExecuteResult::V8(full_state, full_validity),
+    ) = (bounded, full)
+    {
+      assert_eq!(bounded_state, full_state);
+      assert_eq!(bounded_validity, full_validity);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_plan_matches_execution() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+
+    let plan = plan_contract(
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    let result = execute_contract(
+      contract_id,
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(plan.contract_type, ContractType::JAVASCRIPT));
+    assert_eq!(plan.cached, None);
+    let planned = plan
+      .interactions
+      .iter()
+      .map(|interaction| interaction.id.as_str())
+      .collect::<Vec<_>>();
+    let evaluated = result
+      .validity()
+      .keys()
+      .map(|id| id.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(planned, evaluated);
+  }
+
+  #[tokio::test]
+  async fn test_sorted_interactions_match_execution() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+
+    let interactions = fetch_sorted_interactions(
+      &arweave,
+      contract_id.to_owned(),
+      Some(822062),
+      false,
+    )
+    .await
+    .unwrap();
+    let result = execute_contract(
+      contract_id,
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let sorted = interactions
+      .iter()
+      .map(|interaction| interaction.node.id.as_str())
+      .collect::<Vec<_>>();
+    let evaluated = result
+      .validity()
+      .keys()
+      .map(|id| id.as_str())
+      .collect::<Vec<_>>();
+    assert!(!sorted.is_empty());
+    assert_eq!(sorted, evaluated);
+  }
+
+  #[tokio::test]
+  async fn test_shared_fetch_matches_execution() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let contract_id =
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ");
+
+    let shared = execute_contracts(
+      &[contract_id.to_owned()],
+      Some(822062),
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    let full = execute_contract(
+      contract_id,
+      Some(822062),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    if let (
+      [ExecuteResult::V8(shared_state, shared_validity)],
+      ExecuteResult::V8(full_state, full_validity),
+    ) = (&shared[..], full)
+    {
+      assert_eq!(shared_state, &full_state);
+      assert_eq!(shared_validity, &full_validity);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_interactions_for() {
+    let contract_tag = |contract_id: &str| GQLTagInterface {
+      name: String::from("Contract"),
+      value: String::from(contract_id),
+    };
+    let interaction = |id: &str, contract_ids: &[&str]| {
+      let mut interaction = generate_fake_interaction(
+        Null, id, None, None, None, None, None, None, None, None,
+      );
+      for contract_id in contract_ids {
+        interaction.node.tags.push(contract_tag(contract_id));
+      }
+      interaction
+    };
+    let shared = vec![
+      interaction("a", &["A"]),
+      interaction("ab", &["A", "B"]),
+      interaction("b", &["B"]),
+      interaction("c", &["C"]),
+    ];
+
+    let ids = |contract_id: &str| {
+      interactions_for(contract_id, &shared)
+        .into_iter()
+        .map(|interaction| interaction.node.id)
+        .collect::<Vec<String>>()
+    };
+    assert_eq!(ids("A"), vec!["a", "ab"]);
+    assert_eq!(ids("B"), vec!["ab", "b"]);
+    assert!(ids("D").is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_execute_javascript() {
+    let arweave = Arweave::new(
+      80,
+      String::from("arweave.net"),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let result = execute_contract(
+      String::from("t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE"),
+      None,
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    if let ExecuteResult::V8(value, _validity) = result {
+      assert!(!(value.is_null()));
+      assert!(value.get("people").is_some());
+      assert!(value.get("people").unwrap().is_array());
+      let people = value.get("people").unwrap();
+      let people_struct: Vec<People> =
+        serde_json::from_value(people.to_owned()).unwrap();
+      let is_marton_here = people_struct
+        .iter()
+        .find(|data| data.username == *"martonlederer");
+      assert!(is_marton_here.is_some());
+    } else {
+      assert!(false);
+    }
+  }
+
+  /// Serves `routes`, request paths to response bodies, like a gateway.
+  async fn mock_gateway(routes: HashMap<String, Vec<u8>>) -> Arweave {
+    mock_gateway_logged(routes).await.0
+  }
+
+  /// Like [`mock_gateway`], also keeping the head of every request.
+  async fn mock_gateway_logged(
+    routes: HashMap<String, Vec<u8>>,
+  ) -> (Arweave, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let log = requests.clone();
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 4096];
+        let len = socket.read(&mut request).await.unwrap();
+        let request = String::from_utf8_lossy(&request[..len]);
+        log.lock().unwrap().push(request.to_string());
+        let path = request.split(' ').nth(1).unwrap_or_default();
+        let (status, body) = match routes.get(path) {
+          Some(body) => ("200 OK", body.clone()),
+          None => ("404 Not Found", vec![]),
+        };
+        let head = format!(
+          "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+          status,
+          body.len()
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(&body).await.unwrap();
+      }
+    });
+
+    let arweave =
+      Arweave::new_from_url_no_cache(&format!("http://127.0.0.1:{}", port))
+        .unwrap();
+    (arweave, requests)
+  }
+
+  fn mock_transaction(id: &str, tags: &[(&str, &str)]) -> Vec<u8> {
+    let tags = tags
+      .iter()
+      .map(|(name, value)| Tag {
+        name: base64::encode_config(name, base64::URL_SAFE_NO_PAD),
+        value: base64::encode(value),
+      })
+      .collect();
+    serde_json::to_vec(&TransactionData {
+      id: id.to_owned(),
+      tags,
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  /// A GraphQL page of interactions `(id, block height, Input tag)`, all
+  /// from `owner`.
+  fn mock_interactions(
+    interactions: &[(&str, usize, Option<&str>)],
+  ) -> Vec<u8> {
+    let edges = interactions
+      .iter()
+      .map(|(id, height, input)| {
+        let tags = input
+          .iter()
+          .map(|input| serde_json::json!({ "name": "Input", "value": input }))
+          .collect::<Vec<_>>();
+        serde_json::json!({
+          "cursor": id,
+          "node": {
+            "id": id,
+            "owner": { "address": "owner" },
+            "tags": tags,
+            "block": { "height": height, "id": "block", "timestamp": height },
+          },
+        })
+      })
+      .collect::<Vec<_>>();
+    serde_json::to_vec(&serde_json::json!({
+      "data": { "transactions": {
+        "pageInfo": { "hasNextPage": false },
+        "edges": edges,
+      }},
+    }))
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_split_source() {
+    let init_state = r#"{"users":[]}"#;
+    let content_type = ("Content-Type", "application/javascript");
+    let routes = HashMap::from([
+      (
+        String::from("/tx/split-test-single"),
+        mock_transaction(
+          "split-test-single",
+          &[
+            ("Contract-Src", "split-test-src"),
+            ("Init-State", init_state),
+          ],
+        ),
+      ),
+      (
+        String::from("/tx/split-test-src"),
+        mock_transaction("split-test-src", &[content_type]),
+      ),
+      (
+        String::from("/split-test-src"),
+        include_bytes!("../../testdata/contracts/users_contract.js").to_vec(),
+      ),
+      (
+        String::from("/tx/split-test-split"),
+        mock_transaction(
+          "split-test-split",
+          &[
+            ("Contract-Src", "split-test-src-parts"),
+            ("Init-State", init_state),
+          ],
+        ),
+      ),
+      // Tags in the opposite order of the part ids, which must not matter.
+      (
+        String::from("/tx/split-test-src-parts"),
+        mock_transaction(
+          "split-test-src-parts",
+          &[
+            content_type,
+            ("Contract-Src-Part", "split-test-part-b"),
+            ("Contract-Src-Part", "split-test-part-a"),
+          ],
+        ),
+      ),
+      (
+        String::from("/split-test-part-b"),
+        include_bytes!("../../testdata/contracts/users_contract.part1.js")
+          .to_vec(),
+      ),
+      (
+        String::from("/split-test-part-a"),
+        include_bytes!("../../testdata/contracts/users_contract.part2.js")
+          .to_vec(),
+      ),
+      // Deployment blocks, none of the contracts is mined.
+      (
+        String::from("/graphql"),
+        br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+          .to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    let mut results = vec![];
+    for contract_id in ["split-test-single", "split-test-split"] {
+      let contract = arweave
+        .load_contract(
+          String::from(contract_id),
+          None,
+          None,
+          None,
+          false,
+          false,
+          false,
+        )
+        .await
+        .unwrap();
+      assert!(matches!(contract.contract_type, ContractType::JAVASCRIPT));
+      let source = contract.contract_src.clone();
+
+      let result = raw_execute_contract(
+        String::from(contract_id),
+        contract,
+        vec![generate_fake_interaction(
+          serde_json::json!({
+            "function": "add",
+            "name": "Divy"
+          }),
+          "tx1",
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )],
+        IndexMap::new(),
+        None,
+        true,
+        false,
+        |_, _| unreachable!(),
+        &arweave,
+        &EvaluateOptions::default(),
+      )
+      .await
+      .unwrap();
+      results.push((source, process_execution(result, true)));
+    }
+
+    assert_eq!(results[0], results[1]);
+    assert_eq!(
+      results[0].1,
+      serde_json::json!({
+        "state": { "users": ["Divy"] },
+        "validity": { "tx1": true }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_inline_source() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    // No route serves a source transaction, loading one would fail.
+    let routes = HashMap::from([
+      (
+        String::from("/tx/inline-test"),
+        mock_transaction(
+          "inline-test",
+          &[
+            ("Contract-Src-Inline", data_uri.as_str()),
+            ("Init-State", r#"{"users":[]}"#),
+          ],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+          .to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    let contract = arweave
+      .load_contract(
+        String::from("inline-test"),
+        None,
+        None,
+        None,
+        false,
+        false,
+        false,
+      )
+      .await
+      .unwrap();
+    assert!(matches!(contract.contract_type, ContractType::JAVASCRIPT));
+    assert_eq!(contract.contract_src_tx_id, "inline-test");
+    assert_eq!(
+      contract.contract_src,
+      include_bytes!("../../testdata/contracts/users_contract.js").to_vec()
+    );
+
+    let result = raw_execute_contract(
+      String::from("inline-test"),
+      contract,
+      vec![generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": "Divy"
+        }),
+        "tx1",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )],
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| unreachable!(),
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+      process_execution(result, false),
+      serde_json::json!({ "users": ["Divy"] })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_body_input() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    // `body-tx` has no `Input` tag, its input is its data. `tagged-tx` has
+    // both and its tag wins. `plain-tx` has neither, only data items carry
+    // their input in their data.
+    let page = mock_interactions(&[
+      ("body-tx", 1, None),
+      ("tagged-tx", 1, Some(r#"{"function":"add","name":"Tag"}"#)),
+      ("plain-tx", 1, None),
+    ]);
+    let mut page: Value = serde_json::from_slice(&page).unwrap();
+    let edges = page.pointer_mut("/data/transactions/edges").unwrap();
+    for edge in edges.as_array_mut().unwrap() {
+      if edge["node"]["id"] != "plain-tx" {
+        edge["node"]["bundledIn"] = serde_json::json!({ "id": "bundle" });
+      }
+    }
+    let page = serde_json::to_vec(&page).unwrap();
+    let routes = HashMap::from([
+      (
+        String::from("/tx/body-input-test"),
+        mock_transaction(
+          "body-input-test",
+          &[
+            ("Contract-Src-Inline", data_uri.as_str()),
+            ("Init-State", r#"{"users":[]}"#),
+          ],
+        ),
+      ),
+      (String::from("/graphql"), page),
+      (
+        String::from("/body-tx"),
+        br#"{"function":"add","name":"Body"}"#.to_vec(),
+      ),
+      (
+        String::from("/tagged-tx"),
+        br#"{"function":"add","name":"Data"}"#.to_vec(),
+      ),
+      (
+        String::from("/plain-tx"),
+        br#"{"function":"add","name":"Plain"}"#.to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    let result = execute_contract(
+      String::from("body-input-test"),
+      Some(1),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.validity().get("body-tx"), Some(&Value::Bool(true)));
+    assert_eq!(result.validity().get("tagged-tx"), Some(&Value::Bool(true)));
+    assert_eq!(result.validity().get("plain-tx"), Some(&Value::Bool(false)));
+    let state = process_execution(result, false);
+    let mut users =
+      serde_json::from_value::<Vec<String>>(state["users"].clone()).unwrap();
+    users.sort();
+    assert_eq!(users, ["Body", "Tag"]);
+  }
+
+  #[tokio::test]
+  async fn test_confirmations() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    let info = serde_json::json!({
+      "network": "arweave.N.1",
+      "version": 5,
+      "release": 53,
+      "height": 100,
+      "current": "block",
+      "blocks": 101,
+      "peers": 1,
+      "queue_length": 0,
+      "node_state_latency": 0,
+    });
+    // The mock ignores the block filter, like a cache holding interactions
+    // fetched closer to the tip.
+    let input = r#"{"function":"add","name":"Divy"}"#;
+    let routes = HashMap::from([
+      (
+        String::from("/tx/confirmations-test"),
+        mock_transaction(
+          "confirmations-test",
+          &[
+            ("Contract-Src-Inline", data_uri.as_str()),
+            ("Init-State", r#"{"users":[]}"#),
+          ],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        mock_interactions(&[
+          ("tx-85", 85, Some(input)),
+          ("tx-90", 90, Some(input)),
+          ("tx-95", 95, Some(input)),
+        ]),
+      ),
+      (String::from("/info"), serde_json::to_vec(&info).unwrap()),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    // The last 10 blocks are 91 to 100.
+    assert_eq!(
+      confirmed_height(&arweave, None, 10).await.unwrap(),
+      Some(90)
+    );
+    assert_eq!(
+      confirmed_height(&arweave, Some(95), 10).await.unwrap(),
+      Some(90)
+    );
+    assert_eq!(
+      confirmed_height(&arweave, Some(50), 10).await.unwrap(),
+      Some(50)
+    );
+    assert_eq!(confirmed_height(&arweave, None, 0).await.unwrap(), None);
+
+    let result = execute_contract(
+      String::from("confirmations-test"),
+      None,
+      true,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions {
+        confirmations: 10,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    let evaluated = result.validity().keys().cloned().collect::<HashSet<_>>();
+    assert_eq!(
+      evaluated,
+      HashSet::from([String::from("tx-85"), String::from("tx-90")])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_cache_confirmations() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    // Entries outlive the test in the process-wide cache.
+    let contract_id = format!(
+      "cache-confirmations-{}",
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+    );
+    let contract = mock_transaction(
+      &contract_id,
+      &[
+        ("Contract-Src-Inline", data_uri.as_str()),
+        ("Init-State", r#"{"users":[]}"#),
+      ],
+    );
+    // Each interaction adds its id to the users. The mock ignores cursors,
+    // so the interactions after the cached ones are all of them.
+    let gateway = |tip: usize, interactions: &[(&str, usize)]| {
+      let inputs = interactions
+        .iter()
+        .map(|(id, _)| format!(r#"{{"function":"add","name":"{}"}}"#, id))
+        .collect::<Vec<_>>();
+      let page = interactions
+        .iter()
+        .zip(&inputs)
+        .map(|((id, height), input)| (*id, *height, Some(input.as_str())))
+        .collect::<Vec<_>>();
+      let info = serde_json::json!({
+        "network": "arweave.N.1",
+        "version": 5,
+        "release": 53,
+        "height": tip,
+        "current": "block",
+        "blocks": tip + 1,
+        "peers": 1,
+        "queue_length": 0,
+        "node_state_latency": 0,
+      });
+      mock_gateway(HashMap::from([
+        (format!("/tx/{}", contract_id), contract.clone()),
+        (String::from("/graphql"), mock_interactions(&page)),
+        (String::from("/info"), serde_json::to_vec(&info).unwrap()),
+      ]))
+    };
+    // Each mock gateway listens on a port of its own, the cache entries are
+    // the same.
+    let network = "cache-confirmations";
+    let evaluate = |arweave: Arweave| {
+      let contract_id = contract_id.to_owned();
+      let arweave = arweave.with_cache_network(network);
+      async move {
+        let result = execute_contract(
+          contract_id,
+          None,
+          true,
+          false,
+          None,
+          None,
+          &arweave,
+          &EvaluateOptions {
+            cache_confirmations: 5,
+            ..Default::default()
+          },
+        )
+        .await
+        .unwrap();
+        result.state().unwrap()["users"].clone()
+      }
+    };
+    let cached = || {
+      let mut cache = get_cache().lock().unwrap();
+      cache.set_network(network);
+      let state = cache.find_state(contract_id.to_owned()).unwrap().unwrap();
+      let interactions = cache
+        .find_interactions(contract_id.to_owned())
+        .unwrap()
+        .unwrap();
+      let heights = interactions
+        .iter()
+        .map(|interaction| interaction.node.block.height)
+        .collect::<Vec<_>>();
+      (state.state["users"].clone(), heights)
+    };
+    // Sets up the process-wide cache, unless another test already did.
+    Arweave::new_from_url("http://127.0.0.1:1", ArweaveCache::new()).unwrap();
+
+    // Blocks 96 to 100 are too recent to be cached.
+    let arweave = gateway(100, &[("tx-85", 85), ("tx-90", 90), ("tx-99", 99)]);
+    let users = evaluate(arweave.await).await;
+    assert_eq!(users, serde_json::json!(["tx-85", "tx-90", "tx-99"]));
+    let (state, heights) = cached();
+    assert_eq!(state, serde_json::json!(["tx-85", "tx-90"]));
+    assert!(heights.iter().all(|height| *height <= 95));
+
+    // A reorg drops block 99, and `tx-100` comes instead of `tx-99`.
+    let arweave =
+      gateway(101, &[("tx-85", 85), ("tx-90", 90), ("tx-100", 100)]);
+    let users = evaluate(arweave.await).await;
+    assert_eq!(users, serde_json::json!(["tx-85", "tx-90", "tx-100"]));
+    let (state, heights) = cached();
+    assert_eq!(state, serde_json::json!(["tx-85", "tx-90"]));
+    assert!(heights.iter().all(|height| *height <= 96));
+
+    // Once confirmed, `tx-100` is cached.
+    let arweave =
+      gateway(105, &[("tx-85", 85), ("tx-90", 90), ("tx-100", 100)]);
+    let users = evaluate(arweave.await).await;
+    assert_eq!(users, serde_json::json!(["tx-85", "tx-90", "tx-100"]));
+    let (state, _) = cached();
+    assert_eq!(state, serde_json::json!(["tx-85", "tx-90", "tx-100"]));
+  }
+
+  #[tokio::test]
+  async fn test_cache_per_network() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    // Entries outlive the test in the process-wide cache.
+    let contract_id = format!(
+      "cache-per-network-{}",
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+    );
+    let contract = mock_transaction(
+      &contract_id,
+      &[
+        ("Contract-Src-Inline", data_uri.as_str()),
+        ("Init-State", r#"{"users":[]}"#),
+      ],
+    );
+    let info = serde_json::json!({
+      "network": "arweave.N.1",
+      "version": 5,
+      "release": 53,
+      "height": 10,
+      "current": "block",
+      "blocks": 11,
+      "peers": 1,
+      "queue_length": 0,
+      "node_state_latency": 0,
+    });
+    // Two networks with a contract of the same id, each with an
+    // interaction adding `name` to its users.
+    let gateway = |name: &str| {
+      let input = format!(r#"{{"function":"add","name":"{}"}}"#, name);
+      let page = mock_interactions(&[(name, 1, Some(input.as_str()))]);
+      mock_gateway(HashMap::from([
+        (format!("/tx/{}", contract_id), contract.clone()),
+        (String::from("/graphql"), page),
+        (String::from("/info"), serde_json::to_vec(&info).unwrap()),
+      ]))
+    };
+    let evaluate = |arweave: &Arweave| {
+      let contract_id = contract_id.to_owned();
+      let arweave = arweave.clone();
+      async move {
+        let result = execute_contract(
+          contract_id,
+          None,
+          true,
+          false,
+          None,
+          None,
+          &arweave,
+          &EvaluateOptions::default(),
+        )
+        .await
+        .unwrap();
+        result.state().unwrap()["users"].clone()
+      }
+    };
+    // Sets up the process-wide cache, unless another test already did.
+    Arweave::new_from_url("http://127.0.0.1:1", ArweaveCache::new()).unwrap();
+
+    let first = gateway("first").await;
+    let second = gateway("second").await;
+    assert_ne!(first.network(), second.network());
+    // The second time, results come from the cache of their own network.
+    for _ in 0..2 {
+      assert_eq!(evaluate(&first).await, serde_json::json!(["first"]));
+      assert_eq!(evaluate(&second).await, serde_json::json!(["second"]));
+    }
+    for (arweave, name) in [(&first, "first"), (&second, "second")] {
+      let cached = arweave
+        .try_cache(|cache| cache.find_state(contract_id.to_owned()))
+        .flatten()
+        .unwrap();
+      assert_eq!(cached.state["users"], serde_json::json!([name]));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_height_at() {
+    let info = serde_json::json!({
+      "network": "arweave.N.1",
+      "version": 5,
+      "release": 53,
+      "height": 100,
+      "current": "block",
+      "blocks": 101,
+      "peers": 1,
+      "queue_length": 0,
+      "node_state_latency": 0,
+    });
+    // A block every two minutes from the first one.
+    let genesis = 1_600_000_000;
+    let mut routes = (0..=100)
+      .map(|height| {
+        let block = serde_json::json!({ "timestamp": genesis + height * 120 });
+        (
+          format!("/block/height/{}", height),
+          serde_json::to_vec(&block).unwrap(),
+        )
+      })
+      .collect::<HashMap<_, _>>();
+    routes.insert(String::from("/info"), serde_json::to_vec(&info).unwrap());
+    let arweave = mock_gateway(routes).await;
+
+    let at = |timestamp| arweave.get_height_at(timestamp);
+    assert_eq!(at(genesis + 40 * 120).await.unwrap(), 40);
+    assert_eq!(at(genesis + 40 * 120 + 119).await.unwrap(), 40);
+    assert_eq!(at(genesis + 41 * 120).await.unwrap(), 41);
+    assert_eq!(at(genesis - 1).await.unwrap(), 0);
+    assert_eq!(at(genesis + 1000 * 120).await.unwrap(), 100);
+  }
+
+  #[tokio::test]
+  async fn test_resume_from_cursor() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    let gateway = |interactions: &[(&str, usize)]| {
+      let page = interactions
+        .iter()
+        .map(|(id, height)| {
+          let input = format!(r#"{{"function":"add","name":"{}"}}"#, id);
+          (*id, *height, input)
+        })
+        .collect::<Vec<_>>();
+      let page = page
+        .iter()
+        .map(|(id, height, input)| (*id, *height, Some(input.as_str())))
+        .collect::<Vec<_>>();
+      mock_gateway(HashMap::from([
+        (
+          String::from("/tx/cursor-test"),
+          mock_transaction(
+            "cursor-test",
+            &[
+              ("Contract-Src-Inline", data_uri.as_str()),
+              ("Init-State", r#"{"users":[]}"#),
+            ],
+          ),
+        ),
+        (String::from("/graphql"), mock_interactions(&page)),
+      ]))
+    };
+    let evaluate = |arweave: Arweave, options: EvaluateOptions| async move {
+      let result = execute_contract(
+        String::from("cursor-test"),
+        None,
+        false,
+        false,
+        None,
+        None,
+        &arweave,
+        &options,
+      )
+      .await?;
+      let cursor = Cursor::after("cursor-test", None, &result, &options);
+      Ok::<_, AnyError>((result, cursor))
+    };
+
+    let arweave = gateway(&[("tx-1", 1), ("tx-2", 2)]).await;
+    let (result, cursor) = evaluate(arweave, Default::default()).await.unwrap();
+    let token = cursor.encode();
+    let state = process_execution(result, false);
+    assert_eq!(cursor.interaction_id.as_deref(), Some("tx-2"));
+    assert_eq!(cursor.applied, 2);
+
+    // A new interaction came in, only it is evaluated.
+    let arweave = gateway(&[("tx-1", 1), ("tx-2", 2), ("tx-3", 3)]).await;
+    let resume = Cursor::decode(&token)
+      .unwrap()
+      .resume("cursor-test", None, state.clone())
+      .unwrap();
+    let options = EvaluateOptions {
+      resume: Some(resume.clone()),
+      ..Default::default()
+    };
+    let (result, next) = evaluate(arweave, options).await.unwrap();
+    assert_eq!(result.validity().keys().collect::<Vec<_>>(), ["tx-3"]);
+    assert_eq!(
+      process_execution(result, false),
+      serde_json::json!({ "users": ["tx-1", "tx-2", "tx-3"] })
+    );
+    assert_eq!(next.interaction_id.as_deref(), Some("tx-3"));
+    assert_eq!(next.applied, 3);
+
+    // Nothing new, the cursor stays where it was.
+    let arweave = gateway(&[("tx-1", 1), ("tx-2", 2)]).await;
+    let options = EvaluateOptions {
+      resume: Some(resume.clone()),
+      ..Default::default()
+    };
+    let (_, same) = evaluate(arweave, options).await.unwrap();
+    assert_eq!(same.encode(), token);
+
+    // `tx-2` was dropped, the state no longer matches the chain.
+    let arweave = gateway(&[("tx-1", 1), ("tx-3", 3), ("tx-4", 4)]).await;
+    let options = EvaluateOptions {
+      resume: Some(resume),
+      ..Default::default()
+    };
+    assert!(evaluate(arweave, options).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_state_sink() {
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    let routes = HashMap::from([
+      (
+        String::from("/tx/state-sink-test"),
+        mock_transaction(
+          "state-sink-test",
+          &[
+            ("Contract-Src-Inline", data_uri.as_str()),
+            ("Init-State", r#"{"users":[]}"#),
+          ],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        mock_interactions(&[
+          ("tx1", 1, Some(r#"{"function":"add","name":"Divy"}"#)),
+          ("tx2", 2, Some(r#"{"function":"remove"}"#)),
+          ("tx3", 3, Some(r#"{"function":"add","name":"Andres"}"#)),
+        ]),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    let snapshots = Arc::new(Mutex::new(vec![]));
+    let sink_snapshots = snapshots.clone();
+    let options = EvaluateOptions {
+      state_sink: Some(Arc::new(move |id: String, state: Value| {
+        let snapshots = sink_snapshots.clone();
+        async move {
+          // Stands in for a write to a store.
+          tokio::task::yield_now().await;
+          snapshots.lock().unwrap().push((id, state));
+        }
+        .boxed_local()
+      })),
+      ..Default::default()
+    };
+    let result = execute_contract(
+      String::from("state-sink-test"),
+      Some(3),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &options,
+    )
+    .await
+    .unwrap();
+
+    // Interactions at different heights are evaluated in height order.
+    // `tx2` is invalid and leaves the state as it was.
+    let divy = serde_json::json!({ "users": ["Divy"] });
+    let both = serde_json::json!({ "users": ["Divy", "Andres"] });
+    assert_eq!(
+      *snapshots.lock().unwrap(),
+      vec![
+        (String::from("tx1"), divy.clone()),
+        (String::from("tx2"), divy),
+        (String::from("tx3"), both.clone()),
+      ]
+    );
+    assert_eq!(process_execution(result, false), both);
+  }
+
+  /// The interaction each contract of `read_chain.js` has.
+  fn read_chain_interaction() -> GQLEdgeInterface {
+    generate_fake_interaction(
+      serde_json::json!({}),
+      "read-chain-tx",
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+    )
+  }
+
+  /// Routes serving the contracts of `read_chain.js`, under `prefix`.
+  fn read_chain_routes(prefix: &str) -> HashMap<String, Vec<u8>> {
+    let mut routes = HashMap::from([
+      (
+        format!("{}/tx/read-chain-src", prefix),
+        mock_transaction(
+          "read-chain-src",
+          &[("Content-Type", "application/javascript")],
+        ),
+      ),
+      (
+        format!("{}/read-chain-src", prefix),
+        include_bytes!("../../testdata/contracts/read_chain.js").to_vec(),
+      ),
+      // The same interaction for every contract in the chain.
+      (
+        format!("{}/graphql", prefix),
+        serde_json::to_vec(&serde_json::json!({
+          "data": {
+            "transactions": {
+              "pageInfo": { "hasNextPage": false },
+              "edges": [read_chain_interaction()],
+            }
+          }
+        }))
+        .unwrap(),
+      ),
+    ]);
+    for n in 0..=5 {
+      let id = format!("read-chain-{}", n);
+      let tx = mock_transaction(
+        &id,
+        &[("Contract-Src", "read-chain-src"), ("Init-State", "{}")],
+      );
+      routes.insert(format!("{}/tx/{}", prefix, id), tx);
+    }
+    routes
+  }
+
+  /// Evaluates `read-chain-4` of [`read_chain_routes`], which reads the
+  /// last contract of the chain, with `arweave`.
+  async fn read_chain_tail(arweave: &Arweave) -> Value {
+    let contract = arweave
+      .load_contract(
+        String::from("read-chain-4"),
+        None,
+        None,
+        None,
+        false,
+        false,
+        false,
+      )
+      .await
+      .unwrap();
+    let result = raw_execute_contract(
+      String::from("read-chain-4"),
+      contract,
+      vec![read_chain_interaction()],
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| unreachable!(),
+      arweave,
+      // Keeps the read contract out of the caches, so that it is fetched.
+      &EvaluateOptions {
+        max_read_depth: Some(4),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+    process_execution(result, true)
+  }
+
+  #[tokio::test]
+  async fn test_max_read_depth() {
+    let interaction = read_chain_interaction();
+    let arweave = mock_gateway(read_chain_routes("")).await;
+
+    for (max_read_depth, expected) in [
+      (
+        3,
+        serde_json::json!({
+          "reached": 3,
+          "error": "Foreign reads can't be nested more than 3 deep",
+        }),
+      ),
+      (5, serde_json::json!({ "reached": 5 })),
+    ] {
+      let contract = arweave
+        .load_contract(
+          String::from("read-chain-0"),
+          None,
+          None,
+          None,
+          false,
+          false,
+          false,
+        )
+        .await
+        .unwrap();
+      let result = raw_execute_contract(
+        String::from("read-chain-0"),
+        contract,
+        vec![interaction.clone()],
+        IndexMap::new(),
+        None,
+        true,
+        true,
+        |_, _| unreachable!(),
+        &arweave,
+        &EvaluateOptions {
+          max_read_depth: Some(max_read_depth),
+          ..Default::default()
+        },
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(
+        process_execution(result, true),
+        serde_json::json!({
+          "state": expected,
+          "validity": { "read-chain-tx": true }
+        })
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_read_through_path_prefix() {
+    let gateway = mock_gateway(read_chain_routes("/arweave")).await;
+    let arweave = Arweave::new_from_url_no_cache(&format!(
+      "http://127.0.0.1:{}/arweave",
+      gateway.port
+    ))
+    .unwrap();
+
+    assert_eq!(
+      read_chain_tail(&arweave).await,
+      serde_json::json!({
+        "state": { "reached": 5 },
+        "validity": { "read-chain-tx": true }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_through_gql_host() {
+    let mut routes = read_chain_routes("");
+    let page = routes.remove("/graphql").unwrap();
+    let arweave = mock_gateway(routes).await;
+    let gql =
+      mock_gateway(HashMap::from([(String::from("/graphql"), page)])).await;
+    let arweave = arweave
+      .with_gql_url(&format!("http://127.0.0.1:{}", gql.port))
+      .unwrap();
+
+    assert_eq!(
+      read_chain_tail(&arweave).await,
+      serde_json::json!({
+        "state": { "reached": 5 },
+        "validity": { "read-chain-tx": true }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_with_gateway_headers() {
+    let (arweave, requests) = mock_gateway_logged(read_chain_routes("")).await;
+    let headers = [(String::from("X-Api-Key"), String::from("k"))];
+    let arweave = arweave.with_headers(&headers).unwrap();
+
+    assert_eq!(
+      read_chain_tail(&arweave).await,
+      serde_json::json!({
+        "state": { "reached": 5 },
+        "validity": { "read-chain-tx": true }
+      })
+    );
+    let requests = requests.lock().unwrap();
+    let nested = requests
+      .iter()
+      .filter(|request| request.starts_with("GET /tx/read-chain-5 "))
+      .collect::<Vec<_>>();
+    assert!(!nested.is_empty());
+    for request in nested {
+      assert!(request.to_lowercase().contains("\r\nx-api-key: k\r\n"));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_concurrent_ops() {
+    let mut routes = read_chain_routes("");
+    routes.insert(
+      String::from("/wallet/alice/last_tx"),
+      b"alice-last-tx".to_vec(),
+    );
+    let arweave = mock_gateway(routes).await;
+    let source = br#"
+      export async function handle(state) {
+        const [read, lastTx] = await Promise.all([
+          SmartWeave.contracts.readContractState("read-chain-5", 1),
+          SmartWeave.arweave.wallets.getLastTransactionID("alice"),
+        ]);
+        return { state: { read, lastTx } };
+      }
+    "#;
+
+    // Both ops use the op state while the other one is in flight.
+    let result = raw_execute_contract(
+      String::from("concurrent-ops"),
+      generate_fake_loaded_contract_data(
+        source,
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      ),
+      vec![read_chain_interaction()],
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| unreachable!(),
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      process_execution(result, true),
+      serde_json::json!({
+        "state": {
+          "read": { "reached": 5 },
+          "lastTx": "alice-last-tx",
+        },
+        "validity": { "read-chain-tx": true }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_wallet_balance_through_client() {
+    let (gateway, requests) = mock_gateway_logged(HashMap::from([(
+      String::from("/arweave/wallet/alice/balance"),
+      b"1234".to_vec(),
+    )]))
+    .await;
+    let headers = [(String::from("X-Api-Key"), String::from("k"))];
+    let arweave = Arweave::new_from_url_no_cache(&format!(
+      "http://127.0.0.1:{}/arweave",
+      gateway.port
+    ))
+    .unwrap()
+    .with_headers(&headers)
+    .unwrap();
+    let interaction = generate_fake_interaction(
+      serde_json::json!({}),
+      "balance-tx",
+      None,
+      None,
+      Some(String::from("alice")),
+      None,
+      None,
+      None,
+      None,
+      None,
+    );
+
+    let result = raw_execute_contract(
+      String::from("wallet-balance"),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/wallet_balance.js"),
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      ),
+      vec![interaction],
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| unreachable!(),
+      &arweave,
+      &EvaluateOptions {
+        runtime_settings: RuntimeSettings {
+          allow_wallet_balance: true,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      process_execution(result, true),
+      serde_json::json!({
+        "state": { "balance": "1234", "memoized": true },
+        "validity": { "balance-tx": true }
+      })
+    );
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].to_lowercase().contains("\r\nx-api-key: k\r\n"));
+  }
+
+  #[tokio::test]
+  async fn test_unsafe_client_through_client() {
+    let (gateway, requests) = mock_gateway_logged(HashMap::from([
+      (
+        String::from("/arweave/tx/unsafe-tx"),
+        br#"{"id":"unsafe-tx","format":2,"data_size":"0"}"#.to_vec(),
+      ),
+      (String::from("/arweave/unsafe-tx"), b"hello".to_vec()),
+    ]))
+    .await;
+    let headers = [(String::from("X-Api-Key"), String::from("k"))];
+    let arweave = Arweave::new_from_url_no_cache(&format!(
+      "http://127.0.0.1:{}/arweave",
+      gateway.port
+    ))
+    .unwrap()
+    .with_headers(&headers)
+    .unwrap();
+    let source = br#"
+      export async function handle(state) {
+        const tx = await SmartWeave.unsafeClient.transactions.get("unsafe-tx");
+        const data = await SmartWeave.unsafeClient.transactions.getData(
+          "unsafe-tx",
+          { decode: true, string: true },
+        );
+        return { state: { id: tx.id, data } };
+      }
+    "#;
+
+    let result = raw_execute_contract(
+      String::from("unsafe-client"),
+      generate_fake_loaded_contract_data(
+        source,
+        ContractType::JAVASCRIPT,
+        String::from("{}"),
+      ),
+      vec![read_chain_interaction()],
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| unreachable!(),
+      &arweave,
+      &EvaluateOptions {
+        runtime_settings: RuntimeSettings {
+          allow_unsafe_client: true,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      process_execution(result, true),
+      serde_json::json!({
+        "state": { "id": "unsafe-tx", "data": "hello" },
+        "validity": { "read-chain-tx": true }
+      })
+    );
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    for request in requests.iter() {
+      assert!(request.to_lowercase().contains("\r\nx-api-key: k\r\n"));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_contract_not_found() {
+    let routes = HashMap::from([
+      (
+        String::from("/tx/missing-src-contract"),
+        mock_transaction(
+          "missing-src-contract",
+          &[("Contract-Src", "missing-src"), ("Init-State", "{}")],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+          .to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+
+    // A typo'd contract id, then a contract whose source is gone.
+    for (contract_id, missing) in [
+      ("missing-contract", "missing-contract"),
+      ("missing-src-contract", "missing-src"),
+    ] {
+      let err = execute_contract(
+        String::from(contract_id),
+        Some(1),
+        false,
+        false,
+        None,
+        None,
+        &arweave,
+        &EvaluateOptions::default(),
+      )
+      .await
+      .err()
+      .unwrap();
+
+      assert_eq!(
+        err.downcast_ref::<three_em_arweave::arweave::Error>(),
+        Some(&three_em_arweave::arweave::Error::ContractNotFound {
+          id: String::from(missing)
+        })
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_download_budget() {
+    let data_uri = "data:application/javascript,\
+      export function handle(state) { return { state }; }";
+    let routes = HashMap::from([
+      (
+        String::from("/tx/budget-test"),
+        mock_transaction(
+          "budget-test",
+          &[("Contract-Src-Inline", data_uri), ("Init-State", "{}")],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+          .to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+    let evaluate = |arweave: Arweave| async move {
+      let result = execute_contract(
+        String::from("budget-test"),
+        Some(1),
+        false,
+        false,
+        None,
+        None,
+        &arweave,
+        &EvaluateOptions::default(),
+      )
+      .await;
+      (result, arweave.downloaded_bytes())
+    };
+
+    let (result, downloaded) =
+      evaluate(arweave.clone().with_download_budget(1024 * 1024)).await;
+    assert!(result.is_ok());
+    // The contract, its block and an empty page of interactions.
+    assert!(downloaded > 0);
+
+    let (result, downloaded) = evaluate(arweave.with_download_budget(10)).await;
+    let err = result.err().unwrap();
+    assert_eq!(
+      err.downcast_ref::<three_em_arweave::arweave::Error>(),
+      Some(&three_em_arweave::arweave::Error::DownloadBudgetExceeded {
+        limit: 10
+      })
+    );
+    assert_eq!(
+      err.to_string(),
+      "Downloaded more than the budget of 10 bytes"
+    );
+    assert!(downloaded > 10);
+  }
+
+  #[tokio::test]
+  async fn test_timed_out_counts() {
+    let data_uri = "data:application/javascript,\
+      export function handle(state, action) {\
+        if (action.input.function === 'spin') { while (true) {} }\
+        state.n += 1;\
+        return { state };\
+      }";
+    let routes = HashMap::from([
+      (
+        String::from("/tx/timeout-test"),
+        mock_transaction(
+          "timeout-test",
+          &[
+            ("Contract-Src-Inline", data_uri),
+            ("Init-State", r#"{"n":0}"#),
+          ],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        mock_interactions(&[
+          ("spin-tx", 1, Some(r#"{"function":"spin"}"#)),
+          ("add-tx", 1, Some(r#"{"function":"add"}"#)),
+        ]),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+    let options = EvaluateOptions {
+      contract_limits: HashMap::from([(
+        String::from("timeout-test"),
+        Limits {
+          timeout: Some(std::time::Duration::from_millis(200)),
+          ..Default::default()
+        },
+      )]),
+      ..Default::default()
+    };
+
+    let (result, counts) = execute_contract_with_counts(
+      String::from("timeout-test"),
+      Some(1),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &options,
+    )
+    .await
+    .unwrap();
+
+    // The result stands, but another node may find the interaction valid.
+    assert_eq!(result.state(), Some(&serde_json::json!({ "n": 1 })));
+    assert_eq!(counts.timed_out, 1);
+    assert!(!counts.is_authoritative());
+  }
+
+  /// Evaluates `tx1` and `tx2`, mined at 1600000000 and 1600000100.
+  async fn evaluate_determinism(
+    source: &[u8],
+    contract_type: ContractType,
+    init_state: &str,
+    determinism: DeterminismContext,
+  ) -> ExecuteResult {
+    let interactions = [("tx1", 1600000000), ("tx2", 1600000100)]
+      .into_iter()
+      .map(|(id, timestamp)| {
+        generate_fake_interaction(
+          serde_json::json!({}),
+          id,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          Some(timestamp),
+        )
+      })
+      .collect();
+
+    raw_execute_contract(
+      String::from("test"),
+      generate_fake_loaded_contract_data(
+        source,
+        contract_type,
+        String::from(init_state),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      true,
+      |_, _| unreachable!(),
+      &Arweave::new_no_cache(
+        1,
+        String::from("127.0.0.1"),
+        String::from("http"),
+      ),
+      &EvaluateOptions {
+        determinism,
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_determinism_context() {
+    let js = include_bytes!("../../testdata/contracts/determinism.js");
+    let wasm = include_bytes!("../../testdata/07_wasm/07_wasm.wasm");
+    let block = DeterminismContext {
+      clock: Clock::Block,
+      seed: Seed::Interaction,
+    };
+
+    let mut js_states = vec![];
+    for determinism in [DeterminismContext::default(), block, block] {
+      let result =
+        evaluate_determinism(js, ContractType::JAVASCRIPT, "{}", determinism)
+          .await;
+      js_states.push(process_execution(result, true));
+    }
+    assert_eq!(js_states[0]["state"]["now"].as_f64(), Some(1479427200000.0));
+    assert_eq!(js_states[1]["state"]["now"].as_f64(), Some(1600000100000.0));
+    assert_eq!(js_states[1]["state"]["date"], "2020-09-13T12:28:20.000Z");
+    assert_ne!(
+      js_states[0]["state"]["random"],
+      js_states[1]["state"]["random"]
+    );
+    assert_eq!(js_states[1], js_states[2]);
+
+    let mut wasm_states = vec![];
+    for determinism in [DeterminismContext::default(), block, block] {
+      match evaluate_determinism(wasm, ContractType::WASM, "", determinism)
+        .await
+      {
+        ExecuteResult::Bytes(state, validity) => {
+          assert_eq!(validity.get("tx2"), Some(&Value::Bool(true)));
+          wasm_states.push(state);
+        }
+        _ => panic!("Unexpected entry"),
+      }
+    }
+    let nanos = |ms: u64| (ms * 1_000_000).to_le_bytes().to_vec();
+    assert_eq!(wasm_states[0][..8], nanos(1479427200000));
+    assert_eq!(wasm_states[1][..8], nanos(1600000100000));
+    assert_ne!(wasm_states[0][8..], wasm_states[1][8..]);
+    assert_eq!(wasm_states[1], wasm_states[2]);
+  }
+
+  /// Spans as they were created, with the fields recorded on them.
+  #[derive(Clone, Default)]
+  struct SpanRecorder {
+    spans: Arc<Mutex<Vec<(&'static str, HashMap<String, String>)>>>,
+  }
+
+  struct FieldRecorder<'a>(&'a mut HashMap<String, String>);
+
+  impl tracing::field::Visit for FieldRecorder<'_> {
+    fn record_debug(
+      &mut self,
+      field: &tracing::field::Field,
+      value: &dyn std::fmt::Debug,
+    ) {
+      let name = field.name().to_owned();
+      self.0.insert(name, format!("{:?}", value));
+    }
+  }
+
+  impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+      true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::Id {
+      let mut fields = HashMap::new();
+      span.record(&mut FieldRecorder(&mut fields));
+      let mut spans = self.spans.lock().unwrap();
+      spans.push((span.metadata().name(), fields));
+      tracing::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::Id, values: &tracing::span::Record<'_>) {
+      let mut spans = self.spans.lock().unwrap();
+      let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+      values.record(&mut FieldRecorder(fields));
+    }
+
+    fn record_follows_from(&self, _span: &tracing::Id, _follows: &tracing::Id) {
+    }
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::Id) {}
+
+    fn exit(&self, _span: &tracing::Id) {}
+  }
+
+  #[tokio::test]
+  async fn test_evaluation_spans() {
+    let recorder = SpanRecorder::default();
+    // Tests run on their own thread, the subscriber only sees this one.
+    let _subscriber = tracing::subscriber::set_default(recorder.clone());
+
+    let source = base64::encode(include_bytes!(
+      "../../testdata/contracts/users_contract.js"
+    ));
+    let data_uri = format!("data:application/javascript;base64,{}", source);
+    let routes = HashMap::from([
+      (
+        String::from("/tx/tracing-test"),
+        mock_transaction(
+          "tracing-test",
+          &[
+            ("Contract-Src-Inline", data_uri.as_str()),
+            ("Init-State", r#"{"users":[]}"#),
+          ],
+        ),
+      ),
+      (
+        String::from("/graphql"),
+        br#"{"data":{"transactions":{"pageInfo":{"hasNextPage":false},"edges":[]}}}"#
+          .to_vec(),
+      ),
+    ]);
+    let arweave = mock_gateway(routes).await;
+    execute_contract(
+      String::from("tracing-test"),
+      Some(1),
+      false,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // The gateway has no interactions, evaluate one directly.
+    raw_execute_contract(
+      String::from("tracing-test"),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      vec![generate_fake_interaction(
+        serde_json::json!({ "function": "add", "name": "Divy" }),
+        "tx1",
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )],
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| unreachable!(),
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let spans = recorder.spans.lock().unwrap();
+    let span = |name: &str| {
+      spans
+        .iter()
+        .find(|(span, _)| *span == name)
+        .map(|(_, fields)| fields.clone())
+        .unwrap_or_else(|| panic!("No {} span", name))
+    };
+    for name in ["load_contract", "get_interactions", "sort", "execute"] {
+      let fields = span(name);
+      assert_eq!(fields["contract_id"], "tracing-test");
+      assert!(fields.contains_key("duration_ms"));
     }
+    let interaction = span("interaction");
+    assert_eq!(interaction["interaction_id"], "tx1");
+    assert!(interaction.contains_key("duration_ms"));
   }
 }
@@ -0,0 +1,147 @@
+//! Limits every interaction runs under, so that a runaway contract fails
+//! its interaction instead of stalling the node evaluating it, even when
+//! the caller sets none.
+//!
+//! Memory and gas limits decide deterministically which interactions are
+//! valid, so by default only the time interactions take is bounded, along
+//! with the heap JavaScript contracts always had; anything else would make
+//! results differ from those of other clients. Each contract type gets the
+//! defaults of [`DefaultLimits`], which
+//! [`crate::executor::EvaluateOptions::default_limits`] replaces for every
+//! contract and [`crate::executor::EvaluateOptions::contract_limits`] for
+//! some of them. See [`crate::executor::EvaluateOptions::limits`].
+
+use deno_core::error::AnyError;
+use std::cell::Cell;
+use std::time::Duration;
+use three_em_arweave::miscellaneous::ContractType;
+
+/// Default [`Limits::timeout`] of JavaScript and WASM interactions. Real
+/// handlers take milliseconds, so only one that loops forever comes near
+/// it, even on a slow node. Reads of other contracts and other calls to
+/// the gateway don't count.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What one interaction may take. `None` leaves that resource unbounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+  /// Wall-clock time the handler may run for, not counting the time it
+  /// waits on reads of other contracts or other calls to the gateway. An
+  /// interaction taking longer is terminated and invalid. Timing depends
+  /// on the node, so the defaults are far above what a handler that
+  /// terminates needs, and an evaluation in which an interaction timed out
+  /// is never cached and reports it, see
+  /// [`crate::executor::InteractionCounts::timed_out`]. Not enforced for
+  /// EVM contracts, nor for WASM contracts on the Wasmtime engine.
+  pub timeout: Option<Duration>,
+  /// Bytes of memory the contract may use: the V8 heap of a JavaScript
+  /// contract, the linear memory of a WASM one. An interaction needing
+  /// more is invalid. `None` is `three_em_js::DEFAULT_HEAP_LIMIT`, the
+  /// 5 MiB heap JavaScript contracts were always evaluated with, since
+  /// theirs always has a size. Not enforced for EVM
+  /// contracts.
+  pub memory: Option<usize>,
+  /// Gas a WASM interaction may consume, see
+  /// [`crate::executor::EvaluateOptions::max_gas`]. JavaScript and EVM
+  /// contracts aren't metered.
+  pub gas: Option<usize>,
+}
+
+impl Limits {
+  /// These limits, with those they leave unset taken from `fallback`.
+  pub fn or(self, fallback: Limits) -> Limits {
+    Limits {
+      timeout: self.timeout.or(fallback.timeout),
+      memory: self.memory.or(fallback.memory),
+      gas: self.gas.or(fallback.gas),
+    }
+  }
+}
+
+/// The [`Limits`] of each contract type. `Default` holds the defaults
+/// documented on the constants of this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefaultLimits {
+  pub javascript: Limits,
+  pub wasm: Limits,
+  /// Nothing is enforced for EVM contracts: the interpreter neither
+  /// meters gas nor lets a running call be interrupted.
+  pub evm: Limits,
+}
+
+impl DefaultLimits {
+  /// No limits for any contract type, as before defaults existed.
+  pub fn unlimited() -> DefaultLimits {
+    DefaultLimits {
+      javascript: Limits::default(),
+      wasm: Limits::default(),
+      evm: Limits::default(),
+    }
+  }
+
+  pub fn get(&self, contract_type: &ContractType) -> Limits {
+    match contract_type {
+      ContractType::JAVASCRIPT => self.javascript,
+      ContractType::WASM => self.wasm,
+      ContractType::EVM => self.evm,
+      ContractType::UNSUPPORTED(_) => Limits::default(),
+    }
+  }
+}
+
+impl Default for DefaultLimits {
+  fn default() -> DefaultLimits {
+    DefaultLimits {
+      javascript: Limits {
+        timeout: Some(DEFAULT_TIMEOUT),
+        #[cfg(feature = "js")]
+        memory: Some(three_em_js::DEFAULT_HEAP_LIMIT),
+        #[cfg(not(feature = "js"))]
+        memory: None,
+        gas: None,
+      },
+      wasm: Limits {
+        timeout: Some(DEFAULT_TIMEOUT),
+        memory: None,
+        gas: None,
+      },
+      evm: Limits::default(),
+    }
+  }
+}
+
+thread_local! {
+  static TIMEOUTS: Cell<usize> = Cell::new(0);
+}
+
+/// How many interactions have timed out on this thread. Whether one does
+/// depends on the node, so a result evaluated while this went up must not
+/// be cached. An evaluation stays on the thread it started on; others
+/// sharing it can only make it skip caching a result it could have.
+pub(crate) fn timeouts() -> usize {
+  TIMEOUTS.with(Cell::get)
+}
+
+/// Counts `err` in [`timeouts`] if an interaction failed with it for
+/// running past its [`Limits::timeout`].
+pub(crate) fn count_timeout(err: &AnyError) {
+  #[cfg(feature = "js")]
+  let timed_out = matches!(
+    err.downcast_ref(),
+    Some(three_em_js::Error::TimedOut { .. })
+  );
+  #[cfg(not(feature = "js"))]
+  let timed_out = false;
+  #[cfg(feature = "wasm")]
+  let timed_out = timed_out
+    || matches!(
+      err.downcast_ref(),
+      Some(three_em_wasm::Error::TimedOut { .. })
+    );
+  #[cfg(not(any(feature = "js", feature = "wasm")))]
+  let _ = err;
+
+  if timed_out {
+    TIMEOUTS.with(|count| count.set(count.get() + 1));
+  }
+}
@@ -0,0 +1,208 @@
+//! Pausing long evaluations, for operators that need the resources of a
+//! replay back for a while.
+//!
+//! An evaluation given a [`PauseToken`] checks it between interactions.
+//! Once paused, it checkpoints the state it got to and stops; the
+//! checkpoint then resumes it where it stopped, see [`PauseToken::resume`].
+
+use crate::executor::{Resume, ValidityTable};
+use deno_core::serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Pauses the evaluations given it in
+/// [`crate::executor::EvaluateOptions::pause`]. Clones control the same
+/// evaluation, so one can be kept by whatever decides to pause it.
+#[derive(Clone, Debug, Default)]
+pub struct PauseToken {
+  requested: Arc<AtomicBool>,
+  checkpoint: Arc<Mutex<Option<Resume>>>,
+}
+
+impl PauseToken {
+  pub fn new() -> PauseToken {
+    PauseToken::default()
+  }
+
+  /// Asks the evaluation to stop before its next interaction. The one
+  /// being evaluated, if any, finishes first.
+  pub fn pause(&self) {
+    self.requested.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_requested(&self) -> bool {
+    self.requested.load(Ordering::SeqCst)
+  }
+
+  /// Whether an evaluation stopped on this token with interactions left.
+  pub fn is_paused(&self) -> bool {
+    self.checkpoint.lock().unwrap().is_some()
+  }
+
+  /// Where the evaluation stopped, if it did.
+  pub fn checkpoint(&self) -> Option<Resume> {
+    self.checkpoint.lock().unwrap().clone()
+  }
+
+  /// Takes the checkpoint, to pass as
+  /// [`crate::executor::EvaluateOptions::resume`] to an evaluation that
+  /// carries on from it, and clears the request so that the token can
+  /// pause that evaluation in turn.
+  pub fn resume(&self) -> Option<Resume> {
+    self.requested.store(false, Ordering::SeqCst);
+    self.checkpoint.lock().unwrap().take()
+  }
+
+  /// Records that the evaluation stopped at `state`, after the interactions
+  /// in `validity`, on top of the point it was itself resumed from.
+  pub(crate) fn stop(
+    &self,
+    state: Value,
+    validity: &ValidityTable,
+    resumed: Option<&Resume>,
+  ) {
+    *self.checkpoint.lock().unwrap() = Some(Resume {
+      state,
+      applied: resumed.map_or(0, |resume| resume.applied) + validity.len(),
+      after: validity
+        .keys()
+        .last()
+        .cloned()
+        .or_else(|| resumed.and_then(|resume| resume.after.clone())),
+    });
+  }
+}
+
+#[cfg(all(test, feature = "js"))]
+mod tests {
+  use crate::executor::{raw_execute_contract, EvaluateOptions, ExecuteResult};
+  use crate::pause::PauseToken;
+  use crate::test_util::{
+    generate_fake_interaction, generate_fake_loaded_contract_data,
+  };
+  use deno_core::serde_json;
+  use deno_core::serde_json::Value;
+  use indexmap::map::IndexMap;
+  use std::sync::Arc;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::cache::ArweaveCache;
+  use three_em_arweave::cache::CacheExt;
+  use three_em_arweave::gql_result::GQLEdgeInterface;
+  use three_em_arweave::miscellaneous::ContractType;
+
+  #[tokio::test]
+  async fn test_pause_and_resume() {
+    let arweave = Arweave::new(
+      443,
+      "arweave.net".to_string(),
+      String::from("https"),
+      ArweaveCache::new(),
+    );
+    let interactions = || -> Vec<GQLEdgeInterface> {
+      ["tx1", "tx2", "tx3", "tx4", "tx5"]
+        .iter()
+        .map(|id| {
+          generate_fake_interaction(
+            serde_json::json!({ "function": "add", "name": id }),
+            id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+        })
+        .collect()
+    };
+    let evaluate = |init_state: Value,
+                    interactions: Vec<GQLEdgeInterface>,
+                    options: EvaluateOptions| {
+      let arweave = &arweave;
+      async move {
+        raw_execute_contract(
+          String::new(),
+          generate_fake_loaded_contract_data(
+            include_bytes!("../../testdata/contracts/users_contract.js"),
+            ContractType::JAVASCRIPT,
+            init_state.to_string(),
+          ),
+          interactions,
+          IndexMap::new(),
+          None,
+          true,
+          false,
+          |_, _| panic!("not implemented"),
+          arweave,
+          &options,
+        )
+        .await
+        .unwrap()
+      }
+    };
+    let init_state = serde_json::json!({ "users": [] });
+
+    let uninterrupted = evaluate(
+      init_state.clone(),
+      interactions(),
+      EvaluateOptions {
+        pause: Some(PauseToken::new()),
+        ..Default::default()
+      },
+    )
+    .await;
+
+    // Paused by the operator while `tx2` is evaluated.
+    let token = PauseToken::new();
+    let pauser = token.clone();
+    let paused = evaluate(
+      init_state,
+      interactions(),
+      EvaluateOptions {
+        pause: Some(token.clone()),
+        on_step: Some(Arc::new(move |id: &str, _: &Value, _: &Value| {
+          if id == "tx2" {
+            pauser.pause();
+          }
+        })),
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(token.is_paused());
+    let ids: Vec<&String> = paused.validity().keys().collect();
+    assert_eq!(ids, ["tx1", "tx2"]);
+
+    let checkpoint = token.resume().unwrap();
+    assert_eq!(checkpoint.applied, 2);
+    assert_eq!(checkpoint.after.as_deref(), Some("tx2"));
+    assert_eq!(&checkpoint.state, paused.state().unwrap());
+    assert!(!token.is_requested());
+
+    // What `execute_contract` does with `resume`.
+    let mut rest = interactions();
+    rest.drain(..checkpoint.applied);
+    let resumed = evaluate(
+      checkpoint.state.clone(),
+      rest,
+      EvaluateOptions {
+        pause: Some(token.clone()),
+        resume: Some(checkpoint),
+        ..Default::default()
+      },
+    )
+    .await;
+    assert!(!token.is_paused());
+    let ids: Vec<&String> = resumed.validity().keys().collect();
+    assert_eq!(ids, ["tx3", "tx4", "tx5"]);
+    if let (ExecuteResult::V8(resumed, _), ExecuteResult::V8(expected, _)) =
+      (resumed, uninterrupted)
+    {
+      assert_eq!(resumed, expected);
+    } else {
+      panic!("Unexpected entry");
+    }
+  }
+}
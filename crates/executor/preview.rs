@@ -0,0 +1,230 @@
+//! Previewing interactions that aren't on the chain yet.
+//!
+//! Governance tooling asks what a sequence of pending interactions would do
+//! if it were mined in a given order on top of the current state, before
+//! anyone posts them. See [`crate::preview_contract`].
+
+use crate::executor::{ensure_supported_contract, ValidityTable};
+use crate::executor::{raw_execute_contract, EvaluateOptions, Resume};
+use crate::override_init_state;
+use crate::test_util::generate_fake_interaction;
+use deno_core::error::{generic_error, AnyError};
+use deno_core::serde_json::Value;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use three_em_arweave::arweave::{Arweave, LoadedContract};
+use three_em_arweave::gql_result::{GQLAmountInterface, GQLEdgeInterface};
+
+/// An interaction to preview: the wallet that would post it and the input
+/// it would carry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+  pub caller: String,
+  pub input: Value,
+  /// The block it would be mined in, by default the one after the state
+  /// previewed on.
+  #[serde(default)]
+  pub block_height: Option<usize>,
+  /// When that block would be mined, in seconds since the epoch. By
+  /// default the time of the preview.
+  #[serde(default)]
+  pub block_timestamp: Option<usize>,
+}
+
+/// What a sequence of [`Candidate`]s does.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Preview {
+  /// The state after the last candidate.
+  pub state: Value,
+  /// The validity table entry of each candidate, in order.
+  pub validity: Vec<Value>,
+}
+
+/// The id of the `index`th candidate in the validity table.
+fn candidate_id(index: usize) -> String {
+  format!("preview-{}", index)
+}
+
+/// Evaluates `candidates` in order on top of `state`, the state of the
+/// contract after the interactions of `validity`, as if they were its next
+/// interactions, mined at `block_height` unless they say otherwise.
+///
+/// With no interactions in `validity`, `state` is what the init state of
+/// the contract becomes, so the candidates start from the init state
+/// instead, through the contract's `init` with
+/// [`EvaluateOptions::run_init`]. Nothing is posted, and neither the state
+/// nor its validity are cached.
+#[allow(clippy::too_many_arguments)]
+pub async fn preview_state(
+  contract_id: String,
+  mut loaded_contract: LoadedContract,
+  state: Value,
+  validity: ValidityTable,
+  candidates: &[Candidate],
+  block_height: usize,
+  show_errors: bool,
+  arweave: &Arweave,
+  options: &EvaluateOptions,
+) -> Result<Preview, AnyError> {
+  // The state already went through the contract's `init`, like a resumed
+  // one, and the candidates count towards its interaction cap.
+  let applied = validity.len();
+  let resume = Some(Resume {
+    state,
+    applied,
+    after: validity.keys().last().cloned(),
+  });
+  let options = EvaluateOptions {
+    resume: resume.filter(|_| applied > 0),
+    ..options.clone()
+  };
+  override_init_state(&mut loaded_contract, &options)?;
+  ensure_supported_contract(&loaded_contract)?;
+
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as usize;
+  let zero = || GQLAmountInterface {
+    winston: Some(String::from("0")),
+    ar: None,
+  };
+  let interactions = candidates
+    .iter()
+    .enumerate()
+    .map(|(index, candidate)| {
+      generate_fake_interaction(
+        candidate.input.clone(),
+        &candidate_id(index),
+        None,
+        Some(candidate.block_height.unwrap_or(block_height)),
+        Some(candidate.caller.clone()),
+        None,
+        None,
+        Some(zero()),
+        Some(zero()),
+        Some(candidate.block_timestamp.unwrap_or(now)),
+      )
+    })
+    .collect::<Vec<GQLEdgeInterface>>();
+
+  let result = raw_execute_contract(
+    contract_id,
+    loaded_contract,
+    interactions,
+    validity,
+    None,
+    true,
+    show_errors,
+    |_, _| unreachable!("a preview is always evaluated"),
+    arweave,
+    &options,
+  )
+  .await?;
+
+  let state = result
+    .state()
+    .cloned()
+    .ok_or_else(|| generic_error("Only JSON states can be previewed"))?;
+  let validity = result.validity().values().skip(applied).cloned().collect();
+  Ok(Preview { state, validity })
+}
+
+#[cfg(all(test, feature = "js"))]
+mod tests {
+  use crate::executor::{EvaluateOptions, ValidityTable};
+  use crate::preview::{preview_state, Candidate};
+  use crate::test_util::generate_fake_loaded_contract_data;
+  use deno_core::serde_json;
+  use deno_core::serde_json::Value;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::miscellaneous::ContractType;
+
+  #[tokio::test]
+  async fn test_preview_state() {
+    let source = br#"
+      export function init(state) {
+        state.balances.treasury = 100;
+      }
+
+      export async function handle(state, action) {
+        const { function: fn, qty } = action.input;
+        if (fn !== "transfer" || (state.balances[action.caller] || 0) < qty) {
+          throw new ContractError("Cannot transfer");
+        }
+        state.balances[action.caller] -= qty;
+        state.balances.treasury = (state.balances.treasury || 0) + qty;
+        state.lastBlock = SmartWeave.block.height;
+        return { state };
+      }
+    "#;
+    let contract = generate_fake_loaded_contract_data(
+      source,
+      ContractType::JAVASCRIPT,
+      String::from(r#"{ "balances": {} }"#),
+    );
+    let arweave =
+      Arweave::new_no_cache(1, String::from("127.0.0.1"), String::from("http"));
+    // The current state, after two interactions on the chain.
+    let base = serde_json::json!({ "balances": { "alice": 10, "bob": 3 } });
+    let mut validity = ValidityTable::new();
+    validity.insert(String::from("tx1"), Value::Bool(true));
+    validity.insert(String::from("tx2"), Value::Bool(false));
+
+    let transfer = |caller: &str, qty: usize| Candidate {
+      caller: String::from(caller),
+      input: serde_json::json!({ "function": "transfer", "qty": qty }),
+      block_height: None,
+      block_timestamp: None,
+    };
+    let candidates = [
+      transfer("alice", 4),
+      transfer("bob", 5),
+      Candidate {
+        block_height: Some(1234),
+        ..transfer("bob", 3)
+      },
+    ];
+    let preview = preview_state(
+      String::from("preview-test"),
+      contract.clone(),
+      base.clone(),
+      validity.clone(),
+      &candidates,
+      1001,
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // Applied on the base state, without running `init` on it.
+    assert_eq!(
+      preview.validity,
+      [Value::Bool(true), Value::Bool(false), Value::Bool(true)]
+    );
+    assert_eq!(
+      preview.state,
+      serde_json::json!({
+        "balances": { "alice": 6, "bob": 0, "treasury": 7 },
+        "lastBlock": 1234,
+      })
+    );
+
+    // Another order, another outcome.
+    let preview = preview_state(
+      String::from("preview-test"),
+      contract,
+      base,
+      validity,
+      &[transfer("bob", 3), transfer("alice", 11)],
+      1001,
+      false,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(preview.validity, [Value::Bool(true), Value::Bool(false)]);
+    assert_eq!(preview.state["lastBlock"], 1001);
+  }
+}
@@ -50,6 +50,7 @@ pub fn generate_fake_interaction(
       },
       parent: None,
       bundledIn: None,
+      body_input: None,
     },
   }
 }
@@ -67,6 +68,7 @@ pub fn generate_fake_loaded_contract_data(
     contract_type,
     init_state,
     min_fee: None,
+    block: None,
     contract_transaction: TransactionData {
       format: 0,
       id: String::new(),
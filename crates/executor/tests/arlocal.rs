@@ -0,0 +1,174 @@
+// Evaluates a contract deployed to a spawned arlocal, when `arlocal` is on
+// the PATH (`npm install -g arlocal@1.1.30`):
+// `cargo test -p three_em_executor --features arlocal-tests`.
+#![cfg(all(feature = "arlocal-tests", feature = "js"))]
+
+use deno_core::serde_json::{json, Value};
+use sha2::Digest;
+use std::io::ErrorKind;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use three_em_arweave::arweave::Arweave;
+use three_em_executor::execute_contract;
+use three_em_executor::executor::EvaluateOptions;
+
+/// Out of the way of an arlocal already running on the default port.
+const PORT: u16 = 1985;
+
+struct Arlocal(Child);
+
+impl Drop for Arlocal {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+  }
+}
+
+fn encode(data: &[u8]) -> String {
+  base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+/// Posts an unsigned transaction, which arlocal accepts. Its id is derived
+/// from `seed`.
+async fn post_transaction(
+  client: &reqwest::Client,
+  url: &str,
+  seed: &str,
+  data: &[u8],
+  tags: &[(&str, &str)],
+) -> String {
+  let id = encode(&sha2::Sha256::digest(seed.as_bytes()));
+  let tags: Vec<Value> = tags
+    .iter()
+    .map(|(name, value)| {
+      json!({
+        "name": encode(name.as_bytes()),
+        "value": encode(value.as_bytes()),
+      })
+    })
+    .collect();
+  let transaction = json!({
+    "format": 2,
+    "id": id,
+    "last_tx": "",
+    "owner": encode(b"owner"),
+    "tags": tags,
+    "target": "",
+    "quantity": "0",
+    "data": encode(data),
+    "data_size": data.len().to_string(),
+    "data_root": "",
+    "reward": "0",
+    "signature": encode(seed.as_bytes()),
+  });
+  client
+    .post(format!("{}/tx", url))
+    .json(&transaction)
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+  id
+}
+
+async fn mine(client: &reqwest::Client, url: &str) {
+  client
+    .get(format!("{}/mine", url))
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_arlocal() {
+  let child = Command::new("arlocal")
+    .args([&PORT.to_string(), "--hidelogs"])
+    .stdout(Stdio::null())
+    .spawn();
+  let _arlocal = match child {
+    Ok(child) => Arlocal(child),
+    Err(err) if err.kind() == ErrorKind::NotFound => {
+      eprintln!("arlocal is not installed, skipping");
+      return;
+    }
+    Err(err) => panic!("Unable to start arlocal: {}", err),
+  };
+
+  let url = format!("http://localhost:{}", PORT);
+  let client = reqwest::Client::new();
+  let mut started = false;
+  for _ in 0..50 {
+    if client.get(format!("{}/info", url)).send().await.is_ok() {
+      started = true;
+      break;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+  }
+  assert!(started, "arlocal did not start");
+
+  let source = post_transaction(
+    &client,
+    &url,
+    "source",
+    include_bytes!("../../../testdata/contracts/users_contract.js"),
+    &[("Content-Type", "application/javascript")],
+  )
+  .await;
+  let contract = post_transaction(
+    &client,
+    &url,
+    "contract",
+    b"",
+    &[
+      ("App-Name", "SmartWeaveContract"),
+      ("Contract-Src", &source),
+      ("Init-State", r#"{"users":[]}"#),
+    ],
+  )
+  .await;
+  mine(&client, &url).await;
+
+  post_transaction(
+    &client,
+    &url,
+    "interaction",
+    b"",
+    &[
+      ("App-Name", "SmartWeaveAction"),
+      ("Contract", &contract),
+      ("Input", r#"{"function":"add","name":"Divy"}"#),
+    ],
+  )
+  .await;
+  mine(&client, &url).await;
+  // Pending until the next `/mine`, so it isn't evaluated.
+  post_transaction(
+    &client,
+    &url,
+    "pending",
+    b"",
+    &[
+      ("App-Name", "SmartWeaveAction"),
+      ("Contract", &contract),
+      ("Input", r#"{"function":"add","name":"Andres"}"#),
+    ],
+  )
+  .await;
+
+  let arweave = Arweave::new_from_url_no_cache(&url).unwrap().with_arlocal();
+  let result = execute_contract(
+    contract,
+    None,
+    false,
+    false,
+    None,
+    None,
+    &arweave,
+    &EvaluateOptions::default(),
+  )
+  .await
+  .unwrap();
+  assert_eq!(result.state(), Some(&json!({ "users": ["Divy"] })));
+}
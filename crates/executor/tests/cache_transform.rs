@@ -0,0 +1,80 @@
+// Runs in its own process: the cache passed to `Arweave::new` is global and
+// only the first one set is kept.
+#![cfg(feature = "js")]
+
+use deno_core::serde_json;
+use std::fs;
+use std::sync::Arc;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::{ArweaveCache, CacheExt};
+use three_em_executor::execute_contract_with_counts;
+use three_em_executor::executor::EvaluateOptions;
+
+#[tokio::test]
+async fn test_transformed_state_is_never_resumed() {
+  let states = std::env::temp_dir().join("3em_test_cache_transform");
+  let _ = fs::remove_dir_all(&states);
+  fs::create_dir_all(&states).unwrap();
+  let cache = || ArweaveCache {
+    states_cache_folder: states.clone(),
+    ..ArweaveCache::new()
+  };
+
+  let arweave = Arweave::new(
+    443,
+    String::from("arweave.net"),
+    String::from("https"),
+    cache(),
+  );
+  let contract_id = "KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ";
+  let evaluate = |options: EvaluateOptions| {
+    let arweave = &arweave;
+    async move {
+      execute_contract_with_counts(
+        String::from(contract_id),
+        Some(822062),
+        true,
+        false,
+        None,
+        None,
+        arweave,
+        &options,
+      )
+      .await
+      .unwrap()
+    }
+  };
+
+  let redacted = serde_json::json!({ "redacted": true });
+  let transform = redacted.clone();
+  let (result, _) = evaluate(EvaluateOptions {
+    cache_transform: Some(Arc::new(move |state| *state = transform.clone())),
+    ..Default::default()
+  })
+  .await;
+
+  // The evaluation returns the real state, the cache gets the rewritten one.
+  assert_ne!(result.state(), Some(&redacted));
+  let (timestamp, cached) = cache()
+    .find_state_at(String::from(contract_id), usize::MAX)
+    .unwrap()
+    .unwrap();
+  assert_eq!(cached.state, redacted);
+  assert!(cached.transformed);
+
+  // Later evaluations don't continue from it, they start over.
+  let (again, counts) = evaluate(EvaluateOptions {
+    since: Some(timestamp),
+    ..Default::default()
+  })
+  .await;
+  assert_eq!(again.state(), result.state());
+  assert_eq!(again.validity(), result.validity());
+  assert_eq!(counts.processed, result.validity().len());
+  let (_, cached) = cache()
+    .find_state_at(String::from(contract_id), usize::MAX)
+    .unwrap()
+    .unwrap();
+  assert!(!cached.transformed);
+  assert_eq!(Some(&cached.state), result.state());
+}
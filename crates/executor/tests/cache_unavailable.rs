@@ -0,0 +1,128 @@
+// Runs in its own process: the cache passed to `Arweave::new` is global and
+// only the first one set is kept.
+#![cfg(feature = "js")]
+
+use deno_core::error::AnyError;
+use three_em_arweave::arweave::{Arweave, LoadedContract};
+use three_em_arweave::cache::{CacheExt, ContractSource, StateResult};
+use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_executor::execute_contract_with_counts;
+use three_em_executor::executor::EvaluateOptions;
+
+/// A cache whose backend is down.
+#[derive(Debug)]
+struct FailingCache;
+
+fn unavailable<T>() -> Result<T, AnyError> {
+  Err(AnyError::msg("connection refused"))
+}
+
+impl CacheExt for FailingCache {
+  fn new() -> FailingCache {
+    FailingCache
+  }
+
+  fn find_contract(
+    &mut self,
+    _contract_id: String,
+  ) -> Result<Option<LoadedContract>, AnyError> {
+    unavailable()
+  }
+
+  fn find_interactions(
+    &mut self,
+    _contract_id: String,
+  ) -> Result<Option<Vec<GQLEdgeInterface>>, AnyError> {
+    unavailable()
+  }
+
+  fn find_state(
+    &mut self,
+    _contract_id: String,
+  ) -> Result<Option<StateResult>, AnyError> {
+    unavailable()
+  }
+
+  fn find_source(
+    &mut self,
+    _source_tx_id: String,
+  ) -> Result<Option<ContractSource>, AnyError> {
+    unavailable()
+  }
+
+  fn find_state_at(
+    &mut self,
+    _contract_id: String,
+    _timestamp: usize,
+  ) -> Result<Option<(usize, StateResult)>, AnyError> {
+    unavailable()
+  }
+
+  fn cache_contract(
+    &mut self,
+    _loaded_contract: &LoadedContract,
+  ) -> Result<(), AnyError> {
+    unavailable()
+  }
+
+  fn cache_interactions(
+    &mut self,
+    _contract_id: String,
+    _interactions: &[GQLEdgeInterface],
+  ) -> Result<(), AnyError> {
+    unavailable()
+  }
+
+  fn cache_states(
+    &mut self,
+    _contract_id: String,
+    _state: StateResult,
+  ) -> Result<(), AnyError> {
+    unavailable()
+  }
+
+  fn cache_source(
+    &mut self,
+    _source_tx_id: String,
+    _source: &ContractSource,
+  ) -> Result<(), AnyError> {
+    unavailable()
+  }
+
+  fn cache_state_at(
+    &mut self,
+    _contract_id: String,
+    _timestamp: usize,
+    _state: StateResult,
+  ) -> Result<(), AnyError> {
+    unavailable()
+  }
+}
+
+#[tokio::test]
+async fn test_evaluates_uncached_when_cache_fails() {
+  let arweave = Arweave::new(
+    80,
+    String::from("arweave.net"),
+    String::from("https"),
+    FailingCache::new(),
+  );
+
+  // Twice: the first run's writes fail too, so nothing is reused.
+  for _ in 0..2 {
+    let (result, counts) = execute_contract_with_counts(
+      String::from("KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ"),
+      Some(822062),
+      true,
+      false,
+      None,
+      None,
+      &arweave,
+      &EvaluateOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(counts.processed, counts.total);
+    assert_eq!(counts.total, result.validity().len());
+  }
+}
@@ -0,0 +1,64 @@
+// Builds with any combination of the `js`, `wasm` and `evm` features, e.g.
+// `cargo test -p three_em_executor --no-default-features --features evm`.
+
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::executor::{ensure_supported_contract, Error};
+use three_em_executor::harness::evaluate_raw;
+use three_em_executor::test_util::generate_fake_loaded_contract_data;
+
+fn check(contract_type: ContractType) -> Result<(), Error> {
+  let contract =
+    generate_fake_loaded_contract_data(b"", contract_type, String::new());
+  ensure_supported_contract(&contract)
+    .map_err(|err| err.downcast::<Error>().unwrap())
+}
+
+fn expected(enabled: bool, engine: &'static str) -> Result<(), Error> {
+  if enabled {
+    Ok(())
+  } else {
+    Err(Error::EngineNotEnabled {
+      contract_id: String::from("test"),
+      engine,
+    })
+  }
+}
+
+#[tokio::test]
+async fn test_engine_features() {
+  assert_eq!(
+    check(ContractType::JAVASCRIPT),
+    expected(cfg!(feature = "js"), "js")
+  );
+  assert_eq!(
+    check(ContractType::WASM),
+    expected(cfg!(feature = "wasm"), "wasm")
+  );
+  assert_eq!(
+    check(ContractType::EVM),
+    expected(cfg!(feature = "evm"), "evm")
+  );
+}
+
+#[cfg(not(feature = "js"))]
+#[tokio::test]
+async fn test_js_not_enabled() {
+  let err = evaluate_raw(
+    b"export function handle(state) { return { state }; }",
+    "application/javascript",
+    b"{}",
+    &[],
+  )
+  .await
+  .unwrap_err();
+  assert!(err.to_string().contains("needs the js engine"));
+}
+
+#[cfg(not(feature = "wasm"))]
+#[tokio::test]
+async fn test_wasm_not_enabled() {
+  let err = evaluate_raw(b"\0asm\x01\0\0\0", "application/wasm", b"{}", &[])
+    .await
+    .unwrap_err();
+  assert!(err.to_string().contains("needs the wasm engine"));
+}
@@ -0,0 +1,47 @@
+// Runs in its own process: the cache passed to `Arweave::new` is global and
+// only the first one set is kept.
+#![cfg(feature = "js")]
+
+use std::fs;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::{ArweaveCache, CacheExt};
+use three_em_executor::execute_contract;
+use three_em_executor::executor::EvaluateOptions;
+
+#[tokio::test]
+async fn test_oversized_state_is_not_cached() {
+  let states = std::env::temp_dir().join("3em_test_oversized_state");
+  let _ = fs::remove_dir_all(&states);
+  fs::create_dir_all(&states).unwrap();
+  let cache = || ArweaveCache {
+    states_cache_folder: states.clone(),
+    ..ArweaveCache::new()
+  };
+
+  let arweave = Arweave::new(
+    443,
+    String::from("arweave.net"),
+    String::from("https"),
+    cache().with_max_state_size(16),
+  );
+  let contract_id = "KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ";
+  let result = execute_contract(
+    String::from(contract_id),
+    Some(822062),
+    true,
+    false,
+    None,
+    None,
+    &arweave,
+    &EvaluateOptions::default(),
+  )
+  .await
+  .unwrap();
+
+  // Evaluated in full, just left out of the cache.
+  assert!(!result.validity().is_empty());
+  assert!(cache()
+    .find_state(String::from(contract_id))
+    .unwrap()
+    .is_none());
+}
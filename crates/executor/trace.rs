@@ -0,0 +1,188 @@
+//! Hashes of the state after every interaction, for third parties verifying
+//! an evaluation step by step.
+//!
+//! A verifier holding the trace of a disputed evaluation can re-evaluate up
+//! to any interaction and compare its state hash, pinpointing the first
+//! step the two disagree on instead of only learning that the final states
+//! differ.
+
+use crate::executor::StepCallback;
+use deno_core::serde_json;
+use deno_core::serde_json::Value;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceEntry {
+  pub interaction_id: String,
+  /// See [`state_hash`].
+  pub state_hash: String,
+  /// The interaction's entry in the validity table.
+  pub valid: Value,
+}
+
+/// Collects a [`TraceEntry`] for every interaction an evaluation runs.
+///
+/// Hashing the whole state after every interaction is expensive, and
+/// handing out the state at all has the cost of
+/// [`crate::executor::EvaluateOptions::on_step`], which the trace is
+/// recorded through. Only interactions evaluated in the run are traced, and
+/// EVM contracts aren't.
+#[derive(Clone, Default)]
+pub struct Trace {
+  entries: Arc<Mutex<Vec<TraceEntry>>>,
+}
+
+impl Trace {
+  pub fn new() -> Trace {
+    Trace::default()
+  }
+
+  /// A callback for [`crate::executor::EvaluateOptions::on_step`] that
+  /// records into this trace.
+  pub fn on_step(&self) -> StepCallback {
+    let entries = self.entries.clone();
+    Arc::new(move |id: &str, state: &Value, valid: &Value| {
+      entries.lock().unwrap().push(TraceEntry {
+        interaction_id: id.to_owned(),
+        state_hash: state_hash(state),
+        valid: valid.clone(),
+      });
+    })
+  }
+
+  /// The entries recorded so far, in evaluation order.
+  pub fn entries(&self) -> Vec<TraceEntry> {
+    self.entries.lock().unwrap().clone()
+  }
+}
+
+/// Hex SHA-256 of the canonical JSON of `state`: object keys sorted, no
+/// whitespace. Equal states hash the same whatever order their keys were
+/// inserted in.
+pub fn state_hash(state: &Value) -> String {
+  let mut canonical = Vec::new();
+  write_canonical(state, &mut canonical);
+  hex::encode(Sha256::digest(&canonical))
+}
+
+fn write_canonical(value: &Value, out: &mut Vec<u8>) {
+  match value {
+    Value::Object(map) => {
+      let mut entries: Vec<_> = map.iter().collect();
+      entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+      out.push(b'{');
+      for (index, (key, value)) in entries.into_iter().enumerate() {
+        if index > 0 {
+          out.push(b',');
+        }
+        serde_json::to_writer(&mut *out, key).unwrap();
+        out.push(b':');
+        write_canonical(value, out);
+      }
+      out.push(b'}');
+    }
+    Value::Array(items) => {
+      out.push(b'[');
+      for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+          out.push(b',');
+        }
+        write_canonical(item, out);
+      }
+      out.push(b']');
+    }
+    value => serde_json::to_writer(&mut *out, value).unwrap(),
+  }
+}
+
+#[cfg(all(test, feature = "js"))]
+mod tests {
+  use crate::executor::{raw_execute_contract, EvaluateOptions};
+  use crate::test_util::{
+    generate_fake_interaction, generate_fake_loaded_contract_data,
+  };
+  use crate::trace::{state_hash, Trace};
+  use deno_core::serde_json;
+  use indexmap::map::IndexMap;
+  use three_em_arweave::arweave::Arweave;
+  use three_em_arweave::cache::ArweaveCache;
+  use three_em_arweave::cache::CacheExt;
+  use three_em_arweave::miscellaneous::ContractType;
+
+  #[tokio::test]
+  async fn test_trace_hashes() {
+    // `tx2` is invalid, so it leaves the state as `tx1` left it.
+    let interactions = [("tx1", "add"), ("tx2", "remove"), ("tx3", "add")]
+      .iter()
+      .map(|(id, function)| {
+        generate_fake_interaction(
+          serde_json::json!({ "function": function, "name": id }),
+          id,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+          None,
+        )
+      })
+      .collect();
+
+    let trace = Trace::new();
+    let result = raw_execute_contract(
+      String::new(),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| panic!("not implemented"),
+      &Arweave::new(
+        443,
+        "arweave.net".to_string(),
+        String::from("https"),
+        ArweaveCache::new(),
+      ),
+      &EvaluateOptions {
+        on_step: Some(trace.on_step()),
+        ..Default::default()
+      },
+    )
+    .await
+    .unwrap();
+
+    let entries = trace.entries();
+    assert_eq!(entries.len(), 3);
+    let ids: Vec<&str> = entries
+      .iter()
+      .map(|entry| entry.interaction_id.as_str())
+      .collect();
+    assert_eq!(ids, ["tx1", "tx2", "tx3"]);
+    assert_eq!(entries[1].valid, false);
+    let init_state = serde_json::json!({ "users": [] });
+    assert_ne!(entries[0].state_hash, state_hash(&init_state));
+    assert_eq!(entries[0].state_hash, entries[1].state_hash);
+    assert_ne!(entries[1].state_hash, entries[2].state_hash);
+    assert_eq!(entries[2].state_hash, state_hash(result.state().unwrap()));
+
+    // Key order doesn't matter, values do.
+    assert_eq!(
+      state_hash(&serde_json::json!({ "a": 1, "b": [1, { "c": 2, "d": 3 }] })),
+      state_hash(&serde_json::json!({ "b": [1, { "d": 3, "c": 2 }], "a": 1 }))
+    );
+    assert_ne!(
+      state_hash(&serde_json::json!({ "a": 1 })),
+      state_hash(&serde_json::json!({ "a": 2 }))
+    );
+  }
+}
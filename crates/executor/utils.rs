@@ -1,3 +1,4 @@
+use deno_core::error::{generic_error, AnyError};
 use deno_core::serde_json::Value;
 use three_em_arweave::gql_result::{
   GQLAmountInterface, GQLBlockInterface, GQLEdgeInterface, GQLNodeInterface,
@@ -68,6 +69,113 @@ pub fn create_simulated_transaction(
       },
       parent: None,
       bundledIn: None,
+      body_input: None,
     },
   }
 }
+
+/// Bounds on JSON that comes from the chain, such as interaction inputs.
+///
+/// Anyone can post an interaction, so its input is checked against these
+/// limits before it is handed to `serde_json`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JsonLimits {
+  /// Maximum nesting of arrays and objects.
+  pub max_depth: usize,
+  /// Maximum length of the raw input, in bytes. Also applies to the hex
+  /// call data of EVM interactions.
+  pub max_bytes: usize,
+}
+
+impl Default for JsonLimits {
+  fn default() -> Self {
+    JsonLimits {
+      max_depth: 64,
+      max_bytes: 1024 * 1024,
+    }
+  }
+}
+
+/// Rejects an interaction input longer than [`JsonLimits::max_bytes`],
+/// whatever its encoding.
+pub fn check_input_size(
+  input: &str,
+  limits: &JsonLimits,
+) -> Result<(), AnyError> {
+  if input.len() > limits.max_bytes {
+    return Err(generic_error(format!(
+      "Input of {} bytes exceeds the limit of {} bytes",
+      input.len(),
+      limits.max_bytes
+    )));
+  }
+
+  Ok(())
+}
+
+/// Parses untrusted JSON, rejecting it if it exceeds `limits`.
+///
+/// Nesting is measured on the raw text so that a pathological document is
+/// rejected before any `Value` is built for it.
+pub fn parse_json_with_limits(
+  input: &str,
+  limits: &JsonLimits,
+) -> Result<Value, AnyError> {
+  check_input_size(input, limits)?;
+
+  let mut depth = 0_usize;
+  let mut in_string = false;
+  let mut escaped = false;
+  for byte in input.bytes() {
+    if in_string {
+      match byte {
+        _ if escaped => escaped = false,
+        b'\\' => escaped = true,
+        b'"' => in_string = false,
+        _ => {}
+      }
+      continue;
+    }
+
+    match byte {
+      b'"' => in_string = true,
+      b'[' | b'{' => {
+        depth += 1;
+        if depth > limits.max_depth {
+          return Err(generic_error(format!(
+            "JSON input exceeds the maximum nesting depth of {}",
+            limits.max_depth
+          )));
+        }
+      }
+      b']' | b'}' => depth = depth.saturating_sub(1),
+      _ => {}
+    }
+  }
+
+  Ok(deno_core::serde_json::from_str(input)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::utils::{parse_json_with_limits, JsonLimits};
+
+  #[tokio::test]
+  async fn test_parse_json_with_limits() {
+    let limits = JsonLimits {
+      max_depth: 3,
+      max_bytes: 64,
+    };
+
+    let value =
+      parse_json_with_limits(r#"{"a":[{"b":"[[[[\"]]"}]}"#, &limits).unwrap();
+    assert_eq!(value["a"][0]["b"], "[[[[\"]]");
+
+    assert!(parse_json_with_limits("[[[[]]]]", &limits).is_err());
+    assert!(parse_json_with_limits(&"1".repeat(65), &limits).is_err());
+    assert!(parse_json_with_limits("{", &limits).is_err());
+
+    let bomb = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    assert!(parse_json_with_limits(&bomb, &JsonLimits::default()).is_err());
+  }
+}
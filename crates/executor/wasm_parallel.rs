@@ -0,0 +1,184 @@
+//! Parallel verification of non-mutating WASM interactions.
+//!
+//! A WASM handler is a function of `(state, action, interaction context)`
+//! that returns the next state. Whether an interaction mutates the state is
+//! only known after running it, so verification is speculative:
+//!
+//! 1. The next `n` interactions (one per worker) are all evaluated against
+//!    the current state, each on its own `WasmRuntime`.
+//! 2. Results are then walked in sort-key order. An interaction that failed,
+//!    or returned a state byte-for-byte equal to its input, left the state
+//!    untouched, so it saw exactly what the serial loop would have shown it.
+//!    It is settled as is.
+//! 3. The first interaction that returned a different state is settled and
+//!    becomes the new state. Every result after it was computed against a
+//!    stale state and is thrown away; those interactions are re-run in the
+//!    next batch.
+//!
+//! Each step settles at least one interaction, and an interaction is only
+//! ever accepted if its input state matches the serial one. Mutations are
+//! therefore applied one at a time in sort-key order, and the final state
+//! and validity table are the same as with the serial loop.
+//!
+//! This relies on the handler being deterministic in its inputs, which
+//! SmartWeave already requires. Contracts that keep data in WASM globals or
+//! linear memory between calls would observe a different history on each
+//! worker and must not be evaluated in this mode.
+
+use crate::engine::new_wasm_runtime;
+use crate::limits::Limits;
+use deno_core::error::AnyError;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use three_em_smartweave::{DeterminismContext, InteractionContext};
+use three_em_wasm::Error;
+
+/// A single call to the contract handler.
+pub struct WasmCall {
+  pub action: Vec<u8>,
+  pub context: InteractionContext,
+}
+
+struct Job {
+  index: usize,
+  state: Arc<Vec<u8>>,
+  action: Vec<u8>,
+  context: InteractionContext,
+}
+
+type JobResult = (usize, Result<Vec<u8>, AnyError>);
+
+/// A pool of threads, each owning its own instance of the contract.
+pub struct WasmWorkers {
+  jobs: Vec<mpsc::Sender<Job>>,
+  results: mpsc::Receiver<JobResult>,
+  handles: Vec<thread::JoinHandle<()>>,
+}
+
+impl WasmWorkers {
+  pub fn new(
+    wasm: &[u8],
+    workers: usize,
+    sandbox_wasi: bool,
+    determinism: DeterminismContext,
+    limits: Limits,
+  ) -> Result<WasmWorkers, AnyError> {
+    let wasm = Arc::new(wasm.to_vec());
+    let (result_tx, results) = mpsc::channel::<JobResult>();
+    let (ready_tx, ready) = mpsc::channel::<Result<(), AnyError>>();
+
+    let mut jobs = Vec::with_capacity(workers);
+    let mut handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+      let (job_tx, job_rx) = mpsc::channel::<Job>();
+      let wasm = wasm.clone();
+      let result_tx = result_tx.clone();
+      let ready_tx = ready_tx.clone();
+
+      handles.push(thread::spawn(move || {
+        let new_runtime =
+          || new_wasm_runtime(&wasm, sandbox_wasi, determinism, &limits);
+        let mut rt = match new_runtime() {
+          Ok(rt) => {
+            ready_tx.send(Ok(())).unwrap();
+            rt
+          }
+          Err(err) => {
+            ready_tx.send(Err(err)).unwrap();
+            return;
+          }
+        };
+
+        for mut job in job_rx {
+          let mut state = job.state.to_vec();
+          let result = rt.call(&mut state, &mut job.action, job.context);
+          // Like the serial engine, a call over a limit leaves a runtime
+          // that the next ones can't run on.
+          if let Err(err) = &result {
+            if err.downcast_ref::<Error>().is_some() {
+              if let Ok(fresh) = new_runtime() {
+                rt = fresh;
+              }
+            }
+          }
+          if result_tx.send((job.index, result)).is_err() {
+            break;
+          }
+        }
+      }));
+      jobs.push(job_tx);
+    }
+
+    for _ in 0..workers {
+      ready.recv().unwrap()?;
+    }
+
+    Ok(WasmWorkers {
+      jobs,
+      results,
+      handles,
+    })
+  }
+
+  /// Runs `calls` in order starting from `state`.
+  ///
+  /// Returns the final state and the outcome of every call, in the same
+  /// order as `calls`.
+  pub fn run(
+    &self,
+    mut state: Vec<u8>,
+    calls: Vec<WasmCall>,
+  ) -> (Vec<u8>, Vec<Result<(), AnyError>>) {
+    let mut outcomes = Vec::with_capacity(calls.len());
+
+    while outcomes.len() < calls.len() {
+      let start = outcomes.len();
+      let end = calls.len().min(start + self.jobs.len());
+      let shared_state = Arc::new(state.clone());
+
+      for (worker, index) in (start..end).enumerate() {
+        self.jobs[worker]
+          .send(Job {
+            index,
+            state: shared_state.clone(),
+            action: calls[index].action.clone(),
+            context: calls[index].context.clone(),
+          })
+          .unwrap();
+      }
+
+      let mut batch: Vec<Option<Result<Vec<u8>, AnyError>>> =
+        (start..end).map(|_| None).collect();
+      for _ in start..end {
+        let (index, result) = self.results.recv().unwrap();
+        batch[index - start] = Some(result);
+      }
+
+      for result in batch {
+        match result.unwrap() {
+          Err(err) => outcomes.push(Err(err)),
+          Ok(next_state) if next_state == state => outcomes.push(Ok(())),
+          Ok(next_state) => {
+            state = next_state;
+            outcomes.push(Ok(()));
+            // Everything after a mutation ran against a stale state.
+            break;
+          }
+        }
+      }
+    }
+
+    (state, outcomes)
+  }
+}
+
+impl Drop for WasmWorkers {
+  fn drop(&mut self) {
+    // Closing the job channels lets every worker loop finish.
+    self.jobs.clear();
+    for handle in self.handles.drain(..) {
+      let _ = handle.join();
+    }
+  }
+}
@@ -30,6 +30,7 @@ fn create_snapshot(snapshot_path: &Path) {
       three_em_smartweave::init(
         (443, String::from(""), String::from("")),
         never_op,
+        Default::default(),
       ),
     ],
     will_snapshot: true,
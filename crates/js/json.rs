@@ -0,0 +1,121 @@
+//! Moving JSON states in and out of V8 without losing integer precision.
+//!
+//! A JavaScript number is a double, so an integer beyond 2^53 given to a
+//! contract as one would come back rounded, even if the contract never
+//! touched it. Those integers are handed over as BigInts instead, and
+//! BigInts are read back as JSON integers, as long as they fit in 64 bits.
+//! Integral doubles beyond 2^53 stay floating point, so that a state reads
+//! back as the same types it was written with.
+
+use crate::Error;
+use deno_core::error::{generic_error, AnyError};
+use deno_core::serde_json::Value;
+use deno_core::serde_v8;
+
+/// `Number.MAX_SAFE_INTEGER`, the largest integer a double holds along with
+/// its neighbours.
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+fn is_safe_integer(n: i64) -> bool {
+  (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&n)
+}
+
+/// Converts a JSON value to V8, with integers beyond 2^53 as BigInts.
+pub fn to_v8<'s>(
+  scope: &mut v8::HandleScope<'s>,
+  value: &Value,
+) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+  let local = match value {
+    Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+      (Some(n), _) if !is_safe_integer(n) => {
+        v8::BigInt::new_from_i64(scope, n).into()
+      }
+      (None, Some(n)) => v8::BigInt::new_from_u64(scope, n).into(),
+      _ => serde_v8::to_v8(scope, value)?,
+    },
+    Value::Array(items) => {
+      let elements = items
+        .iter()
+        .map(|item| to_v8(scope, item))
+        .collect::<Result<Vec<_>, _>>()?;
+      v8::Array::new_with_elements(scope, &elements).into()
+    }
+    Value::Object(map) => {
+      let object = v8::Object::new(scope);
+      for (key, value) in map {
+        let key = v8::String::new(scope, key)
+          .ok_or_else(|| generic_error("State key is too long"))?;
+        let value = to_v8(scope, value)?;
+        object
+          .set(scope, key.into(), value)
+          .ok_or(Error::Terminated)?;
+      }
+      object.into()
+    }
+    value => serde_v8::to_v8(scope, value)?,
+  };
+  Ok(local)
+}
+
+/// Converts a V8 value to JSON, with BigInts as integers. Like
+/// `JSON.stringify`, object properties that are `undefined` or functions
+/// are left out.
+///
+/// `canonical` is cleared if the value holds BigInts that [`to_v8`] would
+/// give back as numbers.
+pub fn from_v8<'s>(
+  scope: &mut v8::HandleScope<'s>,
+  value: v8::Local<'s, v8::Value>,
+  canonical: &mut bool,
+) -> Result<Value, AnyError> {
+  if let Ok(bigint) = v8::Local::<v8::BigInt>::try_from(value) {
+    if let (n, true) = bigint.i64_value() {
+      if is_safe_integer(n) {
+        *canonical = false;
+      }
+      return Ok(Value::from(n));
+    }
+    return match bigint.u64_value() {
+      (n, true) => Ok(Value::from(n)),
+      _ => Err(generic_error("State holds a BigInt larger than 64 bits")),
+    };
+  }
+
+  if let Ok(number) = v8::Local::<v8::Number>::try_from(value) {
+    let n = number.value();
+    if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER as f64 {
+      return Ok(Value::from(n as i64));
+    }
+    // Not finite numbers are null.
+    return Ok(Value::from(n));
+  }
+
+  if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+    let mut items = Vec::with_capacity(array.length() as usize);
+    for index in 0..array.length() {
+      let item = array.get_index(scope, index).ok_or(Error::Terminated)?;
+      items.push(from_v8(scope, item, canonical)?);
+    }
+    return Ok(Value::Array(items));
+  }
+
+  if value.is_object() && !value.is_function() {
+    let object = value.to_object(scope).ok_or(Error::Terminated)?;
+    let keys = object
+      .get_own_property_names(scope)
+      .ok_or(Error::Terminated)?;
+    let mut map = deno_core::serde_json::Map::new();
+    for index in 0..keys.length() {
+      let key = keys.get_index(scope, index).ok_or(Error::Terminated)?;
+      let item = object.get(scope, key).ok_or(Error::Terminated)?;
+      if item.is_undefined() || item.is_function() {
+        continue;
+      }
+      let key = key.to_rust_string_lossy(scope);
+      map.insert(key, from_v8(scope, item, canonical)?);
+    }
+    return Ok(Value::Object(map));
+  }
+
+  Ok(serde_v8::from_v8(scope, value)?)
+}
@@ -1,8 +1,11 @@
+mod json;
 mod loader;
 pub mod snapshot;
+pub mod watchdog;
 
 use crate::loader::EmbeddedModuleLoader;
-use deno_core::error::AnyError;
+use crate::watchdog::Watchdog;
+use deno_core::error::{generic_error, AnyError};
 use deno_core::serde::de::DeserializeOwned;
 use deno_core::serde::Serialize;
 use deno_core::serde_json::Value;
@@ -15,7 +18,12 @@ use std::cell::RefCell;
 use std::fmt::Debug;
 use std::future::Future;
 use std::rc::Rc;
+use std::time::Duration;
+use three_em_smartweave::ContractInfo;
+use three_em_smartweave::DeterminismContext;
 use three_em_smartweave::InteractionContext;
+use three_em_smartweave::RuntimeSettings;
+use three_em_smartweave::Waiting;
 #[derive(Debug, Clone)]
 pub enum HeapLimitState {
   /// Ok, the heap limit is not exceeded.
@@ -30,16 +38,25 @@ impl Default for HeapLimitState {
   }
 }
 
+/// Bytes the V8 heap of a contract may grow to, see
+/// [`Runtime::with_heap_limit`].
+pub const DEFAULT_HEAP_LIMIT: usize = 5 << 20;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
   /// Isolate is terminated.
   Terminated,
+  /// A call ran for longer than its timeout, and was terminated.
+  TimedOut { timeout: Duration },
 }
 
 impl std::fmt::Display for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
       Error::Terminated => write!(f, "Isolate is terminated"),
+      Error::TimedOut { timeout } => {
+        write!(f, "Interaction timed out after {} ms", timeout.as_millis())
+      }
     }
   }
 }
@@ -72,6 +89,13 @@ pub struct Runtime {
   is_promise: Option<bool>,
   /// Current state value.
   contract_state: v8::Global<v8::Value>,
+  determinism: DeterminismContext,
+  /// See [`Runtime::set_timeout`].
+  timeout: Option<Duration>,
+  /// Started by the first call with a timeout.
+  watchdog: Option<Watchdog>,
+  /// Shared with the ops through the op state.
+  waiting: Waiting,
 }
 
 impl Runtime {
@@ -80,6 +104,36 @@ impl Runtime {
     init: T,
     arweave: (i32, String, String),
     op_smartweave_read_state: F,
+    settings: RuntimeSettings,
+  ) -> Result<Self, AnyError>
+  where
+    T: Serialize + 'static,
+    F: Fn(Rc<RefCell<OpState>>, (String, Option<usize>, Option<bool>), ()) -> R
+      + 'static,
+    R:
+      Future<Output = Result<deno_core::serde_json::Value, AnyError>> + 'static,
+  {
+    Self::with_heap_limit(
+      source,
+      init,
+      arweave,
+      op_smartweave_read_state,
+      settings,
+      DEFAULT_HEAP_LIMIT,
+    )
+    .await
+  }
+
+  /// Like [`Runtime::new`], with a heap of up to `heap_limit` bytes instead
+  /// of [`DEFAULT_HEAP_LIMIT`]. A call that needs more is terminated, see
+  /// [`Runtime::state`].
+  pub async fn with_heap_limit<T, F, R>(
+    source: &str,
+    init: T,
+    arweave: (i32, String, String),
+    op_smartweave_read_state: F,
+    settings: RuntimeSettings,
+    heap_limit: usize,
   ) -> Result<Self, AnyError>
   where
     T: Serialize + 'static,
@@ -104,14 +158,18 @@ impl Runtime {
       true
     });
 
-    let params = v8::CreateParams::default().heap_limits(0, 5 << 20);
+    let params = v8::CreateParams::default().heap_limits(0, heap_limit);
     let mut rt = JsRuntime::new(RuntimeOptions {
       extensions: vec![
         deno_webidl::init(),
         deno_url::init(),
         deno_web::init(BlobStore::default(), None),
         deno_crypto::init(Some(0)),
-        three_em_smartweave::init(arweave, op_smartweave_read_state),
+        three_em_smartweave::init(
+          arweave,
+          op_smartweave_read_state,
+          settings,
+        ),
       ],
       module_loader: Some(module_loader),
       startup_snapshot: Some(snapshot::snapshot()),
@@ -142,6 +200,8 @@ impl Runtime {
       let local = serde_v8::to_v8(scope, init)?;
       v8::Global::new(scope, local)
     };
+    let waiting = Waiting::default();
+    rt.op_state().borrow_mut().put(waiting.clone());
 
     Ok(Self {
       rt,
@@ -149,6 +209,10 @@ impl Runtime {
       state,
       is_promise: None,
       contract_state,
+      determinism: DeterminismContext::default(),
+      timeout: None,
+      watchdog: None,
+      waiting,
     })
   }
 
@@ -160,6 +224,62 @@ impl Runtime {
     self.rt.handle_scope()
   }
 
+  /// Whether the contract module exports a `handle` function.
+  pub fn has_handler(&mut self) -> bool {
+    let scope = &mut self.rt.handle_scope();
+    let module_obj = match self.module.open(scope).to_object(scope) {
+      Some(module_obj) => module_obj,
+      None => return false,
+    };
+    let key = v8::String::new(scope, "handle").unwrap().into();
+
+    match module_obj.get(scope, key) {
+      Some(func_obj) => func_obj.is_function(),
+      None => false,
+    }
+  }
+
+  /// Calls the contract's `init` export, if it has one, with the current
+  /// state. What it returns, or resolves to, becomes the state; if that is
+  /// `undefined`, the state is kept as `init` left it. Returns whether the
+  /// contract has an `init` export.
+  ///
+  /// No interaction is set, so `Date` and `Math.random()` draw from their
+  /// defaults.
+  pub async fn init(&mut self) -> Result<bool, AnyError> {
+    let global = {
+      let scope = &mut self.rt.handle_scope();
+      let module_obj = match self.module.open(scope).to_object(scope) {
+        Some(module_obj) => module_obj,
+        None => return Ok(false),
+      };
+      let key = v8::String::new(scope, "init").unwrap().into();
+      let func = match module_obj
+        .get(scope, key)
+        .and_then(|func_obj| v8::Local::<v8::Function>::try_from(func_obj).ok())
+      {
+        Some(func) => func,
+        None => return Ok(false),
+      };
+
+      let state =
+        v8::Local::<v8::Value>::new(scope, self.contract_state.clone());
+      let undefined = v8::undefined(scope);
+      let local = func
+        .call(scope, undefined.into(), &[state])
+        .ok_or(Error::Terminated)?;
+      v8::Global::new(scope, local)
+    };
+
+    let global = self.rt.resolve_value(global).await?;
+    let scope = &mut self.rt.handle_scope();
+    if !v8::Local::new(scope, global.clone()).is_undefined() {
+      self.contract_state = global;
+    }
+
+    Ok(true)
+  }
+
   pub fn get_contract_state<T>(&mut self) -> Result<T, AnyError>
   where
     T: DeserializeOwned + 'static,
@@ -169,11 +289,111 @@ impl Runtime {
     Ok(serde_v8::from_v8(scope, value)?)
   }
 
+  /// Replaces the current contract state, e.g. to roll back an interaction
+  /// that failed after mutating it.
+  pub fn set_contract_state<T>(&mut self, state: T) -> Result<(), AnyError>
+  where
+    T: Serialize,
+  {
+    let scope = &mut self.rt.handle_scope();
+    let local = serde_v8::to_v8(scope, state)?;
+    self.contract_state = v8::Global::new(scope, local);
+    Ok(())
+  }
+
+  /// The current contract state as JSON, without rounding its integers:
+  /// BigInts are read as integers. Fails if one doesn't fit in 64 bits.
+  ///
+  /// BigInts small enough to be numbers are given back to the contract as
+  /// numbers, like they would be when the state comes from a cache, so the
+  /// handler sees the same state either way.
+  pub fn json_state(&mut self) -> Result<Value, AnyError> {
+    let scope = &mut self.rt.handle_scope();
+    let local = v8::Local::new(scope, self.contract_state.clone());
+    let mut canonical = true;
+    let state = json::from_v8(scope, local, &mut canonical)?;
+    if !canonical {
+      let local = json::to_v8(scope, &state)?;
+      self.contract_state = v8::Global::new(scope, local);
+    }
+    Ok(state)
+  }
+
+  /// Replaces the current contract state with a JSON one. Integers a
+  /// number can't hold exactly, beyond 2^53, are given to the contract as
+  /// BigInts.
+  pub fn set_json_state(&mut self, state: &Value) -> Result<(), AnyError> {
+    let scope = &mut self.rt.handle_scope();
+    let local = json::to_v8(scope, state)?;
+    self.contract_state = v8::Global::new(scope, local);
+    Ok(())
+  }
+
+  /// The state shared by the ops of this runtime.
+  pub fn op_state(&mut self) -> Rc<RefCell<OpState>> {
+    self.rt.op_state()
+  }
+
+  /// Exposes `info` to the contract as `SmartWeave.contract`.
+  pub fn set_contract_info(
+    &mut self,
+    info: ContractInfo,
+  ) -> Result<(), AnyError> {
+    let scope = &mut self.rt.handle_scope();
+    let context = scope.get_current_context();
+    let inner_scope = &mut v8::ContextScope::new(scope, context);
+
+    let global = context.global(inner_scope);
+    let v8_key = serde_v8::to_v8(inner_scope, "contractInfo")?;
+    let v8_val = serde_v8::to_v8(inner_scope, info)?;
+    global.set(inner_scope, v8_key, v8_val);
+    Ok(())
+  }
+
+  /// Sets what `Date` and `Math.random()` draw from in the next calls.
+  pub fn set_determinism(&mut self, determinism: DeterminismContext) {
+    self.determinism = determinism;
+  }
+
+  /// Terminates each of the next calls that runs for longer than
+  /// `timeout`, failing it with [`Error::TimedOut`]. The time an op spends
+  /// in the [`Waiting`] of the op state, like reading a contract, doesn't
+  /// count. `None`, the default, lets calls run for as long as they take.
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout;
+  }
+
   pub async fn call<R>(
     &mut self,
     action: R,
     interaction_data: Option<InteractionContext>,
   ) -> Result<Option<CallResult>, AnyError>
+  where
+    R: Serialize + 'static,
+  {
+    let timeout = match self.timeout {
+      Some(timeout) => timeout,
+      None => return self.call_handler(action, interaction_data).await,
+    };
+    let isolate = self.rt.v8_isolate().thread_safe_handle();
+    self
+      .watchdog
+      .get_or_insert_with(|| Watchdog::new(isolate))
+      .start(timeout, self.waiting.clone());
+    let result = self.call_handler(action, interaction_data).await;
+    if let Some(watchdog) = &self.watchdog {
+      if watchdog.stop(self.rt.v8_isolate()) {
+        return Err(Error::TimedOut { timeout }.into());
+      }
+    }
+    result
+  }
+
+  async fn call_handler<R>(
+    &mut self,
+    action: R,
+    interaction_data: Option<InteractionContext>,
+  ) -> Result<Option<CallResult>, AnyError>
   where
     R: Serialize + 'static,
   {
@@ -182,14 +402,24 @@ impl Runtime {
       let context = scope.get_current_context();
 
       {
-        if interaction_data.is_some() {
+        if let Some(interaction_data) = interaction_data {
           let inner_scope = &mut v8::ContextScope::new(scope, context);
 
           let global = context.global(inner_scope);
+          // Read by the `Date` and `Math.random` overrides in smartweave.js.
+          let now = self.determinism.now_ms(&interaction_data);
+          let seed = self.determinism.seed(&interaction_data);
+          for (key, value) in
+            [("currentClock", now as f64), ("currentSeed", seed as f64)]
+          {
+            let v8_key = serde_v8::to_v8(inner_scope, key).unwrap();
+            let v8_val = v8::Number::new(inner_scope, value);
+            global.set(inner_scope, v8_key, v8_val.into());
+          }
+
           let v8_key =
             serde_v8::to_v8(inner_scope, "currentInteraction").unwrap();
-          let v8_val =
-            serde_v8::to_v8(inner_scope, interaction_data.unwrap()).unwrap();
+          let v8_val = serde_v8::to_v8(inner_scope, interaction_data).unwrap();
           global.set(inner_scope, v8_key, v8_val);
         }
       };
@@ -199,8 +429,12 @@ impl Runtime {
 
       let module_obj = self.module.open(scope).to_object(scope).unwrap();
       let key = v8::String::new(scope, "handle").unwrap().into();
-      let func_obj = module_obj.get(scope, key).unwrap();
-      let func = v8::Local::<v8::Function>::try_from(func_obj).unwrap();
+      let func = module_obj
+        .get(scope, key)
+        .and_then(|func_obj| v8::Local::<v8::Function>::try_from(func_obj).ok())
+        .ok_or_else(|| {
+          generic_error("Contract does not export a handle function")
+        })?;
 
       let state =
         v8::Local::<v8::Value>::new(scope, self.contract_state.clone());
@@ -213,8 +447,9 @@ impl Runtime {
         self.is_promise = Some(local.is_promise());
       }
 
-      if let Some(true) = self.is_promise {
-        let promise = v8::Local::<v8::Promise>::try_from(local).unwrap();
+      if let (Some(true), Ok(promise)) =
+        (self.is_promise, v8::Local::<v8::Promise>::try_from(local))
+      {
         match promise.state() {
           v8::PromiseState::Pending => {}
           v8::PromiseState::Fulfilled | v8::PromiseState::Rejected => {
@@ -238,24 +473,25 @@ impl Runtime {
         .ok_or(Error::Terminated)?;
       let state_key = v8::String::new(scope, "state").unwrap().into();
 
-      // Return value.
+      // Return value. Getters of the handler's result can throw.
       let result_key = v8::String::new(scope, "result").unwrap().into();
-      let result = state.get(scope, result_key).unwrap();
+      let result = state.get(scope, result_key).ok_or(Error::Terminated)?;
       if !result.is_null_or_undefined() {
         return Ok(Some(CallResult::Result(v8::Global::new(scope, result))));
       }
 
-      let state_obj = state.get(scope, state_key).unwrap();
+      let state_obj = state.get(scope, state_key).ok_or(Error::Terminated)?;
       if let Some(state) = state_obj.to_object(scope) {
         // Update the contract state.
         self.contract_state = v8::Global::new(scope, state_obj);
 
         // Contract evolution.
         let evolve_key = v8::String::new(scope, "canEvolve").unwrap().into();
-        let can_evolve = state.get(scope, evolve_key).unwrap();
+        let can_evolve =
+          state.get(scope, evolve_key).ok_or(Error::Terminated)?;
         if can_evolve.boolean_value(scope) {
           let evolve_key = v8::String::new(scope, "evolve").unwrap().into();
-          let evolve = state.get(scope, evolve_key).unwrap();
+          let evolve = state.get(scope, evolve_key).ok_or(Error::Terminated)?;
           return Ok(Some(CallResult::Evolve(
             evolve.to_rust_string_lossy(scope),
           )));
@@ -276,12 +512,15 @@ mod test {
   use deno_core::error::AnyError;
   use deno_core::serde::Deserialize;
   use deno_core::serde::Serialize;
+  use deno_core::serde_json;
   use deno_core::serde_json::Value;
   use deno_core::OpState;
   use deno_core::ZeroCopyBuf;
   use std::cell::RefCell;
   use std::rc::Rc;
+  use std::time::Duration;
   use three_em_smartweave::InteractionContext;
+  use three_em_smartweave::RuntimeSettings;
 
   pub async fn never_op(
     _: Rc<RefCell<OpState>>,
@@ -298,6 +537,7 @@ mod test {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -308,6 +548,149 @@ mod test {
     assert_eq!(value, -69);
   }
 
+  #[tokio::test]
+  async fn test_runtime_set_contract_state() {
+    let mut rt = Runtime::new(
+      "export async function handle(state) { state.n += 1; return { state } }",
+      serde_json::json!({ "n": 0 }),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+
+    rt.call((), None).await.unwrap();
+    rt.set_contract_state(serde_json::json!({ "n": 10 })).unwrap();
+    rt.call((), None).await.unwrap();
+
+    let value = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(value, serde_json::json!({ "n": 11 }));
+  }
+
+  #[tokio::test]
+  async fn test_runtime_json_state() {
+    let mut rt = Runtime::new(
+      r#"
+export async function handle(state, action) {
+  if (action.input === "overflow") {
+    state.small = 2n ** 70n;
+    return { state };
+  }
+  state.types = [typeof state.big, typeof state.negative, typeof state.max];
+  state.small = 1n;
+  return { state };
+}
+"#,
+      (),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+
+    // 2^53 + 1, a double would round it to 2^53.
+    let state = serde_json::json!({
+      "big": 9007199254740993u64,
+      "negative": -9007199254740993i64,
+      "max": u64::MAX,
+      "float": 9007199254740993.0,
+    });
+    rt.set_json_state(&state).unwrap();
+    rt.call(serde_json::json!({ "input": "types" }), None)
+      .await
+      .unwrap();
+
+    let value = rt.json_state().unwrap();
+    assert_eq!(value["big"], state["big"]);
+    assert_eq!(value["negative"], state["negative"]);
+    assert_eq!(value["max"], state["max"]);
+    assert_eq!(value["float"], serde_json::json!(9007199254740992.0));
+    assert_eq!(
+      value["types"],
+      serde_json::json!(["bigint", "bigint", "bigint"])
+    );
+    assert_eq!(value["small"], serde_json::json!(1));
+
+    // A small BigInt is handed back as a number.
+    let small = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(small["small"], serde_json::json!(1));
+
+    rt.call(serde_json::json!({ "input": "overflow" }), None)
+      .await
+      .unwrap();
+    assert!(rt.json_state().is_err());
+  }
+
+  #[tokio::test]
+  async fn test_runtime_has_handler() {
+    let mut rt = Runtime::new(
+      "export async function handle() { return { state: -69 } }",
+      (),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    assert!(rt.has_handler());
+
+    let mut rt = Runtime::new(
+      "export const handle = 1;",
+      (),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    assert!(!rt.has_handler());
+  }
+
+  #[tokio::test]
+  async fn test_runtime_init() {
+    let mut rt = Runtime::new(
+      "export async function init(state) { return { n: state.n * 2 } }",
+      serde_json::json!({ "n": 21 }),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    assert!(rt.init().await.unwrap());
+    let value = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(value, serde_json::json!({ "n": 42 }));
+
+    // Mutating the state in place is enough.
+    let mut rt = Runtime::new(
+      "export function init(state) { state.ready = true }",
+      serde_json::json!({}),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    assert!(rt.init().await.unwrap());
+    let value = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(value, serde_json::json!({ "ready": true }));
+
+    let mut rt = Runtime::new(
+      "export function handle() {}",
+      serde_json::json!({ "n": 1 }),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    assert!(!rt.init().await.unwrap());
+    let value = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(value, serde_json::json!({ "n": 1 }));
+  }
+
   #[tokio::test]
   async fn test_runtime_smartweave() {
     let buf: Vec<u8> = vec![0x00];
@@ -322,6 +705,7 @@ export async function handle(slice) {
       ZeroCopyBuf::from(buf),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -348,6 +732,7 @@ export async function handle() {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -374,6 +759,7 @@ export async function handle() {
       8,
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -406,6 +792,7 @@ export async function handle() {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -431,6 +818,7 @@ export async function handle() {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -450,7 +838,8 @@ export async function handle() {
   "#,
   (),
         (80, String::from("arweave.net"), String::from("https")),
-      never_op
+      never_op,
+      Default::default(),
       )
       .await
       .unwrap();
@@ -469,6 +858,40 @@ export async function handle() {
     }
   }
 
+  #[tokio::test]
+  async fn test_call_timeout() {
+    let mut rt = Runtime::new(
+      r#"
+export async function handle(state, action) {
+  while (action.spin) {}
+  return { state: { done: true } };
+}"#,
+      (),
+      (80, String::from("arweave.net"), String::from("https")),
+      never_op,
+      Default::default(),
+    )
+    .await
+    .unwrap();
+    let timeout = Duration::from_millis(100);
+    rt.set_timeout(Some(timeout));
+
+    let err = rt
+      .call(serde_json::json!({ "spin": true }), None)
+      .await
+      .unwrap_err()
+      .downcast::<Error>()
+      .unwrap();
+    assert_eq!(err, Error::TimedOut { timeout });
+
+    // The isolate runs calls that finish in time again.
+    rt.call(serde_json::json!({ "spin": false }), None)
+      .await
+      .unwrap();
+    let state = rt.get_contract_state::<Value>().unwrap();
+    assert_eq!(state, serde_json::json!({ "done": true }));
+  }
+
   #[tokio::test]
   async fn test_contract_evolve() {
     let mut rt = Runtime::new(
@@ -479,6 +902,7 @@ export async function handle() {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -498,6 +922,7 @@ export async function handle() {
       (),
       (12345, String::from("arweave.net"), String::from("http")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -507,6 +932,58 @@ export async function handle() {
     assert_eq!(host, "http://arweave.net:12345");
   }
 
+  #[tokio::test]
+  async fn test_smartweave_wallet_balance_disabled() {
+    let mut rt = Runtime::new(
+      r#"
+export async function handle() {
+  try {
+    await SmartWeave.arweave.wallets.getBalance("address");
+    return { state: "enabled" };
+  } catch(e) {
+    return { state: e.toString() };
+  }
+}
+"#,
+      (),
+      (443, String::from("arweave.net"), String::from("https")),
+      never_op,
+      RuntimeSettings::default(),
+    )
+    .await
+    .unwrap();
+
+    rt.call((), None).await.unwrap();
+    let result = rt.get_contract_state::<String>().unwrap();
+    assert!(result.contains("getBalance is disabled"));
+  }
+
+  #[tokio::test]
+  async fn test_smartweave_unsafe_client_disabled() {
+    let mut rt = Runtime::new(
+      r#"
+export async function handle() {
+  try {
+    await SmartWeave.unsafeClient.transactions.getData("tx");
+    return { state: "enabled" };
+  } catch(e) {
+    return { state: e.toString() };
+  }
+}
+"#,
+      (),
+      (443, String::from("arweave.net"), String::from("https")),
+      never_op,
+      RuntimeSettings::default(),
+    )
+    .await
+    .unwrap();
+
+    rt.call((), None).await.unwrap();
+    let result = rt.get_contract_state::<String>().unwrap();
+    assert!(result.contains("unsafeClient is disabled"));
+  }
+
   #[tokio::test]
   async fn test_smartweave_get_tx() {
     let mut rt = Runtime::new(
@@ -530,6 +1007,10 @@ export async function handle() {
       (),
       (443, String::from("arweave.net"), String::from("https")),
       never_op,
+      RuntimeSettings {
+        allow_unsafe_client: true,
+        ..Default::default()
+      },
     )
         .await
         .unwrap();
@@ -566,7 +1047,11 @@ try {
 "#,
       (),
       (443, String::from("arweave.net"), String::from("https")),
-      never_op
+      never_op,
+      RuntimeSettings {
+        allow_unsafe_client: true,
+        ..Default::default()
+      },
     )
         .await
         .unwrap();
@@ -597,6 +1082,7 @@ export async function handle() {
       (),
       (80, String::from("arweave.net"), String::from("https")),
       never_op,
+      Default::default(),
     )
     .await
     .unwrap();
@@ -0,0 +1,79 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use three_em_smartweave::Waiting;
+
+enum Command {
+  Watch(Duration, Waiting),
+  Stop,
+}
+
+/// Terminates the code running on an isolate once a call has taken longer
+/// than its timeout, from a thread of its own: a contract stuck in a loop
+/// never yields back to the one running it. The thread watches one call at
+/// a time, for as long as the watchdog lives.
+pub struct Watchdog {
+  commands: mpsc::Sender<Command>,
+  /// Whether each watched call was terminated, once it is stopped.
+  stopped: mpsc::Receiver<bool>,
+}
+
+impl Watchdog {
+  pub fn new(isolate: v8::IsolateHandle) -> Watchdog {
+    let (commands, received) = mpsc::channel();
+    let (stopped_tx, stopped) = mpsc::channel();
+    thread::spawn(move || {
+      while let Ok(Command::Watch(timeout, waiting)) = received.recv() {
+        let fired = watch(&isolate, timeout, &waiting, &received);
+        if stopped_tx.send(fired).is_err() {
+          return;
+        }
+      }
+    });
+
+    Watchdog { commands, stopped }
+  }
+
+  /// Starts watching a call. Time spent in `waiting` doesn't count towards
+  /// `timeout`.
+  pub fn start(&self, timeout: Duration, waiting: Waiting) {
+    let _ = self.commands.send(Command::Watch(timeout, waiting));
+  }
+
+  /// Ends the watch once the call returned. Whether the isolate was
+  /// terminated, in which case it is made usable again; the call may have
+  /// finished just in time, but is counted as timed out all the same.
+  pub fn stop(&self, isolate: &mut v8::Isolate) -> bool {
+    let _ = self.commands.send(Command::Stop);
+    let fired = self.stopped.recv().unwrap_or(false);
+    if fired {
+      isolate.cancel_terminate_execution();
+    }
+    fired
+  }
+}
+
+/// Waits for the watched call to be stopped, terminating it if that takes
+/// longer than `timeout`. Whether it was terminated.
+fn watch(
+  isolate: &v8::IsolateHandle,
+  timeout: Duration,
+  waiting: &Waiting,
+  received: &mpsc::Receiver<Command>,
+) -> bool {
+  let start = Instant::now();
+  loop {
+    // While waiting, the deadline moves along with the time.
+    let deadline = start + timeout + waiting.elapsed();
+    let now = Instant::now();
+    if deadline <= now {
+      isolate.terminate_execution();
+      let _ = received.recv();
+      return true;
+    }
+    match received.recv_timeout(deadline - now) {
+      Err(mpsc::RecvTimeoutError::Timeout) => {}
+      _ => return false,
+    }
+  }
+}
@@ -0,0 +1,100 @@
+use crate::InteractionContext;
+
+/// `Date.now()` in JavaScript contracts, unless configured otherwise:
+/// 2016-11-18 00:00:00.000.
+pub const DEFAULT_CLOCK_MS: u64 = 1479427200000;
+
+/// The seed JavaScript contracts have always drawn `Math.random()` from.
+pub const DEFAULT_SEED: u32 = 2963527434;
+
+/// Where contracts read the time and draw random numbers from, in every
+/// engine.
+///
+/// JavaScript contracts see it through `Date`, `new Date()` and
+/// `Math.random()`. WASM contracts see it through the WASI `clock_time_get`
+/// and `random_get` imports, which are only linked outside of the WASI
+/// sandbox.
+///
+/// Evaluations are reproducible with any context, but two contexts give
+/// different results. The default is what contracts have always seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeterminismContext {
+  pub clock: Clock,
+  pub seed: Seed,
+}
+
+impl Default for DeterminismContext {
+  fn default() -> Self {
+    Self {
+      clock: Clock::Fixed(DEFAULT_CLOCK_MS),
+      seed: Seed::Fixed(DEFAULT_SEED),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+  /// The same time, in milliseconds since the epoch, for every
+  /// interaction.
+  Fixed(u64),
+  /// The timestamp of the block the interaction was mined in.
+  Block,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Seed {
+  /// The same seed for every interaction.
+  Fixed(u32),
+  /// A seed derived from the interaction id, so that each interaction
+  /// draws different numbers.
+  Interaction,
+}
+
+impl DeterminismContext {
+  /// The time `context` is evaluated at, in milliseconds since the epoch.
+  pub fn now_ms(&self, context: &InteractionContext) -> u64 {
+    match self.clock {
+      Clock::Fixed(ms) => ms,
+      Clock::Block => context.block.timestamp as u64 * 1000,
+    }
+  }
+
+  /// The seed random numbers are drawn from while evaluating `context`.
+  pub fn seed(&self, context: &InteractionContext) -> u32 {
+    match self.seed {
+      Seed::Fixed(seed) => seed,
+      // FNV-1a, stable across platforms and releases.
+      Seed::Interaction => context
+        .transaction
+        .id
+        .bytes()
+        .fold(0x811c9dc5, |hash: u32, byte| {
+          (hash ^ byte as u32).wrapping_mul(0x01000193)
+        }),
+    }
+  }
+}
+
+/// The xorshift32 generator behind WASI `random_get`.
+pub struct SeededRng(u32);
+
+impl SeededRng {
+  pub fn new(seed: u32) -> Self {
+    // xorshift never leaves zero.
+    Self(if seed == 0 { DEFAULT_SEED } else { seed })
+  }
+
+  /// Where the generator is at, to resume from with [`SeededRng::new`].
+  pub fn state(&self) -> u32 {
+    self.0
+  }
+
+  pub fn fill(&mut self, buf: &mut [u8]) {
+    for byte in buf {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 17;
+      self.0 ^= self.0 << 5;
+      *byte = (self.0 >> 24) as u8;
+    }
+  }
+}
@@ -0,0 +1,77 @@
+//! Ops behind `SmartWeave.extensions`, the host functions some contracts
+//! expect next to the SmartWeave API, like hashes that WebCrypto doesn't
+//! have or synchronous versions of those it does.
+//!
+//! Every extension returns the same result on every node, except
+//! `randomBytes`, which needs [`RuntimeSettings::allow_random_extension`].
+
+use crate::RuntimeSettings;
+use deno_core::error::AnyError;
+use deno_core::OpState;
+use deno_core::ZeroCopyBuf;
+use deno_crypto::rand::rngs::OsRng;
+use deno_crypto::rand::RngCore;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+use tiny_keccak::{Hasher, Keccak};
+
+/// The most `randomBytes` hands out at once, like `crypto.getRandomValues`.
+const MAX_RANDOM_BYTES: usize = 65536;
+
+/// Keccak-256, as in Ethereum, rather than the standardized SHA3-256.
+pub fn op_smartweave_keccak256(
+  _state: &mut OpState,
+  data: ZeroCopyBuf,
+  _: (),
+) -> Result<ZeroCopyBuf, AnyError> {
+  let mut hasher = Keccak::v256();
+  let mut digest = [0; 32];
+  hasher.update(&data);
+  hasher.finalize(&mut digest);
+  Ok(digest.to_vec().into())
+}
+
+pub fn op_smartweave_sha256(
+  _state: &mut OpState,
+  data: ZeroCopyBuf,
+  _: (),
+) -> Result<ZeroCopyBuf, AnyError> {
+  Ok(Sha256::digest(&data).to_vec().into())
+}
+
+/// Whether `signature` is the Ed25519 signature of `message` by
+/// `public_key`. A key or a signature of the wrong length is a signature
+/// that doesn't verify, not an error.
+pub fn op_smartweave_ed25519_verify(
+  _state: &mut OpState,
+  (public_key, signature): (ZeroCopyBuf, ZeroCopyBuf),
+  message: ZeroCopyBuf,
+) -> Result<bool, AnyError> {
+  Ok(
+    UnparsedPublicKey::new(&ED25519, &*public_key)
+      .verify(&message, &signature)
+      .is_ok(),
+  )
+}
+
+pub fn op_smartweave_random_bytes(
+  state: &mut OpState,
+  length: usize,
+  _: (),
+) -> Result<ZeroCopyBuf, AnyError> {
+  if !state.borrow::<RuntimeSettings>().allow_random_extension {
+    return Err(AnyError::msg(
+      "SmartWeave.extensions.randomBytes is disabled",
+    ));
+  }
+  if length > MAX_RANDOM_BYTES {
+    return Err(AnyError::msg(format!(
+      "SmartWeave.extensions.randomBytes is limited to {} bytes",
+      MAX_RANDOM_BYTES
+    )));
+  }
+
+  let mut bytes = vec![0; length];
+  OsRng.fill_bytes(&mut bytes);
+  Ok(bytes.into())
+}
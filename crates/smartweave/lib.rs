@@ -9,17 +9,87 @@ use deno_core::Extension;
 use deno_core::OpState;
 use deno_core::ZeroCopyBuf;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::future::Future;
 use std::rc::Rc;
 use std::{env, thread};
+use three_em_arweave::arweave::Arweave;
 use three_em_arweave::gql_result::GQLTagInterface;
 
+mod determinism;
+mod extensions;
+mod waiting;
+
+pub use determinism::{
+  Clock, DeterminismContext, Seed, SeededRng, DEFAULT_CLOCK_MS, DEFAULT_SEED,
+};
+pub use waiting::{WaitGuard, Waiting};
+
 pub struct ArweaveInfo {
   pub port: i32,
   pub host: String,
   pub protocol: String,
 }
 
+/// Host capabilities exposed to contracts.
+///
+/// Everything in here is opt-in: these bindings reach out to the network
+/// and can make evaluation non-deterministic across nodes.
+#[derive(Clone, Default)]
+pub struct RuntimeSettings {
+  /// Expose `SmartWeave.arweave.wallets.getBalance(address)`.
+  ///
+  /// The gateway only reports the *current* balance of a wallet, not the
+  /// balance at the height being evaluated. Balances are memoized for the
+  /// lifetime of the runtime so every interaction in one evaluation sees
+  /// the same value, but two evaluations run at different times may not
+  /// agree. Only enable this for contracts that accept that trade-off.
+  pub allow_wallet_balance: bool,
+  /// Expose `SmartWeave.unsafeClient`.
+  ///
+  /// The unsafe client reads arbitrary transactions from the gateway, so a
+  /// contract using it depends on whatever the gateway serves at evaluation
+  /// time. When disabled, touching `SmartWeave.unsafeClient` throws and the
+  /// interaction is marked invalid.
+  pub allow_unsafe_client: bool,
+  /// Expose `SmartWeave.extensions.randomBytes(length)`.
+  ///
+  /// Its bytes come from the operating system, unlike those of
+  /// `crypto.getRandomValues`, which are seeded, so no two nodes draw the
+  /// same ones. When disabled, calling it throws and the interaction is
+  /// marked invalid. The other extensions are always available.
+  pub allow_random_extension: bool,
+}
+
+fn check_unsafe_client(state: &OpState) -> Result<(), AnyError> {
+  if state.borrow::<RuntimeSettings>().allow_unsafe_client {
+    Ok(())
+  } else {
+    Err(AnyError::msg("SmartWeave.unsafeClient is disabled"))
+  }
+}
+
+/// The gateway client of the evaluation, so that requests go to the same
+/// gateway, with the same headers and download budget. Runtimes without one
+/// get a client for their [`ArweaveInfo`].
+fn client(state: &OpState) -> Arweave {
+  match state.try_borrow::<Arweave>() {
+    Some(client) => client.clone(),
+    None => {
+      let arweave = state.borrow::<ArweaveInfo>();
+      Arweave::new_no_cache(
+        arweave.port,
+        arweave.host.to_owned(),
+        arweave.protocol.to_owned(),
+      )
+    }
+  }
+}
+
+/// Wallet balances fetched during the lifetime of a runtime.
+#[derive(Default)]
+struct WalletBalances(HashMap<String, String>);
+
 #[derive(Serialize, Default, Clone)]
 pub struct InteractionTx {
   pub id: String,
@@ -43,9 +113,19 @@ pub struct InteractionContext {
   pub block: InteractionBlock,
 }
 
+/// The contract being evaluated, exposed as `SmartWeave.contract`.
+#[derive(Serialize, Default, Clone)]
+pub struct ContractInfo {
+  pub id: String,
+  /// The block the contract was deployed in, as opposed to the block of
+  /// the interaction being evaluated. `None` while it isn't mined.
+  pub block: Option<InteractionBlock>,
+}
+
 pub fn init<F, R>(
   arweave: (i32, String, String),
   op_smartweave_read_contract: F,
+  settings: RuntimeSettings,
 ) -> Extension
 where
   F: Fn(Rc<RefCell<OpState>>, (String, Option<usize>, Option<bool>), ()) -> R
@@ -74,6 +154,10 @@ where
         "op_smartweave_get_tx_data",
         op_async(op_smartweave_get_tx_data),
       ),
+      (
+        "op_smartweave_unsafe_client_check",
+        op_sync(op_smartweave_unsafe_client_check),
+      ),
       (
         "op_smartweave_unsafe_exit_process",
         op_sync(op_smartweave_unsafe_exit_process),
@@ -83,6 +167,22 @@ where
         op_async(op_smartweave_read_contract),
       ),
       ("op_smartweave_get_tx", op_async(op_smartweave_get_tx)),
+      (
+        "op_smartweave_keccak256",
+        op_sync(extensions::op_smartweave_keccak256),
+      ),
+      (
+        "op_smartweave_sha256",
+        op_sync(extensions::op_smartweave_sha256),
+      ),
+      (
+        "op_smartweave_ed25519_verify",
+        op_sync(extensions::op_smartweave_ed25519_verify),
+      ),
+      (
+        "op_smartweave_random_bytes",
+        op_sync(extensions::op_smartweave_random_bytes),
+      ),
     ])
     .state(move |state| {
       let (port, host, protocol) = arweave.clone();
@@ -91,11 +191,21 @@ where
         host,
         protocol,
       });
+      state.put(settings.clone());
+      state.put(WalletBalances::default());
       Ok(())
     })
     .build()
 }
 
+pub fn op_smartweave_unsafe_client_check(
+  state: &mut OpState,
+  _: (),
+  _: (),
+) -> Result<(), AnyError> {
+  check_unsafe_client(state)
+}
+
 pub fn op_smartweave_unsafe_exit_process(
   _state: &mut OpState,
   _: (),
@@ -106,69 +216,85 @@ pub fn op_smartweave_unsafe_exit_process(
 }
 
 pub async fn op_smartweave_wallet_balance(
-  _state: Rc<RefCell<OpState>>,
+  state: Rc<RefCell<OpState>>,
   address: String,
   _: (),
 ) -> Result<String, AnyError> {
-  let s = _state.borrow();
+  let (client, waiting) = {
+    let s = state.borrow();
+    if !s.borrow::<RuntimeSettings>().allow_wallet_balance {
+      return Err(AnyError::msg(
+        "SmartWeave.arweave.wallets.getBalance is disabled",
+      ));
+    }
 
-  let arweave = s.borrow::<ArweaveInfo>();
+    if let Some(balance) = s.borrow::<WalletBalances>().0.get(&address) {
+      return Ok(balance.to_owned());
+    }
+
+    // The timeout of the runtime only counts its own code.
+    (client(&s), s.try_borrow::<Waiting>().map(Waiting::enter))
+  };
 
   // Winston string
-  let balance =
-    reqwest::get(format!("{}/wallet/{}/balance", get_host(arweave), address))
-      .await?
-      .text()
-      .await?;
+  let balance = client.get_wallet_balance(&address).await?;
+  drop(waiting);
+  state
+    .borrow_mut()
+    .borrow_mut::<WalletBalances>()
+    .0
+    .insert(address, balance.to_owned());
+
   Ok(balance)
 }
 
 pub async fn op_smartweave_wallet_last_tx(
-  _state: Rc<RefCell<OpState>>,
+  state: Rc<RefCell<OpState>>,
   address: String,
   _: (),
 ) -> Result<String, AnyError> {
-  let s = _state.borrow();
-  let arweave = s.borrow::<ArweaveInfo>();
+  // The op state isn't borrowed across the request: other ops awaited
+  // alongside this one, as in a `Promise.all`, may borrow it mutably.
+  let (host, _waiting) = {
+    let s = state.borrow();
+    let host = get_host(s.borrow::<ArweaveInfo>());
+    (host, s.try_borrow::<Waiting>().map(Waiting::enter))
+  };
 
-  let tx =
-    reqwest::get(format!("{}/wallet/{}/last_tx", get_host(arweave), address))
-      .await?
-      .text()
-      .await?;
+  let tx = reqwest::get(format!("{}/wallet/{}/last_tx", host, address))
+    .await?
+    .text()
+    .await?;
   Ok(tx)
 }
 
 pub async fn op_smartweave_get_tx_data(
-  _state: Rc<RefCell<OpState>>,
+  state: Rc<RefCell<OpState>>,
   tx_id: String,
   _: (),
 ) -> Result<ZeroCopyBuf, AnyError> {
-  let s = _state.borrow();
-  let arweave = s.borrow::<ArweaveInfo>();
+  let (client, _waiting) = {
+    let s = state.borrow();
+    check_unsafe_client(&s)?;
+    (client(&s), s.try_borrow::<Waiting>().map(Waiting::enter))
+  };
 
-  let req = reqwest::get(format!("{}/{}", get_host(arweave), tx_id))
-    .await?
-    .bytes()
-    .await?;
-
-  Ok(req.to_vec().into())
+  let data = client.get_transaction_data(&tx_id).await?;
+  Ok(data.into())
 }
 
 pub async fn op_smartweave_get_tx(
-  _state: Rc<RefCell<OpState>>,
+  state: Rc<RefCell<OpState>>,
   tx_id: String,
   _: (),
 ) -> Result<String, AnyError> {
-  let s = _state.borrow();
-  let arweave = s.borrow::<ArweaveInfo>();
-
-  let req = reqwest::get(format!("{}/tx/{}", get_host(arweave), tx_id))
-    .await?
-    .text()
-    .await?;
+  let (client, _waiting) = {
+    let s = state.borrow();
+    check_unsafe_client(&s)?;
+    (client(&s), s.try_borrow::<Waiting>().map(Waiting::enter))
+  };
 
-  Ok(req)
+  client.get_transaction_json(&tx_id).await
 }
 
 pub fn read_contract_state(id: String) -> Value {
@@ -278,6 +278,45 @@
     }
   }
 
+  // Strings are hashed and verified as UTF-8.
+  function toBytes(data) {
+    if (typeof data === "string") {
+      return new TextEncoder().encode(data);
+    }
+    if (data instanceof ArrayBuffer) {
+      return new Uint8Array(data);
+    }
+    if (ArrayBuffer.isView(data)) {
+      return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+    }
+    throw new TypeError("Expected a string, an ArrayBuffer or a typed array");
+  }
+
+  // Host functions some contracts expect, synchronous and returning the
+  // same result on every node. Only `randomBytes` doesn't, and it throws
+  // unless the node allows it.
+  class Extensions {
+    keccak256(data) {
+      return Deno.core.opSync("op_smartweave_keccak256", toBytes(data));
+    }
+
+    sha256(data) {
+      return Deno.core.opSync("op_smartweave_sha256", toBytes(data));
+    }
+
+    ed25519Verify(publicKey, message, signature) {
+      return Deno.core.opSync(
+        "op_smartweave_ed25519_verify",
+        [toBytes(publicKey), toBytes(signature)],
+        toBytes(message),
+      );
+    }
+
+    randomBytes(length) {
+      return Deno.core.opSync("op_smartweave_random_bytes", length);
+    }
+  }
+
   class Arweave {
     /** @deprecated */
     get crypto() {
@@ -307,6 +346,12 @@
       return getInteraction().block;
     }
 
+    get contract() {
+      return {
+        ...globalThis.contractInfo
+      };
+    }
+
     get arweave() {
       return new Arweave();
     }
@@ -315,7 +360,13 @@
       return new Contracts();
     }
 
+    get extensions() {
+      return new Extensions();
+    }
+
     get unsafeClient() {
+      Deno.core.opSync("op_smartweave_unsafe_client_check");
+
       const txGetData = async (txId, opts) => {
         try {
           const arweave = this.arweave;
@@ -399,21 +450,36 @@
   // JSON.stringify is deterministic. Not action required there.
   // https://github.com/nodejs/node/issues/15628#issuecomment-332588533
 
+  // `currentClock` and `currentSeed` are set by the runtime before each
+  // interaction, from its `DeterminismContext`. These are the defaults.
+  const defaultClock = 1479427200000; // 2016-11-18 00:00:00.000
+  const defaultSeed = 2963527434; // 0.69 * 2^32
+
+  function now() {
+    return globalThis.currentClock ?? defaultClock;
+  }
+
   // xorshift128+ RNG adapted from https://github.com/AndreasMadsen/xorshift
-  const s = 0.69 * Math.pow(2, 32);
-  const seed = [
-    s,
-    s,
-    s,
-    s,
-  ];
-  // uint64_t s = [seed ...]
-  let _state0U = seed[0] | 0;
-  let _state0L = seed[1] | 0;
-  let _state1U = seed[2] | 0;
-  let _state1L = seed[3] | 0;
+  let _seed;
+  let _state0U, _state0L, _state1U, _state1L;
+
+  // The generator carries on from one interaction to the next, until the
+  // seed changes.
+  function reseed() {
+    const s = globalThis.currentSeed ?? defaultSeed;
+    if (s === _seed) {
+      return;
+    }
+    _seed = s;
+    // uint64_t s = [seed ...]
+    _state0U = s | 0;
+    _state0L = s | 0;
+    _state1U = s | 0;
+    _state1L = s | 0;
+  }
 
   Math.random = function () {
+    reseed();
     // uint64_t s1 = s[0]
     var s1U = _state0U, s1L = _state0L;
     // uint64_t s0 = s[1]
@@ -474,7 +540,7 @@
 
   const clonedDate = Date;
   function NewDate(...args) {
-    const dateArgs = args.length === 0 ? [1479427200000] : args;
+    const dateArgs = args.length === 0 ? [now()] : args;
     const instance = new clonedDate(...dateArgs);
     Object.setPrototypeOf(instance, Object.getPrototypeOf(NewDate.prototype));
     return instance;
@@ -483,7 +549,7 @@
   NewDate.prototype = Object.create(Date.prototype);
   Object.setPrototypeOf(NewDate, Date);
 
-  NewDate.now = () => 1479427200000; // 2016-11-18 00:00:00.000
+  NewDate.now = now;
 
   Date = NewDate;
 
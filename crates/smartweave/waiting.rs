@@ -0,0 +1,52 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The time a runtime has spent waiting on something other than its own
+/// code, like the contracts it reads, which its timeout doesn't count. Kept
+/// in the op state of the runtime, for ops to [`Waiting::enter`].
+#[derive(Clone, Default)]
+pub struct Waiting(Arc<Mutex<WaitTime>>);
+
+#[derive(Default)]
+struct WaitTime {
+  /// Waits in progress, and since when there has been one.
+  count: usize,
+  since: Option<Instant>,
+  total: Duration,
+}
+
+impl Waiting {
+  /// Stops the clock until the returned guard is dropped.
+  pub fn enter(&self) -> WaitGuard {
+    let mut time = self.0.lock().unwrap();
+    if time.count == 0 {
+      time.since = Some(Instant::now());
+    }
+    time.count += 1;
+    WaitGuard(self.clone())
+  }
+
+  /// The time waited so far, the ongoing wait included.
+  pub fn elapsed(&self) -> Duration {
+    let time = self.0.lock().unwrap();
+    match time.since {
+      Some(since) => time.total + since.elapsed(),
+      None => time.total,
+    }
+  }
+}
+
+/// See [`Waiting::enter`].
+pub struct WaitGuard(Waiting);
+
+impl Drop for WaitGuard {
+  fn drop(&mut self) {
+    let mut time = (self.0).0.lock().unwrap();
+    time.count -= 1;
+    if time.count == 0 {
+      if let Some(since) = time.since.take() {
+        time.total += since.elapsed();
+      }
+    }
+  }
+}
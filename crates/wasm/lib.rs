@@ -1,23 +1,86 @@
-use deno_core::error::AnyError;
+use deno_core::error::{generic_error, AnyError};
 use deno_core::serde_json::Value;
 use deno_core::JsRuntime;
 use deno_core::RuntimeOptions;
 use std::cell::Cell;
-use three_em_js::{snapshot, Error};
-use three_em_smartweave::{read_contract_state, InteractionContext};
+use std::time::Duration;
+use three_em_js::snapshot;
+use three_em_js::watchdog::Watchdog;
+use three_em_smartweave::{
+  read_contract_state, DeterminismContext, InteractionContext, SeededRng,
+  Waiting,
+};
+
+#[cfg(feature = "wasmtime")]
+mod wasmtime_engine;
+
+#[cfg(feature = "wasmtime")]
+pub use wasmtime_engine::WasmtimeRuntime;
+
+/// The engine contracts are evaluated with. Building with the `wasmtime`
+/// feature swaps V8's WebAssembly for Wasmtime.
+#[cfg(not(feature = "wasmtime"))]
+pub type WasmRuntime = V8WasmRuntime;
+#[cfg(feature = "wasmtime")]
+pub type WasmRuntime = WasmtimeRuntime;
+
+/// Why a call failed against the limits of its runtime.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+  /// A call ran for longer than its timeout, and was terminated.
+  TimedOut { timeout: Duration },
+  /// A call left the contract memory larger than its limit.
+  MemoryLimitExceeded { limit: usize },
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::TimedOut { timeout } => {
+        write!(f, "Interaction timed out after {} ms", timeout.as_millis())
+      }
+      Error::MemoryLimitExceeded { limit } => write!(
+        f,
+        "Interaction grew the contract memory over its limit of {} bytes",
+        limit
+      ),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
 
+/// Calls the contract's allocator, `None` if it threw.
 macro_rules! wasm_alloc {
   ($scope: expr, $alloc: expr, $this: expr, $len: expr) => {
-    $alloc.call($scope, $this.into(), &[$len.into()]).unwrap()
+    $alloc.call($scope, $this.into(), &[$len.into()])
   };
 }
 
-pub struct WasmRuntime {
+const OUT_OF_BOUNDS: &str = "WebAssembly memory access out of bounds";
+
+/// Builds the `wasi_snapshot_preview1` imports of a module evaluated with a
+/// WASI sandbox: every function it imports from there throws when called.
+const DENIED_WASI: &str = r#"(module) => Object.fromEntries(
+  WebAssembly.Module.imports(module)
+    .filter(({ module, kind }) =>
+      module === "wasi_snapshot_preview1" && kind === "function")
+    .map(({ name }) => [name, () => {
+      throw new Error(`WASI call ${name} is denied`);
+    }]),
+)"#;
+
+/// Runs contracts on V8's WebAssembly engine.
+pub struct V8WasmRuntime {
   rt: JsRuntime,
   /// The contract handler.
   /// `handle(state_ptr, state_len, action_ptr, action_len) -> result_ptr`
-  /// Length of the result can be obtained by calling `WasmRuntime::result_len`.
+  /// Length of the result can be obtained by calling
+  /// `V8WasmRuntime::result_len`.
   handle: v8::Global<v8::Function>,
+  /// The optional initialization function, see [`V8WasmRuntime::init`].
+  /// `init(state_ptr, state_len) -> result_ptr`
+  init: Option<v8::Global<v8::Function>>,
   /// The length of the updated state.
   /// `get_len() -> usize`
   result_len: v8::Global<v8::Function>,
@@ -26,17 +89,44 @@ pub struct WasmRuntime {
   allocator: v8::Global<v8::Function>,
   /// `WebAssembly.Instance.exports` object.
   exports: v8::Global<v8::Object>,
+  determinism: DeterminismContext,
+  /// See [`V8WasmRuntime::set_gas_limit`].
+  gas_limit: Option<usize>,
+  /// See [`V8WasmRuntime::set_timeout`].
+  timeout: Option<Duration>,
+  /// Started by the first call with a timeout.
+  watchdog: Option<Watchdog>,
+  /// See [`V8WasmRuntime::set_memory_limit`].
+  memory_limit: Option<usize>,
 }
 
-impl WasmRuntime {
-  pub fn new(wasm: &[u8]) -> Result<WasmRuntime, AnyError> {
+impl V8WasmRuntime {
+  pub fn new(wasm: &[u8]) -> Result<V8WasmRuntime, AnyError> {
+    Self::with_wasi_sandbox(wasm, false)
+  }
+
+  /// Like [`V8WasmRuntime::new`], but with `sandbox_wasi` the module gets a
+  /// WASI context without any capability: no filesystem or standard
+  /// streams, no clock and no randomness. Any `wasi_snapshot_preview1`
+  /// function it imports can be linked, and calling one fails the
+  /// interaction.
+  ///
+  /// Without it, only no-op `fd_close`, `fd_seek` and `fd_write`, and
+  /// `clock_time_get` and `random_get` driven by the runtime's
+  /// [`DeterminismContext`] are provided. Modules importing anything else
+  /// can't be instantiated.
+  pub fn with_wasi_sandbox(
+    wasm: &[u8],
+    sandbox_wasi: bool,
+  ) -> Result<V8WasmRuntime, AnyError> {
     let mut rt = JsRuntime::new(RuntimeOptions {
       startup_snapshot: Some(snapshot::snapshot()),
       ..Default::default()
     });
     // Get hold of the WebAssembly object.
     let wasm_obj = rt.execute_script("<anon>", "WebAssembly").unwrap();
-    let (exports, handle, allocator, result_len) = {
+    let denied_wasi = rt.execute_script("<anon>", DENIED_WASI).unwrap();
+    let (exports, handle, init, allocator, result_len) = {
       let scope = &mut rt.handle_scope();
       let buf =
         v8::ArrayBuffer::new_backing_store_from_boxed_slice(wasm.into());
@@ -53,7 +143,7 @@ impl WasmRuntime {
 
       let module = module_constructor
         .new_instance(scope, &[buf.into()])
-        .unwrap();
+        .ok_or_else(|| generic_error("Invalid WebAssembly module"))?;
 
       // Create a new WebAssembly.Instance object.
       let instance_str = v8::String::new(scope, "Instance").unwrap();
@@ -122,12 +212,15 @@ impl WasmRuntime {
           .int32_value(scope)
           .unwrap();
 
-        let tx_bytes = unsafe {
+        let tx_bytes = match unsafe {
           get_backing_store_slice_mut(
             &store,
             tx_id_ptr as usize,
             tx_id_len as usize,
           )
+        } {
+          Some(tx_bytes) => tx_bytes,
+          None => return throw_out_of_bounds(scope),
         };
 
         let length_ptr = args
@@ -137,8 +230,11 @@ impl WasmRuntime {
           .int32_value(scope)
           .unwrap();
 
-        let len_bytes = unsafe {
+        let len_bytes = match unsafe {
           get_backing_store_slice_mut(&store, length_ptr as usize, 4)
+        } {
+          Some(len_bytes) => len_bytes,
+          None => return throw_out_of_bounds(scope),
         };
 
         let tx_id = String::from_utf8_lossy(tx_bytes).to_string();
@@ -150,14 +246,25 @@ impl WasmRuntime {
         len_bytes.swap_with_slice(&mut state_len);
 
         let state_len = v8::Number::new(scope, state.len() as f64);
-        let state_ptr = wasm_alloc!(scope, alloc, undefined, state_len);
+        // Exceptions thrown by the allocator propagate to the contract.
+        let state_ptr = match wasm_alloc!(scope, alloc, undefined, state_len) {
+          Some(state_ptr) => state_ptr,
+          None => return,
+        };
+        let state_ptr_u32 = match state_ptr.uint32_value(scope) {
+          Some(state_ptr_u32) => state_ptr_u32,
+          None => return,
+        };
 
-        let state_region = unsafe {
+        let state_region = match unsafe {
           get_backing_store_slice_mut(
             &store,
-            state_ptr.uint32_value(scope).unwrap() as usize,
+            state_ptr_u32 as usize,
             state.len(),
           )
+        } {
+          Some(state_region) => state_region,
+          None => return throw_out_of_bounds(scope),
         };
 
         state_region.swap_with_slice(&mut state);
@@ -189,6 +296,17 @@ impl WasmRuntime {
 
         let cost = v8::Number::new(scope, cost as f64);
         global.set(scope, cost_str.into(), cost.into()).unwrap();
+
+        // `GAS` is what the current call consumed, `GAS_LIMIT` what it may.
+        let gas = get_global_number(scope, "GAS") + inc as f64;
+        set_global_number(scope, "GAS", gas);
+        let gas_limit = get_global_number(scope, "GAS_LIMIT");
+        if gas > gas_limit {
+          let message = gas_limit_exceeded(gas_limit as usize);
+          let message = v8::String::new(scope, &message).unwrap();
+          let error = v8::Exception::error(scope, message);
+          scope.throw_exception(error);
+        }
       };
 
       let consume_gas_callback = v8::Function::new(scope, consume_gas).unwrap();
@@ -232,12 +350,15 @@ impl WasmRuntime {
           .int32_value(scope)
           .unwrap();
 
-        let error_bytes = unsafe {
+        let error_bytes = match unsafe {
           get_backing_store_slice_mut(
             &store,
             error_ptr as usize,
             error_len as usize,
           )
+        } {
+          Some(error_bytes) => error_bytes,
+          None => return throw_out_of_bounds(scope),
         };
 
         // throw error
@@ -254,50 +375,130 @@ impl WasmRuntime {
       imports.set(scope, ns_str.into(), ns.into());
 
       // wasi_snapshot_preview1
-      let wasi_ns = v8::Object::new(scope);
       let wasi_snapshot_preview1_str =
         v8::String::new(scope, "wasi_snapshot_preview1").unwrap();
+      let wasi_ns = if sandbox_wasi {
+        let denied_wasi = v8::Local::new(scope, &denied_wasi);
+        let denied_wasi = v8::Local::<v8::Function>::try_from(denied_wasi)?;
+        let undefined = v8::undefined(scope);
+        denied_wasi
+          .call(scope, undefined.into(), &[module.into()])
+          .ok_or_else(|| generic_error("Invalid WebAssembly module"))?
+      } else {
+        let wasi_ns = v8::Object::new(scope);
+
+        let wasi_fd_close = v8::String::new(scope, "fd_close").unwrap();
+        let wasi_fd_close_callback =
+          |_: &mut v8::HandleScope,
+           _: v8::FunctionCallbackArguments,
+           _: v8::ReturnValue| {
+            // No-op.
+          };
+        let wasi_fd_close_callback =
+          v8::Function::new(scope, wasi_fd_close_callback).unwrap();
+        wasi_ns.set(scope, wasi_fd_close.into(), wasi_fd_close_callback.into());
+
+        let wasi_fd_seek = v8::String::new(scope, "fd_seek").unwrap();
+        let wasi_fd_seek_callback =
+          |_: &mut v8::HandleScope,
+           _: v8::FunctionCallbackArguments,
+           _: v8::ReturnValue| {
+            // No-op.
+          };
+        let wasi_fd_seek_callback =
+          v8::Function::new(scope, wasi_fd_seek_callback).unwrap();
+        wasi_ns.set(scope, wasi_fd_seek.into(), wasi_fd_seek_callback.into());
+
+        let wasi_fd_write = v8::String::new(scope, "fd_write").unwrap();
+        let wasi_fd_write_callback =
+          |_: &mut v8::HandleScope,
+           _: v8::FunctionCallbackArguments,
+           _: v8::ReturnValue| {
+            // No-op.
+          };
+        let wasi_fd_write_callback =
+          v8::Function::new(scope, wasi_fd_write_callback).unwrap();
+        wasi_ns.set(scope, wasi_fd_write.into(), wasi_fd_write_callback.into());
+
+        // `clock_time_get(id, precision, time_ptr) -> errno`. Every clock
+        // reads `CLOCK`, set before each call.
+        let wasi_clock_time_get =
+          v8::String::new(scope, "clock_time_get").unwrap();
+        let wasi_clock_time_get_callback =
+          |scope: &mut v8::HandleScope,
+           args: v8::FunctionCallbackArguments,
+           mut rv: v8::ReturnValue| {
+            let clock_ms = get_global_number(scope, "CLOCK") as u64;
+            let time_ptr = args.get(2).uint32_value(scope).unwrap_or(0);
+            let store = match memory_of(scope) {
+              Some(store) => store,
+              None => return throw_out_of_bounds(scope),
+            };
+            let time = match unsafe {
+              get_backing_store_slice_mut(&store, time_ptr as usize, 8)
+            } {
+              Some(time) => time,
+              None => return throw_out_of_bounds(scope),
+            };
+            time.copy_from_slice(&(clock_ms * 1_000_000).to_le_bytes());
+            rv.set(v8::Integer::new(scope, 0).into());
+          };
+        let wasi_clock_time_get_callback =
+          v8::Function::new(scope, wasi_clock_time_get_callback).unwrap();
+        wasi_ns.set(
+          scope,
+          wasi_clock_time_get.into(),
+          wasi_clock_time_get_callback.into(),
+        );
+
+        // `random_get(buf, len) -> errno`, drawing from the generator state
+        // in `RANDOM`, seeded before each call.
+        let wasi_random_get = v8::String::new(scope, "random_get").unwrap();
+        let wasi_random_get_callback =
+          |scope: &mut v8::HandleScope,
+           args: v8::FunctionCallbackArguments,
+           mut rv: v8::ReturnValue| {
+            let random = get_global_number(scope, "RANDOM") as u32;
+            let buf_ptr = args.get(0).uint32_value(scope).unwrap_or(0);
+            let buf_len = args.get(1).uint32_value(scope).unwrap_or(0);
+            let store = match memory_of(scope) {
+              Some(store) => store,
+              None => return throw_out_of_bounds(scope),
+            };
+            let buf = match unsafe {
+              get_backing_store_slice_mut(
+                &store,
+                buf_ptr as usize,
+                buf_len as usize,
+              )
+            } {
+              Some(buf) => buf,
+              None => return throw_out_of_bounds(scope),
+            };
+            let mut rng = SeededRng::new(random);
+            rng.fill(buf);
+            set_global_number(scope, "RANDOM", rng.state() as f64);
+            rv.set(v8::Integer::new(scope, 0).into());
+          };
+        let wasi_random_get_callback =
+          v8::Function::new(scope, wasi_random_get_callback).unwrap();
+        wasi_ns.set(
+          scope,
+          wasi_random_get.into(),
+          wasi_random_get_callback.into(),
+        );
 
-      let wasi_fd_close = v8::String::new(scope, "fd_close").unwrap();
-      let wasi_fd_close_callback =
-        |_: &mut v8::HandleScope,
-         _: v8::FunctionCallbackArguments,
-         _: v8::ReturnValue| {
-          // No-op.
-        };
-      let wasi_fd_close_callback =
-        v8::Function::new(scope, wasi_fd_close_callback).unwrap();
-      wasi_ns.set(scope, wasi_fd_close.into(), wasi_fd_close_callback.into());
-
-      let wasi_fd_seek = v8::String::new(scope, "fd_seek").unwrap();
-      let wasi_fd_seek_callback =
-        |_: &mut v8::HandleScope,
-         _: v8::FunctionCallbackArguments,
-         _: v8::ReturnValue| {
-          // No-op.
-        };
-      let wasi_fd_seek_callback =
-        v8::Function::new(scope, wasi_fd_seek_callback).unwrap();
-      wasi_ns.set(scope, wasi_fd_seek.into(), wasi_fd_seek_callback.into());
-
-      let wasi_fd_write = v8::String::new(scope, "fd_write").unwrap();
-      let wasi_fd_write_callback =
-        |_: &mut v8::HandleScope,
-         _: v8::FunctionCallbackArguments,
-         _: v8::ReturnValue| {
-          // No-op.
-        };
-      let wasi_fd_write_callback =
-        v8::Function::new(scope, wasi_fd_write_callback).unwrap();
-      wasi_ns.set(scope, wasi_fd_write.into(), wasi_fd_write_callback.into());
-
-      imports.set(scope, wasi_snapshot_preview1_str.into(), wasi_ns.into());
+        wasi_ns.into()
+      };
+      imports.set(scope, wasi_snapshot_preview1_str.into(), wasi_ns);
 
       // << End wasi_snapshot_preview1
 
       let instance = instance_constructor
         .new_instance(scope, &[module.into(), imports.into()])
-        .unwrap();
+        .ok_or_else(|| {
+          generic_error("WebAssembly module could not be instantiated")
+        })?;
 
       let exports_str = v8::String::new(scope, "exports").unwrap();
       let exports = instance.get(scope, exports_str.into()).unwrap();
@@ -322,6 +523,12 @@ impl WasmRuntime {
       let handle = v8::Local::<v8::Function>::try_from(handle_obj)?;
       let handle = v8::Global::new(scope, handle);
 
+      let init_str = v8::String::new(scope, "init").unwrap();
+      let init = exports
+        .get(scope, init_str.into())
+        .and_then(|init| v8::Local::<v8::Function>::try_from(init).ok())
+        .map(|init| v8::Global::new(scope, init));
+
       let result_len_str = v8::String::new(scope, "get_len").unwrap();
 
       let result_len_obj = exports.get(scope, result_len_str.into()).unwrap();
@@ -334,18 +541,55 @@ impl WasmRuntime {
       let alloc_obj = allocator.open(scope).to_object(scope).unwrap();
       let alloc = v8::Local::<v8::Function>::try_from(alloc_obj)?;
 
-      (exports, handle, allocator, result_len)
+      (exports, handle, init, allocator, result_len)
     };
 
     Ok(Self {
       rt,
       handle,
+      init,
       allocator,
       result_len,
       exports,
+      determinism: DeterminismContext::default(),
+      gas_limit: None,
+      timeout: None,
+      watchdog: None,
+      memory_limit: None,
     })
   }
 
+  /// Sets what the WASI clock and `random_get` read in the next calls.
+  pub fn set_determinism(&mut self, determinism: DeterminismContext) {
+    self.determinism = determinism;
+  }
+
+  /// Caps the gas each of the next calls may consume through
+  /// `3em.consumeGas`, which metered modules call as they run. A call going
+  /// over it throws, failing the interaction. `None`, the default, lets
+  /// calls consume any amount.
+  pub fn set_gas_limit(&mut self, gas_limit: Option<usize>) {
+    self.gas_limit = gas_limit;
+  }
+
+  /// Terminates each of the next calls to the handler that runs for longer
+  /// than `timeout`, failing it with [`Error::TimedOut`]. `None`, the
+  /// default, lets calls run for as long as they take.
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout;
+  }
+
+  /// Fails each of the next calls to the handler that leaves the contract
+  /// memory larger than `memory_limit` bytes with
+  /// [`Error::MemoryLimitExceeded`]. Memory never shrinks, so the calls
+  /// after one that failed this way fail too; a fresh runtime starts from
+  /// the memory the module declares. `None`, the default, lets it grow as
+  /// far as WebAssembly allows.
+  pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+    self.memory_limit = memory_limit;
+  }
+
+  /// Gas consumed by every call so far.
   pub fn get_cost(&mut self) -> usize {
     let scope = &mut self.rt.handle_scope();
     let ctx = scope.get_current_context();
@@ -357,18 +601,139 @@ impl WasmRuntime {
     cost as usize
   }
 
+  /// Calls the contract's `init` export, if it has one, with `state` and
+  /// returns the state it derives. The length of the result is read from
+  /// `get_len`, as for the handler. `None` if the contract has no `init`
+  /// export.
+  ///
+  /// It is not called with an interaction, so the WASI clock and
+  /// `random_get` read what they would for an empty one.
+  pub fn init(
+    &mut self,
+    state: &mut [u8],
+  ) -> Result<Option<Vec<u8>>, AnyError> {
+    let init = match &self.init {
+      Some(init) => init.clone(),
+      None => return Ok(None),
+    };
+    let context = InteractionContext::default();
+    let clock_ms = self.determinism.now_ms(&context);
+    let seed = self.determinism.seed(&context);
+    let gas_limit = self.gas_limit;
+
+    let scope = &mut self.rt.handle_scope();
+    set_global_number(scope, "CLOCK", clock_ms as f64);
+    set_global_number(scope, "RANDOM", SeededRng::new(seed).state() as f64);
+    set_gas_limit(scope, gas_limit);
+    let scope = &mut v8::TryCatch::new(scope);
+    let undefined = v8::undefined(scope);
+
+    let alloc = v8::Local::new(scope, &self.allocator);
+    let state_len = v8::Number::new(scope, state.len() as f64);
+    let state_ptr = wasm_alloc!(scope, alloc, undefined, state_len);
+    let state_ptr_u32 = to_u32(scope, state_ptr)?;
+
+    let store = memory_of(scope)
+      .ok_or_else(|| generic_error("Contract does not export its memory"))?;
+    let state_region = unsafe {
+      get_backing_store_slice_mut(&store, state_ptr_u32 as usize, state.len())
+    }
+    .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
+    state_region.swap_with_slice(state);
+
+    let state_ptr = v8::Number::new(scope, state_ptr_u32 as f64);
+    let init = v8::Local::new(scope, init);
+    let result_ptr = init.call(
+      scope,
+      undefined.into(),
+      &[state_ptr.into(), state_len.into()],
+    );
+    let result_ptr_u32 = to_u32(scope, result_ptr)?;
+    let get_len = v8::Local::new(scope, &self.result_len);
+    let result_len = get_len.call(scope, undefined.into(), &[]);
+    let result_len = to_u32(scope, result_len)?;
+
+    // `init` may have grown the memory, which replaces its buffer.
+    let store = memory_of(scope)
+      .ok_or_else(|| generic_error("Contract does not export its memory"))?;
+    let result = unsafe {
+      get_backing_store_slice_mut(
+        &store,
+        result_ptr_u32 as usize,
+        result_len as usize,
+      )
+    }
+    .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
+
+    Ok(Some(result.to_vec()))
+  }
+
   pub fn call(
     &mut self,
     state: &mut [u8],
     action: &mut [u8],
     interaction_context: InteractionContext,
+  ) -> Result<Vec<u8>, AnyError> {
+    let result = match self.timeout {
+      Some(timeout) => {
+        let isolate = self.rt.v8_isolate().thread_safe_handle();
+        self
+          .watchdog
+          .get_or_insert_with(|| Watchdog::new(isolate))
+          .start(timeout, Waiting::default());
+        let result = self.call_handler(state, action, interaction_context);
+        if let Some(watchdog) = &self.watchdog {
+          if watchdog.stop(self.rt.v8_isolate()) {
+            return Err(Error::TimedOut { timeout }.into());
+          }
+        }
+        result
+      }
+      None => self.call_handler(state, action, interaction_context),
+    }?;
+    self.check_memory()?;
+    Ok(result)
+  }
+
+  /// See [`V8WasmRuntime::set_memory_limit`].
+  fn check_memory(&mut self) -> Result<(), AnyError> {
+    let limit = match self.memory_limit {
+      Some(limit) => limit,
+      None => return Ok(()),
+    };
+    let scope = &mut self.rt.handle_scope();
+    match memory_of(scope) {
+      Some(store) if store.byte_length() > limit => {
+        Err(Error::MemoryLimitExceeded { limit }.into())
+      }
+      _ => Ok(()),
+    }
+  }
+
+  fn call_handler(
+    &mut self,
+    state: &mut [u8],
+    action: &mut [u8],
+    interaction_context: InteractionContext,
   ) -> Result<Vec<u8>, AnyError> {
     let mut interaction = deno_core::serde_json::to_vec(&interaction_context)?;
 
     let interaction_len_high_level = interaction.len();
+    let clock_ms = self.determinism.now_ms(&interaction_context);
+    let seed = self.determinism.seed(&interaction_context);
+    let gas_limit = self.gas_limit;
 
     let result = {
       let scope = &mut self.rt.handle_scope();
+      // Handlers are functions of their inputs, so `random_get` starts over
+      // from the seed on every call.
+      set_global_number(scope, "CLOCK", clock_ms as f64);
+      set_global_number(scope, "RANDOM", SeededRng::new(seed).state() as f64);
+      set_gas_limit(scope, gas_limit);
+      // Exceptions thrown by the contract or its imports, like
+      // `throw_error` or a denied WASI call, fail the interaction with their
+      // message.
+      let scope = &mut v8::TryCatch::new(scope);
       let undefined = v8::undefined(scope);
 
       let alloc_obj = self.allocator.open(scope).to_object(scope).unwrap();
@@ -381,8 +746,7 @@ impl WasmRuntime {
         v8::Number::new(scope, interaction_len_high_level as f64);
       let interaction_ptr_high_level =
         wasm_alloc!(scope, alloc, undefined, interaction_len);
-      let interaction_ptr_32: u32 =
-        interaction_ptr_high_level.uint32_value(scope).unwrap();
+      let interaction_ptr_32 = to_u32(scope, interaction_ptr_high_level)?;
 
       let interaction_ptr = v8::Number::new(scope, interaction_ptr_32 as f64);
       let interaction_len =
@@ -390,9 +754,9 @@ impl WasmRuntime {
 
       // Offset in memory for start of the block.
       let local_ptr = wasm_alloc!(scope, alloc, undefined, state_len);
-      let local_ptr_u32 = local_ptr.uint32_value(scope).unwrap();
+      let local_ptr_u32 = to_u32(scope, local_ptr)?;
       let action_ptr = wasm_alloc!(scope, alloc, undefined, action_len);
-      let action_ptr_u32 = action_ptr.uint32_value(scope).unwrap();
+      let action_ptr_u32 = to_u32(scope, action_ptr)?;
 
       let exports_obj = self.exports.open(scope).to_object(scope).unwrap();
 
@@ -410,7 +774,8 @@ impl WasmRuntime {
 
       let state_mem_region = unsafe {
         get_backing_store_slice_mut(&store, local_ptr_u32 as usize, state.len())
-      };
+      }
+      .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
 
       state_mem_region.swap_with_slice(state);
 
@@ -420,7 +785,8 @@ impl WasmRuntime {
           action_ptr_u32 as usize,
           action.len(),
         )
-      };
+      }
+      .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
 
       action_region.swap_with_slice(action);
 
@@ -430,33 +796,35 @@ impl WasmRuntime {
           interaction_ptr_32 as usize,
           interaction_len_high_level,
         )
-      };
+      }
+      .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
 
       contract_mem_region.swap_with_slice(&mut interaction);
 
       let handler_obj = self.handle.open(scope).to_object(scope).unwrap();
       let handle = v8::Local::<v8::Function>::try_from(handler_obj)?;
 
-      let result_ptr = handle
-        .call(
-          scope,
-          undefined.into(),
-          &[
-            local_ptr,
-            state_len.into(),
-            action_ptr,
-            action_len.into(),
-            interaction_ptr.into(),
-            interaction_len.into(),
-          ],
-        )
-        .ok_or(Error::Terminated)?;
-      let result_ptr_u32 = result_ptr.uint32_value(scope).unwrap();
+      let result_ptr = match handle.call(
+        scope,
+        undefined.into(),
+        &[
+          local_ptr,
+          state_len.into(),
+          action_ptr,
+          action_len.into(),
+          interaction_ptr.into(),
+          interaction_len.into(),
+        ],
+      ) {
+        Some(result_ptr) => result_ptr,
+        None => return Err(call_error(scope)),
+      };
+      let result_ptr_u32 = to_u32(scope, Some(result_ptr))?;
       let get_len_obj = self.result_len.open(scope).to_object(scope).unwrap();
       let get_len = v8::Local::<v8::Function>::try_from(get_len_obj)?;
 
-      let result_len = get_len.call(scope, undefined.into(), &[]).unwrap();
-      let result_len = result_len.uint32_value(scope).unwrap();
+      let result_len = get_len.call(scope, undefined.into(), &[]);
+      let result_len = to_u32(scope, result_len)?;
 
       let result_mem = unsafe {
         get_backing_store_slice_mut(
@@ -464,7 +832,8 @@ impl WasmRuntime {
           result_ptr_u32 as usize,
           result_len as usize,
         )
-      };
+      }
+      .ok_or_else(|| generic_error(OUT_OF_BOUNDS))?;
 
       result_mem.to_vec()
     };
@@ -473,16 +842,96 @@ impl WasmRuntime {
   }
 }
 
+/// The error a call that threw or was terminated ends with.
+fn call_error(scope: &mut v8::TryCatch<v8::HandleScope>) -> AnyError {
+  match scope.exception() {
+    Some(exception) if !scope.has_terminated() => {
+      generic_error(exception.to_rust_string_lossy(scope))
+    }
+    _ => three_em_js::Error::Terminated.into(),
+  }
+}
+
+/// A pointer or length returned by a contract function, or the error it
+/// threw.
+fn to_u32(
+  scope: &mut v8::TryCatch<v8::HandleScope>,
+  value: Option<v8::Local<v8::Value>>,
+) -> Result<u32, AnyError> {
+  match value.and_then(|value| value.uint32_value(scope)) {
+    Some(value) => Ok(value),
+    None => Err(call_error(scope)),
+  }
+}
+
+fn get_global_number(scope: &mut v8::HandleScope, name: &str) -> f64 {
+  let ctx = scope.get_current_context();
+  let global = ctx.global(scope);
+  let key = v8::String::new(scope, name).unwrap();
+  global
+    .get(scope, key.into())
+    .and_then(|value| value.number_value(scope))
+    .unwrap_or_default()
+}
+
+fn set_global_number(scope: &mut v8::HandleScope, name: &str, value: f64) {
+  let ctx = scope.get_current_context();
+  let global = ctx.global(scope);
+  let key = v8::String::new(scope, name).unwrap();
+  let value = v8::Number::new(scope, value);
+  global.set(scope, key.into(), value.into());
+}
+
+/// Starts a call that may consume `gas_limit`, see
+/// [`V8WasmRuntime::set_gas_limit`].
+fn set_gas_limit(scope: &mut v8::HandleScope, gas_limit: Option<usize>) {
+  set_global_number(scope, "GAS", 0.0);
+  let gas_limit = gas_limit.map_or(f64::INFINITY, |gas_limit| gas_limit as f64);
+  set_global_number(scope, "GAS_LIMIT", gas_limit);
+}
+
+/// The error a call consuming more than `gas_limit` fails with.
+fn gas_limit_exceeded(gas_limit: usize) -> String {
+  format!("Interaction exceeded its gas limit of {}", gas_limit)
+}
+
+/// The backing store of the contract's memory, from a host function.
+fn memory_of(
+  scope: &mut v8::HandleScope,
+) -> Option<v8::SharedRef<v8::BackingStore>> {
+  let ctx = scope.get_current_context();
+  let global = ctx.global(scope);
+  let exports_str = v8::String::new(scope, "exports").unwrap();
+  let exports = global.get(scope, exports_str.into())?.to_object(scope)?;
+  let mem_str = v8::String::new(scope, "memory").unwrap();
+  let mem_obj = exports.get(scope, mem_str.into())?.to_object(scope)?;
+  let buffer_str = v8::String::new(scope, "buffer").unwrap();
+  let buffer_obj = mem_obj.get(scope, buffer_str.into())?;
+  let mem_buf = v8::Local::<v8::ArrayBuffer>::try_from(buffer_obj).ok()?;
+  Some(mem_buf.get_backing_store())
+}
+
+/// Fails a host function called with a range outside of the contract's
+/// memory.
+fn throw_out_of_bounds(scope: &mut v8::HandleScope) {
+  let message = v8::String::new(scope, OUT_OF_BOUNDS).unwrap();
+  let error = v8::Exception::error(scope, message);
+  scope.throw_exception(error);
+}
+
+/// The contract memory in `byte_offset..byte_offset + byte_length`, `None`
+/// if that is out of its bounds. Contracts pick the offsets, so they can't
+/// be trusted.
 #[allow(clippy::mut_from_ref)]
 unsafe fn get_backing_store_slice_mut(
   backing_store: &v8::SharedRef<v8::BackingStore>,
   byte_offset: usize,
   byte_length: usize,
-) -> &mut [u8] {
-  let cells: *const [Cell<u8>] =
-    &backing_store[byte_offset..byte_offset + byte_length];
+) -> Option<&mut [u8]> {
+  let byte_end = byte_offset.checked_add(byte_length)?;
+  let cells: *const [Cell<u8>] = backing_store.get(byte_offset..byte_end)?;
   let bytes = cells as *const _ as *mut [u8];
-  &mut *bytes
+  Some(&mut *bytes)
 }
 
 #[cfg(test)]
@@ -548,6 +997,88 @@ mod tests {
       .expect_err("should panic");
   }
 
+  #[tokio::test]
+  async fn test_wasm_init() {
+    let mut rt =
+      WasmRuntime::new(include_bytes!("../../testdata/08_wasm/08_wasm.wasm"))
+        .unwrap();
+    let mut state_bytes =
+      deno_core::serde_json::to_vec(&json!({ "n": 1 })).unwrap();
+    let state = rt.init(&mut state_bytes).unwrap().unwrap();
+    let state: Value = deno_core::serde_json::from_slice(&state).unwrap();
+    assert_eq!(state, json!({ "init": { "n": 1 } }));
+
+    // Contracts without an `init` export don't derive anything.
+    let mut rt =
+      WasmRuntime::new(include_bytes!("../../testdata/01_wasm/01_wasm.wasm"))
+        .unwrap();
+    assert!(rt.init(&mut b"{}".to_vec()).unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_wasm_gas_limit() {
+    let mut rt =
+      WasmRuntime::new(include_bytes!("../../testdata/09_wasm/09_wasm.wasm"))
+        .unwrap();
+    rt.set_gas_limit(Some(1000));
+
+    // 100 gas per byte of the action.
+    let mut state_bytes = b"{}".to_vec();
+    let state = rt
+      .call(&mut state_bytes, &mut b"{}".to_vec(), Default::default())
+      .unwrap();
+    assert_eq!(state, b"{}");
+
+    let mut action_bytes =
+      deno_core::serde_json::to_vec(&json!({ "work": 100 })).unwrap();
+    let err = rt
+      .call(&mut state.clone(), &mut action_bytes, Default::default())
+      .unwrap_err();
+    assert!(
+      err.to_string().contains("exceeded its gas limit of 1000"),
+      "{}",
+      err
+    );
+
+    // The limit is per call, not for the runtime's lifetime.
+    for _ in 0..10 {
+      rt.call(&mut state.clone(), &mut b"{}".to_vec(), Default::default())
+        .unwrap();
+    }
+    assert_eq!(rt.get_cost(), 200 * 11 + 1200);
+  }
+
+  #[tokio::test]
+  async fn test_wasm_invalid_module() {
+    assert!(WasmRuntime::new(b"not wasm").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_wasm_wasi_sandbox() {
+    let wasm = include_bytes!("../../testdata/05_wasm/05_wasm.wasm");
+    // Imports outside the no-op WASI stubs can't be linked without it.
+    assert!(WasmRuntime::new(wasm).is_err());
+
+    let mut rt = WasmRuntime::with_wasi_sandbox(wasm, true).unwrap();
+    let mut action_bytes = deno_core::serde_json::to_vec(&json!({})).unwrap();
+    let mut prev_state_bytes = b"{}".to_vec();
+    let err = rt
+      .call(
+        &mut prev_state_bytes,
+        &mut action_bytes,
+        InteractionContext {
+          transaction: InteractionTx::default(),
+          block: InteractionBlock::default(),
+        },
+      )
+      .unwrap_err();
+    assert!(
+      err.to_string().contains("WASI call random_get is denied"),
+      "{}",
+      err
+    );
+  }
+
   #[tokio::test]
   async fn test_wasm_runtime_asc() {
     let mut rt =
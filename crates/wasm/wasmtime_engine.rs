@@ -0,0 +1,478 @@
+use crate::Error;
+use deno_core::error::{generic_error, AnyError};
+use std::time::Duration;
+use three_em_smartweave::{
+  read_contract_state, DeterminismContext, InteractionContext, SeededRng,
+};
+use wasmtime::{
+  Caller, Engine, ExternType, Func, Instance, Linker, Memory, Module, Store,
+  Trap, Val,
+};
+
+/// Host side of a running contract.
+#[derive(Default)]
+struct HostState {
+  /// Gas consumed through `3em.consumeGas`.
+  cost: i32,
+  /// Gas consumed by the current call.
+  gas: i64,
+  /// See [`crate::V8WasmRuntime::set_gas_limit`].
+  gas_limit: Option<usize>,
+  /// What the WASI clock reads during the current call.
+  clock_ms: u64,
+  /// What WASI `random_get` draws from during the current call.
+  random: u32,
+}
+
+/// A [`crate::V8WasmRuntime`] drop-in backed by Wasmtime.
+///
+/// The contract ABI is the same: the module exports `memory`, `_alloc`,
+/// `get_len`, `handle` and optionally `init`, and may import the `3em`,
+/// `env` and `wasi_snapshot_preview1` functions the V8 engine provides.
+pub struct WasmtimeRuntime {
+  store: Store<HostState>,
+  memory: Memory,
+  /// `handle(state_ptr, state_len, action_ptr, action_len, info_ptr,
+  /// info_len) -> result_ptr`. Handlers may declare fewer parameters.
+  handle: Func,
+  /// `init(state_ptr, state_len) -> result_ptr`, if the contract has one.
+  init: Option<Func>,
+  /// `get_len() -> usize`
+  result_len: Func,
+  /// `_alloc(size) -> ptr`
+  allocator: Func,
+  determinism: DeterminismContext,
+  /// See [`crate::V8WasmRuntime::set_memory_limit`].
+  memory_limit: Option<usize>,
+}
+
+fn memory_of(caller: &mut Caller<'_, HostState>) -> Result<Memory, Trap> {
+  caller
+    .get_export("memory")
+    .and_then(|export| export.into_memory())
+    .ok_or_else(|| Trap::new("Contract does not export its memory"))
+}
+
+fn read_bytes(
+  caller: &mut Caller<'_, HostState>,
+  ptr: i32,
+  len: i32,
+) -> Result<Vec<u8>, Trap> {
+  let memory = memory_of(caller)?;
+  let mut bytes = vec![0; len as usize];
+  memory
+    .read(&*caller, ptr as usize, &mut bytes)
+    .map_err(|err| Trap::new(err.to_string()))?;
+  Ok(bytes)
+}
+
+fn add_imports(linker: &mut Linker<HostState>) -> Result<(), AnyError> {
+  // AssemblyScript needs `abort` to be defined.
+  linker.func_wrap("env", "abort", |_: i32, _: i32, _: i32, _: i32| {})?;
+
+  linker.func_wrap(
+    "3em",
+    "smartweave_read_state",
+    |mut caller: Caller<'_, HostState>,
+     tx_id_ptr: i32,
+     tx_id_len: i32,
+     length_ptr: i32|
+     -> Result<i32, Trap> {
+      let tx_id = read_bytes(&mut caller, tx_id_ptr, tx_id_len)?;
+      let tx_id = String::from_utf8_lossy(&tx_id).to_string();
+
+      let state = read_contract_state(tx_id);
+      let state = deno_core::serde_json::to_vec(&state)
+        .map_err(|err| Trap::new(err.to_string()))?;
+
+      let alloc = caller
+        .get_export("_alloc")
+        .and_then(|export| export.into_func())
+        .ok_or_else(|| Trap::new("Contract does not export _alloc"))?
+        .typed::<i32, i32, _>(&caller)
+        .map_err(|err| Trap::new(err.to_string()))?;
+      let state_ptr = alloc.call(&mut caller, state.len() as i32)?;
+
+      let memory = memory_of(&mut caller)?;
+      let state_len = (state.len() as u32).to_le_bytes();
+      memory
+        .write(&mut caller, length_ptr as usize, &state_len)
+        .and_then(|_| memory.write(&mut caller, state_ptr as usize, &state))
+        .map_err(|err| Trap::new(err.to_string()))?;
+
+      Ok(state_ptr)
+    },
+  )?;
+
+  linker.func_wrap(
+    "3em",
+    "consumeGas",
+    |mut caller: Caller<'_, HostState>, inc: i32| -> Result<(), Trap> {
+      let host = caller.data_mut();
+      host.cost += inc;
+      host.gas += inc as i64;
+      match host.gas_limit {
+        Some(gas_limit) if host.gas > gas_limit as i64 => {
+          Err(Trap::new(crate::gas_limit_exceeded(gas_limit)))
+        }
+        _ => Ok(()),
+      }
+    },
+  )?;
+
+  linker.func_wrap(
+    "3em",
+    "throw_error",
+    |mut caller: Caller<'_, HostState>,
+     error_ptr: i32,
+     error_len: i32|
+     -> Result<(), Trap> {
+      let error = read_bytes(&mut caller, error_ptr, error_len)?;
+      Err(Trap::new(String::from_utf8_lossy(&error)))
+    },
+  )?;
+
+  Ok(())
+}
+
+/// Links `wasi_snapshot_preview1`, see
+/// [`crate::V8WasmRuntime::with_wasi_sandbox`].
+fn add_wasi(
+  linker: &mut Linker<HostState>,
+  module: &Module,
+  sandbox_wasi: bool,
+) -> Result<(), AnyError> {
+  if sandbox_wasi {
+    for import in module.imports() {
+      let (name, ty) = match (import.module(), import.name(), import.ty()) {
+        ("wasi_snapshot_preview1", Some(name), ExternType::Func(ty)) => {
+          (name, ty)
+        }
+        _ => continue,
+      };
+      let message = format!("WASI call {} is denied", name);
+      linker.func_new("wasi_snapshot_preview1", name, ty, move |_, _, _| {
+        Err(Trap::new(message.clone()))
+      })?;
+    }
+
+    return Ok(());
+  }
+
+  // No-op WASI, matching the V8 engine.
+  linker.func_wrap("wasi_snapshot_preview1", "fd_close", |_: i32| 0)?;
+  linker.func_wrap(
+    "wasi_snapshot_preview1",
+    "fd_seek",
+    |_: i32, _: i64, _: i32, _: i32| 0,
+  )?;
+  linker.func_wrap(
+    "wasi_snapshot_preview1",
+    "fd_write",
+    |_: i32, _: i32, _: i32, _: i32| 0,
+  )?;
+  linker.func_wrap(
+    "wasi_snapshot_preview1",
+    "clock_time_get",
+    |mut caller: Caller<'_, HostState>,
+     _: i32,
+     _: i64,
+     time_ptr: i32|
+     -> Result<i32, Trap> {
+      let time = (caller.data().clock_ms * 1_000_000).to_le_bytes();
+      memory_of(&mut caller)?
+        .write(&mut caller, time_ptr as usize, &time)
+        .map_err(|err| Trap::new(err.to_string()))?;
+      Ok(0)
+    },
+  )?;
+  linker.func_wrap(
+    "wasi_snapshot_preview1",
+    "random_get",
+    |mut caller: Caller<'_, HostState>,
+     buf_ptr: i32,
+     buf_len: i32|
+     -> Result<i32, Trap> {
+      let mut rng = SeededRng::new(caller.data().random);
+      let mut buf = vec![0; buf_len as usize];
+      rng.fill(&mut buf);
+      caller.data_mut().random = rng.state();
+      memory_of(&mut caller)?
+        .write(&mut caller, buf_ptr as usize, &buf)
+        .map_err(|err| Trap::new(err.to_string()))?;
+      Ok(0)
+    },
+  )?;
+
+  Ok(())
+}
+
+fn export_func(
+  instance: &Instance,
+  store: &mut Store<HostState>,
+  name: &str,
+) -> Result<Func, AnyError> {
+  instance
+    .get_func(&mut *store, name)
+    .ok_or_else(|| generic_error(format!("Contract does not export {}", name)))
+}
+
+impl WasmtimeRuntime {
+  pub fn new(wasm: &[u8]) -> Result<WasmtimeRuntime, AnyError> {
+    Self::with_wasi_sandbox(wasm, false)
+  }
+
+  /// See [`crate::V8WasmRuntime::with_wasi_sandbox`].
+  pub fn with_wasi_sandbox(
+    wasm: &[u8],
+    sandbox_wasi: bool,
+  ) -> Result<WasmtimeRuntime, AnyError> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm)
+      .map_err(|_| generic_error("Invalid WebAssembly module"))?;
+
+    let mut linker = Linker::new(&engine);
+    add_imports(&mut linker)?;
+    add_wasi(&mut linker, &module, sandbox_wasi)?;
+
+    let mut store = Store::new(&engine, HostState::default());
+    let instance =
+      linker.instantiate(&mut store, &module).map_err(|err| {
+        generic_error(format!(
+          "WebAssembly module could not be instantiated: {}",
+          err
+        ))
+      })?;
+
+    let memory = instance
+      .get_memory(&mut store, "memory")
+      .ok_or_else(|| generic_error("Contract does not export its memory"))?;
+    let handle = export_func(&instance, &mut store, "handle")?;
+    let init = instance.get_func(&mut store, "init");
+    let result_len = export_func(&instance, &mut store, "get_len")?;
+    let allocator = export_func(&instance, &mut store, "_alloc")?;
+
+    Ok(Self {
+      store,
+      memory,
+      handle,
+      init,
+      result_len,
+      allocator,
+      determinism: DeterminismContext::default(),
+      memory_limit: None,
+    })
+  }
+
+  /// See [`crate::V8WasmRuntime::set_determinism`].
+  pub fn set_determinism(&mut self, determinism: DeterminismContext) {
+    self.determinism = determinism;
+  }
+
+  /// See [`crate::V8WasmRuntime::set_gas_limit`].
+  pub fn set_gas_limit(&mut self, gas_limit: Option<usize>) {
+    self.store.data_mut().gas_limit = gas_limit;
+  }
+
+  /// Calls aren't timed on Wasmtime, unlike on V8, see
+  /// [`crate::V8WasmRuntime::set_timeout`]. Only gas bounds how long a
+  /// metered module runs.
+  pub fn set_timeout(&mut self, _timeout: Option<Duration>) {}
+
+  /// See [`crate::V8WasmRuntime::set_memory_limit`].
+  pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+    self.memory_limit = memory_limit;
+  }
+
+  pub fn get_cost(&mut self) -> usize {
+    self.store.data().cost as usize
+  }
+
+  fn alloc(&mut self, bytes: &[u8]) -> Result<i32, AnyError> {
+    let ptr = self
+      .allocator
+      .typed::<i32, i32, _>(&self.store)?
+      .call(&mut self.store, bytes.len() as i32)?;
+    self.memory.write(&mut self.store, ptr as usize, bytes)?;
+    Ok(ptr)
+  }
+
+  /// See [`crate::V8WasmRuntime::init`].
+  pub fn init(
+    &mut self,
+    state: &mut [u8],
+  ) -> Result<Option<Vec<u8>>, AnyError> {
+    let init = match self.init {
+      Some(init) => init,
+      None => return Ok(None),
+    };
+    let context = InteractionContext::default();
+    let host = self.store.data_mut();
+    host.clock_ms = self.determinism.now_ms(&context);
+    host.random = SeededRng::new(self.determinism.seed(&context)).state();
+    host.gas = 0;
+
+    let state_ptr = self.alloc(state)?;
+    let result_ptr = init
+      .typed::<(i32, i32), i32, _>(&self.store)?
+      .call(&mut self.store, (state_ptr, state.len() as i32))?;
+    self.read_result(result_ptr).map(Some)
+  }
+
+  /// The `get_len` bytes at `ptr`, what a contract function returned.
+  fn read_result(&mut self, ptr: i32) -> Result<Vec<u8>, AnyError> {
+    let result_len = self
+      .result_len
+      .typed::<(), i32, _>(&self.store)?
+      .call(&mut self.store, ())?;
+
+    let mut result = vec![0; result_len as usize];
+    self.memory.read(&self.store, ptr as usize, &mut result)?;
+
+    Ok(result)
+  }
+
+  pub fn call(
+    &mut self,
+    state: &mut [u8],
+    action: &mut [u8],
+    interaction_context: InteractionContext,
+  ) -> Result<Vec<u8>, AnyError> {
+    let interaction = deno_core::serde_json::to_vec(&interaction_context)?;
+    let host = self.store.data_mut();
+    host.clock_ms = self.determinism.now_ms(&interaction_context);
+    host.random =
+      SeededRng::new(self.determinism.seed(&interaction_context)).state();
+    host.gas = 0;
+
+    // Same allocation order as the V8 engine, so contracts see the same
+    // pointers on both.
+    let interaction_ptr = self.alloc(&interaction)?;
+    let state_ptr = self.alloc(state)?;
+    let action_ptr = self.alloc(action)?;
+
+    let args = [
+      Val::I32(state_ptr),
+      Val::I32(state.len() as i32),
+      Val::I32(action_ptr),
+      Val::I32(action.len() as i32),
+      Val::I32(interaction_ptr),
+      Val::I32(interaction.len() as i32),
+    ];
+    // V8 drops extra arguments, and some handlers ignore the context.
+    let arity = self.handle.ty(&self.store).params().len();
+    let mut result_ptr = [Val::I32(0)];
+    self
+      .handle
+      .call(&mut self.store, &args[..arity], &mut result_ptr)?;
+    let result_ptr = result_ptr[0].unwrap_i32();
+    let result = self.read_result(result_ptr)?;
+
+    match self.memory_limit {
+      Some(limit) if self.memory.data_size(&self.store) > limit => {
+        Err(Error::MemoryLimitExceeded { limit }.into())
+      }
+      _ => Ok(result),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{V8WasmRuntime, WasmtimeRuntime};
+  use deno_core::serde_json::json;
+  use three_em_smartweave::{
+    InteractionBlock, InteractionContext, InteractionTx,
+  };
+
+  fn context(id: &str) -> InteractionContext {
+    InteractionContext {
+      transaction: InteractionTx {
+        id: String::from(id),
+        owner: String::from("ANDRES1234"),
+        ..Default::default()
+      },
+      block: InteractionBlock {
+        height: 100,
+        ..Default::default()
+      },
+    }
+  }
+
+  #[tokio::test]
+  async fn test_engines_agree() {
+    let fixtures: [(&[u8], _, &[&str]); 5] = [
+      (
+        include_bytes!("../../testdata/01_wasm/01_wasm.wasm"),
+        json!({ "counter": 0 }),
+        &["tx1", "tx2", "tx3"],
+      ),
+      (
+        include_bytes!("../../testdata/02_wasm/02_wasm.wasm"),
+        json!({ "counter": 0 }),
+        &["tx1", "tx2", "tx3"],
+      ),
+      (
+        include_bytes!("../../testdata/03_wasm/03_wasm.wasm"),
+        json!({ "txId": "", "owner": "", "height": 0 }),
+        &["POCAHONTAS"],
+      ),
+      (
+        include_bytes!("../../testdata/04_wasm/04_wasm.wasm"),
+        json!({ "v": "a" }),
+        &["rx", "wb", "rc", "wd"],
+      ),
+      (
+        include_bytes!("../../testdata/09_wasm/09_wasm.wasm"),
+        json!({}),
+        &["tx1", "tx2"],
+      ),
+    ];
+
+    for (wasm, init_state, ids) in fixtures {
+      let mut v8 = V8WasmRuntime::new(wasm).unwrap();
+      let mut wasmtime = WasmtimeRuntime::new(wasm).unwrap();
+      let init_state = deno_core::serde_json::to_vec(&init_state).unwrap();
+      let (mut v8_state, mut wasmtime_state) =
+        (init_state.clone(), init_state);
+
+      for id in ids {
+        let action = deno_core::serde_json::to_vec(&json!({})).unwrap();
+        v8_state = v8
+          .call(&mut v8_state, &mut action.clone(), context(id))
+          .unwrap();
+        wasmtime_state = wasmtime
+          .call(&mut wasmtime_state, &mut action.clone(), context(id))
+          .unwrap();
+        assert_eq!(v8_state, wasmtime_state);
+      }
+      assert_eq!(v8.get_cost(), wasmtime.get_cost());
+    }
+
+    // Both derive the same state from `init`.
+    let wasm = include_bytes!("../../testdata/08_wasm/08_wasm.wasm");
+    let init_state = deno_core::serde_json::to_vec(&json!({})).unwrap();
+    let v8_state = V8WasmRuntime::new(wasm)
+      .unwrap()
+      .init(&mut init_state.clone())
+      .unwrap();
+    let wasmtime_state = WasmtimeRuntime::new(wasm)
+      .unwrap()
+      .init(&mut init_state.clone())
+      .unwrap();
+    assert_eq!(v8_state, Some(br#"{"init":{}}"#.to_vec()));
+    assert_eq!(v8_state, wasmtime_state);
+
+    // Both reject the same broken input.
+    let wasm = include_bytes!("../../testdata/01_wasm/01_wasm.wasm");
+    let mut action = deno_core::serde_json::to_vec(&json!({})).unwrap();
+    assert!(V8WasmRuntime::new(wasm)
+      .unwrap()
+      .call(&mut vec![], &mut action.clone(), context("tx1"))
+      .is_err());
+    assert!(WasmtimeRuntime::new(wasm)
+      .unwrap()
+      .call(&mut vec![], &mut action, context("tx1"))
+      .is_err());
+  }
+}
@@ -0,0 +1,33 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use three_em_executor::harness::evaluate_raw;
+
+#[derive(Arbitrary, Debug)]
+struct Contract {
+  wasm: bool,
+  source: Vec<u8>,
+  init_state: Vec<u8>,
+  inputs: Vec<Vec<u8>>,
+}
+
+fuzz_target!(|contract: Contract| {
+  let content_type = if contract.wasm {
+    "application/wasm"
+  } else {
+    "application/javascript"
+  };
+
+  let rt = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+  // Errors are expected, only panics and crashes are findings.
+  let _ = rt.block_on(evaluate_raw(
+    &contract.source,
+    content_type,
+    &contract.init_state,
+    &contract.inputs,
+  ));
+});
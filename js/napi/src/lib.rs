@@ -10,6 +10,7 @@ use three_em_arweave::gql_result::{GQLEdgeInterface, GQLTagInterface};
 use three_em_executor::execute_contract as execute;
 use three_em_executor::simulate_contract as simulate;
 use three_em_executor::utils::create_simulated_transaction;
+use three_em_executor::EvaluateOptions;
 use three_em_executor::ExecuteResult;
 use three_em_executor::ValidityTable;
 use tokio::runtime::Handle;
@@ -110,7 +111,7 @@ fn get_result(
         state,
         validity: validity_to_hashmap(validity),
       }),
-      ExecuteResult::Evm(..) => todo!(),
+      ExecuteResult::Evm(..) | ExecuteResult::Bytes(..) => todo!(),
     }
   } else {
     process_result.unwrap();
@@ -215,6 +216,7 @@ async fn execute_contract(
         None,
         None,
         &arweave,
+        &EvaluateOptions::default(),
       )
       .await;
 
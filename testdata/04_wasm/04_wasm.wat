@@ -0,0 +1,41 @@
+;; A read-mostly contract. The state is expected to look like `{"v":"a"}`.
+;;
+;; Interactions whose transaction id starts with `w` overwrite the value
+;; with the second character of the id; every other interaction returns
+;; the state untouched.
+(module
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (global $len (mut i32) (i32.const 0))
+
+  (func (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    global.get $len)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    local.get $state_len
+    global.set $len
+    ;; The interaction context starts with `{"transaction":{"id":"`.
+    (if (i32.eq (i32.load8_u offset=22 (local.get $info)) (i32.const 119))
+      (then
+        (i32.store8 offset=6
+          (local.get $state)
+          (i32.load8_u offset=23 (local.get $info)))))
+    ;; Every buffer is consumed by the time the next call allocates.
+    i32.const 1024
+    global.set $heap
+    local.get $state)
+)
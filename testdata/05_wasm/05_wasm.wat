@@ -0,0 +1,34 @@
+;; A contract that asks WASI for random bytes on every interaction.
+;;
+;; Only the `--wasi-sandbox` runtime instantiates it, and the sandbox denies
+;; the call, so every interaction fails.
+(module
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+
+  (func (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    i32.const 0)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    i32.const 0
+    i32.const 8
+    call $random_get
+    drop
+    i32.const 0))
@@ -0,0 +1,44 @@
+;; A contract whose state is a binary blob rather than JSON.
+;;
+;; Every interaction appends a 0xff byte to the state, so an empty init
+;; state becomes `ff`, `ffff`, `ffffff`... States up to 1023 bytes fit
+;; below the heap.
+(module
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (global $len (mut i32) (i32.const 0))
+
+  (func (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    global.get $len)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    (local $i i32)
+    local.get $state_len
+    i32.const 1
+    i32.add
+    global.set $len
+    (block $done
+      (loop $fill
+        (br_if $done (i32.ge_u (local.get $i) (global.get $len)))
+        (i32.store8 (local.get $i) (i32.const 255))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $fill)))
+    ;; Every buffer is consumed by the time the next call allocates.
+    i32.const 1024
+    global.set $heap
+    i32.const 0)
+)
@@ -0,0 +1,38 @@
+;; A contract reading the WASI clock and randomness.
+;;
+;; Every interaction replaces the state with 16 bytes: the time
+;; `clock_time_get` reports, in nanoseconds, followed by 8 bytes from
+;; `random_get`.
+(module
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+
+  (func (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    i32.const 16)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    (drop (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 0)))
+    (drop (call $random_get (i32.const 8) (i32.const 8)))
+    ;; Every buffer is consumed by the time the next call allocates.
+    i32.const 1024
+    global.set $heap
+    i32.const 0)
+)
@@ -0,0 +1,49 @@
+;; A contract with an `init` export.
+;;
+;; `init` wraps the init state as `{"init":<state>}`, the state the
+;; interactions then start from. `handle` leaves the state as it is.
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\22init\22:")
+  (global $heap (mut i32) (i32.const 1024))
+  (global $len (mut i32) (i32.const 0))
+
+  (func $alloc (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    global.get $len)
+
+  (func (export "init")
+    (param $state i32) (param $state_len i32)
+    (result i32)
+    (local $out i32)
+    (local.set $out
+      (call $alloc (i32.add (local.get $state_len) (i32.const 9))))
+    (memory.copy (local.get $out) (i32.const 0) (i32.const 8))
+    (memory.copy
+      (i32.add (local.get $out) (i32.const 8))
+      (local.get $state)
+      (local.get $state_len))
+    ;; The closing brace.
+    (i32.store8
+      (i32.add (i32.add (local.get $out) (i32.const 8)) (local.get $state_len))
+      (i32.const 125))
+    (global.set $len (i32.add (local.get $state_len) (i32.const 9)))
+    local.get $out)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    (global.set $len (local.get $state_len))
+    local.get $state)
+)
@@ -0,0 +1,33 @@
+;; A metered contract whose interactions cost gas by the size of their
+;; action.
+;;
+;; `handle` consumes 100 gas per byte of the action through
+;; `3em.consumeGas`, then leaves the state as it is.
+(module
+  (import "3em" "consumeGas" (func $consume_gas (param i32)))
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (global $len (mut i32) (i32.const 0))
+
+  (func $alloc (export "_alloc") (param $size i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    local.set $ptr
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "get_len") (result i32)
+    global.get $len)
+
+  (func (export "handle")
+    (param $state i32) (param $state_len i32)
+    (param $action i32) (param $action_len i32)
+    (param $info i32) (param $info_len i32)
+    (result i32)
+    (call $consume_gas (i32.mul (local.get $action_len) (i32.const 100)))
+    (global.set $len (local.get $state_len))
+    local.get $state)
+)
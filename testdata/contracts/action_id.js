@@ -0,0 +1,5 @@
+export async function handle(state, action) {
+  state.ids.push(action.id);
+  state.heights.push(action.block.height);
+  return { state };
+}
@@ -0,0 +1,19 @@
+// A token whose balances can go beyond 2^53, which the runtime hands over
+// as BigInts. Amounts are counted in BigInts whatever they come in as.
+export async function handle(state, action) {
+  const { input, caller } = action;
+
+  if (input.function === "transfer") {
+    const qty = BigInt(input.qty);
+    const balance = BigInt(state.balances[caller] ?? 0);
+    if (qty <= 0n || balance < qty) {
+      throw new Error("Not enough balance");
+    }
+    state.balances[caller] = balance - qty;
+    state.balances[input.target] =
+      BigInt(state.balances[input.target] ?? 0) + qty;
+    return { state };
+  }
+
+  throw new Error("Unknown function");
+}
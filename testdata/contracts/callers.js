@@ -0,0 +1,4 @@
+export async function handle(state, action) {
+  state.callers.push(action.caller);
+  return { state };
+}
@@ -0,0 +1,13 @@
+export async function handle(state, action) {
+  const deployed = SmartWeave.contract.block;
+  if (!deployed) {
+    throw new Error("Contract is not mined");
+  }
+
+  state.age = SmartWeave.block.height - deployed.height;
+  state.deployedAt = deployed.timestamp;
+
+  return {
+    state
+  };
+}
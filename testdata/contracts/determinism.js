@@ -0,0 +1,7 @@
+// Records what the clock and `Math.random()` return.
+export function handle(state, action) {
+  state.now = Date.now();
+  state.date = new Date().toISOString();
+  state.random = Math.random();
+  return { state };
+}
@@ -0,0 +1,4 @@
+export async function handle(state, action) {
+  state.actions.push(action);
+  return { state };
+}
@@ -0,0 +1,30 @@
+function toHex(bytes) {
+  return Array.from(bytes, (byte) => byte.toString(16).padStart(2, "0"))
+    .join("");
+}
+
+function fromHex(hex) {
+  return new Uint8Array(hex.match(/../g).map((byte) => parseInt(byte, 16)));
+}
+
+export async function handle(state, action) {
+  const { extensions } = SmartWeave;
+  const input = action.input;
+  switch (input.function) {
+    case "hash":
+      state.keccak256 = toHex(extensions.keccak256(input.data));
+      state.sha256 = toHex(extensions.sha256(input.data));
+      break;
+    case "verify":
+      state.verified = extensions.ed25519Verify(
+        fromHex(input.publicKey),
+        input.message,
+        fromHex(input.signature),
+      );
+      break;
+    case "random":
+      state.random = extensions.randomBytes(8).length;
+      break;
+  }
+  return { state };
+}
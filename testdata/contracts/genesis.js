@@ -0,0 +1,18 @@
+export async function handle(state, action) {
+  const input = action.input;
+
+  if (input.function === "genesis") {
+    state.owner = action.caller;
+    state.supply = input.supply;
+    state.balances = { [action.caller]: input.supply };
+    return { state };
+  }
+
+  if (input.function === "mint") {
+    state.supply += input.qty;
+    state.balances[state.owner] += input.qty;
+    return { state };
+  }
+
+  throw new Error("Unknown function");
+}
@@ -0,0 +1,25 @@
+// Splits the supply evenly between the holders the init state lists,
+// before any interaction.
+export function init(state) {
+  const share = state.supply / state.holders.length;
+  state.balances = {};
+  for (const holder of state.holders) {
+    state.balances[holder] = share;
+  }
+  return state;
+}
+
+export async function handle(state, action) {
+  const { input, caller } = action;
+
+  if (input.function === "transfer") {
+    if (state.balances[caller] < input.qty) {
+      throw new Error("Not enough balance");
+    }
+    state.balances[caller] -= input.qty;
+    state.balances[input.target] += input.qty;
+    return { state };
+  }
+
+  throw new Error("Unknown function");
+}
@@ -0,0 +1,14 @@
+export async function handle(state, action) {
+  // Math.random in a comment is fine.
+  const winner = Math.random() < 0.5 ? "heads" : "tails";
+  const playedAt = Date.now();
+
+  if (action.input.function === "price") {
+    // Fetching is not.
+    const response = await fetch("https://example.com/price");
+    state.price = await response.json();
+  }
+
+  state.games.push({ winner, playedAt });
+  return { state };
+}
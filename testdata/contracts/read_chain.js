@@ -0,0 +1,26 @@
+// `read-chain-<n>` reads `read-chain-<n + 1>`, and so on down to the last
+// contract. The state tells how far down the chain the reads got.
+const LAST = 5;
+
+export async function handle(state, action) {
+  const n = Number(SmartWeave.contract.id.split("-").pop());
+  if (n === LAST) {
+    state.reached = n;
+    return { state };
+  }
+
+  try {
+    const next = await SmartWeave.contracts.readContractState(
+      `read-chain-${n + 1}`,
+      1,
+    );
+    state.reached = next.reached;
+    if (next.error) {
+      state.error = next.error;
+    }
+  } catch (e) {
+    state.reached = n;
+    state.error = e.message;
+  }
+  return { state };
+}
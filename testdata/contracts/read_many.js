@@ -0,0 +1,10 @@
+// Reads `sibling-0` to `sibling-<count - 1>`, each of them twice.
+export async function handle(state, action) {
+  for (let round = 0; round < 2; round++) {
+    for (let i = 0; i < action.input.count; i++) {
+      await SmartWeave.contracts.readContractState(`sibling-${i}`);
+    }
+  }
+  state.read = action.input.count;
+  return { state };
+}
@@ -0,0 +1,10 @@
+export async function handle(state, action) {
+  // Every interaction reads the same sibling at the same height.
+  const sibling = await SmartWeave.contracts.readContractState(
+    "0zplqhFARjHyR-dBdNEY1TpZuKL0mWm--RFq6LByoew",
+    921589,
+  );
+  state.reads = (state.reads || 0) + 1;
+  state.siblingKeys = Object.keys(sibling).length;
+  return { state };
+}
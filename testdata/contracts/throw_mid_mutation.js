@@ -0,0 +1,10 @@
+export async function handle(state, action) {
+  state.counter += 1;
+  state.log.push(action.input.name);
+
+  if (action.input.fail) {
+    throw new Error("Failed after mutating the state");
+  }
+
+  return { state };
+}
@@ -0,0 +1,11 @@
+export async function handle(state, action) {
+  const data = await SmartWeave.unsafeClient.transactions.getData(
+    action.input.id,
+    { decode: true, string: true },
+  );
+  return {
+    state: {
+      length: data.length,
+    }
+  };
+}
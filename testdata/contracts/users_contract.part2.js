@@ -0,0 +1,6 @@
+ }
+
+ return {
+     state
+ };
+}
@@ -0,0 +1,10 @@
+export async function handle(state, action) {
+  const balance = await SmartWeave.arweave.wallets.getBalance(action.caller);
+  const again = await SmartWeave.arweave.wallets.getBalance(action.caller);
+  return {
+    state: {
+      balance,
+      memoized: balance === again,
+    }
+  };
+}
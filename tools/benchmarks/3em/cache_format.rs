@@ -0,0 +1,38 @@
+use deno_core::serde_json;
+use three_em_arweave::cache::{
+  ArweaveCache, CacheExt, CacheFormat, StateResult,
+};
+use three_em_executor::IndexMap;
+
+// Warm loads of a big cached state (a ledger of 50k balances with about
+// as many validity entries), the way a node reads it before evaluating new
+// interactions.
+const LOADS: usize = 50;
+const ACCOUNTS: usize = 50_000;
+
+fn main() {
+  // Usage: bench_cache_format [json|cbor]
+  let format = match std::env::args().nth(1).as_deref() {
+    Some("cbor") => CacheFormat::Cbor,
+    _ => CacheFormat::Json,
+  };
+
+  let balances: serde_json::Map<String, serde_json::Value> = (0..ACCOUNTS)
+    .map(|i| (format!("wallet{}", i), serde_json::json!(i * 1_000)))
+    .collect();
+  let validity: IndexMap<String, serde_json::Value> = (0..ACCOUNTS)
+    .map(|i| (format!("tx{}", i), serde_json::json!(i % 7 != 0)))
+    .collect();
+  let state = StateResult {
+    state: serde_json::json!({ "ticker": "BENCH", "balances": balances }),
+    validity,
+  };
+
+  let mut cache = ArweaveCache::new().with_format(format);
+  let id = format!("bench_cache_format_{:?}", format);
+  cache.cache_states(id.clone(), state).unwrap();
+
+  for _ in 0..LOADS {
+    cache.find_state(id.clone()).unwrap().unwrap();
+  }
+}
@@ -0,0 +1,58 @@
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::executor::raw_execute_contract;
+use three_em_executor::test_util::{
+  generate_fake_interaction, generate_fake_loaded_contract_data,
+};
+use three_em_executor::EvaluateOptions;
+use three_em_executor::IndexMap;
+
+// Every interaction reads the same sibling contract at the same height.
+const INTERACTIONS: usize = 1_000;
+
+#[tokio::main]
+async fn main() {
+  let interactions: Vec<GQLEdgeInterface> = (0..INTERACTIONS)
+    .map(|i| {
+      generate_fake_interaction(
+        Default::default(),
+        &format!("tx{}", i),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    })
+    .collect();
+
+  let arweave = Arweave::new(
+    443,
+    "arweave.net".to_string(),
+    String::from("https"),
+    ArweaveCache::new(),
+  );
+
+  raw_execute_contract(
+    String::from("bench_foreign_reads"),
+    generate_fake_loaded_contract_data(
+      include_bytes!("../../../testdata/contracts/read_sibling.js"),
+      ContractType::JAVASCRIPT,
+      String::from("{}"),
+    ),
+    interactions,
+    IndexMap::new(),
+    None,
+    true,
+    false,
+    |_, _| unreachable!(),
+    &arweave,
+    &EvaluateOptions::default(),
+  )
+  .await;
+}
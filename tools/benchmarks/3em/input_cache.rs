@@ -0,0 +1,78 @@
+use deno_core::serde_json;
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::executor::raw_execute_contract;
+use three_em_executor::test_util::{
+  generate_fake_interaction, generate_fake_loaded_contract_data,
+};
+use three_em_executor::EvaluateOptions;
+use three_em_executor::IndexMap;
+
+// A contract evaluated over and over, as during development, whose
+// interactions carry large inputs (about 60 KiB each).
+const EVALUATIONS: usize = 20;
+const INTERACTIONS: usize = 200;
+const PAYLOAD_ITEMS: usize = 12_000;
+
+#[tokio::main]
+async fn main() {
+  // Usage: bench_input_cache [cached|uncached]
+  let cache_inputs = std::env::args()
+    .nth(1)
+    .map_or(true, |mode| mode == "cached");
+
+  let payload: Vec<usize> = (0..PAYLOAD_ITEMS).collect();
+  let interactions: Vec<GQLEdgeInterface> = (0..INTERACTIONS)
+    .map(|i| {
+      generate_fake_interaction(
+        serde_json::json!({
+          "function": "add",
+          "name": format!("user{}", i),
+          "payload": payload,
+        }),
+        &format!("tx{}", i),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    })
+    .collect();
+
+  let arweave = Arweave::new(
+    443,
+    "arweave.net".to_string(),
+    String::from("https"),
+    ArweaveCache::new(),
+  );
+  let options = EvaluateOptions {
+    cache_inputs,
+    ..Default::default()
+  };
+
+  for _ in 0..EVALUATIONS {
+    raw_execute_contract(
+      String::from("bench_input_cache"),
+      generate_fake_loaded_contract_data(
+        include_bytes!("../../../testdata/contracts/users_contract.js"),
+        ContractType::JAVASCRIPT,
+        String::from(r#"{"users":[]}"#),
+      ),
+      interactions.clone(),
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| unreachable!(),
+      &arweave,
+      &options,
+    )
+    .await;
+  }
+}
@@ -3,6 +3,7 @@ use three_em_arweave::arweave::Arweave;
 use three_em_arweave::cache::CacheExt;
 use three_em_arweave::lru_cache::ArweaveLruCache;
 use three_em_executor::execute_contract;
+use three_em_executor::EvaluateOptions;
 
 #[tokio::main]
 async fn main() {
@@ -26,6 +27,7 @@ async fn main() {
       None,
       None,
       &arweave,
+      &EvaluateOptions::default(),
     )
     .await
     .unwrap();
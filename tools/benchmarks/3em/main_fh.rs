@@ -1,7 +1,8 @@
+mod timing;
+
 use three_em_arweave::arweave::Arweave;
 use three_em_arweave::cache::ArweaveCache;
 use three_em_arweave::cache::CacheExt;
-use three_em_executor::execute_contract;
 
 #[tokio::main]
 async fn main() {
@@ -12,15 +13,10 @@ async fn main() {
     ArweaveCache::new(),
   );
 
-  execute_contract(
-    "t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE".to_string(),
-    Some(749180),
-    true,
-    false,
-    None,
-    None,
+  timing::bench(
     &arweave,
+    "t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE",
+    Some(749180),
   )
-  .await
-  .unwrap();
+  .await;
 }
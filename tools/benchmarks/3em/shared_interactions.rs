@@ -0,0 +1,77 @@
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_executor::executor::raw_execute_contract;
+use three_em_executor::{
+  execute_contracts, sort_interactions, EvaluateOptions, IndexMap,
+};
+
+// Pass the ids of contracts that share interactions to measure the overlap.
+// By default they don't, which still saves the per-contract GQL queries.
+const CONTRACTS: &[&str] = &[
+  "t9T7DIOGxx4VWXoCEeYYarFYeERTpWIC1V3y-BPZgKE",
+  "KfU_1Uxe3-h2r3tP6ZMfMT-HBFlM887tTFtS-p4edYQ",
+  "0zplqhFARjHyR-dBdNEY1TpZuKL0mWm--RFq6LByoew",
+];
+const HEIGHT: usize = 922000;
+
+#[tokio::main]
+async fn main() {
+  // Usage: bench_shared_interactions [shared|per-contract] [contract id...]
+  let mut args = std::env::args().skip(1);
+  let shared = args.next().map_or(true, |mode| mode == "shared");
+  let contract_ids: Vec<String> = match args.collect::<Vec<String>>() {
+    ids if ids.is_empty() => {
+      CONTRACTS.iter().map(|id| id.to_string()).collect()
+    }
+    ids => ids,
+  };
+
+  let arweave = Arweave::new(
+    443,
+    "arweave.net".to_string(),
+    String::from("https"),
+    ArweaveCache::new(),
+  );
+  let options = EvaluateOptions::default();
+
+  if shared {
+    execute_contracts(&contract_ids, Some(HEIGHT), false, &arweave, &options)
+      .await
+      .unwrap();
+    return;
+  }
+
+  for contract_id in contract_ids {
+    let loaded_contract = arweave
+      .load_contract(
+        contract_id.to_owned(),
+        None,
+        None,
+        None,
+        false,
+        false,
+        false,
+      )
+      .await
+      .unwrap();
+    let (mut interactions, _, _) = arweave
+      .get_interactions(contract_id.to_owned(), Some(HEIGHT), false)
+      .await
+      .unwrap();
+    sort_interactions(&mut interactions);
+
+    raw_execute_contract(
+      contract_id,
+      loaded_contract,
+      interactions,
+      IndexMap::new(),
+      None,
+      true,
+      false,
+      |_, _| unreachable!(),
+      &arweave,
+      &options,
+    )
+    .await;
+  }
+}
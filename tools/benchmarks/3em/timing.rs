@@ -0,0 +1,138 @@
+//! Phase timings shared by the benchmark binaries.
+//!
+//! Each binary takes `--iterations N` (default 1), prints a timing line per
+//! evaluation and, with more than one, the min, median and max per phase.
+
+use std::time::{Duration, Instant};
+use three_em_arweave::arweave::Arweave;
+use three_em_executor::executor::raw_execute_contract;
+use three_em_executor::{sort_interactions, EvaluateOptions, IndexMap};
+
+/// Time spent in each phase of one evaluation.
+#[derive(Clone, Copy)]
+pub struct Timings {
+  /// Loading the contract source and init state.
+  pub load: Duration,
+  /// Fetching and sorting the interactions.
+  pub fetch: Duration,
+  /// Evaluating every interaction.
+  pub execute: Duration,
+  pub total: Duration,
+}
+
+impl std::fmt::Display for Timings {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "load={:.3}ms fetch={:.3}ms execute={:.3}ms total={:.3}ms",
+      millis(self.load),
+      millis(self.fetch),
+      millis(self.execute),
+      millis(self.total)
+    )
+  }
+}
+
+fn millis(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1000.0
+}
+
+/// Value of `--iterations`, 1 when not given.
+fn iterations() -> usize {
+  let args: Vec<String> = std::env::args().collect();
+  match args.iter().position(|arg| arg == "--iterations") {
+    Some(i) => args
+      .get(i + 1)
+      .and_then(|n| n.parse().ok())
+      .filter(|n| *n > 0)
+      .expect("--iterations must be a positive number"),
+    None => 1,
+  }
+}
+
+/// Evaluates `contract_id` from its init state. The contract and its
+/// interactions may come from the cache, the state never does, so every
+/// iteration runs all interactions.
+async fn evaluate(
+  arweave: &Arweave,
+  contract_id: &str,
+  height: Option<usize>,
+) -> Timings {
+  let start = Instant::now();
+  let contract = arweave
+    .load_contract(contract_id.to_owned(), None, None, None, true, false, false)
+    .await
+    .unwrap();
+  let load = start.elapsed();
+
+  let (mut interactions, _, _) = arweave
+    .get_interactions(contract_id.to_owned(), height, true)
+    .await
+    .unwrap();
+  sort_interactions(&mut interactions);
+  let fetch = start.elapsed() - load;
+
+  raw_execute_contract(
+    contract_id.to_owned(),
+    contract,
+    interactions,
+    IndexMap::new(),
+    None,
+    true,
+    false,
+    |_, _| unreachable!(),
+    arweave,
+    &EvaluateOptions::default(),
+  )
+  .await;
+  let total = start.elapsed();
+
+  Timings {
+    load,
+    fetch,
+    execute: total - load - fetch,
+    total,
+  }
+}
+
+/// Evaluates `contract_id` `--iterations` times and prints the timings.
+pub async fn bench(
+  arweave: &Arweave,
+  contract_id: &str,
+  height: Option<usize>,
+) {
+  let mut runs = vec![];
+  for _ in 0..iterations() {
+    let timings = evaluate(arweave, contract_id, height).await;
+    println!("{}", timings);
+    runs.push(timings);
+  }
+
+  if runs.len() > 1 {
+    let sorted = |phase: fn(&Timings) -> Duration| {
+      let mut durations: Vec<Duration> = runs.iter().map(phase).collect();
+      durations.sort();
+      durations
+    };
+    let (load, fetch, execute, total) = (
+      sorted(|t| t.load),
+      sorted(|t| t.fetch),
+      sorted(|t| t.execute),
+      sorted(|t| t.total),
+    );
+
+    for (label, i) in [
+      ("min", 0),
+      ("median", runs.len() / 2),
+      ("max", runs.len() - 1),
+    ] {
+      let timings = Timings {
+        load: load[i],
+        fetch: fetch[i],
+        execute: execute[i],
+        total: total[i],
+      };
+      println!("{} {}", label, timings);
+    }
+  }
+}
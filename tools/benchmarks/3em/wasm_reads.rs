@@ -0,0 +1,75 @@
+use three_em_arweave::arweave::Arweave;
+use three_em_arweave::cache::ArweaveCache;
+use three_em_arweave::gql_result::GQLEdgeInterface;
+use three_em_arweave::miscellaneous::ContractType;
+use three_em_executor::executor::raw_execute_contract;
+use three_em_executor::test_util::{
+  generate_fake_interaction, generate_fake_loaded_contract_data,
+};
+use three_em_executor::EvaluateOptions;
+use three_em_executor::IndexMap;
+
+// Read-heavy workload: one write every 100 interactions.
+const INTERACTIONS: usize = 10_000;
+const WRITE_EVERY: usize = 100;
+
+#[tokio::main]
+async fn main() {
+  // Usage: bench_wasm_reads [workers]
+  let workers = std::env::args()
+    .nth(1)
+    .map(|n| n.parse().expect("workers must be a number"))
+    .unwrap_or(0);
+
+  let interactions: Vec<GQLEdgeInterface> = (0..INTERACTIONS)
+    .map(|i| {
+      let id = if i % WRITE_EVERY == 0 {
+        // `w` followed by the value to write.
+        format!("w{}{}", (b'a' + (i / WRITE_EVERY % 26) as u8) as char, i)
+      } else {
+        format!("r{}", i)
+      };
+
+      generate_fake_interaction(
+        Default::default(),
+        &id,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      )
+    })
+    .collect();
+
+  let arweave = Arweave::new(
+    443,
+    "arweave.net".to_string(),
+    String::from("https"),
+    ArweaveCache::new(),
+  );
+
+  raw_execute_contract(
+    String::from("bench_wasm_reads"),
+    generate_fake_loaded_contract_data(
+      include_bytes!("../../../testdata/04_wasm/04_wasm.wasm"),
+      ContractType::WASM,
+      String::from(r#"{"v":"a"}"#),
+    ),
+    interactions,
+    IndexMap::new(),
+    None,
+    true,
+    false,
+    |_, _| unreachable!(),
+    &arweave,
+    &EvaluateOptions {
+      wasm_read_workers: workers,
+      ..Default::default()
+    },
+  )
+  .await;
+}
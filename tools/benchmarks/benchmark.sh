@@ -6,6 +6,15 @@ hyperfine \
   --command-name "3em_js" "$REPO/$BUILDS/bench" \
   --command-name "3em_evm" "$REPO/$BUILDS/bench_evm" \
   --command-name "3em_wasm" "$REPO/$BUILDS/bench_wasm" \
+  --command-name "3em_wasm_reads" "$REPO/$BUILDS/bench_wasm_reads" \
+  --command-name "3em_wasm_reads_parallel" "$REPO/$BUILDS/bench_wasm_reads 8" \
+  --command-name "3em_foreign_reads" "$REPO/$BUILDS/bench_foreign_reads" \
+  --command-name "3em_per_contract_fetch" "$REPO/$BUILDS/bench_shared_interactions per-contract" \
+  --command-name "3em_shared_fetch" "$REPO/$BUILDS/bench_shared_interactions shared" \
+  --command-name "3em_inputs_uncached" "$REPO/$BUILDS/bench_input_cache uncached" \
+  --command-name "3em_inputs_cached" "$REPO/$BUILDS/bench_input_cache cached" \
+  --command-name "3em_cache_json" "$REPO/$BUILDS/bench_cache_format json" \
+  --command-name "3em_cache_cbor" "$REPO/$BUILDS/bench_cache_format cbor" \
   --command-name "smartweave.js" "node $REPO/tools/benchmarks/smartweave/index.js" \
   --runs 20 \
   --warmup 5 \